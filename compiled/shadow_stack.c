@@ -0,0 +1,64 @@
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+
+// --shadow-stackでコンパイルしたKSCの関数は、入口でksc_shadow_pushを、returnの前でksc_shadow_popを呼ぶ
+// クラッシュしたときにはシグナルハンドラがこのスタックをKSCの関数名で表示する
+
+#define KSC_SHADOW_STACK_SIZE 1024
+
+static const char *ksc_shadow_stack[KSC_SHADOW_STACK_SIZE];
+static int ksc_shadow_depth = 0;
+static char ksc_signal_stack[65536];
+
+void ksc_shadow_push(const char *name)
+{
+    if (ksc_shadow_depth < KSC_SHADOW_STACK_SIZE)
+    {
+        ksc_shadow_stack[ksc_shadow_depth] = name;
+    }
+    ksc_shadow_depth++;
+}
+
+void ksc_shadow_pop(void)
+{
+    if (ksc_shadow_depth > 0)
+    {
+        ksc_shadow_depth--;
+    }
+}
+
+static void ksc_print_backtrace(int sig)
+{
+    fprintf(stderr, "ksc: program received signal %d\n", sig);
+    fprintf(stderr, "KSC call stack (most recent call first):\n");
+    if (ksc_shadow_depth > KSC_SHADOW_STACK_SIZE)
+    {
+        fprintf(stderr, "  ... %d frames not recorded\n", ksc_shadow_depth - KSC_SHADOW_STACK_SIZE);
+    }
+    for (int i = (ksc_shadow_depth < KSC_SHADOW_STACK_SIZE ? ksc_shadow_depth : KSC_SHADOW_STACK_SIZE) - 1; i >= 0; i--)
+    {
+        fprintf(stderr, "  #%d %s\n", ksc_shadow_depth - 1 - i, ksc_shadow_stack[i]);
+    }
+    _exit(128 + sig);
+}
+
+// スタックオーバーフローでもハンドラが動くように、別のスタックで受け取る
+__attribute__((constructor)) static void ksc_install_signal_handlers(void)
+{
+    stack_t signal_stack = {0};
+    signal_stack.ss_sp = ksc_signal_stack;
+    signal_stack.ss_size = sizeof(ksc_signal_stack);
+    sigaltstack(&signal_stack, NULL);
+
+    struct sigaction action = {0};
+    action.sa_handler = ksc_print_backtrace;
+    action.sa_flags = SA_ONSTACK;
+    sigemptyset(&action.sa_mask);
+    int signals[] = {SIGSEGV, SIGABRT, SIGILL, SIGFPE, SIGBUS};
+    for (unsigned i = 0; i < sizeof(signals) / sizeof(signals[0]); i++)
+    {
+        sigaction(signals[i], &action, NULL);
+    }
+}
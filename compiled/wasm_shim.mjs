@@ -0,0 +1,89 @@
+// wasm32-unknown-unknown向けにビルドしたKSCのモジュールを動かすためのホスト側のランタイム
+// libcがないので、KSCが呼ぶprint(ksc_print_*)とメモリ確保(malloc/realloc/free)と文字列関数をここで用意する
+//
+//   node ./wasm_shim.mjs ./ksc.wasm    (エクスポートされたmainを呼び、戻り値を終了コードにする)
+
+const decoder = new TextDecoder();
+
+export async function instantiate(bytes, write = (text) => process.stdout.write(text)) {
+    let instance = null;
+    const memory = () => new Uint8Array(instance.exports.memory.buffer);
+
+    // ヒープは__heap_baseから始まる単純なバンプアロケータ(freeしても再利用しない)
+    // reallocで中身を移せるように、ブロックの前の8バイトに大きさを書いておく
+    let heapTop = 0;
+    const malloc = (size) => {
+        if (heapTop === 0) {
+            heapTop = instance.exports.__heap_base.value;
+        }
+        const block = (heapTop + 8 + 7) & ~7;
+        const end = block + size;
+        const pages = Math.ceil((end - instance.exports.memory.buffer.byteLength) / 65536);
+        if (pages > 0) {
+            instance.exports.memory.grow(pages);
+        }
+        new DataView(instance.exports.memory.buffer).setUint32(block - 8, size, true);
+        heapTop = end;
+        return block;
+    };
+    const blockSize = (pointer) => new DataView(instance.exports.memory.buffer).getUint32(pointer - 8, true);
+
+    const strlen = (pointer) => {
+        const bytes = memory();
+        let end = pointer;
+        while (bytes[end] !== 0) {
+            end++;
+        }
+        return end - pointer;
+    };
+    const readString = (pointer) => decoder.decode(memory().subarray(pointer, pointer + strlen(pointer)));
+
+    const env = {
+        ksc_print_f64: (value) => write(value.toFixed(6)),
+        ksc_print_i64: (value) => write(BigInt.asIntN(64, value).toString()),
+        ksc_print_u64: (value) => write(BigInt.asUintN(64, value).toString()),
+        ksc_print_str: (pointer) => write(readString(pointer)),
+        malloc,
+        realloc: (pointer, size) => {
+            const block = malloc(size);
+            if (pointer !== 0) {
+                memory().copyWithin(block, pointer, pointer + Math.min(blockSize(pointer), size));
+            }
+            return block;
+        },
+        free: (_pointer) => {},
+        strlen,
+        strcpy: (destination, source) => {
+            memory().copyWithin(destination, source, source + strlen(source) + 1);
+            return destination;
+        },
+        strcat: (destination, source) => {
+            const end = destination + strlen(destination);
+            memory().copyWithin(end, source, source + strlen(source) + 1);
+            return destination;
+        },
+        memcpy: (destination, source, size) => {
+            memory().copyWithin(destination, source, source + size);
+            return destination;
+        },
+        memmove: (destination, source, size) => {
+            memory().copyWithin(destination, source, source + size);
+            return destination;
+        },
+        memset: (destination, value, size) => {
+            memory().fill(value, destination, destination + size);
+            return destination;
+        },
+    };
+
+    ({ instance } = await WebAssembly.instantiate(bytes, { env }));
+    return instance;
+}
+
+if (typeof process !== "undefined" && process.argv[1] && import.meta.url.endsWith(process.argv[1].split("/").pop())) {
+    const { readFile } = await import("node:fs/promises");
+    const instance = await instantiate(await readFile(process.argv[2] ?? "./ksc.wasm"));
+    const result = instance.exports.main ? instance.exports.main() : 0;
+    // KSCのmainはNumberや整数も返せるので、終了コードにするときは整数に切り捨てる
+    process.exitCode = typeof result === "bigint" ? Number(BigInt.asIntN(32, result)) : Math.trunc(result ?? 0);
+}
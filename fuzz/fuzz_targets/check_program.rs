@@ -0,0 +1,94 @@
+//! 任意のバイト列からASTを作り、名前と型の検査(check_program)に通す。誤りは診断として返るだけで、panicしてはいけない
+//! パーサーができたら、バイト列をそのままソースコードとして読ませるターゲットを足す
+//! cargo fuzz run check_program
+#![no_main]
+
+use ksc1000::{check_program, BinaryOperation, BinaryOperator, Expression, LogicalOperator, Predicate, UnaryOperator};
+use libfuzzer_sys::fuzz_target;
+
+/// 名前は少ない候補から選び、宣言と参照がぶつかりやすくする
+const NAMES: [&str; 5] = ["a", "b", "f", "main", "Point"];
+const TYPES: [&str; 9] = ["i32", "u8", "Number", "Bool", "String", "Void", "Point", "Function(i32) -> i32", "Number[2]"];
+const MAX_DEPTH: usize = 8;
+
+/// バイト列を先頭から読んで式を選ぶ(読み終わったら0が続くものとする)
+struct Decoder<'d>{
+    bytes: &'d [u8],
+    position: usize
+}
+
+impl<'d> Decoder<'d>{
+    fn byte(&mut self) -> u8 {
+        let byte = self.bytes.get(self.position).copied().unwrap_or(0);
+        self.position += 1;
+        return byte;
+    }
+
+    fn pick<T: Copy>(&mut self, candidates: &[T]) -> T {
+        return candidates[self.byte() as usize % candidates.len()];
+    }
+
+    fn name(&mut self) -> String {
+        return self.pick(&NAMES).to_string();
+    }
+
+    fn typename(&mut self) -> String {
+        return self.pick(&TYPES).to_string();
+    }
+
+    fn block(&mut self, depth: usize) -> Vec<Expression> {
+        let length = self.byte() % 4;
+        return (0..length).map(|_| self.expression(depth + 1)).collect();
+    }
+
+    fn boxed(&mut self, depth: usize) -> Box<Expression> {
+        return Box::from(self.expression(depth + 1));
+    }
+
+    fn expression(&mut self, depth: usize) -> Expression {
+        // 深くなったら葉だけを選ぶ
+        let choice = if depth >= MAX_DEPTH || self.position >= self.bytes.len() { self.byte() % 5 } else { self.byte() % 16 };
+        return match choice {
+            0 => Expression::IntLiteral(self.byte() as i8 as i64),
+            1 => Expression::NumberLiteral(self.byte() as f64 / 4.0),
+            2 => Expression::BoolLiteral(self.byte() % 2 == 0),
+            3 => Expression::StringLiteral(self.name()),
+            4 => Expression::Identifier(self.name()),
+            5 => {
+                let op = match self.byte() % 3 {
+                    0 => BinaryOperation::Arithmetic(self.pick(&[BinaryOperator::ADD, BinaryOperator::SUB, BinaryOperator::MUL, BinaryOperator::DIV, BinaryOperator::REM, BinaryOperator::AND, BinaryOperator::SHL])),
+                    1 => BinaryOperation::Comparison(self.pick(&[Predicate::EQUAL, Predicate::NOT_EQUAL, Predicate::LESS_THAN, Predicate::GREATER_THAN_OR_EQUAL])),
+                    _ => BinaryOperation::Logical(self.pick(&[LogicalOperator::AND, LogicalOperator::OR]))
+                };
+                Expression::Binary { op, lhs: self.boxed(depth), rhs: self.boxed(depth) }
+            },
+            6 => Expression::Unary { op: self.pick(&[UnaryOperator::NEG, UnaryOperator::NOT]), operand: self.boxed(depth) },
+            7 => Expression::Call { callee: self.name(), args: self.block(depth) },
+            8 => Expression::If { condition: self.boxed(depth), then: self.block(depth), otherwise: self.block(depth) },
+            9 => Expression::VariableDeclaration { typename: self.typename(), name: self.name(), mutable: self.byte() % 2 == 0, align: None, value: self.boxed(depth) },
+            10 => Expression::Assignment { target: self.name(), value: self.boxed(depth) },
+            11 => {
+                let count = (self.byte() % 3) as usize;
+                Expression::Function {
+                    name: self.name(),
+                    return_type: self.typename(),
+                    param_types: (0..count).map(|_| self.typename()).collect(),
+                    param_names: (0..count).map(|_| self.name()).collect(),
+                    type_params: vec![],
+                    content: self.block(depth)
+                }
+            },
+            12 => Expression::Return(if self.byte() % 2 == 0 { None } else { Some(self.boxed(depth)) }),
+            13 => Expression::ConstDeclaration { typename: self.typename(), name: self.name(), value: self.boxed(depth) },
+            14 => Expression::Cast { value: self.boxed(depth), target_type: self.typename() },
+            _ => Expression::SomeValue(self.boxed(depth))
+        };
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut decoder = Decoder{ bytes: data, position: 0 };
+    let program = decoder.block(0);
+    // 誤りのあるプログラムはErrになるだけなので、結果は見ない
+    let _ = check_program(&program);
+});
@@ -0,0 +1,8733 @@
+// return x;で値を返すのと、ASTを&Vec<Expression>で受け取るのはこのリポジトリの書き方
+#![allow(clippy::needless_return, clippy::ptr_arg)]
+
+#[cfg(feature = "llvm")]
+use crypto::{sha2::Sha256, digest::Digest};
+#[cfg(feature = "llvm")]
+use inkwell::{attributes::{Attribute, AttributeLoc}, passes::PassManager, values::InstructionOpcode, context::Context, builder::Builder, module::Module, targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetData, TargetMachine, TargetTriple}, OptimizationLevel, types::{ArrayType, AnyTypeEnum, BasicMetadataTypeEnum, BasicTypeEnum, PointerType, FunctionType, AnyType, BasicType, FloatType, IntType, StructType}, values::{FloatValue, FunctionValue, BasicValue, AnyValue, BasicValueEnum, IntValue, PointerValue, BasicMetadataValueEnum, StructValue, CallableValue}, IntPredicate, basic_block::BasicBlock, FloatPredicate, AddressSpace};
+#[cfg(feature = "llvm")]
+use inkwell::{debug_info::{DebugInfoBuilder, DICompileUnit, DIFlags, DIFlagsConstants, DIType, DWARFEmissionKind, DWARFSourceLanguage, AsDIScope}, module::{FlagBehavior, Linkage}};
+#[cfg(feature = "llvm")]
+use std::{env, cell::RefCell, collections::HashMap, mem::discriminant, path::PathBuf};
+#[cfg(feature = "llvm")]
+use std::fs::File;
+#[cfg(feature = "llvm")]
+use std::io::prelude::*;
+
+/// Compiler::newに渡すContextとBuilderを、このcrateと同じ版のinkwellで作れるように公開する
+#[cfg(feature = "llvm")]
+pub use inkwell;
+
+#[derive(Debug, Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub enum Predicate{
+    EQUAL,
+    NOT_EQUAL,
+    GREATER_THAN,
+    GREATER_THAN_OR_EQUAL,
+    LESS_THAN,
+    LESS_THAN_OR_EQUAL,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BinaryOperator{
+    ADD,SUB,MUL,DIV,
+    REM,
+    /// ビット演算(整数のみ)
+    AND,OR,XOR,
+    /// シフト(整数のみ)。ASHRは算術右シフト、LSHRは論理右シフト
+    SHL,ASHR,LSHR
+}
+
+impl BinaryOperator{
+    /// ビット演算やシフトかどうか(整数にしか使えない)
+    fn is_bitwise(&self) -> bool {
+        return matches!(self, BinaryOperator::AND | BinaryOperator::OR | BinaryOperator::XOR | BinaryOperator::SHL | BinaryOperator::ASHR | BinaryOperator::LSHR);
+    }
+}
+
+/// 論理演算子(右辺は必要なときだけ評価する)
+#[derive(Debug, Clone, Copy)]
+pub enum LogicalOperator{
+    AND,OR
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum UnaryOperator{
+    NEG,NOT
+}
+
+impl std::fmt::Display for Predicate{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return write!(f, "{}", match self {
+            Predicate::EQUAL => "==",
+            Predicate::NOT_EQUAL => "!=",
+            Predicate::GREATER_THAN => ">",
+            Predicate::GREATER_THAN_OR_EQUAL => ">=",
+            Predicate::LESS_THAN => "<",
+            Predicate::LESS_THAN_OR_EQUAL => "<=",
+        });
+    }
+}
+
+impl std::fmt::Display for BinaryOperator{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return write!(f, "{}", match self {
+            BinaryOperator::ADD => "+",
+            BinaryOperator::SUB => "-",
+            BinaryOperator::MUL => "*",
+            BinaryOperator::DIV => "/",
+            BinaryOperator::REM => "%",
+            BinaryOperator::AND => "&",
+            BinaryOperator::OR => "|",
+            BinaryOperator::XOR => "^",
+            BinaryOperator::SHL => "<<",
+            BinaryOperator::ASHR => ">>",
+            BinaryOperator::LSHR => ">>>",
+        });
+    }
+}
+
+impl std::fmt::Display for LogicalOperator{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return write!(f, "{}", match self {
+            LogicalOperator::AND => "&&",
+            LogicalOperator::OR => "||",
+        });
+    }
+}
+
+impl std::fmt::Display for UnaryOperator{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return write!(f, "{}", match self {
+            UnaryOperator::NEG => "-",
+            UnaryOperator::NOT => "!",
+        });
+    }
+}
+
+/// 式の入れ子の深さの上限の既定値(--max-depthで変えられる)
+/// 名前解決、意味解析、コード生成はどれも再帰で木をたどるので、スタックが溢れる前にエラーにする
+const DEFAULT_MAX_EXPRESSION_DEPTH: usize = 256;
+
+/// コンパイル時に評価した定数の値(整数のリテラルと同じくi32)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstantValue{
+    Number(f64),
+    Int(i32),
+    Bool(bool)
+}
+
+/// 二項演算の種類(四則演算、比較演算と論理演算)
+#[derive(Debug, Clone, Copy)]
+pub enum BinaryOperation{
+    Arithmetic(BinaryOperator),
+    Comparison(Predicate),
+    Logical(LogicalOperator)
+}
+
+impl std::fmt::Display for BinaryOperation{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            BinaryOperation::Arithmetic(op) => write!(f, "{op}"),
+            BinaryOperation::Comparison(predicate) => write!(f, "{predicate}"),
+            BinaryOperation::Logical(op) => write!(f, "{op}"),
+        };
+    }
+}
+
+#[derive(Clone)]
+#[cfg(feature = "llvm")]
+enum KSCType<'ctx>{
+    Number(FloatType<'ctx>),
+    /// 整数(i8/i16/i32/i64とu8/u16/u32/u64)
+    Int{
+        reference: IntType<'ctx>,
+        signed: bool
+    },
+    Bool(IntType<'ctx>),
+    Function{
+        reference: PointerType<'ctx>,
+        return_type: Box<KSCType<'ctx>>,
+        parameter: Vec<KSCType<'ctx>>
+    },
+    /// 環境を捕まえた無名関数(LLVMでは関数ポインタと環境へのポインタの組)
+    Closure{
+        reference: StructType<'ctx>,
+        return_type: Box<KSCType<'ctx>>,
+        parameter: Vec<KSCType<'ctx>>
+    },
+    Void,
+    String(PointerType<'ctx>),
+    RawPtr{
+        reference: PointerType<'ctx>,
+        pointee: Box<KSCType<'ctx>>
+    },
+    Array{
+        reference: ArrayType<'ctx>,
+        element: Box<KSCType<'ctx>>,
+        length: u32
+    },
+    Struct{
+        name: String,
+        reference: StructType<'ctx>,
+        field_names: Vec<String>,
+        contents: Vec<KSCType<'ctx>>,
+        default_values: Vec<Option<KSCValue<'ctx>>>,
+        /// 宣言の順のフィールドの番号から、LLVMの構造体での番号への対応(layout_struct_fieldsが決める)
+        field_indices: Vec<u32>,
+    },
+    List{
+        reference: PointerType<'ctx>,
+        element: Box<KSCType<'ctx>>
+    },
+    /// タプル(LLVMでは名前のない構造体)
+    Tuple{
+        reference: StructType<'ctx>,
+        elements: Vec<KSCType<'ctx>>
+    },
+    /// 既存の型を包んだ別の型(LLVMでは要素が1つの構造体)
+    NewType{
+        name: String,
+        reference: StructType<'ctx>,
+        underlying: Box<KSCType<'ctx>>
+    },
+    /// 列挙型(LLVMでは{ i32 タグ, [N x i64] 中身 }の構造体。タグはヴァリアントの番号)
+    /// 中身はいちばん大きいヴァリアントの値が入る大きさで、ヴァリアントごとの構造体として読み書きする
+    Enum{
+        name: String,
+        reference: StructType<'ctx>,
+        variant_names: Vec<String>,
+        payloads: Vec<Vec<KSCType<'ctx>>>
+    },
+    /// 値がないかもしれない型(LLVMでは{ i1 値があるか, 中身 }の構造体。値がなければ中身は0)
+    Optional{
+        reference: StructType<'ctx>,
+        inner: Box<KSCType<'ctx>>
+    }
+}
+
+#[cfg(feature = "llvm")]
+impl<'ctx> KSCType<'ctx>{
+    /// 符号付き整数かどうか(割り算や大小比較の命令を選ぶのに使う)
+    fn is_signed(&self) -> bool {
+        return matches!(self, KSCType::Int { signed: true, .. });
+    }
+
+    /// i32かどうか(配列の添字やポインタのオフセットはi32)
+    fn is_i32(&self) -> bool {
+        return matches!(self, KSCType::Int { reference, signed: true } if reference.get_bit_width() == 32);
+    }
+
+    /// 診断に表示するための型の名前
+    fn type_name(&self) -> String {
+        return match self {
+            KSCType::Number(_) => "Number".to_string(),
+            KSCType::Int { reference, signed } => format!("{}{}", if *signed { "i" } else { "u" }, reference.get_bit_width()),
+            KSCType::Bool(_) => "Bool".to_string(),
+            KSCType::Function { return_type, parameter, .. } => {
+                let parameter = parameter.iter().map(|p| p.type_name()).collect::<Vec<String>>().join(", ");
+                format!("Function({parameter}) -> {}", return_type.type_name())
+            },
+            KSCType::Closure { return_type, parameter, .. } => {
+                let parameter = parameter.iter().map(|p| p.type_name()).collect::<Vec<String>>().join(", ");
+                format!("Closure({parameter}) -> {}", return_type.type_name())
+            },
+            KSCType::Void => "Void".to_string(),
+            KSCType::String(_) => "String".to_string(),
+            KSCType::RawPtr { pointee, .. } => format!("RawPtr<{}>", pointee.type_name()),
+            // 関数の配列は、戻り値の型の配列と区別するために括弧で囲む
+            KSCType::Array { element, length, .. } if matches!(element.as_ref(), KSCType::Function { .. } | KSCType::Closure { .. }) => format!("({})[{length}]", element.type_name()),
+            KSCType::Array { element, length, .. } => format!("{}[{length}]", element.type_name()),
+            KSCType::Struct { name, .. } => name.to_string(),
+            KSCType::List { element, .. } => format!("List<{}>", element.type_name()),
+            KSCType::Tuple { elements, .. } => format!("({})", elements.iter().map(|e| e.type_name()).collect::<Vec<String>>().join(", ")),
+            KSCType::NewType { name, .. } => name.to_string(),
+            KSCType::Enum { name, .. } => name.to_string(),
+            KSCType::Optional { inner, .. } => format!("Optional<{}>", inner.type_name()),
+        };
+    }
+}
+
+/// 型の互換性
+/// KSCにはまだ暗黙の型変換がないので、同じ型かそうでないかの二通り
+#[derive(PartialEq)]
+#[cfg(feature = "llvm")]
+enum Compatibility{
+    Identical,
+    Incompatible
+}
+
+#[derive(Clone)]
+#[cfg(feature = "llvm")]
+struct KSCValue<'ctx>{
+    valuetype: KSCType<'ctx>,
+    value: Option<BasicValueEnum<'ctx>>
+}
+
+/// 組み込み関数
+#[derive(Clone, Copy)]
+enum BuiltinFunction{
+    Fma,
+    Print,
+    Println,
+    /// partial(f, x, ..)。fの先頭の引数を束縛したクロージャを作る
+    Partial,
+    /// format("x = {}", x)。{}を値の文字列に置き換えた文字列を作る
+    Format,
+    /// wrapping_add(a, b)、checked_mul(a, b)など。整数の演算があふれたときの扱いを--overflowによらず選ぶ
+    Overflowing(#[cfg_attr(not(feature = "llvm"), allow(dead_code))] BinaryOperator, OverflowMode),
+    /// assert(condition, "message")。条件が偽ならメッセージを表示して止まる(メッセージは省略できる)
+    Assert
+}
+
+impl BuiltinFunction{
+    /// 名前から組み込み関数を探す(ユーザー定義の関数より先に探される)
+    fn from_name(name: &str) -> Option<BuiltinFunction> {
+        return match name {
+            "fma" => Some(BuiltinFunction::Fma),
+            "print" => Some(BuiltinFunction::Print),
+            "println" => Some(BuiltinFunction::Println),
+            "partial" => Some(BuiltinFunction::Partial),
+            "format" => Some(BuiltinFunction::Format),
+            "assert" => Some(BuiltinFunction::Assert),
+            "wrapping_add" => Some(BuiltinFunction::Overflowing(BinaryOperator::ADD, OverflowMode::Wrap)),
+            "wrapping_sub" => Some(BuiltinFunction::Overflowing(BinaryOperator::SUB, OverflowMode::Wrap)),
+            "wrapping_mul" => Some(BuiltinFunction::Overflowing(BinaryOperator::MUL, OverflowMode::Wrap)),
+            "trapping_add" => Some(BuiltinFunction::Overflowing(BinaryOperator::ADD, OverflowMode::Trap)),
+            "trapping_sub" => Some(BuiltinFunction::Overflowing(BinaryOperator::SUB, OverflowMode::Trap)),
+            "trapping_mul" => Some(BuiltinFunction::Overflowing(BinaryOperator::MUL, OverflowMode::Trap)),
+            "checked_add" => Some(BuiltinFunction::Overflowing(BinaryOperator::ADD, OverflowMode::Checked)),
+            "checked_sub" => Some(BuiltinFunction::Overflowing(BinaryOperator::SUB, OverflowMode::Checked)),
+            "checked_mul" => Some(BuiltinFunction::Overflowing(BinaryOperator::MUL, OverflowMode::Checked)),
+            _ => None
+        };
+    }
+}
+
+/// ソースコード上の範囲(SourceMapに登録したファイルの番号と、バイト単位の開始位置と終了位置)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span{
+    pub file_id: usize,
+    pub start: usize,
+    pub end: usize
+}
+
+/// ソースファイル
+struct SourceFile{
+    name: String,
+    text: String
+}
+
+/// コンパイルするソースファイルの一覧(Spanの位置を行と列に直したり、エラーの箇所を表示したりする)
+struct SourceMap{
+    files: Vec<SourceFile>
+}
+
+impl SourceMap{
+    fn new() -> SourceMap {
+        return SourceMap{ files: vec![] };
+    }
+
+    /// ファイルを登録して、Spanで使う番号を返す
+    #[cfg(feature = "llvm")]
+    fn add_file(&mut self, name: &str, text: &str) -> usize {
+        self.files.push(SourceFile{ name: name.to_string(), text: text.to_string() });
+        return self.files.len() - 1;
+    }
+
+    /// Spanの開始位置の行と列(どちらも1から数える)
+    fn line_column(&self, span: &Span) -> Option<(u32, u32)> {
+        let file = self.files.get(span.file_id)?;
+        let before = file.text.get(..span.start)?;
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+        return Some((line as u32, column as u32));
+    }
+
+    /// エラーを、位置がわかればその行を添えて表示できるようにする
+    fn render(&self, error: &CompileError) -> String {
+        return self.render_diagnostic("error", error);
+    }
+
+    /// 警告を、位置がわかればその行を添えて表示できるようにする
+    #[cfg(feature = "llvm")]
+    fn render_warning(&self, warning: &CompileError) -> String {
+        return self.render_diagnostic("warning", warning);
+    }
+
+    fn render_diagnostic(&self, label: &str, diagnostic: &CompileError) -> String {
+        let heading = format!("{label}: {}", diagnostic.message);
+        let (span, file, (line, column)) = match diagnostic.span.and_then(|span| Some((span, self.files.get(span.file_id)?, self.line_column(&span)?))) {
+            Some(location) => location,
+            None => return heading
+        };
+        let source_line = file.text.lines().nth(line as usize - 1).unwrap_or("");
+        let width = file.text.get(span.start..span.end)
+                        .map(|text| text.chars().take_while(|c| *c != '\n').count())
+                        .unwrap_or(0)
+                        .max(1);
+        let gutter = " ".repeat(line.to_string().len());
+        return format!(
+            "{heading}\n{gutter}--> {}:{line}:{column}\n{gutter} |\n{line} | {source_line}\n{gutter} | {}{}",
+            file.name, " ".repeat(column as usize - 1), "^".repeat(width)
+        );
+    }
+}
+
+/// コンパイルエラー(KSCのプログラム側の誤りを報告する診断)
+/// spanはエラーの起きた式の位置(位置のついた式の中で起きたときだけわかる)
+#[derive(Debug)]
+pub struct CompileError{
+    pub message: String,
+    pub span: Option<Span>
+}
+
+impl std::fmt::Display for CompileError{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "error: {}", self.message)
+    }
+}
+
+/// 診断の重さ
+#[derive(Clone, Copy, PartialEq)]
+enum Severity{
+    Error,
+    #[cfg(feature = "llvm")]
+    Warning
+}
+
+/// 見つかった順に集めた診断
+/// 最初のエラーで止めずに解析を続け、最後にまとめて報告する
+struct DiagnosticBag{
+    diagnostics: Vec<(Severity, CompileError)>
+}
+
+impl DiagnosticBag{
+    fn new() -> DiagnosticBag {
+        return DiagnosticBag{ diagnostics: vec![] };
+    }
+
+    fn error(&mut self, error: CompileError) {
+        self.diagnostics.push((Severity::Error, error));
+    }
+
+    #[cfg(feature = "llvm")]
+    fn warning(&mut self, warning: CompileError) {
+        self.diagnostics.push((Severity::Warning, warning));
+    }
+
+    /// 別の診断を後ろにつなげる
+    fn append(&mut self, mut other: DiagnosticBag) {
+        self.diagnostics.append(&mut other.diagnostics);
+    }
+
+    fn error_count(&self) -> usize {
+        return self.diagnostics.iter().filter(|(severity, _)| *severity == Severity::Error).count();
+    }
+
+    fn has_errors(&self) -> bool {
+        return self.error_count() > 0;
+    }
+
+    #[cfg(feature = "llvm")]
+    fn is_empty(&self) -> bool {
+        return self.diagnostics.is_empty();
+    }
+
+    /// すべての診断を表示できるようにする(エラーがあれば最後にその数を添える)
+    fn render(&self, sources: &SourceMap) -> String {
+        let mut rendered = self.diagnostics.iter()
+                            .map(|(severity, diagnostic)| match severity {
+                                Severity::Error => sources.render(diagnostic),
+                                #[cfg(feature = "llvm")]
+                                Severity::Warning => sources.render_warning(diagnostic),
+                            })
+                            .collect::<Vec<String>>();
+        match self.error_count() {
+            0 => {},
+            1 => rendered.push("error: aborting due to 1 previous error".to_string()),
+            count => rendered.push(format!("error: aborting due to {count} previous errors")),
+        }
+        return rendered.join("\n\n");
+    }
+}
+
+/// 変数(名前に束縛された値の置き場所)
+#[cfg(feature = "llvm")]
+struct KSCVariable<'ctx>{
+    valuetype: KSCType<'ctx>,
+    pointer: PointerValue<'ctx>,
+    mutable: bool
+}
+
+/// スタック(スコープごとに用意する、定義された変数や型を保存するもの。スコープを抜けるとpop)
+#[cfg(feature = "llvm")]
+struct Stack<'ctx>{
+    types: HashMap<String, KSCType<'ctx>>,
+    values: HashMap<String, KSCVariable<'ctx>>,
+    /// このスコープの変数のうち、参照カウントで管理する値を持つもの(スコープを抜けるときに解放する)
+    owned: Vec<(PointerValue<'ctx>, KSCType<'ctx>)>,
+    /// constで宣言した定数(値はコンパイル時に決まっているので、場所を持たない)
+    constants: HashMap<String, ConstantValue>
+}
+
+/// unsafeな箇所の記録(--emit unsafe-reportで出力する)
+#[cfg(feature = "llvm")]
+enum UnsafeUsage{
+    Block{ function: String },
+    ExternCall{ function: String, callee: String }
+}
+
+#[cfg(feature = "llvm")]
+impl std::fmt::Display for UnsafeUsage{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UnsafeUsage::Block { function } => write!(f, "unsafe block in '{function}'"),
+            UnsafeUsage::ExternCall { function, callee } => write!(f, "extern call to '{callee}' in '{function}'"),
+        }
+    }
+}
+
+/// 浮動小数点演算のモード
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FloatMode{
+    /// IEEE 754に厳密に従う(デフォルト)
+    Strict,
+    /// 再現性と引き換えに高速化を許す(fast-math)
+    /// a * b + cはfmaにまとめる(create_fused_add)。ほかは関数属性だけで、命令(faddなど)にはfast-mathフラグが付かない(apply_float_mode)
+    Fast
+}
+
+/// 整数の+、-、*があふれたときの扱い
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowMode{
+    /// 2の補数で折り返す(デフォルト)
+    Wrap,
+    /// llvm.trapで止める
+    Trap,
+    /// 結果をOptionalにし、あふれたら値なしにする(checked_addなどの組み込み関数だけで使える)
+    Checked
+}
+
+/// コンパイルのフェーズ(パイプラインの順に並ぶ。--stop-afterやCompilerEventで使う)
+#[cfg(feature = "llvm")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase{
+    /// ASTができたところ
+    Parse,
+    /// 意味解析(型検査はコード生成と同時に行う)と検証まで
+    Typecheck,
+    /// 最適化前のIR
+    Codegen,
+    /// 最適化後のIR
+    Opt,
+    /// 出力(IR、ビットコード、アセンブリ、インターフェース)の書き出し。--stop-afterでは選べない
+    Emit
+}
+
+/// コンパイラの進み具合を知らせるイベント(on_eventで登録した関数に渡される)
+/// GUIやLSPが、大きなプログラムのコンパイル中に進捗を表示するために使う
+#[cfg(feature = "llvm")]
+#[derive(Debug)]
+pub enum CompilerEvent<'e>{
+    PhaseStarted(Phase),
+    PhaseFinished(Phase),
+    /// 関数(クロージャを含む)の本体を書き終えた
+    FunctionCompiled{ name: &'e str },
+    /// 最適化の段階(パスのまとまり)を1つ実行し終えた
+    OptimizationStage{ name: &'e str, elapsed: std::time::Duration },
+    /// --dump-ir-per-functionのときのIR(関数を書き終えたときはその関数の、最適化の段階の後はモジュール全体のIR)
+    IrDump{ after: &'e str, ir: &'e str },
+    /// コンパイルエラーが起きた(エラー自体はbuild()などの戻り値でも返る)
+    Diagnostic(&'e CompileError),
+    /// 構造体のフィールドを並べ替えて小さくした(大きさはバイト。--opt-reportで表示する)
+    StructReordered{ name: &'e str, declared_size: u64, size: u64 }
+}
+
+/// ループの文脈(breakで抜ける先とcontinueで戻る先のブロック)
+/// scope_depthとtemporariesは、ループに入ったときのスコープの数と一時的な参照の数。抜けるときにそれより後のものを解放する
+#[cfg(feature = "llvm")]
+#[derive(Clone, Copy)]
+struct LoopContext<'ctx>{
+    header_block: BasicBlock<'ctx>,
+    exit_block: BasicBlock<'ctx>,
+    scope_depth: usize,
+    temporaries: usize
+}
+
+/// 総称型の構造体の宣言(型引数の名前、フィールドの名前と型)
+#[cfg(feature = "llvm")]
+type GenericStruct = (Vec<String>, Vec<String>, Vec<String>);
+
+/// on_eventで登録した、コンパイラのイベントを受け取る関数
+#[cfg(feature = "llvm")]
+type EventListener<'a> = Box<dyn FnMut(&CompilerEvent) + 'a>;
+
+/// コンパイラ構造体
+/// 埋め込んで使うときは、new、initialize_module_by_filepath、add_source、on_event、build、verify、optimize、emit_as_textの順に呼ぶ
+/// buildは名前や型の誤りを調べ終えたプログラムを受け取るので、先にcheck_programを通しておく
+#[cfg(feature = "llvm")]
+pub struct Compiler<'a, 'ctx>{
+    context: &'ctx Context,
+    builder: &'a Builder<'ctx>,
+    module: Option<Module<'ctx>>,
+    functions: HashMap<String, (FunctionValue<'ctx>, KSCType<'ctx>)>,
+    stack_current_function: Vec<FunctionValue<'ctx>>,
+    stack_loop: Vec<LoopContext<'ctx>>,
+    stack_return_type: Vec<KSCType<'ctx>>,
+    stack: Vec<Stack<'ctx>>,
+    /// 式が新しく作り、まだ持ち主(変数など)の決まっていない参照。文の終わりで解放する
+    temporaries: Vec<(BasicValueEnum<'ctx>, KSCType<'ctx>)>,
+    /// コンパイル中の関数ごとの、本体のスコープの深さとtemporariesの長さ(returnで解放する範囲)
+    function_frames: Vec<(usize, usize)>,
+    unsafe_depth: usize,
+    bounds_check: bool,
+    shadow_stack: bool,
+    sanitize_address: bool,
+    /// 関数を書き終えるたびと最適化の段階ごとに、IrDumpでIRを知らせる(--dump-ir-per-function)
+    dump_ir_per_function: bool,
+    peak_scope_depth: usize,
+    expression_depth: usize,
+    max_expression_depth: usize,
+    /// 関数の命令数とコード生成の時間の上限(超えたら警告する。Noneなら調べない)
+    instruction_budget: Option<usize>,
+    codegen_time_budget: Option<std::time::Duration>,
+    extern_functions: Vec<String>,
+    unsafe_usages: Vec<UnsafeUsage>,
+    float_mode: FloatMode,
+    /// 整数の+、-、*があふれたときの扱い(--overflow)
+    overflow_mode: OverflowMode,
+    /// assertをコンパイルしない(-O1以上で--release-asserts offのとき)
+    strip_asserts: bool,
+    /// ライブラリとしてビルドする(Cのmainを作らないので、KSCのmainがなくてもよい)
+    library: bool,
+    closure_count: usize,
+    /// 総称型の構造体の宣言(型引数の名前、フィールドの名前と型)。型引数を与えて使われたときに構造体を作る
+    generic_structs: HashMap<String, GenericStruct>,
+    /// 型引数を与えて作った型(Pair<Number, i32>やList<Number>)。同じ型引数には同じLLVMの型を使う
+    generic_instances: HashMap<String, KSCType<'ctx>>,
+    /// コンパイル中のimpl ブロックの型(Selfが指す型)
+    impl_types: Vec<KSCType<'ctx>>,
+    /// 次にコンパイルする関数をメソッドにするときの型(Functionの式が取り出す)
+    pending_receiver: Option<KSCType<'ctx>>,
+    /// 総称型の関数の宣言。型引数を与えて呼ばれたときに、次にコンパイルする関数の名前をpending_instanceにして本体をコンパイルする
+    generic_functions: HashMap<String, &'ctx Expression>,
+    pending_instance: Option<String>,
+    /// トレイトのメソッドの名前とシグネチャ、トレイトを実装した型(トレイト名, 型名)
+    traits: HashMap<String, (Vec<String>, Vec<String>)>,
+    trait_impls: Vec<(String, String)>,
+    debug_info: Option<(DebugInfoBuilder<'ctx>, DICompileUnit<'ctx>)>,
+    sources: SourceMap,
+    current_span: Option<Span>,
+    event_listeners: RefCell<Vec<EventListener<'a>>>,
+    exported_types: Vec<String>,
+    imported_names: Vec<String>,
+    diagnostics: DiagnosticBag,
+    must_use_functions: Vec<String>,
+    must_use_types: Vec<String>,
+    allowed_lints: Vec<String>,
+    /// @repr(c)をつけた構造体(総称型は型引数のない名前)。フィールドを宣言の順に並べる
+    repr_c_structs: Vec<String>
+}
+
+/// スタックなど変数や型の管理のための関連関数()
+#[cfg(feature = "llvm")]
+impl<'a, 'ctx> Compiler<'a, 'ctx>{
+
+    /// 登録された関数すべてにイベントを渡す
+    fn emit_event(&self, event: CompilerEvent) {
+        for listener in self.event_listeners.borrow_mut().iter_mut() {
+            listener(&event);
+        }
+    }
+
+    /// 新しい型を名前をつけて最新のスタックに登録
+    fn insert_new_type_to_stack(&mut self, name: &str, ksctype: KSCType<'ctx>) {
+        if self.stack.len() == 1 {
+            self.exported_types.push(name.to_string());
+        }
+        self.stack.last_mut()
+            .unwrap_or_else(||panic!("There is no stack yet!"))
+            .types
+            .insert(name.to_string(), ksctype);
+    }
+
+    /// 新しいスコープに入る
+    fn enter_scope(&mut self) {
+        self.stack.push(Stack{ types: HashMap::new(), values: HashMap::new(), owned: vec![], constants: HashMap::new() });
+        self.peak_scope_depth = self.peak_scope_depth.max(self.stack.len());
+    }
+
+    /// 新しい変数を最新のスタックに登録(同じスコープの同名の変数は上書きされる)
+    fn insert_new_variable_to_stack(&mut self, name: &str, variable: KSCVariable<'ctx>) {
+        self.declare_debug_variable(name, &variable);
+        self.stack.last_mut()
+            .unwrap_or_else(||panic!("There is no stack yet!"))
+            .values
+            .insert(name.to_string(), variable);
+    }
+
+    /// 変数を内側のスコープから順に探す
+    fn search_variable(&self, name: &str) -> Option<&KSCVariable<'ctx>> {
+        return self.stack.iter().rev().find_map(|stack| stack.values.get(name));
+    }
+
+    /// 定数を内側のスコープから探す(内側で同じ名前の変数が宣言されていれば、その変数が見える)
+    fn search_constant(&self, name: &str) -> Option<ConstantValue> {
+        for stack in self.stack.iter().rev() {
+            if stack.values.contains_key(name) {
+                return None;
+            }
+            if let Some(constant) = stack.constants.get(name) {
+                return Some(*constant);
+            }
+        }
+        return None;
+    }
+
+    /// 式をコンパイル時に評価する(評価できなければNone)
+    /// --overflow trapで、折り返せば評価できる式はあふれているので、実行時に止まるコードを作る代わりにエラーにする
+    fn fold_constant(&self, expression: &Expression) -> Result<Option<ConstantValue>, CompileError> {
+        let constant = |name: &str| self.search_constant(name);
+        let folded = expression.evaluate_constant(&constant, self.overflow_mode);
+        if folded.is_none() && self.overflow_mode == OverflowMode::Trap && expression.evaluate_constant(&constant, OverflowMode::Wrap).is_some() {
+            return Err(CompileError{ message: format!("The constant expression '{expression}' overflows i32, and --overflow trap does not allow it to wrap."), span: None });
+        }
+        return Ok(folded);
+    }
+
+    /// 関数を名前で記号表に登録する(同じ名前なら上書き)
+    fn register_function(&mut self, name: &str, func: FunctionValue<'ctx>, ksctype: KSCType<'ctx>) {
+        self.functions.insert(name.to_string(), (func, ksctype));
+    }
+
+    /// 関数を記号表から探す
+    fn search_function(&self, name: &str) -> Option<&(FunctionValue<'ctx>, KSCType<'ctx>)> {
+        return self.functions.get(name);
+    }
+
+    /// ユーザーが定義した型を内側のスコープから順に探す
+    fn search_user_type(&self, typename: &str) -> Option<KSCType<'ctx>> {
+        return self.stack.iter().rev().find_map(|stack| stack.types.get(typename)).cloned();
+    }
+
+    /// 2つの型が互換かどうか(型の比較はすべてここを通す)
+    /// 型の別名は登録するときに元の型に置き換わるので、ここでは元の型と区別されない。newtypeは構造体と同じく宣言ごとに別の型
+    /// 構造体は公称的(nominal)に扱う。同じ宣言から作られた型(LLVMの型が同じ)だけが同じ型で、フィールドが同じでも別の宣言なら別の型になる
+    /// フィールドが同じ構造体どうしはStructConversionで明示的に変換できる
+    /// 関数は引数と戻り値の型がすべて同じときに同じ型とみなす
+    fn types_compatible(&self, a: &KSCType<'ctx>, b: &KSCType<'ctx>) -> Compatibility {
+        let identical = match (a, b) {
+            (KSCType::Number(_), KSCType::Number(_)) => true,
+            (KSCType::Int { reference: a_reference, signed: a_signed }, KSCType::Int { reference: b_reference, signed: b_signed }) => {
+                a_reference.get_bit_width() == b_reference.get_bit_width() && a_signed == b_signed
+            },
+            (KSCType::Bool(_), KSCType::Bool(_)) => true,
+            (KSCType::Void, KSCType::Void) => true,
+            (KSCType::String(_), KSCType::String(_)) => true,
+            (KSCType::Function { return_type: a_return, parameter: a_parameter, .. }, KSCType::Function { return_type: b_return, parameter: b_parameter, .. }) => {
+                a_parameter.len() == b_parameter.len()
+                    && a_parameter.iter().zip(b_parameter.iter()).all(|(a, b)| self.types_compatible(a, b) == Compatibility::Identical)
+                    && self.types_compatible(a_return, b_return) == Compatibility::Identical
+            },
+            (KSCType::Closure { return_type: a_return, parameter: a_parameter, .. }, KSCType::Closure { return_type: b_return, parameter: b_parameter, .. }) => {
+                a_parameter.len() == b_parameter.len()
+                    && a_parameter.iter().zip(b_parameter.iter()).all(|(a, b)| self.types_compatible(a, b) == Compatibility::Identical)
+                    && self.types_compatible(a_return, b_return) == Compatibility::Identical
+            },
+            (KSCType::RawPtr { pointee: a_pointee, .. }, KSCType::RawPtr { pointee: b_pointee, .. }) => {
+                self.types_compatible(a_pointee, b_pointee) == Compatibility::Identical
+            },
+            (KSCType::Array { element: a_element, length: a_length, .. }, KSCType::Array { element: b_element, length: b_length, .. }) => {
+                a_length == b_length && self.types_compatible(a_element, b_element) == Compatibility::Identical
+            },
+            (KSCType::Struct { reference: a_reference, .. }, KSCType::Struct { reference: b_reference, .. }) => a_reference == b_reference,
+            (KSCType::List { element: a_element, .. }, KSCType::List { element: b_element, .. }) => {
+                self.types_compatible(a_element, b_element) == Compatibility::Identical
+            },
+            (KSCType::Tuple { elements: a_elements, .. }, KSCType::Tuple { elements: b_elements, .. }) => {
+                a_elements.len() == b_elements.len()
+                    && a_elements.iter().zip(b_elements.iter()).all(|(a, b)| self.types_compatible(a, b) == Compatibility::Identical)
+            },
+            (KSCType::NewType { reference: a_reference, .. }, KSCType::NewType { reference: b_reference, .. }) => a_reference == b_reference,
+            (KSCType::Enum { reference: a_reference, .. }, KSCType::Enum { reference: b_reference, .. }) => a_reference == b_reference,
+            (KSCType::Optional { inner: a_inner, .. }, KSCType::Optional { inner: b_inner, .. }) => {
+                self.types_compatible(a_inner, b_inner) == Compatibility::Identical
+            },
+            _ => false
+        };
+        return if identical { Compatibility::Identical } else { Compatibility::Incompatible };
+    }
+
+    /// 2つの構造体のフィールドが名前も型もすべて同じかどうか(明示的な変換ができるかどうか)
+    fn structurally_equal(&self, a: &KSCType<'ctx>, b: &KSCType<'ctx>) -> bool {
+        return match (a, b) {
+            (KSCType::Struct { field_names: a_names, contents: a_contents, .. }, KSCType::Struct { field_names: b_names, contents: b_contents, .. }) => {
+                a_names == b_names
+                    && a_contents.iter().zip(b_contents.iter()).all(|(a, b)| self.types_compatible(a, b) == Compatibility::Identical)
+            },
+            _ => false
+        };
+    }
+
+    /// KSCの型に対応するLLVMの型(Voidには値の型がないのでNone)
+    fn get_basic_type(&self, ksctype: &KSCType<'ctx>) -> Option<BasicTypeEnum<'ctx>> {
+        return match ksctype {
+            KSCType::Number(ft) => Some(BasicTypeEnum::FloatType(*ft)),
+            KSCType::Int { reference, .. } => Some(BasicTypeEnum::IntType(*reference)),
+            KSCType::Bool(it) => Some(BasicTypeEnum::IntType(*it)),
+            KSCType::Function { reference, .. } => Some(BasicTypeEnum::PointerType(*reference)),
+            KSCType::Closure { reference, .. } => Some(BasicTypeEnum::StructType(*reference)),
+            KSCType::Void => None,
+            KSCType::String(pt) => Some(BasicTypeEnum::PointerType(*pt)),
+            KSCType::RawPtr { reference, .. } => Some(BasicTypeEnum::PointerType(*reference)),
+            KSCType::Array { reference, .. } => Some(BasicTypeEnum::ArrayType(*reference)),
+            KSCType::Struct { reference, .. } => Some(BasicTypeEnum::StructType(*reference)),
+            KSCType::List { reference, .. } => Some(BasicTypeEnum::PointerType(*reference)),
+            KSCType::Tuple { reference, .. } => Some(BasicTypeEnum::StructType(*reference)),
+            KSCType::NewType { reference, .. } => Some(BasicTypeEnum::StructType(*reference)),
+            KSCType::Enum { reference, .. } => Some(BasicTypeEnum::StructType(*reference)),
+            KSCType::Optional { reference, .. } => Some(BasicTypeEnum::StructType(*reference)),
+        };
+    }
+
+    /// 戻り値としてのLLVMの型
+    /// 「こと返り値に関しては」Void型はInkwellのvoid型と同様に扱う。
+    fn get_return_type(&self, ksctype: &KSCType<'ctx>) -> AnyTypeEnum<'ctx> {
+        return match self.get_basic_type(ksctype) {
+            Some(basic_type) => basic_type.as_any_type_enum(),
+            None => AnyTypeEnum::VoidType(self.context.void_type()),
+        };
+    }
+
+    /// Listのヘッダ構造体 ksc_list { i8* data, i64 len, i64 cap } (Listの値はこれを指すポインタ)
+    fn get_list_header_type(&self) -> StructType<'ctx> {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        if let Some(header) = module.get_struct_type("ksc_list") {
+            return header;
+        }
+        let header = self.context.opaque_struct_type("ksc_list");
+        let i64_type = self.context.i64_type();
+        header.set_body(&[self.context.i8_type().ptr_type(AddressSpace::Generic).into(), i64_type.into(), i64_type.into()], false);
+        return header;
+    }
+
+    /// 要素の型ごとのListの型(ヘッダーはksc_listと同じ形の、List<T>という名前の構造体。ランタイム関数にはksc_list*にして渡す)
+    fn get_list_type(&mut self, element: KSCType<'ctx>) -> KSCType<'ctx> {
+        let name = format!("List<{}>", element.type_name());
+        if let Some(instance) = self.generic_instances.get(&name) {
+            return instance.clone();
+        }
+        let header = self.context.opaque_struct_type(&name);
+        header.set_body(&self.get_list_header_type().get_field_types(), false);
+        let list = KSCType::List{ reference: header.ptr_type(AddressSpace::Generic), element: Box::from(element) };
+        self.generic_instances.insert(name, list.clone());
+        return list;
+    }
+
+    /// KSCの構造体のLLVMの型を作る。field_typesは宣言の順で、戻り値の対応でLLVMでの番号に読み替える(KSCType::Structのfield_indices)
+    /// @repr(c)の構造体はCと同じく宣言の順に並べる。それ以外はアラインメントの大きいフィールドから並べてパディングを減らす(同じアラインメントなら宣言の順)
+    /// 並べ替えで小さくなったときはStructReorderedで知らせる
+    fn create_ksc_struct_type(&self, name: &str, field_types: &Vec<BasicTypeEnum<'ctx>>, repr_c: bool) -> (StructType<'ctx>, Vec<u32>) {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let data_layout = module.get_data_layout();
+        let target_data = TargetData::create(data_layout.as_str().to_str().unwrap_or_else(|e|panic!("Invalid data layout: {e}")));
+        let mut order = (0..field_types.len()).collect::<Vec<usize>>();
+        if !repr_c {
+            order.sort_by_key(|index| std::cmp::Reverse(target_data.get_abi_alignment(&field_types[*index])));
+        }
+        let mut field_indices = vec![0; field_types.len()];
+        for (position, index) in order.iter().enumerate() {
+            field_indices[*index] = position as u32;
+        }
+        let layout_types = order.iter().map(|index| field_types[*index]).collect::<Vec<BasicTypeEnum>>();
+        let declared_size = target_data.get_abi_size(&self.context.struct_type(field_types, false));
+        let size = target_data.get_abi_size(&self.context.struct_type(&layout_types, false));
+        if size < declared_size {
+            self.emit_event(CompilerEvent::StructReordered{ name, declared_size, size });
+        }
+        let reference = self.context.opaque_struct_type(name);
+        reference.set_body(&layout_types, false);
+        return (reference, field_indices);
+    }
+
+    /// 総称型の構造体に型引数を与えた構造体(Pair<Number, i32>)。フィールドの型の中の型引数の名前を実際の型に置き換えて作る
+    /// 型引数は使われた場所のスコープで探す
+    fn instantiate_generic_struct(&mut self, typename: &str) -> Result<KSCType<'ctx>, CompileError> {
+        let (base, args) = typename[..typename.len() - 1].split_once('<').unwrap();
+        let (params, field_names, field_types) = self.generic_structs[base].clone();
+        let args = split_type_list(args).iter().map(|arg| self.search_ksc_type(arg)).collect::<Result<Vec<KSCType>, CompileError>>()?;
+        if args.len() != params.len() {
+            return Err(CompileError{ message: format!("Generic struct '{base}' takes {} type arguments, but {} were given.", params.len(), args.len()), span: None });
+        }
+        let name = format!("{base}<{}>", args.iter().map(|arg| arg.type_name()).collect::<Vec<String>>().join(", "));
+        if let Some(instance) = self.generic_instances.get(&name) {
+            return Ok(instance.clone());
+        }
+        // 関数の型は配列の要素などに入れても読めるように括弧で囲む
+        let arg_names = args.iter()
+                            .map(|arg| match arg {
+                                KSCType::Function { .. } | KSCType::Closure { .. } => format!("({})", arg.type_name()),
+                                _ => arg.type_name()
+                            })
+                            .collect::<Vec<String>>();
+        let contents = field_types.iter()
+                            .map(|field_type| self.search_ksc_type(&substitute_type_params(field_type, &params, &arg_names)))
+                            .collect::<Result<Vec<KSCType>, CompileError>>()?;
+        let field_llvm_types = field_names.iter().zip(contents.iter())
+                                .map(|(field_name, content)| self.get_basic_type(content).ok_or_else(|| CompileError{ message: format!("Field '{field_name}' of struct '{name}' cannot be Void."), span: None }))
+                                .collect::<Result<Vec<BasicTypeEnum>, CompileError>>()?;
+        let (reference, field_indices) = self.create_ksc_struct_type(&name, &field_llvm_types, self.repr_c_structs.iter().any(|repr_c| repr_c == base));
+        let instance = KSCType::Struct{
+            name: name.clone(),
+            reference,
+            default_values: vec![None; field_names.len()],
+            field_names,
+            contents,
+            field_indices
+        };
+        self.generic_instances.insert(name, instance.clone());
+        return Ok(instance);
+    }
+
+    /// 関数の型(closureがtrueなら先頭に環境へのポインタi8*を受け取る)
+    fn get_signature_type(&self, return_type: &KSCType<'ctx>, parameter: &Vec<KSCType<'ctx>>, closure: bool) -> Result<FunctionType<'ctx>, CompileError> {
+        let mut param_types: Vec<BasicMetadataTypeEnum> = vec![];
+        if closure {
+            param_types.push(self.context.i8_type().ptr_type(AddressSpace::Generic).into());
+        }
+        for p in parameter {
+            let basic_type = self.get_basic_type(p).ok_or_else(|| CompileError{ message: "You cannot expect Void as argument.".to_string(), span: None })?;
+            param_types.push(BasicMetadataTypeEnum::from(basic_type));
+        }
+        return Ok(match self.get_basic_type(return_type) {
+            Some(basic_type) => basic_type.fn_type(&param_types, false),
+            None => self.context.void_type().fn_type(&param_types, false),
+        });
+    }
+
+    /// クロージャの値の型 { 関数ポインタ, 環境へのポインタi8* }
+    fn get_closure_type(&self, fn_type: FunctionType<'ctx>) -> StructType<'ctx> {
+        let env_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        return self.context.struct_type(&[fn_type.ptr_type(AddressSpace::Generic).into(), env_type.into()], false);
+    }
+
+    /// 中身がinnerのOptionalの型
+    fn get_optional_type(&self, inner: KSCType<'ctx>) -> Result<KSCType<'ctx>, CompileError> {
+        let inner_type = self.get_basic_type(&inner)
+                            .ok_or_else(|| CompileError{ message: "Type 'Optional<Void>' is not allowed. Use Bool instead.".to_string(), span: None })?;
+        let reference = self.context.struct_type(&[self.context.bool_type().into(), inner_type], false);
+        return Ok(KSCType::Optional{ reference, inner: Box::from(inner) });
+    }
+
+    /// "(Number, Number) -> Number"の形のシグネチャから引数と戻り値の型を探す
+    fn search_signature(&mut self, typename: &String, signature: &str) -> Result<(Vec<KSCType<'ctx>>, KSCType<'ctx>), CompileError> {
+        let (parameter, return_type) = split_signature(signature)
+                                        .ok_or_else(|| CompileError{ message: format!("Type '{typename}' must be written like 'Function(Number, Number) -> Number'."), span: None })?;
+        let parameter = split_type_list(parameter).iter()
+                            .map(|p| self.search_ksc_type(p))
+                            .collect::<Result<Vec<KSCType>, CompileError>>()?;
+        let return_type = self.search_ksc_type(&return_type.trim().to_string())?;
+        return Ok((parameter, return_type));
+    }
+
+    /// 型名から型を探す(組み込みの型、Function(..) -> ..などの型の構文、スコープに登録された型の順に探す)
+    /// 定義されていない型や、Voidを入れた配列などの誤った型はエラーにする
+    fn search_ksc_type(&mut self, typename: &String) -> Result<KSCType<'ctx>, CompileError>{
+        let error = |message: String| CompileError{ message, span: None };
+        return Ok(match typename.as_str(){
+            "Number" => KSCType::Number(self.context.f64_type()),
+            "Bool" => KSCType::Bool(self.context.bool_type()),
+            "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64" => KSCType::Int{
+                reference: self.context.custom_width_int_type(typename[1..].parse().unwrap()),
+                signed: typename.starts_with('i')
+            },
+            "Void" => KSCType::Void,
+            "String" => KSCType::String(self.context.i8_type().ptr_type(AddressSpace::Generic)),
+            "Self" => self.impl_types.last().cloned().ok_or_else(|| error("Type 'Self' can only be used inside an impl block.".to_string()))?,
+            "Function" => return Err(error("Type 'Function' needs a signature, such as 'Function(Number, Number) -> Number'.".to_string())),
+            "Struct" => return Err(error("Type 'Struct' is not allowed. Use the name of the struct instead.".to_string())),
+            "Closure" => return Err(error("Type 'Closure' needs a signature, such as 'Closure(Number) -> Number'.".to_string())),
+            _ if typename.starts_with("Function(") => {
+                let (parameter, return_type) = self.search_signature(typename, &typename["Function".len()..])?;
+                let fn_type = self.get_signature_type(&return_type, &parameter, false)?;
+                KSCType::Function{ reference: fn_type.ptr_type(AddressSpace::Generic), return_type: Box::from(return_type), parameter }
+            },
+            _ if typename.starts_with("Closure(") => {
+                let (parameter, return_type) = self.search_signature(typename, &typename["Closure".len()..])?;
+                let fn_type = self.get_signature_type(&return_type, &parameter, true)?;
+                KSCType::Closure{ reference: self.get_closure_type(fn_type), return_type: Box::from(return_type), parameter }
+            },
+            _ if typename.ends_with(']') && typename.contains('[') => {
+                let (element, length) = typename[..typename.len() - 1].rsplit_once('[').unwrap();
+                // 長さは数か、整数の定数で書く
+                let length: u32 = match (length.trim().parse(), self.search_constant(length.trim())) {
+                    (Ok(length), _) => length,
+                    (Err(_), Some(ConstantValue::Int(length))) if length >= 0 => length as u32,
+                    _ => return Err(error(format!("The length of array type '{typename}' must be a number or a non-negative integer constant.")))
+                };
+                let element = self.search_ksc_type(&element.to_string())?;
+                let reference = self.get_basic_type(&element)
+                                    .ok_or_else(|| error(format!("Type '{typename}' is not allowed. Arrays cannot contain Void.")))?
+                                    .array_type(length);
+                KSCType::Array{ reference, element: Box::from(element), length }
+            },
+            // 要素が1つだけの括弧は型をまとめるだけ((Function(Number) -> Number)[4])
+            _ if typename.starts_with('(') && typename.ends_with(')') && split_type_list(&typename[1..typename.len() - 1]).len() == 1 => {
+                self.search_ksc_type(&typename[1..typename.len() - 1].to_string())?
+            },
+            _ if typename.starts_with('(') && typename.ends_with(')') => {
+                let elements = split_type_list(&typename[1..typename.len() - 1]).iter()
+                                .map(|element| self.search_ksc_type(element))
+                                .collect::<Result<Vec<KSCType>, CompileError>>()?;
+                if elements.len() < 2 {
+                    return Err(error(format!("Type '{typename}' is not allowed. A tuple needs at least 2 elements.")));
+                }
+                let element_types = elements.iter()
+                                    .map(|element| self.get_basic_type(element).ok_or_else(|| error(format!("Type '{typename}' is not allowed. Tuples cannot contain Void."))))
+                                    .collect::<Result<Vec<BasicTypeEnum>, CompileError>>()?;
+                KSCType::Tuple{ reference: self.context.struct_type(&element_types, false), elements }
+            },
+            _ if typename.starts_with("List<") && typename.ends_with('>') => {
+                let element = self.search_ksc_type(&typename["List<".len()..typename.len() - 1].to_string())?;
+                if self.get_basic_type(&element).is_none() {
+                    return Err(error(format!("Type '{typename}' is not allowed. Lists cannot contain Void.")));
+                }
+                self.get_list_type(element)
+            },
+            _ if typename.starts_with("RawPtr<") && typename.ends_with('>') => {
+                let pointee = self.search_ksc_type(&typename["RawPtr<".len()..typename.len() - 1].to_string())?;
+                let reference = self.get_basic_type(&pointee)
+                                    .ok_or_else(|| error(format!("Type '{typename}' is not allowed. RawPtr cannot point to Void.")))?
+                                    .ptr_type(AddressSpace::Generic);
+                KSCType::RawPtr{ reference, pointee: Box::from(pointee) }
+            },
+            _ if typename.starts_with("Optional<") && typename.ends_with('>') => {
+                let inner = self.search_ksc_type(&typename["Optional<".len()..typename.len() - 1].to_string())?;
+                self.get_optional_type(inner)?
+            },
+            _ if typename.ends_with('>') && typename.split_once('<').map_or(false, |(base, _)| self.generic_structs.contains_key(base)) => {
+                self.instantiate_generic_struct(typename)?
+            },
+            _ => self.search_user_type(typename).ok_or_else(|| error(format!("Type '{typename}' is not defined!")))?
+        });
+    }
+}
+
+/// コンパイル関連関数 (実際にIRを書く)
+#[cfg(feature = "llvm")]
+impl<'a, 'ctx> Compiler<'a, 'ctx> where 'a: 'ctx{
+
+    /// contextとbuilderは呼び出し側で作る(inkwellはこのcrateから再公開している)
+    pub fn new (context: &'a Context, builder: &'a Builder) -> Compiler<'a, 'ctx>{
+        return Compiler{
+            context,
+            builder,
+            module: None,
+            functions: HashMap::new(),
+            stack_current_function: vec![],
+            stack_loop: vec![],
+            stack_return_type: vec![],
+            stack: vec![Stack{ types: HashMap::new(), values: HashMap::new(), owned: vec![], constants: HashMap::new() }],
+            temporaries: vec![],
+            function_frames: vec![],
+            unsafe_depth: 0,
+            bounds_check: false,
+            shadow_stack: false,
+            sanitize_address: false,
+            dump_ir_per_function: false,
+            peak_scope_depth: 1,
+            expression_depth: 0,
+            max_expression_depth: DEFAULT_MAX_EXPRESSION_DEPTH,
+            instruction_budget: None,
+            codegen_time_budget: None,
+            extern_functions: vec![],
+            unsafe_usages: vec![],
+            float_mode: FloatMode::Strict,
+            overflow_mode: OverflowMode::Wrap,
+            strip_asserts: false,
+            library: false,
+            closure_count: 0,
+            generic_structs: HashMap::new(),
+            generic_instances: HashMap::new(),
+            impl_types: vec![],
+            pending_receiver: None,
+            generic_functions: HashMap::new(),
+            pending_instance: None,
+            traits: HashMap::new(),
+            trait_impls: vec![],
+            debug_info: None,
+            sources: SourceMap::new(),
+            current_span: None,
+            event_listeners: RefCell::new(vec![]),
+            exported_types: vec![],
+            imported_names: vec![],
+            diagnostics: DiagnosticBag::new(),
+            must_use_functions: vec![],
+            must_use_types: vec![],
+            allowed_lints: vec![],
+            repr_c_structs: vec![]
+        };
+    }
+
+    /// ソースファイルを登録して、そのファイルの式につけるSpanのfile_idを返す(登録した順に0から数える)
+    /// エラーの位置とデバッグ情報の行は、登録したファイルの中身から求める
+    pub fn add_source(&mut self, name: &str, text: &str) -> usize {
+        return self.sources.add_file(name, text);
+    }
+
+    /// コンパイラのイベントを受け取る関数を登録する
+    pub fn on_event(&self, listener: impl FnMut(&CompilerEvent) + 'a) {
+        self.event_listeners.borrow_mut().push(Box::new(listener));
+    }
+
+    /// 関数の本体を書き終えたことを知らせる(--dump-ir-per-functionのときはその関数のIRも渡す)
+    fn emit_function_compiled(&self, name: &str, func: FunctionValue<'ctx>) {
+        self.emit_event(CompilerEvent::FunctionCompiled{ name });
+        if self.dump_ir_per_function {
+            let ir = func.print_to_string().to_string();
+            self.emit_event(CompilerEvent::IrDump{ after: &format!("function '{name}'"), ir: &ir });
+        }
+    }
+
+    /// モジュールのターゲット(--target)を設定する
+    /// ポインタの幅はターゲットで違う(wasm32では32bit)ので、データレイアウトもターゲットに合わせる。コード生成より前に呼ぶ
+    fn set_target_triple(&self, triple: &str) -> Result<(), CompileError> {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let target_triple = TargetTriple::create(triple);
+        module.set_triple(&target_triple);
+        Target::initialize_all(&InitializationConfig::default());
+        let target = Target::from_triple(&target_triple)
+                        .map_err(|message| CompileError{ message: format!("Unknown target '{triple}': {message}"), span: None })?;
+        let machine = target.create_target_machine(&target_triple, "generic", "", OptimizationLevel::None, RelocMode::Default, CodeModel::Default)
+                        .ok_or_else(|| CompileError{ message: format!("Cannot create a target machine for '{triple}'."), span: None })?;
+        module.set_data_layout(&machine.get_target_data().get_data_layout());
+        return Ok(());
+    }
+
+    /// ターゲットがWASIのないWebAssembly(wasm32-unknown-unknown)か
+    /// libcがないので、printfやmallocはホスト(compiled/wasm_shim.js)が用意する
+    fn is_freestanding_wasm(&self) -> bool {
+        return self.module.as_ref()
+                .map(|module| module.get_triple().as_str().to_string_lossy().starts_with("wasm32-unknown"))
+                .unwrap_or(false);
+    }
+
+    /// ターゲットがWebAssembly(wasm32-*)か
+    fn is_wasm(&self) -> bool {
+        return self.module.as_ref()
+                .map(|module| module.get_triple().as_str().to_string_lossy().starts_with("wasm32"))
+                .unwrap_or(false);
+    }
+
+    /// モジュールのLLVM IR(モジュールをまだ作っていなければNone)
+    pub fn emit_as_text(&self) -> Option<String> {
+        if let Some(module) = &self.module {
+            return Some(module.print_to_string().to_string());
+        }
+        return None;
+    }
+
+    /// モジュールのターゲット(設定されていなければこのマシン)のアセンブリを書き出す(最適化の後に呼ぶ。levelは-Oの数字)
+    fn emit_assembly(&self, level: u8) -> Result<String, CompileError> {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        Target::initialize_all(&InitializationConfig::default());
+        let triple = match module.get_triple() {
+            triple if triple.as_str().to_bytes().is_empty() => TargetMachine::get_default_triple(),
+            triple => triple
+        };
+        let triple_name = triple.as_str().to_string_lossy().to_string();
+        let target = Target::from_triple(&triple)
+                        .map_err(|message| CompileError{ message: format!("Unknown target '{triple_name}': {message}"), span: None })?;
+        let optimization = match level {
+            0 => OptimizationLevel::None,
+            1 => OptimizationLevel::Less,
+            2 => OptimizationLevel::Default,
+            _ => OptimizationLevel::Aggressive,
+        };
+        let machine = target.create_target_machine(&triple, "generic", "", optimization, RelocMode::Default, CodeModel::Default)
+                        .ok_or_else(|| CompileError{ message: format!("Cannot create a target machine for '{triple_name}'."), span: None })?;
+        module.set_data_layout(&machine.get_target_data().get_data_layout());
+        let buffer = machine.write_to_memory_buffer(module, FileType::Assembly)
+                        .map_err(|message| CompileError{ message: format!("Failed to generate assembly for '{triple_name}': {message}"), span: None })?;
+        return Ok(String::from_utf8_lossy(buffer.as_slice()).to_string());
+    }
+
+    /// モジュールをビットコード(.bc)としてファイルに書き出す(書き出せたかどうかを返す)
+    fn emit_bitcode(&self, path: &PathBuf) -> bool {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        return module.write_bitcode_to_path(path);
+    }
+
+    /// 最適化パスを走らせる(build()の後に呼ぶ。levelは-Oの数字)
+    /// -O0でもmem2regだけは走らせて、変数や仮引数のalloca/store/loadをレジスタに昇格させる
+    /// その前に冗長なloadを取り除く(eliminate_redundant_loads)。段階ごとに時間を測り、OptimizationStageとして知らせる
+    /// eliminate_redundant_loadsは自前でIRを書き換えるので、その後にもう一度検証する(壊していたらErr)
+    pub fn optimize(&self, level: u8) -> Result<(), CompileError> {
+        self.emit_event(CompilerEvent::PhaseStarted(Phase::Opt));
+        let started = std::time::Instant::now();
+        self.eliminate_redundant_loads();
+        self.finish_optimization_stage("redundant-loads", started);
+        self.verify()?;
+        self.run_optimization_stage("mem2reg", |passes| passes.add_promote_memory_to_register_pass());
+        if level >= 1 {
+            self.run_optimization_stage("O1", |passes| {
+                passes.add_instruction_combining_pass();
+                passes.add_reassociate_pass();
+                passes.add_cfg_simplification_pass();
+            });
+        }
+        if level >= 2 {
+            self.run_optimization_stage("O2", |passes| {
+                passes.add_function_inlining_pass();
+                passes.add_gvn_pass();
+                passes.add_instruction_combining_pass();
+                passes.add_cfg_simplification_pass();
+            });
+        }
+        if level >= 3 {
+            self.run_optimization_stage("O3", |passes| {
+                passes.add_loop_rotate_pass();
+                passes.add_licm_pass();
+                passes.add_ind_var_simplify_pass();
+                passes.add_loop_unroll_pass();
+                passes.add_aggressive_dce_pass();
+                passes.add_cfg_simplification_pass();
+            });
+        }
+        self.emit_event(CompilerEvent::PhaseFinished(Phase::Opt));
+        return Ok(());
+    }
+
+    /// add_passesで加えたパスをモジュールに1度かける(最適化の1段階)
+    fn run_optimization_stage(&self, name: &str, add_passes: impl FnOnce(&PassManager<Module<'ctx>>)) {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let started = std::time::Instant::now();
+        let pass_manager = PassManager::create(());
+        add_passes(&pass_manager);
+        pass_manager.run_on(module);
+        self.finish_optimization_stage(name, started);
+    }
+
+    /// 最適化の段階にかかった時間を知らせる(--dump-ir-per-functionのときはその後のモジュールのIRも渡す)
+    fn finish_optimization_stage(&self, name: &str, started: std::time::Instant) {
+        self.emit_event(CompilerEvent::OptimizationStage{ name, elapsed: started.elapsed() });
+        if self.dump_ir_per_function {
+            let ir = self.emit_as_text().unwrap_or_default();
+            self.emit_event(CompilerEvent::IrDump{ after: &format!("optimization stage '{name}'"), ir: &ir });
+        }
+    }
+
+    /// 関数の命令の数
+    fn count_instructions(&self, func: FunctionValue<'ctx>) -> usize {
+        return func.get_basic_blocks().iter().map(|block| {
+            let mut count = 0;
+            let mut instruction = block.get_first_instruction();
+            while let Some(current) = instruction {
+                count += 1;
+                instruction = current.get_next_instruction();
+            }
+            return count;
+        }).sum();
+    }
+
+    /// 同じブロックの中で、storeした値を同じポインタからloadし直している命令を取り除く
+    /// 間に他のstoreや関数呼び出しがあると別名で書き換えられているかもしれないので、そこで記録を捨てる
+    /// loadは見つけたその場で置き換えて消す。取り除いたloadの値をさらにstoreしてloadし直す連鎖でも、後のstoreはもう元の値を指している
+    fn eliminate_redundant_loads(&self) {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        for func in module.get_functions() {
+            for block in func.get_basic_blocks() {
+                let mut last_store: Option<(BasicValueEnum<'ctx>, BasicValueEnum<'ctx>)> = None;
+                let mut instruction = block.get_first_instruction();
+                while let Some(current) = instruction {
+                    instruction = current.get_next_instruction();
+                    match current.get_opcode() {
+                        InstructionOpcode::Store => {
+                            let value = current.get_operand(0).and_then(|operand| operand.left());
+                            let pointer = current.get_operand(1).and_then(|operand| operand.left());
+                            last_store = value.zip(pointer);
+                        },
+                        InstructionOpcode::Load => {
+                            let pointer = current.get_operand(0).and_then(|operand| operand.left());
+                            match (last_store, pointer) {
+                                (Some((stored_value, stored_pointer)), Some(pointer)) if stored_pointer == pointer => {
+                                    let loaded_value = BasicValueEnum::try_from(current.as_any_value_enum())
+                                                        .unwrap_or_else(|_|panic!("load does not produce a value."));
+                                    self.replace_value(loaded_value, stored_value);
+                                    current.erase_from_basic_block();
+                                },
+                                _ => {}
+                            }
+                        },
+                        InstructionOpcode::Call | InstructionOpcode::Invoke => last_store = None,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// 値の使用箇所をすべて別の値に置き換える
+    fn replace_value(&self, old: BasicValueEnum<'ctx>, new: BasicValueEnum<'ctx>) {
+        match (old, new) {
+            (BasicValueEnum::ArrayValue(old), BasicValueEnum::ArrayValue(new)) => old.replace_all_uses_with(new),
+            (BasicValueEnum::IntValue(old), BasicValueEnum::IntValue(new)) => old.replace_all_uses_with(new),
+            (BasicValueEnum::FloatValue(old), BasicValueEnum::FloatValue(new)) => old.replace_all_uses_with(new),
+            (BasicValueEnum::PointerValue(old), BasicValueEnum::PointerValue(new)) => old.replace_all_uses_with(new),
+            (BasicValueEnum::StructValue(old), BasicValueEnum::StructValue(new)) => old.replace_all_uses_with(new),
+            (BasicValueEnum::VectorValue(old), BasicValueEnum::VectorValue(new)) => old.replace_all_uses_with(new),
+            _ => panic!("A value cannot be replaced with a value of a different type."),
+        }
+    }
+
+    /// モジュールを作成
+    fn create_module(&mut self, module_name: &str) {
+        self.module = Some(self.context.create_module(module_name));
+    }
+
+    /// 関数を作成(宣言してブロックを作成し、仮引数をallocaしたポインタも返す)
+    fn create_function(&mut self, name: &str, return_type: &AnyTypeEnum<'ctx>, param_types: &[BasicMetadataTypeEnum<'ctx>], param_names: &Vec<&str>) -> (FunctionValue<'ctx>, Vec<PointerValue<'ctx>>) {
+
+        // 戻り値の型を参照
+        let fn_type = match return_type{
+            AnyTypeEnum::ArrayType(v) => v.fn_type(param_types, false),
+            AnyTypeEnum::FloatType(v) => v.fn_type(param_types, false),
+            AnyTypeEnum::FunctionType(v) => *v,
+            AnyTypeEnum::IntType(v) => v.fn_type(param_types, false),
+            AnyTypeEnum::PointerType(v) => v.fn_type(param_types, false),
+            AnyTypeEnum::StructType(v) => v.fn_type(param_types, false),
+            AnyTypeEnum::VectorType(v) => v.fn_type(param_types, false),
+            AnyTypeEnum::VoidType(v) => v.fn_type(param_types, false),
+        };
+        if let Some(module) = &self.module {
+            // 先に宣言されている(本体のまだない)関数があれば、それに本体を書く
+            let func = match module.get_function(Self::symbol_name(name)) {
+                Some(declared) if declared.count_basic_blocks() == 0 => declared,
+                _ => module.add_function(Self::symbol_name(name), fn_type, None),
+            };
+            self.apply_float_mode(func);
+            self.apply_sanitizer(func);
+            self.apply_wasm_export(func, name);
+            let func_bb = self.context.append_basic_block(func, name);
+            self.builder.position_at_end(func_bb);
+            self.attach_debug_info(func, name);
+            self.create_shadow_stack_push(name);
+            if param_types.len() != param_names.len() {
+                panic!("The number of parameters does not match the type and name.");
+            }
+            let mut param_pointers = vec![];
+            for (i, arg) in func.get_param_iter().enumerate() {
+                let param_name = param_names[i];
+                let alloca = self.builder.build_alloca(arg.get_type(), param_name);
+                self.builder.build_store(alloca, arg);
+                param_pointers.push(alloca);
+            }
+            return (func, param_pointers);
+        }
+        else
+        {
+            panic!("Failed to create function ({}). There is no Module yet. Create module first.", name);
+        }
+    }
+
+    /// --sanitize addressのときは関数をAddressSanitizerの計装対象にする
+    /// clangは.bcを入力にすると属性を付けないので、コンパイラ側で付けておく
+    fn apply_sanitizer(&self, func: FunctionValue<'ctx>) {
+        if !self.sanitize_address {
+            return;
+        }
+        let attribute = self.context.create_enum_attribute(Attribute::get_named_enum_kind_id("sanitize_address"), 0);
+        func.add_attribute(AttributeLoc::Function, attribute);
+    }
+
+    /// WebAssemblyでは、KSCの関数をKSCでの名前でエクスポートする(_で始まる関数はインターフェースと同じく外に出さない)
+    /// WASIではmainは_startから呼ばれるので、KSCのmainはエクスポートしない
+    fn apply_wasm_export(&self, func: FunctionValue<'ctx>, name: &str) {
+        if !self.is_wasm() || name.starts_with('_') || (name == "main" && !self.is_freestanding_wasm()) {
+            return;
+        }
+        func.add_attribute(AttributeLoc::Function, self.context.create_string_attribute("wasm-export-name", name));
+    }
+
+    /// いまコンパイルしている式の行と列(位置がわからなければ0)
+    fn current_line_column(&self) -> (u32, u32) {
+        return self.current_span
+                .and_then(|span| self.sources.line_column(&span))
+                .unwrap_or((0, 0));
+    }
+
+    /// -gのとき、関数にDISubprogramを付けて、ビルダーの位置情報をその関数の中にする
+    /// 関数の行はいまコンパイルしている式(関数の定義)の行にする
+    fn attach_debug_info(&self, func: FunctionValue<'ctx>, name: &str) {
+        let (dibuilder, compile_unit) = match &self.debug_info {
+            Some(debug_info) => debug_info,
+            None => return
+        };
+        let (line, column) = self.current_line_column();
+        let file = compile_unit.get_file();
+        let subroutine_type = dibuilder.create_subroutine_type(file, None, &[], DIFlags::ZERO);
+        let linkage_name = func.get_name().to_string_lossy().to_string();
+        let subprogram = dibuilder.create_function(compile_unit.as_debug_info_scope(), name, Some(&linkage_name), file, line, subroutine_type, false, true, line, DIFlags::ZERO, false);
+        func.set_subprogram(subprogram);
+        let location = dibuilder.create_debug_location(self.context, line, column, subprogram.as_debug_info_scope(), None);
+        self.builder.set_current_debug_location(self.context, location);
+    }
+
+    /// ビルダーの位置情報を今コンパイルしている関数と式に合わせる(別の関数を書き出した後や、位置のついた式に入ったときに呼ぶ)
+    /// 関数の外なら位置情報を外す。ほかの関数の位置を指したままの命令はLLVMの検証に通らない
+    fn restore_debug_location(&self) {
+        let dibuilder = match &self.debug_info {
+            Some((dibuilder, _)) => dibuilder,
+            None => return
+        };
+        match self.stack_current_function.last().and_then(|func| func.get_subprogram()) {
+            Some(subprogram) => {
+                let (line, column) = self.current_line_column();
+                let location = dibuilder.create_debug_location(self.context, line, column, subprogram.as_debug_info_scope(), None);
+                self.builder.set_current_debug_location(self.context, location);
+            },
+            None => self.builder.unset_current_debug_location(),
+        }
+    }
+
+    /// KSCの型に対応するDWARFの基本型(デバッガで値を表示できる型だけ)
+    fn get_debug_type(&self, ksctype: &KSCType<'ctx>) -> Option<DIType<'ctx>> {
+        let (dibuilder, _) = self.debug_info.as_ref()?;
+        // DW_ATE_boolean, DW_ATE_float, DW_ATE_signed, DW_ATE_unsigned
+        let (size_in_bits, encoding) = match ksctype {
+            KSCType::Bool(_) => (8, 0x02),
+            KSCType::Number(_) => (64, 0x04),
+            KSCType::Int { reference, signed: true } => (reference.get_bit_width() as u64, 0x05),
+            KSCType::Int { reference, signed: false } => (reference.get_bit_width() as u64, 0x08),
+            _ => return None
+        };
+        return dibuilder.create_basic_type(&ksctype.type_name(), size_in_bits, encoding, DIFlags::ZERO).ok().map(|basic_type| basic_type.as_type());
+    }
+
+    /// -gのとき、変数をデバッガから見えるようにする(llvm.dbg.declare)
+    /// allocaに置かれた、基本型の変数だけが対象
+    fn declare_debug_variable(&self, name: &str, variable: &KSCVariable<'ctx>) {
+        let subprogram = self.stack_current_function.last().and_then(|func| func.get_subprogram());
+        let block = self.builder.get_insert_block();
+        let ditype = self.get_debug_type(&variable.valuetype);
+        let ((dibuilder, compile_unit), subprogram, block, ditype) = match (&self.debug_info, subprogram, block, ditype) {
+            (Some(debug_info), Some(subprogram), Some(block), Some(ditype)) => (debug_info, subprogram, block, ditype),
+            _ => return
+        };
+        if variable.pointer.as_instruction_value().map(|instruction| instruction.get_opcode()) != Some(InstructionOpcode::Alloca) {
+            return;
+        }
+        let scope = subprogram.as_debug_info_scope();
+        let (line, column) = self.current_line_column();
+        let local_variable = dibuilder.create_auto_variable(scope, name, compile_unit.get_file(), line, ditype, true, DIFlags::ZERO, 0);
+        let location = dibuilder.create_debug_location(self.context, line, column, scope, None);
+        dibuilder.insert_declare_at_end(variable.pointer, Some(local_variable), None, location, block);
+    }
+
+    /// 浮動小数点演算のモードを関数に適用する
+    /// LLVM10のC APIとinkwellには命令にfast-mathフラグを付ける関数がないので、命令ごとのフラグは付けていない
+    /// 代わりに関数属性として付ける。これを読むのはおもにバックエンドで、命令のフラグを見るIRの最適化(instcombineの並べ替えなど)には効かない
+    fn apply_float_mode(&self, func: FunctionValue<'ctx>) {
+        if self.float_mode == FloatMode::Strict {
+            return;
+        }
+        for key in ["unsafe-fp-math", "no-infs-fp-math", "no-nans-fp-math", "no-signed-zeros-fp-math"] {
+            let attribute = self.context.create_string_attribute(key, "true");
+            func.add_attribute(AttributeLoc::Function, attribute);
+        }
+    }
+
+    /// 関数を作成(宣言のみ)
+    fn create_function_declare(&mut self, name: &str, return_type: &AnyTypeEnum<'ctx>, param_types: &Vec<AnyTypeEnum<'ctx>>) -> FunctionValue<'ctx> {
+
+        // 仮引数の型を参照
+        let param_types = &param_types.iter().map(|param_type| {
+            return match param_type {
+                AnyTypeEnum::ArrayType(t) => BasicMetadataTypeEnum::ArrayType(*t),
+                AnyTypeEnum::FloatType(t) => BasicMetadataTypeEnum::FloatType(*t),
+                AnyTypeEnum::FunctionType(_) => panic!("Function type cannot be param."),
+                AnyTypeEnum::IntType(t) => BasicMetadataTypeEnum::IntType(*t),
+                AnyTypeEnum::PointerType(t) => BasicMetadataTypeEnum::PointerType(*t),
+                AnyTypeEnum::StructType(t) => BasicMetadataTypeEnum::StructType(*t),
+                AnyTypeEnum::VectorType(t) => BasicMetadataTypeEnum::VectorType(*t),
+                AnyTypeEnum::VoidType(_) => panic!("Void type cannot be param."),
+            }
+        }).collect::<Vec<BasicMetadataTypeEnum>>();
+
+        // 戻り値の型を参照
+        let fn_type = match return_type {
+            AnyTypeEnum::ArrayType(t) => t.fn_type(param_types, false),
+            AnyTypeEnum::FloatType(t) => t.fn_type(param_types, false),
+            AnyTypeEnum::FunctionType(_) => panic!("Function type cannot be returned."),
+            AnyTypeEnum::IntType(t) => t.fn_type(param_types, false),
+            AnyTypeEnum::PointerType(t) => t.fn_type(param_types, false),
+            AnyTypeEnum::StructType(t) => t.fn_type(param_types, false),
+            AnyTypeEnum::VectorType(t) => t.fn_type(param_types, false),
+            AnyTypeEnum::VoidType(t) => t.fn_type(param_types, false),
+        };
+        if let Some(module) = &self.module {
+            return module.add_function(Self::symbol_name(name), fn_type, None);
+        }
+        else
+        {
+            panic!("Failed to craete function ({}). There is no Module yet. Create module first.", name);
+        }
+
+    }
+
+    /// KSCの関数名に対応するLLVMのシンボル名
+    /// Cのmainはcreate_entry_pointが作るので、KSCのmainはksc.mainという名前にする
+    fn symbol_name(name: &str) -> &str {
+        return if name == "main" { "ksc.main" } else { name };
+    }
+
+    /// プログラムの入口になるCのmain(i32を返す)を作り、KSCのmainを呼ぶ
+    /// KSCのmainの戻り値がNumberか整数ならi32にしてプロセスの終了コードにする。Voidなら0を返す
+    fn create_entry_point(&mut self) -> Result<(), CompileError> {
+        let (ksc_main, ksctype) = self.search_function("main")
+                                    .cloned()
+                                    .ok_or_else(|| CompileError{ message: "The program has no 'main' function.".to_string(), span: None })?;
+        let (return_type, parameter) = match ksctype {
+            KSCType::Function { return_type, parameter, .. } => (return_type, parameter),
+            _ => unreachable!()
+        };
+        if !parameter.is_empty() {
+            return Err(CompileError{ message: format!("'main' must not take parameters, but it takes {}.", parameter.len()), span: None });
+        }
+        let exit_code_type = self.context.i32_type();
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let entry = module.add_function("main", exit_code_type.fn_type(&[], false), None);
+        let entry_block = self.context.append_basic_block(entry, "entry");
+        self.builder.position_at_end(entry_block);
+        self.attach_debug_info(entry, "main");
+        let result = self.builder.build_call(ksc_main, &[], "result").try_as_basic_value().left();
+        let exit_code = match (return_type.as_ref(), result) {
+            (KSCType::Void, _) => exit_code_type.const_zero(),
+            (KSCType::Int { signed, .. }, Some(result)) => self.create_int_cast(result.into_int_value(), exit_code_type, *signed),
+            (KSCType::Number(_), Some(result)) => self.create_float_to_int(result.into_float_value(), exit_code_type, true),
+            (return_type, _) => return Err(CompileError{ message: format!("'main' must return Void, Number or an integer, but it returns '{}'.", return_type.type_name()), span: None })
+        };
+        self.builder.build_return(Some(&exit_code));
+        return Ok(());
+    }
+
+    /// return文を作成
+    fn create_return(&self, value: &Option<BasicValueEnum>) {
+        self.create_shadow_stack_pop();
+        if let Some(value) = value {
+            self.builder.build_return(Some(value));
+        } else{
+            self.builder.build_return(None);
+        }
+    }
+
+    /// 関数の入口でシャドウスタックに関数名を積む(--shadow-stackのときだけ。ランタイムはcompiled/shadow_stack.c)
+    fn create_shadow_stack_push(&self, name: &str) {
+        if !self.shadow_stack {
+            return;
+        }
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let push = self.get_or_declare_function("ksc_shadow_push", self.context.void_type().fn_type(&[i8_ptr_type.into()], false));
+        let name = self.create_constant_string(name);
+        self.builder.build_call(push, &[name.into()], "");
+    }
+
+    /// returnの前でシャドウスタックから関数名を降ろす(--shadow-stackのときだけ)
+    fn create_shadow_stack_pop(&self) {
+        if !self.shadow_stack {
+            return;
+        }
+        let pop = self.get_or_declare_function("ksc_shadow_pop", self.context.void_type().fn_type(&[], false));
+        self.builder.build_call(pop, &[], "");
+    }
+
+    /// if式を作成(分岐側)
+    /// (condition_bool) ? (then_value) : (else_value)
+    fn create_if_branch(&self, condition_bool: IntValue) -> (BasicBlock<'ctx>, BasicBlock<'ctx>, BasicBlock<'ctx>) {
+        let zero_const = self.context.custom_width_int_type(1).const_zero();
+        let condition = self
+                    .builder
+                    .build_int_compare(IntPredicate::NE, condition_bool, zero_const, "ifcond");
+
+        let then_block = self.create_block("then");
+        let else_block = self.create_block("else");
+        let cont_block = self.create_block("ifcont");
+
+        self.builder.build_conditional_branch(condition, then_block, else_block);
+
+        return (then_block, else_block, cont_block);
+    }
+
+    /// if式を作成(書き込み対象のブロックを選ぶ)
+    fn start_if_branch(&self, branch: &BasicBlock){
+        self.builder.position_at_end(*branch);
+    }
+
+    /// if式を作成(書き込み終わり)
+    fn end_if_branch(&self, branch: &BasicBlock) -> BasicBlock<'ctx>{
+        self.builder.build_unconditional_branch(*branch);
+        return self.builder.get_insert_block().unwrap();
+    }
+
+    /// if式を作成(マージ)
+    /// どちらかの枝が値を持たない(文として使われた)か、値の型が違うときはphiを作らずNoneを返す
+    fn merge_if_branch(&self, then_value: Option<BasicValueEnum<'ctx>>, else_value: Option<BasicValueEnum<'ctx>>, then_block: BasicBlock, else_block: BasicBlock, cont_block: BasicBlock) -> Option<BasicValueEnum<'ctx>>{
+        self.builder.position_at_end(cont_block);
+        let (then_value, else_value) = match (then_value, else_value) {
+            (Some(then_value), Some(else_value)) if then_value.get_type() == else_value.get_type() => (then_value, else_value),
+            _ => return None
+        };
+        let phi = self.builder.build_phi(then_value.get_type(), "iftmp");
+        phi.add_incoming(&[(&then_value, then_block), (&else_value, else_block)]);
+        return Some(phi.as_basic_value());
+    }
+
+    /// if式の値にならなかった枝の値の参照を、合流したブロックで解放する
+    /// pointersは枝の終わりのブロックとその値の参照(get_rc_pointers)。通らなかった枝からはnullが来るので、解放しても何もしない
+    fn create_release_unmerged(&self, pointers: &[(BasicBlock<'ctx>, Vec<PointerValue<'ctx>>)]) {
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let null = i8_ptr_type.const_null();
+        // phiはブロックの先頭にまとめて置く必要があるので、先にすべて作ってから解放する
+        let mut phis = vec![];
+        for (index, (_, branch_pointers)) in pointers.iter().enumerate() {
+            for pointer in branch_pointers {
+                let phi = self.builder.build_phi(i8_ptr_type, "unmerged");
+                for (other, (block, _)) in pointers.iter().enumerate() {
+                    phi.add_incoming(&[(if other == index { pointer } else { &null }, *block)]);
+                }
+                phis.push(phi);
+            }
+        }
+        if phis.is_empty() {
+            return;
+        }
+        let release = self.get_or_create_release();
+        for phi in phis {
+            self.builder.build_call(release, &[phi.as_basic_value().into()], "");
+        }
+    }
+
+    /// 終端命令の後ろに書かれた命令を受け止めるブロックを作成
+    fn start_unreachable_block(&self, name: &str) {
+        let block = self.create_block(name);
+        self.builder.position_at_end(block);
+    }
+
+    /// 今書いている関数の末尾に新しいブロックを作る(ビルダーの位置は変えない)
+    fn create_block(&self, name: &str) -> BasicBlock<'ctx> {
+        let parent = self.builder.get_insert_block()
+                        .and_then(|block| block.get_parent())
+                        .unwrap_or_else(||panic!("No function."));
+        return self.context.append_basic_block(parent, name);
+    }
+
+    /// 比較演算子
+    fn create_comparison_operator(&self, op:Predicate ,left: BasicValueEnum<'ctx>, right: BasicValueEnum<'ctx>, signed: bool) -> IntValue<'ctx> {
+        if discriminant(&left) != discriminant(&right) {
+            panic!("The left value and the right value have different types.");
+        }
+        let condition = match left {
+            BasicValueEnum::ArrayValue(_) => panic!("ArrayValue is not comparable."),
+            BasicValueEnum::IntValue(_) => {
+                let op = match (op, signed) {
+                    (Predicate::EQUAL, _) => IntPredicate::EQ,
+                    (Predicate::NOT_EQUAL, _) => IntPredicate::NE,
+                    (Predicate::GREATER_THAN, true) => IntPredicate::SGT,
+                    (Predicate::GREATER_THAN_OR_EQUAL, true) => IntPredicate::SGE,
+                    (Predicate::LESS_THAN, true) => IntPredicate::SLT,
+                    (Predicate::LESS_THAN_OR_EQUAL, true) => IntPredicate::SLE,
+                    (Predicate::GREATER_THAN, false) => IntPredicate::UGT,
+                    (Predicate::GREATER_THAN_OR_EQUAL, false) => IntPredicate::UGE,
+                    (Predicate::LESS_THAN, false) => IntPredicate::ULT,
+                    (Predicate::LESS_THAN_OR_EQUAL, false) => IntPredicate::ULE,
+                };
+                if let (BasicValueEnum::IntValue(left), BasicValueEnum::IntValue(right)) = (left,right) {
+                    self.builder.build_int_compare(op, left, right, "compared")
+                } else{
+                    panic!("The left value and the right value have different types.")
+                }
+            },
+            BasicValueEnum::FloatValue(_) => {
+                let op = match op {
+                    Predicate::EQUAL => FloatPredicate::OEQ,
+                    Predicate::NOT_EQUAL => FloatPredicate::ONE,
+                    Predicate::GREATER_THAN => FloatPredicate::OGT,
+                    Predicate::GREATER_THAN_OR_EQUAL => FloatPredicate::OGE,
+                    Predicate::LESS_THAN => FloatPredicate::OLT,
+                    Predicate::LESS_THAN_OR_EQUAL => FloatPredicate::OLE,
+                };
+                if let (BasicValueEnum::FloatValue(left), BasicValueEnum::FloatValue(right)) = (left,right) {
+                    self.builder.build_float_compare(op, left, right, "compared")
+                } else{
+                    panic!("The left value and the right value have different types.")
+                }
+            },
+            BasicValueEnum::PointerValue(_) => panic!("PointerValue is not comparable."),
+            BasicValueEnum::StructValue(_) => panic!("StructValue is not comparable."),
+            BasicValueEnum::VectorValue(_) => panic!("VectorValue is not comparable."),
+        };
+        return condition;
+    }
+
+    /// 定数
+    fn create_constant_number(&self, constant_type: &BasicTypeEnum<'ctx>, number: f64) -> BasicValueEnum<'ctx> {
+        return match constant_type {
+            BasicTypeEnum::ArrayType(_) => panic!("Constants of type ArrayType cannot be declared!"),
+            BasicTypeEnum::FloatType(floattype) => BasicValueEnum::FloatValue(floattype.const_float(number)),
+            BasicTypeEnum::IntType(inttype) if inttype.get_bit_width() == 1 => BasicValueEnum::IntValue(inttype.const_int((number != 0.0) as u64, false)),
+            BasicTypeEnum::IntType(inttype) => BasicValueEnum::IntValue(inttype.const_int(number.round() as i64 as u64, true)),
+            BasicTypeEnum::PointerType(_) => panic!("Constants of type PointerType cannot be declared!"),
+            BasicTypeEnum::StructType(_) => panic!("Constants of type StructType cannot be declared!"),
+            BasicTypeEnum::VectorType(_) => panic!("Constants of type VectorType cannot be declared!"),
+        }
+    }
+
+    /// 二項演算子
+    fn create_binnary_operator(&self, op: BinaryOperator, left: &BasicValueEnum<'ctx>, right: &BasicValueEnum<'ctx>, signed: bool) -> BasicValueEnum<'ctx>{
+        if discriminant(left) != discriminant(right) {
+            panic!("The left value and the right value have different types.");
+        }
+        let ret:BasicValueEnum = match left{
+            BasicValueEnum::ArrayValue(_) => panic!("Four arithmetic operations are not possible with ArrayValue."),
+            BasicValueEnum::IntValue(left) => {
+                if let BasicValueEnum::IntValue(right) = right {
+                    BasicValueEnum::IntValue( match op {
+                        BinaryOperator::ADD | BinaryOperator::SUB | BinaryOperator::MUL if self.overflow_mode == OverflowMode::Trap => self.create_trapping_int_operator(op, *left, *right, signed),
+                        BinaryOperator::ADD => self.builder.build_int_add(*left, *right, "add"),
+                        BinaryOperator::SUB => self.builder.build_int_sub(*left, *right, "sub"),
+                        BinaryOperator::MUL => self.builder.build_int_mul(*left, *right, "mul"),
+                        BinaryOperator::DIV if signed => self.builder.build_int_signed_div(*left, *right, "div"),
+                        BinaryOperator::REM if signed => self.builder.build_int_signed_rem(*left, *right, "rem"),
+                        BinaryOperator::DIV => self.builder.build_int_unsigned_div(*left, *right, "div"),
+                        BinaryOperator::REM => self.builder.build_int_unsigned_rem(*left, *right, "rem"),
+                        BinaryOperator::AND => self.builder.build_and(*left, *right, "and"),
+                        BinaryOperator::OR => self.builder.build_or(*left, *right, "or"),
+                        BinaryOperator::XOR => self.builder.build_xor(*left, *right, "xor"),
+                        BinaryOperator::SHL => self.builder.build_left_shift(*left, *right, "shl"),
+                        BinaryOperator::ASHR => self.builder.build_right_shift(*left, *right, true, "ashr"),
+                        BinaryOperator::LSHR => self.builder.build_right_shift(*left, *right, false, "lshr"),
+                    } )
+                }else{
+                    panic!("The left value and the right value have different types.");
+                }
+            },
+            BasicValueEnum::FloatValue(left) => {
+                if let BasicValueEnum::FloatValue(right) = right {
+                    BasicValueEnum::FloatValue( match op {
+                        BinaryOperator::ADD => self.builder.build_float_add(*left, *right, "add"),
+                        BinaryOperator::SUB => self.builder.build_float_sub(*left, *right, "sub"),
+                        BinaryOperator::MUL => self.builder.build_float_mul(*left, *right, "mul"),
+                        BinaryOperator::DIV => self.builder.build_float_div(*left, *right, "div"),
+                        BinaryOperator::REM => self.builder.build_float_rem(*left, *right, "rem"),
+                        _ => panic!("Bitwise operations are not possible with FloatValue."),
+                    } )
+                }else{
+                    panic!("The left value and the right value have different types.");
+                }
+            },
+            BasicValueEnum::PointerValue(_) => panic!("Four arithmetic operations are not possible with PointerValue."),
+            BasicValueEnum::StructValue(_) => panic!("Four arithmetic operations are not possible with StructValue. Structs call their operator methods instead."),
+            BasicValueEnum::VectorValue(_) => panic!("Four arithmetic operations are not possible with VectorValue."),
+        };
+        return ret;
+    }
+
+
+    /// 整数の幅を変える(狭くするときは切り捨て、広げるときはsignedなら符号拡張、そうでなければゼロ拡張)
+    fn create_int_cast(&self, value: IntValue<'ctx>, target: IntType<'ctx>, signed: bool) -> IntValue<'ctx> {
+        let source_width = value.get_type().get_bit_width();
+        let target_width = target.get_bit_width();
+        return if source_width == target_width {
+            value
+        } else if source_width > target_width {
+            self.builder.build_int_truncate(value, target, "trunc")
+        } else if signed {
+            self.builder.build_int_s_extend(value, target, "sext")
+        } else {
+            self.builder.build_int_z_extend(value, target, "zext")
+        };
+    }
+
+    /// 小数を整数に変換する(0の方向に切り捨て)
+    fn create_float_to_int(&self, value: FloatValue<'ctx>, target: IntType<'ctx>, signed: bool) -> IntValue<'ctx> {
+        return if signed {
+            self.builder.build_float_to_signed_int(value, target, "fptosi")
+        } else {
+            self.builder.build_float_to_unsigned_int(value, target, "fptoui")
+        };
+    }
+
+    /// 整数を小数に変換する
+    fn create_int_to_float(&self, value: IntValue<'ctx>, target: FloatType<'ctx>, signed: bool) -> FloatValue<'ctx> {
+        return if signed {
+            self.builder.build_signed_int_to_float(value, target, "sitofp")
+        } else {
+            self.builder.build_unsigned_int_to_float(value, target, "uitofp")
+        };
+    }
+
+    /// 単項演算子
+    fn create_unary_operator(&self, op: UnaryOperator, operand: &BasicValueEnum<'ctx>) -> BasicValueEnum<'ctx>{
+        return match (op, operand) {
+            (UnaryOperator::NEG, BasicValueEnum::IntValue(operand)) => BasicValueEnum::IntValue(self.builder.build_int_neg(*operand, "neg")),
+            (UnaryOperator::NEG, BasicValueEnum::FloatValue(operand)) => BasicValueEnum::FloatValue(self.builder.build_float_neg(*operand, "neg")),
+            (UnaryOperator::NOT, BasicValueEnum::IntValue(operand)) => BasicValueEnum::IntValue(self.builder.build_not(*operand, "not")),
+            (UnaryOperator::NEG, _) => panic!("Negation is only possible with IntValue and FloatValue."),
+            (UnaryOperator::NOT, _) => panic!("Logical not is only possible with IntValue."),
+        };
+    }
+
+    /// 関数呼び出し
+    fn create_function_call(&self, name: &str, args: &Vec<BasicValueEnum<'ctx>>) -> Option<BasicValueEnum<'ctx>>{
+        let (func, _) = self.search_function(name).unwrap_or_else(||panic!("Function {} not found!", name));
+        let argsv: Vec<BasicMetadataValueEnum> = args.iter().by_ref().map(|&val| val.into()).collect();
+        return self.builder.build_call(*func, &argsv, name).try_as_basic_value().left();
+    }
+
+    /// タプルの値を作る
+    fn create_tuple(&self, tuple_type: StructType<'ctx>, values: &Vec<BasicValueEnum<'ctx>>) -> BasicValueEnum<'ctx> {
+        let mut tuple = tuple_type.get_undef();
+        for (index, value) in values.iter().enumerate() {
+            tuple = self.builder.build_insert_value(tuple, *value, index as u32, "tuple").unwrap().into_struct_value();
+        }
+        return tuple.as_basic_value_enum();
+    }
+
+    /// クロージャの値を作る(関数ポインタと環境へのポインタを組にする)
+    fn create_closure(&self, func: FunctionValue<'ctx>, env: PointerValue<'ctx>, closure_type: StructType<'ctx>) -> BasicValueEnum<'ctx> {
+        let closure = self.builder.build_insert_value(closure_type.get_undef(), func.as_global_value().as_pointer_value(), 0, "closure_fn").unwrap().into_struct_value();
+        let closure = self.builder.build_insert_value(closure, env, 1, "closure").unwrap();
+        return closure.into_struct_value().as_basic_value_enum();
+    }
+
+    /// 関数ポインタを通した呼び出し
+    fn create_indirect_call(&self, func: PointerValue<'ctx>, args: &Vec<BasicValueEnum<'ctx>>) -> Option<BasicValueEnum<'ctx>> {
+        let argsv: Vec<BasicMetadataValueEnum> = args.iter().map(|&val| val.into()).collect();
+        let callable = CallableValue::try_from(func).unwrap_or_else(|_|panic!("The value is not a function pointer."));
+        return self.builder.build_call(callable, &argsv, "indirect_call").try_as_basic_value().left();
+    }
+
+    /// クロージャの呼び出し(環境へのポインタを先頭の引数として渡す)
+    fn create_closure_call(&self, closure: StructValue<'ctx>, args: &Vec<BasicValueEnum<'ctx>>) -> Option<BasicValueEnum<'ctx>> {
+        let func = self.builder.build_extract_value(closure, 0, "closure_fn").unwrap().into_pointer_value();
+        let env = self.builder.build_extract_value(closure, 1, "closure_env").unwrap();
+        let mut argsv: Vec<BasicMetadataValueEnum> = vec![env.into()];
+        argsv.extend(args.iter().map(|&val| BasicMetadataValueEnum::from(val)));
+        let callable = CallableValue::try_from(func).unwrap_or_else(|_|panic!("The closure does not hold a function pointer."));
+        return self.builder.build_call(callable, &argsv, "closure_call").try_as_basic_value().left();
+    }
+
+    /// 部分適用のラッパー関数を書き出す
+    /// ラッパーは環境{ 呼ぶ関数, 束縛した引数.. }を先頭の引数に受け取り、束縛した引数の後ろに残りの引数を並べて呼ぶ
+    fn create_partial_wrapper(&self, name: &str, fn_type: FunctionType<'ctx>, env_type: StructType<'ctx>, calls_closure: bool) -> FunctionValue<'ctx> {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let func = module.add_function(name, fn_type, None);
+        self.apply_float_mode(func);
+        let previous_block = self.builder.get_insert_block();
+        self.builder.position_at_end(self.context.append_basic_block(func, "entry"));
+        self.attach_debug_info(func, name);
+
+        let env = self.builder.build_pointer_cast(func.get_nth_param(0).unwrap().into_pointer_value(), env_type.ptr_type(AddressSpace::Generic), "env");
+        let callee = self.create_load_variable(self.create_field_pointer(env, 0, "callee"), "callee");
+        let mut args = (1..env_type.count_fields())
+                        .map(|index| self.create_load_variable(self.create_field_pointer(env, index, "bound"), "bound"))
+                        .collect::<Vec<BasicValueEnum>>();
+        args.extend(func.get_param_iter().skip(1));
+        let result = if calls_closure {
+            self.create_closure_call(callee.into_struct_value(), &args)
+        } else {
+            self.create_indirect_call(callee.into_pointer_value(), &args)
+        };
+        match result {
+            Some(value) => self.builder.build_return(Some(&value)),
+            None => self.builder.build_return(None),
+        };
+
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+        self.restore_debug_location();
+        return func;
+    }
+
+    /// 関数ポインタを並べた表を、書き換えられないグローバル変数として置く(表へのポインタを返す)
+    fn create_function_table(&self, element_type: PointerType<'ctx>, functions: &[PointerValue<'ctx>]) -> PointerValue<'ctx> {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let table = element_type.const_array(functions);
+        let global = module.add_global(table.get_type(), None, "fn_table");
+        global.set_initializer(&table);
+        global.set_constant(true);
+        global.set_linkage(Linkage::Private);
+        global.set_unnamed_addr(true);
+        return global.as_pointer_value();
+    }
+
+    /// Cのsize_tにあたる整数型(ポインタと同じ幅。wasm32では32bit)
+    fn get_size_type(&self) -> IntType<'ctx> {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let data_layout = module.get_data_layout();
+        let target_data = TargetData::create(data_layout.as_str().to_str().unwrap_or_else(|e|panic!("Invalid data layout: {e}")));
+        return self.context.ptr_sized_int_type(&target_data, None);
+    }
+
+    /// i64で数えた大きさをsize_tにする(mallocやreallocに渡すとき)
+    fn create_size_cast(&self, size: IntValue<'ctx>) -> IntValue<'ctx> {
+        return self.builder.build_int_cast(size, self.get_size_type(), "size");
+    }
+
+    /// ksc_allocでヒープに確保する(i8*を返す)
+    fn create_heap_allocation(&self, basic_type: BasicTypeEnum<'ctx>, name: &str) -> PointerValue<'ctx> {
+        let alloc = self.get_or_create_alloc();
+        let size = self.create_size_cast(basic_type.size_of().unwrap_or_else(||panic!("'{name}' has no size.")));
+        return self.builder.build_call(alloc, &[size.into()], name).try_as_basic_value().left().unwrap().into_pointer_value();
+    }
+
+    /// ksc_allocで確保したメモリをksc_freeで解放する
+    fn create_heap_free(&self, pointer: PointerValue<'ctx>) {
+        let free = self.get_or_create_free();
+        let memory = self.builder.build_pointer_cast(pointer, self.context.i8_type().ptr_type(AddressSpace::Generic), "memory");
+        self.builder.build_call(free, &[memory.into()], "");
+    }
+
+    /// 関数の先頭ブロックにallocaを作る(先頭ブロックのallocaでないとmem2regで昇格できない)
+    fn create_entry_block_alloca(&self, basic_type: BasicTypeEnum<'ctx>, name: &str) -> PointerValue<'ctx> {
+        let entry = self.builder.get_insert_block()
+                        .and_then(|block| block.get_parent())
+                        .and_then(|func| func.get_first_basic_block())
+                        .unwrap_or_else(||panic!("No function."));
+        let entry_builder = self.context.create_builder();
+        match entry.get_first_instruction() {
+            Some(instruction) => entry_builder.position_before(&instruction),
+            None => entry_builder.position_at_end(entry),
+        }
+        return entry_builder.build_alloca(basic_type, name);
+    }
+
+    /// 変数を作成(allocaして初期値を書き込む。alignが指定されていればそのアライメントで確保する)
+    fn create_variable(&self, name: &str, value: &BasicValueEnum<'ctx>, align: Option<u32>) -> PointerValue<'ctx> {
+        let alloca = self.create_entry_block_alloca(value.get_type(), name);
+        let store = self.builder.build_store(alloca, *value);
+        if let Some(align) = align {
+            alloca.as_instruction_value()
+                .unwrap_or_else(||panic!("alloca is not an instruction."))
+                .set_alignment(align)
+                .unwrap_or_else(|e|panic!("Failed to set alignment of '{name}': {e}"));
+            store.set_alignment(align).unwrap_or_else(|e|panic!("Failed to set alignment of '{name}': {e}"));
+        }
+        return alloca;
+    }
+
+    /// グローバル変数を作成(初期値は定数。mutでなければLLVMの定数にする)
+    fn create_global_variable(&self, name: &str, value: &BasicValueEnum<'ctx>, mutable: bool, align: Option<u32>) -> PointerValue<'ctx> {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let global = module.add_global(value.get_type(), Some(AddressSpace::Generic), name);
+        global.set_initializer(value);
+        global.set_constant(!mutable);
+        global.set_linkage(Linkage::Internal);
+        if let Some(align) = align {
+            global.set_alignment(align);
+        }
+        return global.as_pointer_value();
+    }
+
+    /// 変数を読み出す
+    fn create_load_variable(&self, pointer: PointerValue<'ctx>, name: &str) -> BasicValueEnum<'ctx> {
+        return self.builder.build_load(pointer, name);
+    }
+
+    /// 変数に書き込む
+    fn create_store_variable(&self, pointer: PointerValue<'ctx>, value: &BasicValueEnum<'ctx>) {
+        self.builder.build_store(pointer, *value);
+    }
+
+    /// 積和演算 a * b + c (llvm.fma.f64で丸めは一回だけ)
+    /// WebAssemblyにはfmaの命令がなく、llvm.fma.f64はlibmのfmaの呼び出しになるので、wasm32-unknown-unknownでは乗算と加算に分ける(丸めは二回になる)
+    fn create_fma(&self, a: FloatValue<'ctx>, b: FloatValue<'ctx>, c: FloatValue<'ctx>) -> FloatValue<'ctx> {
+        if self.is_freestanding_wasm() {
+            let product = self.builder.build_float_mul(a, b, "product");
+            return self.builder.build_float_add(product, c, "fma");
+        }
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let intrinsic = module.get_function("llvm.fma.f64").unwrap_or_else(|| {
+            let f64_type = self.context.f64_type();
+            let param: BasicMetadataTypeEnum = f64_type.into();
+            module.add_function("llvm.fma.f64", f64_type.fn_type(&[param, param, param], false), None)
+        });
+        let argsv: Vec<BasicMetadataValueEnum> = vec![a.into(), b.into(), c.into()];
+        return self.builder.build_call(intrinsic, &argsv, "fma")
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap_or_else(||panic!("llvm.fma.f64 returned no value."))
+                    .into_float_value();
+    }
+
+    /// --fp fastのとき、a * b + cの加算を積和演算(create_fma)にまとめる。まとめられなければNone
+    /// オペランドのどちらかが、いま作ったばかりでまだどこからも使われていないfmulのときだけまとめ、そのfmulは消す
+    fn create_fused_add(&self, left: FloatValue<'ctx>, right: FloatValue<'ctx>) -> Option<FloatValue<'ctx>> {
+        if self.float_mode != FloatMode::Fast || self.is_freestanding_wasm() {
+            return None;
+        }
+        for (product, addend) in [(left, right), (right, left)] {
+            let instruction = match product.as_instruction_value() {
+                Some(instruction) if instruction.get_opcode() == InstructionOpcode::FMul && instruction.get_first_use().is_none() => instruction,
+                _ => continue
+            };
+            if let (Some(a), Some(b)) = (instruction.get_operand(0).and_then(|operand| operand.left()), instruction.get_operand(1).and_then(|operand| operand.left())) {
+                let fused = self.create_fma(a.into_float_value(), b.into_float_value(), addend);
+                instruction.erase_from_basic_block();
+                return Some(fused);
+            }
+        }
+        return None;
+    }
+
+    /// 文字列定数(グローバル定数として書き出し、その先頭へのポインタを返す)
+    fn create_constant_string(&self, text: &str) -> PointerValue<'ctx> {
+        return self.builder.build_global_string_ptr(text, "str").as_pointer_value();
+    }
+
+    /// KSCの文字列リテラル。参照の数が-1(定数)のヘッダーの後ろに文字列を置いたグローバル定数を作り、文字列の先頭へのポインタを返す
+    /// ほかのStringと同じようにksc_retainやksc_releaseに渡せる
+    fn create_string_literal(&self, text: &str) -> PointerValue<'ctx> {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let header_type = self.get_rc_header_type();
+        let header = header_type.const_named_struct(&[
+            self.context.i64_type().const_all_ones().into(),
+            self.get_drop_function_type().ptr_type(AddressSpace::Generic).const_null().into()
+        ]);
+        // 終端の0は自分で付ける
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.push(0);
+        let literal = self.context.const_struct(&[header.into(), self.context.const_string(&bytes, false).into()], false);
+        let global = module.add_global(literal.get_type(), None, "str");
+        global.set_initializer(&literal);
+        global.set_constant(true);
+        global.set_linkage(Linkage::Private);
+        global.set_unnamed_addr(true);
+        let zero = self.context.i32_type().const_zero();
+        let one = self.context.i32_type().const_int(1, false);
+        return unsafe { global.as_pointer_value().const_in_bounds_gep(&[zero, one, zero]) };
+    }
+
+    /// 外部関数(libcなど)を必要なときに1度だけ宣言する
+    fn get_or_declare_function(&self, name: &str, fn_type: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        return module.get_function(name).unwrap_or_else(||module.add_function(name, fn_type, None));
+    }
+
+    /// 文字列の連結(ランタイム関数ksc_string_concatを呼ぶ)
+    fn create_string_concat(&self, left: PointerValue<'ctx>, right: PointerValue<'ctx>) -> PointerValue<'ctx> {
+        let helper = self.get_or_create_string_concat_helper();
+        let argsv: Vec<BasicMetadataValueEnum> = vec![left.into(), right.into()];
+        return self.builder.build_call(helper, &argsv, "concat")
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap_or_else(||panic!("ksc_string_concat returned no value."))
+                    .into_pointer_value();
+    }
+
+    /// ランタイム関数ksc_string_concat(a, b)をモジュールに書き出す
+    /// strlenで長さを測ってksc_rc_allocで確保し、strcpyとstrcatで詰める
+    fn get_or_create_string_concat_helper(&self) -> FunctionValue<'ctx> {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        if let Some(helper) = module.get_function("ksc_string_concat") {
+            return helper;
+        }
+
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let size_type = self.get_size_type();
+        let strlen = self.get_or_declare_function("strlen", size_type.fn_type(&[i8_ptr_type.into()], false));
+        let rc_alloc = self.get_or_create_rc_alloc();
+        let strcpy = self.get_or_declare_function("strcpy", i8_ptr_type.fn_type(&[i8_ptr_type.into(), i8_ptr_type.into()], false));
+        let strcat = self.get_or_declare_function("strcat", i8_ptr_type.fn_type(&[i8_ptr_type.into(), i8_ptr_type.into()], false));
+
+        let helper = module.add_function("ksc_string_concat", i8_ptr_type.fn_type(&[i8_ptr_type.into(), i8_ptr_type.into()], false), None);
+        let previous_block = self.builder.get_insert_block();
+        self.builder.position_at_end(self.context.append_basic_block(helper, "entry"));
+        self.attach_debug_info(helper, "ksc_string_concat");
+
+        let left = helper.get_nth_param(0).unwrap().into_pointer_value();
+        let right = helper.get_nth_param(1).unwrap().into_pointer_value();
+        let left_len = self.builder.build_call(strlen, &[left.into()], "left_len").try_as_basic_value().left().unwrap().into_int_value();
+        let right_len = self.builder.build_call(strlen, &[right.into()], "right_len").try_as_basic_value().left().unwrap().into_int_value();
+        let len = self.builder.build_int_add(left_len, right_len, "len");
+        let size = self.builder.build_int_add(len, size_type.const_int(1, false), "size");
+        let no_drop = self.get_drop_function_type().ptr_type(AddressSpace::Generic).const_null();
+        let buffer = self.builder.build_call(rc_alloc, &[size.into(), no_drop.into()], "buffer").try_as_basic_value().left().unwrap().into_pointer_value();
+        self.builder.build_call(strcpy, &[buffer.into(), left.into()], "");
+        self.builder.build_call(strcat, &[buffer.into(), right.into()], "");
+        self.builder.build_return(Some(&buffer));
+
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+        self.restore_debug_location();
+        return helper;
+    }
+
+    /// 型のビット幅(モジュールのデータレイアウトに従う)
+    fn get_bit_size(&self, basic_type: &BasicTypeEnum<'ctx>) -> u64 {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let data_layout = module.get_data_layout();
+        let target_data = TargetData::create(data_layout.as_str().to_str().unwrap_or_else(|e|panic!("Invalid data layout: {e}")));
+        return target_data.get_bit_size(basic_type);
+    }
+
+    /// ビット列をそのまま別の型として読み替える(サイズが同じことは呼び出し側で確認する)
+    fn create_bitcast(&self, value: BasicValueEnum<'ctx>, destination_type: BasicTypeEnum<'ctx>) -> BasicValueEnum<'ctx> {
+        let is_aggregate = value.is_struct_value() || value.is_array_value() || destination_type.is_struct_type() || destination_type.is_array_type();
+        if !is_aggregate && value.is_pointer_value() == destination_type.is_pointer_type() {
+            return self.builder.build_bitcast(value, destination_type, "bitcast");
+        }
+        // ポインタと整数の間や集成体はbitcast命令で変換できないので、メモリを経由して読み替える
+        let source = self.builder.build_alloca(value.get_type(), "bitcast_source");
+        self.builder.build_store(source, value);
+        let casted = self.builder.build_pointer_cast(source, destination_type.ptr_type(AddressSpace::Generic), "bitcast_pointer");
+        return self.builder.build_load(casted, "bitcast");
+    }
+
+    /// printfを呼ぶ(printfは必要なときに宣言される)
+    fn create_printf(&self, format: &str, args: &Vec<BasicValueEnum<'ctx>>) {
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let printf = self.get_or_declare_function("printf", self.context.i32_type().fn_type(&[i8_ptr_type.into()], true));
+        let format = self.create_constant_string(format);
+        let mut argsv: Vec<BasicMetadataValueEnum> = vec![format.into()];
+        argsv.extend(args.iter().map(|&arg| BasicMetadataValueEnum::from(arg)));
+        self.builder.build_call(printf, &argsv, "printf");
+    }
+
+    /// snprintfで書式どおりの文字列を作る(参照カウントで管理する文字列)
+    /// 1回目の呼び出しで長さを測り、その長さで確保した文字列に2回目の呼び出しで書く
+    fn create_formatted_string(&self, format: &str, args: &Vec<BasicValueEnum<'ctx>>) -> PointerValue<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let size_type = self.get_size_type();
+        let snprintf = self.get_or_declare_function("snprintf", self.context.i32_type().fn_type(&[i8_ptr_type.into(), size_type.into(), i8_ptr_type.into()], true));
+        let rc_alloc = self.get_or_create_rc_alloc();
+        let format = self.create_constant_string(format);
+        let call_snprintf = |buffer: PointerValue<'ctx>, size: IntValue<'ctx>| {
+            let mut argsv: Vec<BasicMetadataValueEnum> = vec![buffer.into(), size.into(), format.into()];
+            argsv.extend(args.iter().map(|&arg| BasicMetadataValueEnum::from(arg)));
+            return self.builder.build_call(snprintf, &argsv, "snprintf").try_as_basic_value().left().unwrap().into_int_value();
+        };
+        let length = call_snprintf(i8_ptr_type.const_null(), size_type.const_zero());
+        let length = self.builder.build_int_z_extend_or_bit_cast(length, size_type, "length");
+        let size = self.builder.build_int_add(length, size_type.const_int(1, false), "size");
+        let no_drop = self.get_drop_function_type().ptr_type(AddressSpace::Generic).const_null();
+        let buffer = self.builder.build_call(rc_alloc, &[size.into(), no_drop.into()], "formatted").try_as_basic_value().left().unwrap().into_pointer_value();
+        call_snprintf(buffer, size);
+        return buffer;
+    }
+
+    /// wasm32-unknown-unknownにはprintfがないので、値ごとにその種類のホスト関数(ksc_print_f64など。compiled/wasm_shim.js)を呼ぶ
+    fn create_host_print(&self, printers: &[&str], values: &[BasicValueEnum<'ctx>], newline: bool) {
+        for (printer, value) in printers.iter().zip(values) {
+            let print = self.get_or_declare_function(printer, self.context.void_type().fn_type(&[value.get_type().into()], false));
+            self.builder.build_call(print, &[(*value).into()], "");
+        }
+        if newline {
+            let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+            let print = self.get_or_declare_function("ksc_print_str", self.context.void_type().fn_type(&[i8_ptr_type.into()], false));
+            self.builder.build_call(print, &[self.create_constant_string("\n").into()], "");
+        }
+    }
+
+    /// Boolを"true"か"false"の文字列にする
+    fn create_bool_to_string(&self, value: IntValue<'ctx>) -> PointerValue<'ctx> {
+        let true_string = self.create_constant_string("true");
+        let false_string = self.create_constant_string("false");
+        return self.builder.build_select(value, true_string, false_string, "bool_string").into_pointer_value();
+    }
+
+    /// ポインタをoffset個分ずらす
+    fn create_pointer_offset(&self, pointer: PointerValue<'ctx>, offset: IntValue<'ctx>) -> PointerValue<'ctx> {
+        return unsafe { self.builder.build_gep(pointer, &[offset], "offset") };
+    }
+
+    /// 構造体の型を作成
+    fn create_struct_type(&self, name: &str, field_types: &Vec<BasicTypeEnum<'ctx>>) -> StructType<'ctx> {
+        let struct_type = self.context.opaque_struct_type(name);
+        struct_type.set_body(field_types, false);
+        return struct_type;
+    }
+
+    /// 列挙型の型を作成(中身の大きさはヴァリアントの構造体のいちばん大きいものに合わせる)
+    fn create_enum_type(&self, name: &str, variant_names: Vec<String>, payloads: Vec<Vec<KSCType<'ctx>>>) -> KSCType<'ctx> {
+        let words = payloads.iter()
+                        .map(|payload| (self.get_bit_size(&BasicTypeEnum::StructType(self.get_variant_payload_type(payload))) + 63) / 64)
+                        .max()
+                        .unwrap_or(0);
+        let reference = self.create_struct_type(name, &vec![self.context.i32_type().into(), self.context.i64_type().array_type(words as u32).into()]);
+        return KSCType::Enum{ name: name.to_string(), reference, variant_names, payloads };
+    }
+
+    /// ヴァリアントの値を並べた構造体の型
+    fn get_variant_payload_type(&self, payload: &[KSCType<'ctx>]) -> StructType<'ctx> {
+        let fields = payload.iter()
+                        .map(|valuetype| self.get_basic_type(valuetype).unwrap_or_else(||panic!("A variant cannot hold Void.")))
+                        .collect::<Vec<BasicTypeEnum>>();
+        return self.context.struct_type(&fields, false);
+    }
+
+    /// 列挙型の値を指すポインタから、ヴァリアントの値の構造体へのポインタを得る
+    fn create_variant_payload_pointer(&self, pointer: PointerValue<'ctx>, payload: &[KSCType<'ctx>]) -> PointerValue<'ctx> {
+        let bytes = self.create_field_pointer(pointer, 1, "payload");
+        return self.builder.build_pointer_cast(bytes, self.get_variant_payload_type(payload).ptr_type(AddressSpace::Generic), "variant");
+    }
+
+    /// 構造体のフィールドへのポインタ
+    fn create_field_pointer(&self, pointer: PointerValue<'ctx>, index: u32, name: &str) -> PointerValue<'ctx> {
+        return self.builder.build_struct_gep(pointer, index, name).unwrap_or_else(|_|panic!("Field {index} does not exist."));
+    }
+
+    /// 配列の要素へのポインタ
+    fn create_element_pointer(&self, pointer: PointerValue<'ctx>, index: IntValue<'ctx>) -> PointerValue<'ctx> {
+        let zero = self.context.i32_type().const_zero();
+        return unsafe { self.builder.build_in_bounds_gep(pointer, &[zero, index], "element") };
+    }
+
+    /// 添字が範囲外ならllvm.trapで止める(負の添字も符号なしで比べると範囲外になる)
+    fn create_bounds_check(&self, index: IntValue<'ctx>, length: IntValue<'ctx>) {
+        let in_bounds = self.builder.build_int_compare(IntPredicate::ULT, index, length, "in_bounds");
+        self.create_trap_unless(in_bounds, "out_of_bounds", "in_bounds");
+    }
+
+    /// 整数の+、-、*を、あふれたかどうか(i1)と一緒に計算する(llvm.sadd.with.overflowなど。符号なしならuadd)
+    fn create_overflowing_int_operator(&self, op: BinaryOperator, left: IntValue<'ctx>, right: IntValue<'ctx>, signed: bool) -> (IntValue<'ctx>, IntValue<'ctx>) {
+        let name = match op {
+            BinaryOperator::ADD => "add",
+            BinaryOperator::SUB => "sub",
+            BinaryOperator::MUL => "mul",
+            _ => panic!("Only +, - and * can check for overflow.")
+        };
+        let inttype = left.get_type();
+        let intrinsic = format!("llvm.{}{}.with.overflow.i{}", if signed { "s" } else { "u" }, name, inttype.get_bit_width());
+        let result_type = self.context.struct_type(&[inttype.into(), self.context.bool_type().into()], false);
+        let func = self.get_or_declare_function(&intrinsic, result_type.fn_type(&[inttype.into(), inttype.into()], false));
+        let result = self.builder.build_call(func, &[left.into(), right.into()], name)
+                        .try_as_basic_value().left()
+                        .unwrap_or_else(||panic!("'{intrinsic}' returns a value."))
+                        .into_struct_value();
+        let value = self.builder.build_extract_value(result, 0, "value").unwrap().into_int_value();
+        let overflowed = self.builder.build_extract_value(result, 1, "overflowed").unwrap().into_int_value();
+        return (value, overflowed);
+    }
+
+    /// 整数の+、-、*を計算し、あふれたらllvm.trapで止める
+    fn create_trapping_int_operator(&self, op: BinaryOperator, left: IntValue<'ctx>, right: IntValue<'ctx>, signed: bool) -> IntValue<'ctx> {
+        let (value, overflowed) = self.create_overflowing_int_operator(op, left, right, signed);
+        let fits = self.builder.build_not(overflowed, "fits");
+        self.create_trap_unless(fits, "overflow", "no_overflow");
+        return value;
+    }
+
+    /// ランタイム関数ksc_panic(i8* message)
+    /// メッセージを標準エラー出力に書いてllvm.trapで止める(wasm32-unknown-unknownでは標準エラー出力がないのでksc_print_strで書く)
+    fn get_or_create_panic(&self) -> FunctionValue<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let fn_type = self.context.void_type().fn_type(&[i8_ptr_type.into()], false);
+        return self.get_or_create_runtime_function("ksc_panic", fn_type, |func| {
+            let message = func.get_nth_param(0).unwrap().into_pointer_value();
+            if self.is_freestanding_wasm() {
+                let print = self.get_or_declare_function("ksc_print_str", self.context.void_type().fn_type(&[i8_ptr_type.into()], false));
+                self.builder.build_call(print, &[message.into()], "");
+            } else {
+                let size_type = self.get_size_type();
+                let strlen = self.get_or_declare_function("strlen", size_type.fn_type(&[i8_ptr_type.into()], false));
+                let write = self.get_or_declare_function("write", size_type.fn_type(&[self.context.i32_type().into(), i8_ptr_type.into(), size_type.into()], false));
+                let length = self.builder.build_call(strlen, &[message.into()], "length").try_as_basic_value().left().unwrap();
+                let stderr = self.context.i32_type().const_int(2, false);
+                self.builder.build_call(write, &[stderr.into(), message.into(), length.into()], "");
+            }
+            let trap = self.get_or_declare_function("llvm.trap", self.context.void_type().fn_type(&[], false));
+            self.builder.build_call(trap, &[], "");
+            self.builder.build_unreachable();
+        });
+    }
+
+    /// conditionが偽ならmessageを表示して止まる(ksc_panic)
+    fn create_assert(&self, condition: IntValue<'ctx>, message: &str) {
+        let parent = self.builder.get_insert_block()
+                        .and_then(|block| block.get_parent())
+                        .unwrap_or_else(||panic!("No function."));
+        let failed_block = self.context.append_basic_block(parent, "assert_failed");
+        let cont_block = self.context.append_basic_block(parent, "assert_ok");
+        self.builder.build_conditional_branch(condition, cont_block, failed_block);
+        self.builder.position_at_end(failed_block);
+        let panic = self.get_or_create_panic();
+        self.builder.build_call(panic, &[self.create_constant_string(message).into()], "");
+        self.builder.build_unreachable();
+        self.builder.position_at_end(cont_block);
+    }
+
+    /// conditionが偽ならllvm.trapで止める(ブロック名はそれぞれ止まる側と続く側)
+    fn create_trap_unless(&self, condition: IntValue<'ctx>, trap_name: &str, cont_name: &str) {
+        let parent = self.builder.get_insert_block()
+                        .and_then(|block| block.get_parent())
+                        .unwrap_or_else(||panic!("No function."));
+        let trap_block = self.context.append_basic_block(parent, trap_name);
+        let cont_block = self.context.append_basic_block(parent, cont_name);
+        self.builder.build_conditional_branch(condition, cont_block, trap_block);
+        self.builder.position_at_end(trap_block);
+        let trap = self.get_or_declare_function("llvm.trap", self.context.void_type().fn_type(&[], false));
+        self.builder.build_call(trap, &[], "");
+        self.builder.build_unreachable();
+        self.builder.position_at_end(cont_block);
+    }
+
+    /// ランタイム関数をモジュールに書き出す(すでにあればそれを返す)
+    /// bodyには書き出し先の関数が渡され、ビルダーはその先頭ブロックを指している。書き終わったら元の位置に戻す
+    fn get_or_create_runtime_function(&self, name: &str, fn_type: FunctionType<'ctx>, body: impl FnOnce(FunctionValue<'ctx>)) -> FunctionValue<'ctx> {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        if let Some(func) = module.get_function(name) {
+            return func;
+        }
+        let func = module.add_function(name, fn_type, None);
+        self.apply_sanitizer(func);
+        let previous_block = self.builder.get_insert_block();
+        self.builder.position_at_end(self.context.append_basic_block(func, "entry"));
+        self.attach_debug_info(func, name);
+        body(func);
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+        self.restore_debug_location();
+        return func;
+    }
+
+    /// ランタイム関数ksc_alloc(size_t size) -> i8*
+    /// KSCのヒープ確保(new、文字列の連結、List、クロージャの環境)はすべてこれを通す。確保できなければllvm.trapで止める
+    fn get_or_create_alloc(&self) -> FunctionValue<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let size_type = self.get_size_type();
+        let malloc = self.get_or_declare_function("malloc", i8_ptr_type.fn_type(&[size_type.into()], false));
+        return self.get_or_create_runtime_function("ksc_alloc", i8_ptr_type.fn_type(&[size_type.into()], false), |func| {
+            let size = func.get_nth_param(0).unwrap();
+            let memory = self.builder.build_call(malloc, &[size.into()], "memory").try_as_basic_value().left().unwrap().into_pointer_value();
+            self.create_trap_unless(self.builder.build_is_not_null(memory, "allocated"), "out_of_memory", "allocated");
+            self.builder.build_return(Some(&memory));
+        });
+    }
+
+    /// ランタイム関数ksc_realloc(i8* memory, size_t size) -> i8*
+    /// ksc_allocで確保したメモリを広げる。確保できなければllvm.trapで止める
+    fn get_or_create_realloc(&self) -> FunctionValue<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let size_type = self.get_size_type();
+        let realloc = self.get_or_declare_function("realloc", i8_ptr_type.fn_type(&[i8_ptr_type.into(), size_type.into()], false));
+        return self.get_or_create_runtime_function("ksc_realloc", i8_ptr_type.fn_type(&[i8_ptr_type.into(), size_type.into()], false), |func| {
+            let memory = func.get_nth_param(0).unwrap();
+            let size = func.get_nth_param(1).unwrap();
+            let memory = self.builder.build_call(realloc, &[memory.into(), size.into()], "memory").try_as_basic_value().left().unwrap().into_pointer_value();
+            self.create_trap_unless(self.builder.build_is_not_null(memory, "allocated"), "out_of_memory", "allocated");
+            self.builder.build_return(Some(&memory));
+        });
+    }
+
+    /// ランタイム関数ksc_free(i8* memory)
+    fn get_or_create_free(&self) -> FunctionValue<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let free = self.get_or_declare_function("free", self.context.void_type().fn_type(&[i8_ptr_type.into()], false));
+        return self.get_or_create_runtime_function("ksc_free", self.context.void_type().fn_type(&[i8_ptr_type.into()], false), |func| {
+            let memory = func.get_nth_param(0).unwrap();
+            self.builder.build_call(free, &[memory.into()], "");
+            self.builder.build_return(None);
+        });
+    }
+
+    /// 参照カウントのヘッダー{参照の数 i64, 数が0になったときに中身の参照を解放する関数(なければnull)}
+    /// ksc_rc_allocで確保した領域の先頭に置く。数が負の値は定数(文字列リテラル)で、数えない
+    fn get_rc_header_type(&self) -> StructType<'ctx> {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        if let Some(header) = module.get_struct_type("ksc_rc_header") {
+            return header;
+        }
+        let header = self.context.opaque_struct_type("ksc_rc_header");
+        header.set_body(&[self.context.i64_type().into(), self.get_drop_function_type().ptr_type(AddressSpace::Generic).into()], false);
+        return header;
+    }
+
+    /// 中身の参照を解放する関数の型 void(i8*)(ksc_retainとksc_releaseも同じ型)
+    fn get_drop_function_type(&self) -> FunctionType<'ctx> {
+        return self.context.void_type().fn_type(&[self.context.i8_type().ptr_type(AddressSpace::Generic).into()], false);
+    }
+
+    /// ksc_rc_allocが返したポインタから、その前にあるヘッダーへのポインタを得る
+    fn create_rc_header_pointer(&self, pointer: PointerValue<'ctx>) -> PointerValue<'ctx> {
+        let header = self.builder.build_pointer_cast(pointer, self.get_rc_header_type().ptr_type(AddressSpace::Generic), "rc_payload");
+        return unsafe { self.builder.build_gep(header, &[self.context.i32_type().const_int(-1i64 as u64, true)], "rc_header") };
+    }
+
+    /// ランタイム関数ksc_rc_alloc(size_t size, void(i8*)* drop) -> i8*
+    /// ヘッダーの後ろにsizeバイトを確保して参照の数を1にし、ヘッダーの後ろへのポインタを返す
+    fn get_or_create_rc_alloc(&self) -> FunctionValue<'ctx> {
+        let header_type = self.get_rc_header_type();
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let drop_ptr_type = self.get_drop_function_type().ptr_type(AddressSpace::Generic);
+        let alloc = self.get_or_create_alloc();
+        let fn_type = i8_ptr_type.fn_type(&[self.get_size_type().into(), drop_ptr_type.into()], false);
+        return self.get_or_create_runtime_function("ksc_rc_alloc", fn_type, |func| {
+            let size = func.get_nth_param(0).unwrap().into_int_value();
+            let drop = func.get_nth_param(1).unwrap();
+            let header_size = self.create_size_cast(header_type.size_of().unwrap_or_else(||panic!("ksc_rc_header has no size.")));
+            let total = self.builder.build_int_add(size, header_size, "total");
+            let memory = self.builder.build_call(alloc, &[total.into()], "memory").try_as_basic_value().left().unwrap().into_pointer_value();
+            let header = self.builder.build_pointer_cast(memory, header_type.ptr_type(AddressSpace::Generic), "header");
+            self.builder.build_store(self.create_field_pointer(header, 0, "count"), self.context.i64_type().const_int(1, false));
+            self.builder.build_store(self.create_field_pointer(header, 1, "drop"), drop);
+            let payload = unsafe { self.builder.build_gep(header, &[self.context.i32_type().const_int(1, false)], "payload") };
+            self.builder.build_return(Some(&self.builder.build_pointer_cast(payload, i8_ptr_type, "payload")));
+        });
+    }
+
+    /// ランタイム関数ksc_retain(i8* pointer)。参照の数を1つ増やす(nullと定数では何もしない)
+    fn get_or_create_retain(&self) -> FunctionValue<'ctx> {
+        return self.get_or_create_runtime_function("ksc_retain", self.get_drop_function_type(), |func| {
+            let pointer = func.get_nth_param(0).unwrap().into_pointer_value();
+            let count_block = self.context.append_basic_block(func, "count");
+            let increment_block = self.context.append_basic_block(func, "increment");
+            let done_block = self.context.append_basic_block(func, "done");
+            self.builder.build_conditional_branch(self.builder.build_is_null(pointer, "is_null"), done_block, count_block);
+
+            self.builder.position_at_end(count_block);
+            let count_pointer = self.create_field_pointer(self.create_rc_header_pointer(pointer), 0, "count_pointer");
+            let count = self.builder.build_load(count_pointer, "count").into_int_value();
+            let counted = self.builder.build_int_compare(IntPredicate::SGE, count, count.get_type().const_zero(), "counted");
+            self.builder.build_conditional_branch(counted, increment_block, done_block);
+
+            self.builder.position_at_end(increment_block);
+            let incremented = self.builder.build_int_add(count, count.get_type().const_int(1, false), "incremented");
+            self.builder.build_store(count_pointer, incremented);
+            self.builder.build_unconditional_branch(done_block);
+
+            self.builder.position_at_end(done_block);
+            self.builder.build_return(None);
+        });
+    }
+
+    /// ランタイム関数ksc_release(i8* pointer)。参照の数を1つ減らし、0になったら中身の参照を解放してから領域を解放する
+    /// nullと定数では何もしない
+    fn get_or_create_release(&self) -> FunctionValue<'ctx> {
+        let free = self.get_or_create_free();
+        return self.get_or_create_runtime_function("ksc_release", self.get_drop_function_type(), |func| {
+            let pointer = func.get_nth_param(0).unwrap().into_pointer_value();
+            let count_block = self.context.append_basic_block(func, "count");
+            let decrement_block = self.context.append_basic_block(func, "decrement");
+            let drop_block = self.context.append_basic_block(func, "drop");
+            let free_block = self.context.append_basic_block(func, "free");
+            let done_block = self.context.append_basic_block(func, "done");
+            self.builder.build_conditional_branch(self.builder.build_is_null(pointer, "is_null"), done_block, count_block);
+
+            self.builder.position_at_end(count_block);
+            let header = self.create_rc_header_pointer(pointer);
+            let count_pointer = self.create_field_pointer(header, 0, "count_pointer");
+            let count = self.builder.build_load(count_pointer, "count").into_int_value();
+            let counted = self.builder.build_int_compare(IntPredicate::SGE, count, count.get_type().const_zero(), "counted");
+            self.builder.build_conditional_branch(counted, decrement_block, done_block);
+
+            self.builder.position_at_end(decrement_block);
+            let decremented = self.builder.build_int_sub(count, count.get_type().const_int(1, false), "decremented");
+            self.builder.build_store(count_pointer, decremented);
+            let is_last = self.builder.build_int_compare(IntPredicate::EQ, decremented, count.get_type().const_zero(), "is_last");
+            let check_block = self.context.append_basic_block(func, "check_drop");
+            self.builder.build_conditional_branch(is_last, check_block, done_block);
+
+            self.builder.position_at_end(check_block);
+            let drop = self.builder.build_load(self.create_field_pointer(header, 1, "drop_pointer"), "drop").into_pointer_value();
+            self.builder.build_conditional_branch(self.builder.build_is_not_null(drop, "has_drop"), drop_block, free_block);
+
+            self.builder.position_at_end(drop_block);
+            self.create_indirect_call(drop, &vec![pointer.as_basic_value_enum()]);
+            self.builder.build_unconditional_branch(free_block);
+
+            self.builder.position_at_end(free_block);
+            let memory = self.builder.build_pointer_cast(header, self.context.i8_type().ptr_type(AddressSpace::Generic), "memory");
+            self.builder.build_call(free, &[memory.into()], "");
+            self.builder.build_unconditional_branch(done_block);
+
+            self.builder.position_at_end(done_block);
+            self.builder.build_return(None);
+        });
+    }
+
+    /// 参照カウントで管理する型か(String、List、クロージャと、それを中に持つnewtype、Optional、構造体、タプル、列挙型)
+    /// 配列の要素の参照は数えない
+    fn is_reference_counted(valuetype: &KSCType<'ctx>) -> bool {
+        return match valuetype {
+            KSCType::String(_) | KSCType::List { .. } | KSCType::Closure { .. } => true,
+            KSCType::NewType { underlying, .. } | KSCType::Optional { inner: underlying, .. } => Self::is_reference_counted(underlying),
+            KSCType::Struct { contents, .. } => contents.iter().any(Self::is_reference_counted),
+            KSCType::Tuple { elements, .. } => elements.iter().any(Self::is_reference_counted),
+            KSCType::Enum { payloads, .. } => payloads.iter().flatten().any(Self::is_reference_counted),
+            _ => false
+        };
+    }
+
+    /// 参照の数を数えるポインタ(i8*)を値からすべて取り出す
+    /// Stringは文字列、Listはヘッダー、クロージャは環境(捕まえた変数がなければnull)を指す。構造体とタプルはフィールドごとに取り出す
+    /// 列挙型はいまのヴァリアントの値だけを数えるよう、ほかのヴァリアントのポインタはnullにする
+    fn get_rc_pointers(&self, value: BasicValueEnum<'ctx>, valuetype: &KSCType<'ctx>) -> Vec<PointerValue<'ctx>> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        return match valuetype {
+            KSCType::String(_) => vec![value.into_pointer_value()],
+            KSCType::List { .. } => vec![self.builder.build_pointer_cast(value.into_pointer_value(), i8_ptr_type, "list_bytes")],
+            KSCType::Closure { .. } => vec![self.builder.build_extract_value(value.into_struct_value(), 1, "env").unwrap().into_pointer_value()],
+            KSCType::NewType { underlying, .. } => {
+                let inner = self.builder.build_extract_value(value.into_struct_value(), 0, "unwrapped").unwrap();
+                self.get_rc_pointers(inner, underlying)
+            },
+            // 値のないOptionalの中身は0(null)なので、そのまま渡してよい
+            KSCType::Optional { inner, .. } => {
+                let payload = self.builder.build_extract_value(value.into_struct_value(), 1, "payload").unwrap();
+                self.get_rc_pointers(payload, inner)
+            },
+            KSCType::Struct { contents, field_indices, .. } => {
+                contents.iter().zip(field_indices.iter())
+                    .filter(|(content, _)| Self::is_reference_counted(content))
+                    .flat_map(|(content, index)| {
+                        let field = self.builder.build_extract_value(value.into_struct_value(), *index, "field").unwrap();
+                        self.get_rc_pointers(field, content)
+                    })
+                    .collect()
+            },
+            KSCType::Tuple { elements, .. } => {
+                elements.iter().enumerate()
+                    .filter(|(_, element)| Self::is_reference_counted(element))
+                    .flat_map(|(index, element)| {
+                        let element_value = self.builder.build_extract_value(value.into_struct_value(), index as u32, "element").unwrap();
+                        self.get_rc_pointers(element_value, element)
+                    })
+                    .collect()
+            },
+            KSCType::Enum { reference, payloads, .. } if Self::is_reference_counted(valuetype) => {
+                let pointer = self.create_entry_block_alloca(BasicTypeEnum::StructType(*reference), "enum");
+                self.builder.build_store(pointer, value);
+                let tag = self.create_load_variable(self.create_field_pointer(pointer, 0, "tag"), "tag").into_int_value();
+                let mut pointers = vec![];
+                for (variant_index, payload) in payloads.iter().enumerate().filter(|(_, payload)| payload.iter().any(Self::is_reference_counted)) {
+                    let is_variant = self.builder.build_int_compare(IntPredicate::EQ, tag, tag.get_type().const_int(variant_index as u64, false), "is_variant");
+                    let payload_pointer = self.create_variant_payload_pointer(pointer, payload);
+                    for (index, valuetype) in payload.iter().enumerate().filter(|(_, valuetype)| Self::is_reference_counted(valuetype)) {
+                        let field = self.create_load_variable(self.create_field_pointer(payload_pointer, index as u32, "variant_value"), "variant_value");
+                        for rc_pointer in self.get_rc_pointers(field, valuetype) {
+                            let selected = self.builder.build_select(is_variant, rc_pointer, i8_ptr_type.const_null(), "variant_rc");
+                            pointers.push(selected.into_pointer_value());
+                        }
+                    }
+                }
+                pointers
+            },
+            _ => vec![]
+        };
+    }
+
+    /// いまのブロックに命令を足せるか(ブロックがあって、まだ終端命令がない)
+    fn can_insert_instructions(&self) -> bool {
+        return self.builder.get_insert_block().map(|block| block.get_terminator().is_none()).unwrap_or(false);
+    }
+
+    /// 参照カウントで管理する値なら、参照の数を増やす
+    fn create_retain(&self, value: BasicValueEnum<'ctx>, valuetype: &KSCType<'ctx>) {
+        if !Self::is_reference_counted(valuetype) || !self.can_insert_instructions() {
+            return;
+        }
+        let retain = self.get_or_create_retain();
+        for pointer in self.get_rc_pointers(value, valuetype) {
+            self.builder.build_call(retain, &[pointer.into()], "");
+        }
+    }
+
+    /// 参照カウントで管理する値なら、参照の数を減らす
+    fn create_release(&self, value: BasicValueEnum<'ctx>, valuetype: &KSCType<'ctx>) {
+        if !Self::is_reference_counted(valuetype) || !self.can_insert_instructions() {
+            return;
+        }
+        let release = self.get_or_create_release();
+        for pointer in self.get_rc_pointers(value, valuetype) {
+            self.builder.build_call(release, &[pointer.into()], "");
+        }
+    }
+
+    /// ksc_rc_allocで確保する(i8*を返す。dropは数が0になったときに呼ぶ関数)
+    fn create_rc_allocation(&self, basic_type: BasicTypeEnum<'ctx>, drop: Option<FunctionValue<'ctx>>, name: &str) -> PointerValue<'ctx> {
+        let rc_alloc = self.get_or_create_rc_alloc();
+        let size = self.create_size_cast(basic_type.size_of().unwrap_or_else(||panic!("'{name}' has no size.")));
+        let drop = match drop {
+            Some(drop) => drop.as_global_value().as_pointer_value(),
+            None => self.get_drop_function_type().ptr_type(AddressSpace::Generic).const_null()
+        };
+        return self.builder.build_call(rc_alloc, &[size.into(), drop.into()], name).try_as_basic_value().left().unwrap().into_pointer_value();
+    }
+
+    /// 構造体(クロージャやpartialの環境)の、参照カウントで管理するフィールドを解放する関数を書き出す
+    /// 解放するフィールドがなければNone
+    fn get_or_create_env_drop(&self, name: &str, env_type: StructType<'ctx>, field_types: &[KSCType<'ctx>]) -> Option<FunctionValue<'ctx>> {
+        if !field_types.iter().any(Self::is_reference_counted) {
+            return None;
+        }
+        let release = self.get_or_create_release();
+        return Some(self.get_or_create_runtime_function(name, self.get_drop_function_type(), |func| {
+            let env = self.builder.build_pointer_cast(func.get_nth_param(0).unwrap().into_pointer_value(), env_type.ptr_type(AddressSpace::Generic), "env");
+            for (index, field_type) in field_types.iter().enumerate().filter(|(_, field_type)| Self::is_reference_counted(field_type)) {
+                let field = self.create_load_variable(self.create_field_pointer(env, index as u32, "field"), "field");
+                for pointer in self.get_rc_pointers(field, field_type) {
+                    self.builder.build_call(release, &[pointer.into()], "");
+                }
+            }
+            self.builder.build_return(None);
+        }));
+    }
+
+    /// ランタイム関数ksc_list_drop(i8* list)。要素の領域を解放する
+    /// 要素が参照カウントで管理する型なら、要素の型ごとの関数(ksc_list_drop.String など)で先に要素を1つずつ解放する
+    fn get_or_create_list_drop(&self, element: &KSCType<'ctx>) -> FunctionValue<'ctx> {
+        let header_ptr_type = self.get_list_header_type().ptr_type(AddressSpace::Generic);
+        let free = self.get_or_create_free();
+        let release = self.get_or_create_release();
+        let element_type = self.get_basic_type(element).unwrap_or_else(||panic!("Lists cannot contain Void."));
+        let counted = Self::is_reference_counted(element);
+        let name = if counted { format!("ksc_list_drop.{}", element.type_name()) } else { "ksc_list_drop".to_string() };
+        return self.get_or_create_runtime_function(&name, self.get_drop_function_type(), |func| {
+            let list = self.builder.build_pointer_cast(func.get_nth_param(0).unwrap().into_pointer_value(), header_ptr_type, "list");
+            let data = self.builder.build_load(self.create_field_pointer(list, 0, "data_pointer"), "data").into_pointer_value();
+            if counted {
+                let len = self.builder.build_load(self.create_field_pointer(list, 1, "len_pointer"), "len").into_int_value();
+                let entry_block = self.builder.get_insert_block().unwrap();
+                let loop_block = self.context.append_basic_block(func, "release_element");
+                let end_block = self.context.append_basic_block(func, "released");
+                let is_empty = self.builder.build_int_compare(IntPredicate::EQ, len, len.get_type().const_zero(), "is_empty");
+                self.builder.build_conditional_branch(is_empty, end_block, loop_block);
+
+                self.builder.position_at_end(loop_block);
+                let index = self.builder.build_phi(len.get_type(), "index");
+                let elements = self.builder.build_pointer_cast(data, element_type.ptr_type(AddressSpace::Generic), "elements");
+                let element_value = self.builder.build_load(self.create_pointer_offset(elements, index.as_basic_value().into_int_value()), "element");
+                for pointer in self.get_rc_pointers(element_value, element) {
+                    self.builder.build_call(release, &[pointer.into()], "");
+                }
+                let next = self.builder.build_int_add(index.as_basic_value().into_int_value(), len.get_type().const_int(1, false), "next");
+                index.add_incoming(&[(&len.get_type().const_zero(), entry_block), (&next, loop_block)]);
+                let finished = self.builder.build_int_compare(IntPredicate::EQ, next, len, "finished");
+                self.builder.build_conditional_branch(finished, end_block, loop_block);
+
+                self.builder.position_at_end(end_block);
+            }
+            self.builder.build_call(free, &[data.into()], "");
+            self.builder.build_return(None);
+        });
+    }
+
+    /// ランタイム関数ksc_string_from_c(i8* text) -> i8*
+    /// 外部関数が返した文字列(ヘッダーがない)を、参照カウントで管理する文字列にコピーする
+    fn get_or_create_string_from_c(&self) -> FunctionValue<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let size_type = self.get_size_type();
+        let strlen = self.get_or_declare_function("strlen", size_type.fn_type(&[i8_ptr_type.into()], false));
+        let strcpy = self.get_or_declare_function("strcpy", i8_ptr_type.fn_type(&[i8_ptr_type.into(), i8_ptr_type.into()], false));
+        let rc_alloc = self.get_or_create_rc_alloc();
+        return self.get_or_create_runtime_function("ksc_string_from_c", i8_ptr_type.fn_type(&[i8_ptr_type.into()], false), |func| {
+            let text = func.get_nth_param(0).unwrap();
+            let len = self.builder.build_call(strlen, &[text.into()], "len").try_as_basic_value().left().unwrap().into_int_value();
+            let size = self.builder.build_int_add(len, size_type.const_int(1, false), "size");
+            let no_drop = self.get_drop_function_type().ptr_type(AddressSpace::Generic).const_null();
+            let copy = self.builder.build_call(rc_alloc, &[size.into(), no_drop.into()], "copy").try_as_basic_value().left().unwrap();
+            self.builder.build_call(strcpy, &[copy.into(), text.into()], "");
+            self.builder.build_return(Some(&copy));
+        });
+    }
+
+    /// ランタイム関数ksc_list_new(void(i8*)* drop) -> ksc_list*
+    /// ヘッダーはksc_rc_allocで確保し、dropには要素の型に合わせたksc_list_dropを渡す
+    fn get_or_create_list_new(&self) -> FunctionValue<'ctx> {
+        let header_type = self.get_list_header_type();
+        let header_ptr_type = header_type.ptr_type(AddressSpace::Generic);
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let i64_type = self.context.i64_type();
+        let rc_alloc = self.get_or_create_rc_alloc();
+        let drop_ptr_type = self.get_drop_function_type().ptr_type(AddressSpace::Generic);
+        return self.get_or_create_runtime_function("ksc_list_new", header_ptr_type.fn_type(&[drop_ptr_type.into()], false), |func| {
+            let size = self.create_size_cast(header_type.size_of().unwrap_or_else(||panic!("ksc_list has no size.")));
+            let drop = func.get_nth_param(0).unwrap();
+            let memory = self.builder.build_call(rc_alloc, &[size.into(), drop.into()], "memory").try_as_basic_value().left().unwrap().into_pointer_value();
+            let list = self.builder.build_pointer_cast(memory, header_ptr_type, "list");
+            self.builder.build_store(self.create_field_pointer(list, 0, "data"), i8_ptr_type.const_null());
+            self.builder.build_store(self.create_field_pointer(list, 1, "len"), i64_type.const_zero());
+            self.builder.build_store(self.create_field_pointer(list, 2, "cap"), i64_type.const_zero());
+            self.builder.build_return(Some(&list));
+        });
+    }
+
+    /// ランタイム関数ksc_list_push(ksc_list* list, i8* element, i64 element_size)
+    /// 容量が足りなければksc_reallocで倍に広げてから、要素をmemcpyで末尾に書き込む
+    fn get_or_create_list_push(&self) -> FunctionValue<'ctx> {
+        let header_ptr_type = self.get_list_header_type().ptr_type(AddressSpace::Generic);
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let i64_type = self.context.i64_type();
+        let realloc = self.get_or_create_realloc();
+        let fn_type = self.context.void_type().fn_type(&[header_ptr_type.into(), i8_ptr_type.into(), i64_type.into()], false);
+        return self.get_or_create_runtime_function("ksc_list_push", fn_type, |func| {
+            let list = func.get_nth_param(0).unwrap().into_pointer_value();
+            let element = func.get_nth_param(1).unwrap().into_pointer_value();
+            let element_size = func.get_nth_param(2).unwrap().into_int_value();
+            let data_pointer = self.create_field_pointer(list, 0, "data_pointer");
+            let len_pointer = self.create_field_pointer(list, 1, "len_pointer");
+            let cap_pointer = self.create_field_pointer(list, 2, "cap_pointer");
+            let len = self.builder.build_load(len_pointer, "len").into_int_value();
+            let cap = self.builder.build_load(cap_pointer, "cap").into_int_value();
+
+            let grow_block = self.context.append_basic_block(func, "grow");
+            let store_block = self.context.append_basic_block(func, "store");
+            let full = self.builder.build_int_compare(IntPredicate::EQ, len, cap, "full");
+            self.builder.build_conditional_branch(full, grow_block, store_block);
+
+            self.builder.position_at_end(grow_block);
+            let is_empty = self.builder.build_int_compare(IntPredicate::EQ, cap, i64_type.const_zero(), "is_empty");
+            let doubled = self.builder.build_int_mul(cap, i64_type.const_int(2, false), "doubled");
+            let new_cap = self.builder.build_select(is_empty, i64_type.const_int(4, false), doubled, "new_cap").into_int_value();
+            let new_size = self.builder.build_int_mul(new_cap, element_size, "new_size");
+            let data = self.builder.build_load(data_pointer, "data").into_pointer_value();
+            let new_data = self.builder.build_call(realloc, &[data.into(), self.create_size_cast(new_size).into()], "new_data").try_as_basic_value().left().unwrap();
+            self.builder.build_store(data_pointer, new_data);
+            self.builder.build_store(cap_pointer, new_cap);
+            self.builder.build_unconditional_branch(store_block);
+
+            self.builder.position_at_end(store_block);
+            let data = self.builder.build_load(data_pointer, "data").into_pointer_value();
+            let offset = self.builder.build_int_mul(len, element_size, "offset");
+            let destination = unsafe { self.builder.build_gep(data, &[offset], "destination") };
+            self.builder.build_memcpy(destination, 1, element, 1, element_size).unwrap_or_else(|e|panic!("Failed to copy a list element: {e}"));
+            let new_len = self.builder.build_int_add(len, i64_type.const_int(1, false), "new_len");
+            self.builder.build_store(len_pointer, new_len);
+            self.builder.build_return(None);
+        });
+    }
+
+    /// ランタイム関数ksc_list_get(ksc_list* list, i64 index, i64 element_size) -> i8*
+    /// 範囲外の添字ではllvm.trapで止まる
+    fn get_or_create_list_get(&self) -> FunctionValue<'ctx> {
+        let header_ptr_type = self.get_list_header_type().ptr_type(AddressSpace::Generic);
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let i64_type = self.context.i64_type();
+        let fn_type = i8_ptr_type.fn_type(&[header_ptr_type.into(), i64_type.into(), i64_type.into()], false);
+        return self.get_or_create_runtime_function("ksc_list_get", fn_type, |func| {
+            let list = func.get_nth_param(0).unwrap().into_pointer_value();
+            let index = func.get_nth_param(1).unwrap().into_int_value();
+            let element_size = func.get_nth_param(2).unwrap().into_int_value();
+            let len = self.builder.build_load(self.create_field_pointer(list, 1, "len_pointer"), "len").into_int_value();
+            self.create_bounds_check(index, len);
+            let data = self.builder.build_load(self.create_field_pointer(list, 0, "data_pointer"), "data").into_pointer_value();
+            let offset = self.builder.build_int_mul(index, element_size, "offset");
+            let element = unsafe { self.builder.build_gep(data, &[offset], "element") };
+            self.builder.build_return(Some(&element));
+        });
+    }
+
+    /// ランタイム関数ksc_list_len(ksc_list* list) -> i64
+    fn get_or_create_list_len(&self) -> FunctionValue<'ctx> {
+        let header_ptr_type = self.get_list_header_type().ptr_type(AddressSpace::Generic);
+        let fn_type = self.context.i64_type().fn_type(&[header_ptr_type.into()], false);
+        return self.get_or_create_runtime_function("ksc_list_len", fn_type, |func| {
+            let list = func.get_nth_param(0).unwrap().into_pointer_value();
+            let len = self.builder.build_load(self.create_field_pointer(list, 1, "len_pointer"), "len");
+            self.builder.build_return(Some(&len));
+        });
+    }
+
+    /// 空のListを作る
+    fn create_list_new(&self, listtype: &KSCType<'ctx>) -> PointerValue<'ctx> {
+        let (reference, element) = match listtype {
+            KSCType::List { reference, element } => (*reference, element.as_ref()),
+            _ => panic!("'{}' is not a list.", listtype.type_name())
+        };
+        let list_new = self.get_or_create_list_new();
+        let drop = self.get_or_create_list_drop(element).as_global_value().as_pointer_value();
+        let list = self.builder.build_call(list_new, &[drop.into()], "list").try_as_basic_value().left().unwrap().into_pointer_value();
+        return self.builder.build_pointer_cast(list, reference, "list");
+    }
+
+    /// List<T>*をランタイム関数に渡すksc_list*にする
+    fn create_list_header_cast(&self, list: PointerValue<'ctx>) -> PointerValue<'ctx> {
+        return self.builder.build_pointer_cast(list, self.get_list_header_type().ptr_type(AddressSpace::Generic), "list_header");
+    }
+
+    /// Listの末尾に要素を追加する
+    fn create_list_push(&self, list: PointerValue<'ctx>, value: &BasicValueEnum<'ctx>) {
+        let list_push = self.get_or_create_list_push();
+        let element = self.create_entry_block_alloca(value.get_type(), "element");
+        self.builder.build_store(element, *value);
+        let element = self.builder.build_pointer_cast(element, self.context.i8_type().ptr_type(AddressSpace::Generic), "element_bytes");
+        let element_size = value.get_type().size_of().unwrap_or_else(||panic!("A list element has no size."));
+        let list = self.create_list_header_cast(list);
+        self.builder.build_call(list_push, &[list.into(), element.into(), element_size.into()], "");
+    }
+
+    /// Listの要素を読む(添字はi32)
+    fn create_list_get(&self, list: PointerValue<'ctx>, index: IntValue<'ctx>, element_type: BasicTypeEnum<'ctx>) -> BasicValueEnum<'ctx> {
+        let list_get = self.get_or_create_list_get();
+        let index = self.builder.build_int_s_extend(index, self.context.i64_type(), "index");
+        let element_size = element_type.size_of().unwrap_or_else(||panic!("A list element has no size."));
+        let list = self.create_list_header_cast(list);
+        let element = self.builder.build_call(list_get, &[list.into(), index.into(), element_size.into()], "element_bytes")
+                        .try_as_basic_value()
+                        .left()
+                        .unwrap()
+                        .into_pointer_value();
+        let element = self.builder.build_pointer_cast(element, element_type.ptr_type(AddressSpace::Generic), "element_pointer");
+        return self.builder.build_load(element, "element");
+    }
+
+    /// Listの長さ(i32に切り詰める)
+    fn create_list_len(&self, list: PointerValue<'ctx>) -> IntValue<'ctx> {
+        let list_len = self.get_or_create_list_len();
+        let list = self.create_list_header_cast(list);
+        let len = self.builder.build_call(list_len, &[list.into()], "len").try_as_basic_value().left().unwrap().into_int_value();
+        return self.builder.build_int_truncate(len, self.context.i32_type(), "len");
+    }
+
+    /// 値が定数かどうか
+    fn is_constant(&self, value: &BasicValueEnum<'ctx>) -> bool {
+        return match value {
+            BasicValueEnum::ArrayValue(v) => v.is_const(),
+            BasicValueEnum::IntValue(v) => v.is_const(),
+            BasicValueEnum::FloatValue(v) => v.is_const(),
+            BasicValueEnum::PointerValue(v) => v.is_const(),
+            BasicValueEnum::StructValue(_) => false,
+            BasicValueEnum::VectorValue(v) => v.is_const(),
+        };
+    }
+
+    /// 構造体、タプルや配列の値を、読み出さずにllvm.memcpyでsourceからdestinationに写す
+    /// 大きさとアラインメントはモジュールのデータレイアウトから決める
+    fn create_aggregate_copy(&self, destination: PointerValue<'ctx>, source: PointerValue<'ctx>, basic_type: BasicTypeEnum<'ctx>) {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let data_layout = module.get_data_layout();
+        let target_data = TargetData::create(data_layout.as_str().to_str().unwrap_or_else(|e|panic!("Invalid data layout: {e}")));
+        let size = self.get_size_type().const_int(target_data.get_abi_size(&basic_type), false);
+        let align = target_data.get_abi_alignment(&basic_type);
+        self.builder.build_memcpy(destination, align, source, align, size).unwrap_or_else(|e|panic!("Failed to copy an aggregate: {e}"));
+    }
+}
+
+
+///matchのパターン
+#[derive(Debug)]
+pub enum Pattern{
+    ///何にでも当てはまる(_)
+    Wildcard,
+    ///Boolのリテラル
+    Bool(bool),
+    ///整数のリテラル
+    Int(i64),
+    ///整数の範囲(start..=end、両端を含む)
+    Range{
+        start: i64,
+        end: i64
+    },
+    ///どれかに当てはまる(1 | 2 | 3)。どの選択肢も同じ名前を束縛しなければならない
+    Or(Vec<Pattern>),
+    ///当てはまった値に名前をつける(x @ 1..=9)。名前だけのパターンはx @ _
+    Binding{
+        name: String,
+        pattern: Box<Pattern>
+    },
+    ///列挙型のヴァリアント(Rect(w, h))。namesはヴァリアントの値につける名前で、"_"の値は捨てる
+    Variant{
+        variant: String,
+        names: Vec<String>
+    }
+}
+
+impl Pattern{
+    /// |で並んだ選択肢(束縛は外して、入れ子のOrは平らにする)
+    #[cfg(feature = "llvm")]
+    fn alternatives(&self) -> Vec<&Pattern> {
+        return match self {
+            Pattern::Or(patterns) => patterns.iter().flat_map(|pattern| pattern.alternatives()).collect(),
+            Pattern::Binding { pattern, .. } => pattern.alternatives(),
+            _ => vec![self]
+        };
+    }
+
+    /// パターンが束縛する名前(並びは名前順)
+    fn bindings(&self) -> Result<Vec<&str>, String> {
+        let mut names = match self {
+            Pattern::Binding { name, pattern } => {
+                let mut names = pattern.bindings()?;
+                if names.contains(&name.as_str()) {
+                    return Err(format!("'{name}' is bound more than once in the pattern '{self}'."));
+                }
+                names.push(name.as_str());
+                names
+            },
+            Pattern::Variant { names, .. } => {
+                let mut bound: Vec<&str> = vec![];
+                for name in names.iter().filter(|name| name.as_str() != "_") {
+                    if bound.contains(&name.as_str()) {
+                        return Err(format!("'{name}' is bound more than once in the pattern '{self}'."));
+                    }
+                    bound.push(name.as_str());
+                }
+                bound
+            },
+            Pattern::Or(patterns) => {
+                let mut alternatives = patterns.iter().map(|pattern| pattern.bindings());
+                let first = alternatives.next().unwrap_or(Ok(vec![]))?;
+                for names in alternatives {
+                    if names? != first {
+                        return Err(format!("All alternatives of the pattern '{self}' must bind the same names."));
+                    }
+                }
+                first
+            },
+            _ => vec![]
+        };
+        names.sort();
+        return Ok(names);
+    }
+
+    /// パターン全体がヴァリアントなら(束縛は外して)そのヴァリアント
+    fn variant(&self) -> Option<(&str, &Vec<String>)> {
+        return match self {
+            Pattern::Binding { pattern, .. } => pattern.variant(),
+            Pattern::Variant { variant, names } => Some((variant.as_str(), names)),
+            _ => None
+        };
+    }
+}
+
+impl std::fmt::Display for Pattern{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Bool(boolean) => write!(f, "{boolean}"),
+            Pattern::Int(number) => write!(f, "{number}"),
+            Pattern::Range { start, end } => write!(f, "{start}..={end}"),
+            Pattern::Or(patterns) => write!(f, "{}", patterns.iter().map(|pattern| pattern.to_string()).collect::<Vec<String>>().join(" | ")),
+            Pattern::Binding { name, pattern } => write!(f, "{name} @ {pattern}"),
+            Pattern::Variant { variant, names } if names.is_empty() => write!(f, "{variant}"),
+            Pattern::Variant { variant, names } => write!(f, "{variant}({})", names.join(", ")),
+        }
+    }
+}
+
+///式につける属性
+#[derive(Debug, Clone, PartialEq)]
+pub enum KSCAttribute{
+    ///@must_use(関数につけると戻り値を、構造体やnewtypeにつけるとその型の値を、捨てたときに警告する)
+    MustUse,
+    ///@allow(lint)(その式の中ではlintの警告を出さない。いまあるlintはunused_resultだけ)
+    Allow(String),
+    ///@repr(ksc)か@repr(c)(構造体のフィールドの並べ方。ksc(デフォルト)はパディングが小さくなるように並べ替え、cは宣言の順に並べる)
+    Repr(String)
+}
+
+impl std::fmt::Display for KSCAttribute{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            KSCAttribute::MustUse => write!(f, "@must_use"),
+            KSCAttribute::Allow(lint) => write!(f, "@allow({lint})"),
+            KSCAttribute::Repr(repr) => write!(f, "@repr({repr})"),
+        };
+    }
+}
+
+///matchの腕(パターンに当てはまり、guardがあればそれも真なら、bodyを評価する。bodyの最後の式の値が腕の値)
+#[derive(Debug)]
+pub struct MatchArm{
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: Vec<Expression>
+}
+
+///式
+#[derive(Debug)]
+pub enum Expression{
+    ///関数
+    ///type_paramsがあれば総称型の関数(fn max<T: Comparable>)。型引数の名前と、その型が実装していなければならないトレイトの組
+    ///宣言では関数を作らず、max<Point>(a, b)のように型引数を与えて呼ばれたときに作る
+    Function{
+        name: String,
+        return_type: String,
+        param_types: Vec<String>,
+        param_names: Vec<String>,
+        type_params: Vec<(String, Vec<String>)>,
+        content: Vec<Expression>
+    },
+
+    ///無名関数(本体で使われている外側の変数は、作られたときの値が環境にコピーされる)
+    ///本体の最後の式の値が戻り値になる
+    Closure{
+        return_type: String,
+        param_types: Vec<String>,
+        param_names: Vec<String>,
+        content: Vec<Expression>
+    },
+
+    ///型にメソッドをつける(impl Point { fn len(self) -> Number { ... } })
+    ///methodsは関数で、名前は型名::メソッド名になる。最初の仮引数がselfなら、値へのポインタを受け取るメソッドになる
+    ///trait_nameがあればトレイトの実装(impl Comparable for Point)で、トレイトのメソッドをすべて同じシグネチャで定義する
+    Impl{
+        typename: String,
+        trait_name: Option<String>,
+        methods: Vec<Expression>
+    },
+
+    ///トレイトの宣言(メソッドの名前とシグネチャの組)
+    ///シグネチャは関数の型で書き、実装する型をSelfと書く(compare: Function(Self, Self) -> Bool)。selfを受け取るメソッドは最初の仮引数がSelfになる
+    TraitDeclaration{
+        name: String,
+        method_names: Vec<String>,
+        method_types: Vec<String>
+    },
+
+    ///外部関数(extern "C")の宣言
+    ExternFunction{
+        name: String,
+        return_type: String,
+        param_types: Vec<String>
+    },
+
+    ///構造体の宣言(defaultsは省略されたフィールドを埋める定数)
+    ///type_paramsがあれば総称型の構造体(struct Pair<A, B>)。宣言では型を作らず、Pair<Number, i32>のように型引数を与えて使われたときに作る
+    StructDeclaration{
+        name: String,
+        type_params: Vec<String>,
+        field_names: Vec<String>,
+        field_types: Vec<String>,
+        defaults: Vec<Option<Expression>>
+    },
+
+    ///列挙型の宣言(enum Shape = Circle(Number) | Rect(Number, Number) | Empty)
+    ///variant_typesはヴァリアントごとの値の型の並び(値のないヴァリアントは空)
+    EnumDeclaration{
+        name: String,
+        variant_names: Vec<String>,
+        variant_types: Vec<Vec<String>>
+    },
+
+    ///列挙型の値を作る(Shape::Rect(2.0, 3.0))
+    EnumVariant{
+        typename: String,
+        variant: String,
+        values: Vec<Expression>
+    },
+
+    ///型の別名(type Meters = Number)。元の型とまったく同じ型として扱う
+    TypeAlias{
+        name: String,
+        target: String
+    },
+
+    ///newtypeの宣言(newtype UserId = i32)。元の型とは別の型になり、WrapとUnwrapでだけ行き来できる
+    NewTypeDeclaration{
+        name: String,
+        underlying: String
+    },
+
+    ///値をnewtypeで包む
+    Wrap{
+        typename: String,
+        value: Box<Expression>
+    },
+
+    ///newtypeから元の値を取り出す
+    Unwrap(Box<Expression>),
+
+    ///変数宣言(typenameが空か"auto"なら初期値の型になる)
+    VariableDeclaration{
+        typename: String,
+        name: String,
+        mutable: bool,
+        align: Option<u32>,
+        value: Box<Expression>
+    },
+
+    ///定数宣言(const N = 4)。初期値はコンパイル時に評価できる式で、配列の長さにも使える(Number[N])
+    ///typenameが空なら初期値の型になる
+    ConstDeclaration{
+        typename: String,
+        name: String,
+        value: Box<Expression>
+    },
+
+    ///Numberのリテラル
+    NumberLiteral(f64),
+
+    ///i32のリテラル
+    IntLiteral(i64),
+
+    ///Boolのリテラル
+    BoolLiteral(bool),
+
+    ///文字列のリテラル
+    StringLiteral(String),
+
+    ///構造体のリテラル(省略されたフィールドは宣言時のデフォルト値で埋める)
+    StructLiteral{
+        typename: String,
+        field_values: Vec<(String, Expression)>
+    },
+
+    ///配列のリテラル
+    ArrayLiteral(Vec<Expression>),
+
+    ///配列の添字による参照
+    Index{
+        array: Box<Expression>,
+        index: Box<Expression>
+    },
+
+    ///空のListを作る(List<T>())
+    ListNew(String),
+
+    ///Listの末尾に要素を追加する
+    ListPush{
+        list: Box<Expression>,
+        value: Box<Expression>
+    },
+
+    ///Listの長さ
+    ListLen(Box<Expression>),
+
+    ///フィールドが同じ別の構造体への明示的な変換
+    StructConversion{
+        value: Box<Expression>,
+        target_type: String
+    },
+
+    ///構造体のフィールドの参照。タプルでは要素の番号がフィールドの名前になる(t.0)
+    FieldAccess{
+        object: Box<Expression>,
+        field: String
+    },
+
+    ///変数の参照
+    Identifier(String),
+
+    ///変数への代入
+    Assignment{
+        target: String,
+        value: Box<Expression>
+    },
+
+    ///関数呼び出し
+    Call{
+        callee: String,
+        args: Vec<Expression>
+    },
+
+    ///式の値(関数ポインタかクロージャ)の呼び出し(ops[i](a, b))
+    CallValue{
+        callee: Box<Expression>,
+        args: Vec<Expression>
+    },
+
+    ///メソッドの呼び出し(p.len())。objectへのポインタがselfとして渡される
+    MethodCall{
+        object: Box<Expression>,
+        method: String,
+        args: Vec<Expression>
+    },
+
+    ///単項演算(符号反転と論理否定)
+    Unary{
+        op: UnaryOperator,
+        operand: Box<Expression>
+    },
+
+    ///二項演算(四則演算と比較演算)
+    Binary{
+        op: BinaryOperation,
+        lhs: Box<Expression>,
+        rhs: Box<Expression>
+    },
+
+    ///値を別の型に変換する(x as T)。整数どうし、Numberと整数、Boolから整数に変換できる
+    Cast{
+        value: Box<Expression>,
+        target_type: String
+    },
+
+    ///連鎖した比較(a < b < c)。a < b and b < cと同じで、bは1度だけ評価され、偽になったところで残りは評価しない
+    ChainedComparison{
+        operands: Vec<Expression>,
+        predicates: Vec<Predicate>
+    },
+
+    ///ビット列を別の型として読み替える(bitcast<T>(x))
+    BitCast{
+        value: Box<Expression>,
+        target_type: String
+    },
+
+    ///unsafeブロック(生ポインタの操作はこの中でのみ許される)
+    Unsafe(Vec<Expression>),
+
+    ///変数を指す生ポインタを得る
+    AddressOf(String),
+
+    ///生ポインタをずらす
+    PointerOffset{
+        pointer: Box<Expression>,
+        offset: Box<Expression>
+    },
+
+    ///生ポインタの指す先を読む
+    PointerLoad(Box<Expression>),
+
+    ///生ポインタの指す先に書き込む
+    PointerStore{
+        pointer: Box<Expression>,
+        value: Box<Expression>
+    },
+
+    ///値をヒープにコピーして、それを指す生ポインタを得る(new x)。関数から返しても指す先は残る
+    New(Box<Expression>),
+
+    ///newで確保した生ポインタを解放する(delete p)
+    Delete(Box<Expression>),
+
+    ///関数から値を返す(値がなければVoidを返す)
+    Return(Option<Box<Expression>>),
+
+    ///タプルのリテラル((a, b))
+    TupleLiteral(Vec<Expression>),
+
+    ///タプルを分解して変数を宣言する(let (q, r) = divmod(x, y))。名前が"_"の要素は捨てる
+    TupleDestructure{
+        names: Vec<String>,
+        mutable: bool,
+        value: Box<Expression>
+    },
+
+    ///値のないOptional(none<T>)。Tは中身の型
+    NoneLiteral(String),
+
+    ///値のあるOptional(some(x))
+    SomeValue(Box<Expression>),
+
+    ///条件分岐(if c { .. } else { .. })。文としても式としても使える
+    ///両方の枝の最後の式が値を持ち、型が同じなら、それが式の値になる。otherwiseが空のときや、どちらかの枝の値がVoidのときは値はVoid
+    If{
+        condition: Box<Expression>,
+        then: Vec<Expression>,
+        otherwise: Vec<Expression>
+    },
+
+    ///Optionalに値があれば、それにnameという名前をつけてthenを、なければotherwiseを評価する(if let x = o { .. } else { .. })
+    ///otherwiseが空なら値はVoid。そうでなければ両方の最後の式の型が同じでなければならず、それが式の値になる
+    IfLet{
+        name: String,
+        value: Box<Expression>,
+        then: Vec<Expression>,
+        otherwise: Vec<Expression>
+    },
+
+    ///パターンマッチ(Bool、Intか列挙型の値を、上の腕から順にパターンと照らし合わせる)
+    ///パターンがすべての値を網羅していないときや、前の腕で網羅済みで決して選ばれない腕があるときはエラー(ガードのある腕は網羅に数えない)
+    Match{
+        scrutinee: Box<Expression>,
+        arms: Vec<MatchArm>
+    },
+
+    ///別のファイルのモジュールを使う(import "foo.ksc")。パスはこのファイルのディレクトリからの相対パス
+    ///相手のモジュールは先にコンパイルしておき、そのインターフェース(foo.ksci)から型と関数を読み込む
+    Import(String),
+
+    ///条件が成り立つあいだ本体を繰り返す(while c { .. })。値はVoid
+    While{
+        condition: Box<Expression>,
+        content: Vec<Expression>
+    },
+
+    ///いちばん内側のループを抜ける
+    Break,
+
+    ///いちばん内側のループの条件の評価に戻る
+    Continue,
+
+    ///属性のついた式(@must_use fn ...や@allow(unused_result) f())。中の式と同じ意味
+    Attributed{
+        attributes: Vec<KSCAttribute>,
+        expression: Box<Expression>
+    },
+
+    ///ソースコード上の位置のついた式(パーサーが式を包む)。中の式と同じ意味で、エラーやデバッグ情報に位置を与える
+    Spanned{
+        span: Span,
+        expression: Box<Expression>
+    }
+}
+
+impl Expression{
+    /// 値を作るだけの式か(文として書いて値を捨てると何もしないのと同じ)
+    /// 部分式の中の呼び出しなどは見ない
+    #[cfg(feature = "llvm")]
+    fn is_pure_value(&self) -> bool {
+        return matches!(self.unspanned(),
+            Expression::NumberLiteral(_)
+            | Expression::IntLiteral(_)
+            | Expression::BoolLiteral(_)
+            | Expression::StringLiteral(_)
+            | Expression::Identifier(_)
+            | Expression::AddressOf(_)
+            | Expression::StructLiteral { .. }
+            | Expression::FieldAccess { .. }
+            | Expression::StructConversion { .. }
+            | Expression::ArrayLiteral(_)
+            | Expression::TupleLiteral(_)
+            | Expression::Index { .. }
+            | Expression::ListNew(_)
+            | Expression::ListLen(_)
+            | Expression::Wrap { .. }
+            | Expression::Unwrap(_)
+            | Expression::NoneLiteral(_)
+            | Expression::SomeValue(_)
+            | Expression::EnumVariant { .. }
+            | Expression::Closure { .. }
+            | Expression::Unary { .. }
+            | Expression::Binary { op: BinaryOperation::Arithmetic(_) | BinaryOperation::Comparison(_), .. }
+            | Expression::ChainedComparison { .. }
+            | Expression::Cast { .. }
+            | Expression::BitCast { .. }
+        );
+    }
+
+    /// 位置と属性の包みを外した式
+    fn unspanned(&self) -> &Expression {
+        return match self {
+            Expression::Spanned { expression, .. } | Expression::Attributed { expression, .. } => expression.unspanned(),
+            _ => self
+        };
+    }
+
+    /// 包みについている属性(外側から順に)
+    #[cfg(feature = "llvm")]
+    fn attributes(&self) -> Vec<&KSCAttribute> {
+        return match self {
+            Expression::Spanned { expression, .. } => expression.attributes(),
+            Expression::Attributed { attributes, expression } => attributes.iter().chain(expression.attributes()).collect(),
+            _ => vec![]
+        };
+    }
+
+    /// 式の種類の名前
+    fn kind_name(&self) -> &'static str {
+        return match self {
+            Expression::Function { .. } => "Function",
+            Expression::Closure { .. } => "Closure",
+            Expression::Impl { .. } => "Impl",
+            Expression::TraitDeclaration { .. } => "TraitDeclaration",
+            Expression::ExternFunction { .. } => "ExternFunction",
+            Expression::StructDeclaration { .. } => "StructDeclaration",
+            Expression::EnumDeclaration { .. } => "EnumDeclaration",
+            Expression::EnumVariant { .. } => "EnumVariant",
+            Expression::TypeAlias { .. } => "TypeAlias",
+            Expression::NewTypeDeclaration { .. } => "NewTypeDeclaration",
+            Expression::Wrap { .. } => "Wrap",
+            Expression::Unwrap(_) => "Unwrap",
+            Expression::VariableDeclaration { .. } => "VariableDeclaration",
+            Expression::ConstDeclaration { .. } => "ConstDeclaration",
+            Expression::NumberLiteral(_) => "NumberLiteral",
+            Expression::IntLiteral(_) => "IntLiteral",
+            Expression::BoolLiteral(_) => "BoolLiteral",
+            Expression::StringLiteral(_) => "StringLiteral",
+            Expression::StructLiteral { .. } => "StructLiteral",
+            Expression::FieldAccess { .. } => "FieldAccess",
+            Expression::StructConversion { .. } => "StructConversion",
+            Expression::ArrayLiteral(_) => "ArrayLiteral",
+            Expression::Index { .. } => "Index",
+            Expression::ListNew(_) => "ListNew",
+            Expression::ListPush { .. } => "ListPush",
+            Expression::ListLen(_) => "ListLen",
+            Expression::Identifier(_) => "Identifier",
+            Expression::Assignment { .. } => "Assignment",
+            Expression::Call { .. } => "Call",
+            Expression::CallValue { .. } => "CallValue",
+            Expression::MethodCall { .. } => "MethodCall",
+            Expression::Unary { .. } => "Unary",
+            Expression::Binary { .. } => "Binary",
+            Expression::ChainedComparison { .. } => "ChainedComparison",
+            Expression::Cast { .. } => "Cast",
+            Expression::BitCast { .. } => "BitCast",
+            Expression::Unsafe(_) => "Unsafe",
+            Expression::AddressOf(_) => "AddressOf",
+            Expression::PointerOffset { .. } => "PointerOffset",
+            Expression::PointerLoad(_) => "PointerLoad",
+            Expression::PointerStore { .. } => "PointerStore",
+            Expression::New(_) => "New",
+            Expression::Delete(_) => "Delete",
+            Expression::Return(_) => "Return",
+            Expression::TupleLiteral(_) => "TupleLiteral",
+            Expression::TupleDestructure { .. } => "TupleDestructure",
+            Expression::NoneLiteral(_) => "NoneLiteral",
+            Expression::SomeValue(_) => "SomeValue",
+            Expression::If { .. } => "If",
+            Expression::IfLet { .. } => "IfLet",
+            Expression::Match { .. } => "Match",
+            Expression::While { .. } => "While",
+            Expression::Import(_) => "Import",
+            Expression::Break => "Break",
+            Expression::Continue => "Continue",
+            Expression::Spanned { expression, .. } | Expression::Attributed { expression, .. } => expression.kind_name(),
+        };
+    }
+
+    /// この式とその子で名前を使われている変数(クロージャが捕まえる変数を探すのに使う)
+    /// 深い入れ子でもスタックが溢れないように、再帰せずに木をたどる
+    #[cfg(feature = "llvm")]
+    fn referenced_variables(&self) -> Vec<&str> {
+        let mut names = vec![];
+        let mut pending = vec![self];
+        while let Some(expression) = pending.pop() {
+            match expression.unspanned() {
+                Expression::Identifier(name) | Expression::AddressOf(name) => names.push(name.as_str()),
+                Expression::Assignment { target, .. } => names.push(target.as_str()),
+                Expression::Call { callee, .. } => names.push(callee.as_str()),
+                _ => {}
+            }
+            pending.extend(expression.unspanned().children().into_iter().rev());
+        }
+        return names;
+    }
+
+    /// 入れ子の深さがlimitを超えるかどうか(再帰せずに調べるので、どれだけ深くてもスタックは溢れない)
+    fn is_nested_deeper_than(&self, limit: usize) -> bool {
+        let mut pending = vec![(self, 1)];
+        while let Some((expression, depth)) = pending.pop() {
+            if depth > limit {
+                return true;
+            }
+            pending.extend(expression.children().into_iter().map(|child| (child, depth + 1)));
+        }
+        return false;
+    }
+
+    /// リテラルと定数だけからなる式をコンパイル時に評価する(評価できなければNone)
+    /// 実行時と同じ結果になるように、整数はi32として計算し、あふれたらoverflowに従う(Wrapなら折り返し、Trapなら評価しない)
+    /// 0での除算や幅以上のシフトは評価しない。constantは識別子が定数ならその値を返す
+    #[cfg(feature = "llvm")]
+    fn evaluate_constant(&self, constant: &dyn Fn(&str) -> Option<ConstantValue>, overflow: OverflowMode) -> Option<ConstantValue> {
+        return self.evaluate_constant_within(constant, overflow, DEFAULT_MAX_EXPRESSION_DEPTH);
+    }
+
+    /// evaluate_constantの本体。depthは残りの入れ子の深さで、尽きたら評価しない(再帰でスタックが溢れないように)
+    #[cfg(feature = "llvm")]
+    fn evaluate_constant_within(&self, constant: &dyn Fn(&str) -> Option<ConstantValue>, overflow: OverflowMode, depth: usize) -> Option<ConstantValue> {
+        if depth == 0 {
+            return None;
+        }
+        return match self.unspanned() {
+            Expression::NumberLiteral(number) => Some(ConstantValue::Number(*number)),
+            Expression::IntLiteral(number) => i32::try_from(*number).ok().map(ConstantValue::Int),
+            Expression::BoolLiteral(value) => Some(ConstantValue::Bool(*value)),
+            Expression::Identifier(name) => constant(name),
+            Expression::Unary { op, operand } => match (op, operand.evaluate_constant_within(constant, overflow, depth - 1)?) {
+                (UnaryOperator::NEG, ConstantValue::Number(value)) => Some(ConstantValue::Number(-value)),
+                (UnaryOperator::NEG, ConstantValue::Int(value)) if overflow == OverflowMode::Trap => value.checked_neg().map(ConstantValue::Int),
+                (UnaryOperator::NEG, ConstantValue::Int(value)) => Some(ConstantValue::Int(value.wrapping_neg())),
+                (UnaryOperator::NOT, ConstantValue::Bool(value)) => Some(ConstantValue::Bool(!value)),
+                _ => None
+            },
+            Expression::Binary { op, lhs, rhs } => {
+                let left = lhs.evaluate_constant_within(constant, overflow, depth - 1)?;
+                let right = rhs.evaluate_constant_within(constant, overflow, depth - 1)?;
+                match op {
+                    BinaryOperation::Arithmetic(op) => Self::evaluate_arithmetic(*op, left, right, overflow),
+                    BinaryOperation::Comparison(predicate) => Self::evaluate_comparison(*predicate, left, right),
+                    BinaryOperation::Logical(op) => match (op, left, right) {
+                        (LogicalOperator::AND, ConstantValue::Bool(left), ConstantValue::Bool(right)) => Some(ConstantValue::Bool(left && right)),
+                        (LogicalOperator::OR, ConstantValue::Bool(left), ConstantValue::Bool(right)) => Some(ConstantValue::Bool(left || right)),
+                        _ => None
+                    }
+                }
+            },
+            _ => None
+        };
+    }
+
+    #[cfg(feature = "llvm")]
+    fn evaluate_arithmetic(op: BinaryOperator, left: ConstantValue, right: ConstantValue, overflow: OverflowMode) -> Option<ConstantValue> {
+        return match (left, right) {
+            (ConstantValue::Number(left), ConstantValue::Number(right)) => match op {
+                BinaryOperator::ADD => Some(ConstantValue::Number(left + right)),
+                BinaryOperator::SUB => Some(ConstantValue::Number(left - right)),
+                BinaryOperator::MUL => Some(ConstantValue::Number(left * right)),
+                BinaryOperator::DIV => Some(ConstantValue::Number(left / right)),
+                BinaryOperator::REM => Some(ConstantValue::Number(left % right)),
+                _ => None
+            },
+            (ConstantValue::Int(left), ConstantValue::Int(right)) => {
+                let shift = u32::try_from(right).ok().filter(|shift| *shift < i32::BITS);
+                let trap = overflow == OverflowMode::Trap;
+                match op {
+                    BinaryOperator::ADD if trap => left.checked_add(right),
+                    BinaryOperator::SUB if trap => left.checked_sub(right),
+                    BinaryOperator::MUL if trap => left.checked_mul(right),
+                    BinaryOperator::ADD => Some(left.wrapping_add(right)),
+                    BinaryOperator::SUB => Some(left.wrapping_sub(right)),
+                    BinaryOperator::MUL => Some(left.wrapping_mul(right)),
+                    BinaryOperator::DIV => left.checked_div(right),
+                    BinaryOperator::REM => left.checked_rem(right),
+                    BinaryOperator::AND => Some(left & right),
+                    BinaryOperator::OR => Some(left | right),
+                    BinaryOperator::XOR => Some(left ^ right),
+                    BinaryOperator::SHL => shift.map(|shift| left << shift),
+                    BinaryOperator::ASHR => shift.map(|shift| left >> shift),
+                    BinaryOperator::LSHR => shift.map(|shift| ((left as u32) >> shift) as i32),
+                }.map(ConstantValue::Int)
+            },
+            _ => None
+        };
+    }
+
+    #[cfg(feature = "llvm")]
+    fn evaluate_comparison(predicate: Predicate, left: ConstantValue, right: ConstantValue) -> Option<ConstantValue> {
+        let ordering = match (left, right) {
+            (ConstantValue::Number(left), ConstantValue::Number(right)) => left.partial_cmp(&right),
+            (ConstantValue::Int(left), ConstantValue::Int(right)) => Some(left.cmp(&right)),
+            (ConstantValue::Bool(left), ConstantValue::Bool(right)) => Some(left.cmp(&right)),
+            _ => return None
+        };
+        // NaNとの比較は!=だけが真になる
+        let result = match (predicate, ordering) {
+            (Predicate::NOT_EQUAL, None) => true,
+            (_, None) => false,
+            (Predicate::EQUAL, Some(ordering)) => ordering.is_eq(),
+            (Predicate::NOT_EQUAL, Some(ordering)) => ordering.is_ne(),
+            (Predicate::GREATER_THAN, Some(ordering)) => ordering.is_gt(),
+            (Predicate::GREATER_THAN_OR_EQUAL, Some(ordering)) => ordering.is_ge(),
+            (Predicate::LESS_THAN, Some(ordering)) => ordering.is_lt(),
+            (Predicate::LESS_THAN_OR_EQUAL, Some(ordering)) => ordering.is_le(),
+        };
+        return Some(ConstantValue::Bool(result));
+    }
+
+    /// 直下の子の式(ASTを辿るときに使う)
+    fn children(&self) -> Vec<&Expression> {
+        return match self {
+            Expression::Spanned { expression, .. } | Expression::Attributed { expression, .. } => expression.children(),
+            Expression::Function { content, .. } => content.iter().collect(),
+            Expression::Closure { content, .. } => content.iter().collect(),
+            Expression::Impl { methods, .. } => methods.iter().collect(),
+            Expression::StructDeclaration { defaults, .. } => defaults.iter().flatten().collect(),
+            Expression::VariableDeclaration { value, .. } => vec![value.as_ref()],
+            Expression::ConstDeclaration { value, .. } => vec![value.as_ref()],
+            Expression::Wrap { value, .. } => vec![value.as_ref()],
+            Expression::Unwrap(value) => vec![value.as_ref()],
+            Expression::StructLiteral { field_values, .. } => field_values.iter().map(|(_, value)| value).collect(),
+            Expression::EnumVariant { values, .. } => values.iter().collect(),
+            Expression::FieldAccess { object, .. } => vec![object.as_ref()],
+            Expression::StructConversion { value, .. } => vec![value.as_ref()],
+            Expression::ArrayLiteral(elements) => elements.iter().collect(),
+            Expression::Index { array, index } => vec![array.as_ref(), index.as_ref()],
+            Expression::ListPush { list, value } => vec![list.as_ref(), value.as_ref()],
+            Expression::ListLen(list) => vec![list.as_ref()],
+            Expression::Assignment { value, .. } => vec![value.as_ref()],
+            Expression::Call { args, .. } => args.iter().collect(),
+            Expression::CallValue { callee, args } => std::iter::once(callee.as_ref()).chain(args.iter()).collect(),
+            Expression::MethodCall { object, args, .. } => std::iter::once(object.as_ref()).chain(args.iter()).collect(),
+            Expression::Unary { operand, .. } => vec![operand.as_ref()],
+            Expression::Binary { lhs, rhs, .. } => vec![lhs.as_ref(), rhs.as_ref()],
+            Expression::ChainedComparison { operands, .. } => operands.iter().collect(),
+            Expression::Cast { value, .. } => vec![value.as_ref()],
+            Expression::BitCast { value, .. } => vec![value.as_ref()],
+            Expression::Unsafe(content) => content.iter().collect(),
+            Expression::PointerOffset { pointer, offset } => vec![pointer.as_ref(), offset.as_ref()],
+            Expression::PointerLoad(pointer) => vec![pointer.as_ref()],
+            Expression::PointerStore { pointer, value } => vec![pointer.as_ref(), value.as_ref()],
+            Expression::New(value) => vec![value.as_ref()],
+            Expression::Delete(pointer) => vec![pointer.as_ref()],
+            Expression::Return(value) => value.iter().map(|value| value.as_ref()).collect(),
+            Expression::TupleLiteral(elements) => elements.iter().collect(),
+            Expression::TupleDestructure { value, .. } => vec![value.as_ref()],
+            Expression::SomeValue(value) => vec![value.as_ref()],
+            Expression::If { condition, then, otherwise } => std::iter::once(condition.as_ref()).chain(then.iter()).chain(otherwise.iter()).collect(),
+            Expression::IfLet { value, then, otherwise, .. } => std::iter::once(value.as_ref()).chain(then.iter()).chain(otherwise.iter()).collect(),
+            Expression::While { condition, content } => std::iter::once(condition.as_ref()).chain(content.iter()).collect(),
+            Expression::Match { scrutinee, arms } => {
+                let mut children = vec![scrutinee.as_ref()];
+                children.extend(arms.iter().flat_map(|arm| arm.guard.iter().chain(arm.body.iter())));
+                children
+            },
+            Expression::ExternFunction { .. }
+            | Expression::TraitDeclaration { .. }
+            | Expression::EnumDeclaration { .. }
+            | Expression::TypeAlias { .. }
+            | Expression::NewTypeDeclaration { .. }
+            | Expression::NumberLiteral(_)
+            | Expression::IntLiteral(_)
+            | Expression::BoolLiteral(_)
+            | Expression::StringLiteral(_)
+            | Expression::Identifier(_)
+            | Expression::AddressOf(_)
+            | Expression::ListNew(_)
+            | Expression::NoneLiteral(_)
+            | Expression::Import(_)
+            | Expression::Break
+            | Expression::Continue => vec![],
+        };
+    }
+
+    /// 式の木を、種類の名前を字下げして並べた文字列にする(--stop-after parseで出力する)
+    /// 入れ子が深すぎるところは...にして、それより下は書かない
+    #[cfg(feature = "llvm")]
+    fn outline(&self, depth: usize) -> String {
+        if depth >= DEFAULT_MAX_EXPRESSION_DEPTH {
+            return format!("{}...\n", "  ".repeat(depth));
+        }
+        let mut outline = format!("{}{}\n", "  ".repeat(depth), self.kind_name());
+        for child in self.children() {
+            outline.push_str(&child.outline(depth + 1));
+        }
+        return outline;
+    }
+
+    /// 式をKSCのソースコードの形にする(--dump-astで出力する)。indentはブロックの字下げの深さ
+    /// typesがあれば、ブロックの中の文の後ろにその型を// コメントとして書く(キーはSpannedを外した式のアドレス)
+    fn pretty(&self, indent: usize, types: &std::collections::HashMap<*const Expression, String>) -> String {
+        let list = |expressions: &Vec<Expression>| expressions.iter().map(|expression| expression.pretty(indent, types)).collect::<Vec<String>>().join(", ");
+        let block = |content: &Vec<Expression>| Expression::pretty_block(content, indent, types);
+        // 二項演算の中の二項演算は括弧で囲み、どう結びついたかを見えるようにする
+        let operand = |expression: &Expression| match expression.unspanned() {
+            Expression::Binary { .. } | Expression::ChainedComparison { .. } | Expression::Cast { .. } => format!("({})", expression.pretty(indent, types)),
+            _ => expression.pretty(indent, types)
+        };
+        let parameters = |names: &Vec<String>, typenames: &Vec<String>| names.iter().zip(typenames).map(|(name, typename)| format!("{name}: {typename}")).collect::<Vec<String>>().join(", ");
+        return match self {
+            Expression::Function { name, return_type, param_types, param_names, type_params, content } => {
+                let generics = if type_params.is_empty() {
+                    String::new()
+                } else {
+                    let params = type_params.iter().map(|(param, bounds)| if bounds.is_empty() { param.clone() } else { format!("{param}: {}", bounds.join(" + ")) });
+                    format!("<{}>", params.collect::<Vec<String>>().join(", "))
+                };
+                format!("fn {name}{generics}({}) -> {return_type} {}", parameters(param_names, param_types), block(content))
+            },
+            Expression::Closure { return_type, param_types, param_names, content } => format!("|{}| -> {return_type} {}", parameters(param_names, param_types), block(content)),
+            Expression::Impl { typename, trait_name, methods } => match trait_name {
+                Some(trait_name) => format!("impl {trait_name} for {typename} {}", block(methods)),
+                None => format!("impl {typename} {}", block(methods)),
+            },
+            Expression::TraitDeclaration { name, method_names, method_types } => {
+                let methods = method_names.iter().zip(method_types).map(|(method, typename)| format!("{}{method}: {typename}\n", "    ".repeat(indent + 1)));
+                format!("trait {name} {{\n{}{}}}", methods.collect::<String>(), "    ".repeat(indent))
+            },
+            Expression::ExternFunction { name, return_type, param_types } => format!("extern \"C\" fn {name}({}) -> {return_type}", param_types.join(", ")),
+            Expression::StructDeclaration { name, type_params, field_names, field_types, defaults } => {
+                let generics = if type_params.is_empty() { String::new() } else { format!("<{}>", type_params.join(", ")) };
+                let fields = field_names.iter().zip(field_types).zip(defaults).map(|((field, typename), default)| match default {
+                    Some(default) => format!("{}{field}: {typename} = {},\n", "    ".repeat(indent + 1), default.pretty(indent + 1, types)),
+                    None => format!("{}{field}: {typename},\n", "    ".repeat(indent + 1)),
+                });
+                format!("struct {name}{generics} {{\n{}{}}}", fields.collect::<String>(), "    ".repeat(indent))
+            },
+            Expression::EnumDeclaration { name, variant_names, variant_types } => {
+                let variants = variant_names.iter().zip(variant_types).map(|(variant, typenames)| if typenames.is_empty() { variant.clone() } else { format!("{variant}({})", typenames.join(", ")) });
+                format!("enum {name} = {}", variants.collect::<Vec<String>>().join(" | "))
+            },
+            Expression::EnumVariant { typename, variant, values } if values.is_empty() => format!("{typename}::{variant}"),
+            Expression::EnumVariant { typename, variant, values } => format!("{typename}::{variant}({})", list(values)),
+            Expression::TypeAlias { name, target } => format!("type {name} = {target}"),
+            Expression::NewTypeDeclaration { name, underlying } => format!("newtype {name} = {underlying}"),
+            Expression::Wrap { typename, value } => format!("wrap<{typename}>({})", value.pretty(indent, types)),
+            Expression::Unwrap(value) => format!("unwrap({})", value.pretty(indent, types)),
+            Expression::VariableDeclaration { typename, name, mutable, align, value } => {
+                let mut declaration = if *mutable { format!("let mut {name}") } else { format!("let {name}") };
+                if !typename.is_empty() && typename != "auto" {
+                    declaration += &format!(": {typename}");
+                }
+                if let Some(align) = align {
+                    declaration += &format!(" align({align})");
+                }
+                format!("{declaration} = {}", value.pretty(indent, types))
+            },
+            Expression::ConstDeclaration { typename, name, value } if typename.is_empty() => format!("const {name} = {}", value.pretty(indent, types)),
+            Expression::ConstDeclaration { typename, name, value } => format!("const {name}: {typename} = {}", value.pretty(indent, types)),
+            Expression::NumberLiteral(number) => format!("{number:?}"),
+            Expression::IntLiteral(number) => format!("{number}"),
+            Expression::BoolLiteral(boolean) => format!("{boolean}"),
+            Expression::StringLiteral(string) => format!("{string:?}"),
+            Expression::StructLiteral { typename, field_values } => {
+                let fields = field_values.iter().map(|(field, value)| format!("{field}: {}", value.pretty(indent, types)));
+                format!("{typename} {{ {} }}", fields.collect::<Vec<String>>().join(", "))
+            },
+            Expression::ArrayLiteral(elements) => format!("[{}]", list(elements)),
+            Expression::Index { array, index } => format!("{}[{}]", operand(array), index.pretty(indent, types)),
+            Expression::ListNew(element) => format!("List<{element}>()"),
+            Expression::ListPush { list, value } => format!("{}.push({})", operand(list), value.pretty(indent, types)),
+            Expression::ListLen(list) => format!("{}.len()", operand(list)),
+            Expression::StructConversion { value, target_type } => format!("convert<{target_type}>({})", value.pretty(indent, types)),
+            Expression::FieldAccess { object, field } => format!("{}.{field}", operand(object)),
+            Expression::Identifier(name) => name.clone(),
+            Expression::Assignment { target, value } => format!("{target} = {}", value.pretty(indent, types)),
+            Expression::Call { callee, args } => format!("{callee}({})", list(args)),
+            Expression::CallValue { callee, args } => format!("{}({})", operand(callee), list(args)),
+            Expression::MethodCall { object, method, args } => format!("{}.{method}({})", operand(object), list(args)),
+            Expression::Unary { op, operand: value } => format!("{op}{}", operand(value)),
+            Expression::Binary { op, lhs, rhs } => format!("{} {op} {}", operand(lhs), operand(rhs)),
+            Expression::Cast { value, target_type } => format!("{} as {target_type}", operand(value)),
+            Expression::ChainedComparison { operands, predicates } => {
+                let mut chain = operands.first().map(&operand).unwrap_or_default();
+                for (predicate, next) in predicates.iter().zip(operands.iter().skip(1)) {
+                    chain += &format!(" {predicate} {}", operand(next));
+                }
+                chain
+            },
+            Expression::BitCast { value, target_type } => format!("bitcast<{target_type}>({})", value.pretty(indent, types)),
+            Expression::Unsafe(content) => format!("unsafe {}", block(content)),
+            Expression::AddressOf(name) => format!("&{name}"),
+            Expression::PointerOffset { pointer, offset } => format!("{}.offset({})", operand(pointer), offset.pretty(indent, types)),
+            Expression::PointerLoad(pointer) => format!("*{}", operand(pointer)),
+            Expression::PointerStore { pointer, value } => format!("*{} = {}", operand(pointer), value.pretty(indent, types)),
+            Expression::New(value) => format!("new {}", operand(value)),
+            Expression::Delete(pointer) => format!("delete {}", operand(pointer)),
+            Expression::Return(Some(value)) => format!("return {}", value.pretty(indent, types)),
+            Expression::Return(None) => "return".to_string(),
+            Expression::TupleLiteral(elements) => format!("({})", list(elements)),
+            Expression::TupleDestructure { names, mutable, value } => format!("let {}({}) = {}", if *mutable { "mut " } else { "" }, names.join(", "), value.pretty(indent, types)),
+            Expression::NoneLiteral(inner) => format!("none<{inner}>"),
+            Expression::SomeValue(value) => format!("some({})", value.pretty(indent, types)),
+            Expression::If { condition, then, otherwise } if otherwise.is_empty() => format!("if {} {}", condition.pretty(indent, types), block(then)),
+            Expression::If { condition, then, otherwise } => format!("if {} {} else {}", condition.pretty(indent, types), block(then), block(otherwise)),
+            Expression::IfLet { name, value, then, otherwise } if otherwise.is_empty() => format!("if let {name} = {} {}", value.pretty(indent, types), block(then)),
+            Expression::IfLet { name, value, then, otherwise } => format!("if let {name} = {} {} else {}", value.pretty(indent, types), block(then), block(otherwise)),
+            Expression::Match { scrutinee, arms } => {
+                let arms = arms.iter().map(|arm| {
+                    let guard = arm.guard.as_ref().map(|guard| format!(" if {}", guard.pretty(indent + 1, types))).unwrap_or_default();
+                    format!("{}{}{guard} => {}\n", "    ".repeat(indent + 1), arm.pattern, Expression::pretty_block(&arm.body, indent + 1, types))
+                });
+                format!("match {} {{\n{}{}}}", scrutinee.pretty(indent, types), arms.collect::<String>(), "    ".repeat(indent))
+            },
+            Expression::While { condition, content } => format!("while {} {}", condition.pretty(indent, types), block(content)),
+            Expression::Import(path) => format!("import {path:?}"),
+            Expression::Break => "break".to_string(),
+            Expression::Continue => "continue".to_string(),
+            Expression::Attributed { attributes, expression } => {
+                let attributes = attributes.iter().map(|attribute| format!("{attribute} "));
+                format!("{}{}", attributes.collect::<String>(), expression.pretty(indent, types))
+            },
+            Expression::Spanned { expression, .. } => expression.pretty(indent, types),
+        };
+    }
+
+    /// { }で囲んだ文の並び(文ごとに1行で、型がわかっていれば行末に書く)
+    fn pretty_block(content: &Vec<Expression>, indent: usize, types: &std::collections::HashMap<*const Expression, String>) -> String {
+        if content.is_empty() {
+            return "{}".to_string();
+        }
+        let mut block = "{\n".to_string();
+        for statement in content {
+            block += &format!("{}{}", "    ".repeat(indent + 1), statement.pretty(indent + 1, types));
+            if let Some(valuetype) = types.get(&(statement.unspanned() as *const Expression)) {
+                block += &format!(" // {valuetype}");
+            }
+            block += "\n";
+        }
+        return block + &"    ".repeat(indent) + "}";
+    }
+}
+
+impl std::fmt::Display for Expression{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // prettyは再帰で木をたどるので、深すぎる式はソースコードの形にしない
+        if self.is_nested_deeper_than(DEFAULT_MAX_EXPRESSION_DEPTH) {
+            return write!(f, "/* {} nested more than {DEFAULT_MAX_EXPRESSION_DEPTH} levels deep */", self.kind_name());
+        }
+        return write!(f, "{}", self.pretty(0, &std::collections::HashMap::new()));
+    }
+}
+
+/// 名前解決(LLVMを使わずに、定義されていない変数や関数を使っている箇所をすべて集める)
+/// スコープの決まりはコード生成と同じ。関数の本体、クロージャ、unsafeブロック、matchの腕が新しいスコープになる
+struct NameResolver<'p>{
+    scopes: Vec<Vec<&'p str>>,
+    functions: Vec<&'p str>,
+    /// impl ブロックで定義されたメソッドの名前(型名::メソッド名)
+    methods: Vec<String>,
+    current_span: Option<Span>,
+    /// いま見ている式の入れ子の深さと、その上限(--max-depth)
+    depth: usize,
+    max_depth: usize,
+    diagnostics: DiagnosticBag
+}
+
+impl<'p> NameResolver<'p>{
+    /// プログラム全体の名前を解決して、見つかったエラーをすべて返す
+    /// importedはインターフェースから読み込んだ名前(関数として扱う)。入れ子がmax_depthより深い式もエラーにする
+    fn resolve(program: &'p Vec<Expression>, imported: &'p [String], max_depth: usize) -> DiagnosticBag {
+        let functions = imported.iter().map(|name| name.as_str()).collect();
+        let mut resolver = NameResolver{ scopes: vec![vec![]], functions, methods: vec![], current_span: None, depth: 0, max_depth, diagnostics: DiagnosticBag::new() };
+        // トップレベルの関数は先に宣言されるので、定義より前からでも呼べる
+        for expression in program {
+            let function = match expression.unspanned() {
+                Expression::VariableDeclaration { value, .. } => value.unspanned(),
+                expression => expression
+            };
+            match function {
+                Expression::Function { name, .. } => resolver.functions.push(name),
+                Expression::Impl { typename, methods, .. } => resolver.declare_methods(typename, methods),
+                _ => {}
+            }
+        }
+        for expression in program {
+            resolver.visit(expression);
+        }
+        return resolver.diagnostics;
+    }
+
+    fn is_variable(&self, name: &str) -> bool {
+        return self.scopes.iter().any(|scope| scope.contains(&name));
+    }
+
+    /// 総称型の関数は型引数を与えた名前(max<Point>)でも呼べる
+    fn is_function(&self, name: &str) -> bool {
+        let base = name.split_once('<').map_or(name, |(base, _)| base);
+        return self.functions.contains(&name) || self.functions.contains(&base) || self.methods.iter().any(|method| method == name);
+    }
+
+    fn declare_methods(&mut self, typename: &str, methods: &'p [Expression]) {
+        for method in methods {
+            if let Expression::Function { name, .. } = method.unspanned() {
+                self.methods.push(format!("{typename}::{name}"));
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &'p str) {
+        self.scopes.last_mut().unwrap_or_else(||panic!("There is no scope yet!")).push(name);
+    }
+
+    fn report(&mut self, message: String) {
+        self.diagnostics.error(CompileError{ message, span: self.current_span });
+    }
+
+    /// 新しいスコープで、namesを宣言してから式を順に見る
+    fn visit_scope(&mut self, names: Vec<&'p str>, content: impl IntoIterator<Item = &'p Expression>) {
+        self.scopes.push(names);
+        for expression in content {
+            self.visit(expression);
+        }
+        self.scopes.pop();
+    }
+
+    /// 式の名前を解決する
+    /// 再帰でスタックが溢れないように、入れ子がmax_depthより深ければエラーにしてその中は見ない(Analyzerはここで報告したものを報告しない)
+    fn visit(&mut self, expression: &'p Expression) {
+        if self.depth >= self.max_depth {
+            self.report(format!("Expressions are nested too deeply. The limit is {}.", self.max_depth));
+            return;
+        }
+        self.depth += 1;
+        self.visit_unguarded(expression);
+        self.depth -= 1;
+    }
+
+    /// 式の名前を解決する(深さの確認はvisitで行う)
+    fn visit_unguarded(&mut self, expression: &'p Expression) {
+        match expression {
+            Expression::Spanned { span, expression } => {
+                let previous_span = self.current_span.replace(*span);
+                self.visit(expression);
+                self.current_span = previous_span;
+            },
+            Expression::Attributed { expression, .. } => self.visit(expression),
+            Expression::Function { name, param_names, content, .. } => {
+                self.functions.push(name);
+                self.visit_scope(param_names.iter().map(|name| name.as_str()).collect(), content);
+            },
+            Expression::Closure { param_names, content, .. } => {
+                self.visit_scope(param_names.iter().map(|name| name.as_str()).collect(), content);
+            },
+            Expression::Impl { typename, methods, .. } => {
+                // メソッドの名前は関数として登録しない(本体の中でも型名::メソッド名で呼ぶ)
+                self.declare_methods(typename, methods);
+                for method in methods {
+                    match method.unspanned() {
+                        Expression::Function { param_names, content, .. } => self.visit_scope(param_names.iter().map(|name| name.as_str()).collect(), content),
+                        _ => self.visit(method)
+                    }
+                }
+            },
+            Expression::ExternFunction { name, .. } => self.functions.push(name),
+            Expression::VariableDeclaration { name, value, .. } | Expression::ConstDeclaration { name, value, .. } => {
+                self.visit(value);
+                self.declare(name);
+            },
+            Expression::TupleDestructure { names, value, .. } => {
+                self.visit(value);
+                for name in names.iter().filter(|name| name.as_str() != "_") {
+                    self.declare(name);
+                }
+            },
+            Expression::Identifier(name) => {
+                // 関数の名前は、その関数を指す値として使える
+                if !self.is_variable(name) && !self.is_function(name) {
+                    self.report(format!("Variable '{name}' is not defined."));
+                }
+            },
+            Expression::AddressOf(name) => {
+                if !self.is_variable(name) {
+                    self.report(format!("Variable '{name}' is not defined."));
+                }
+            },
+            Expression::Assignment { target, value } => {
+                self.visit(value);
+                if !self.is_variable(target) {
+                    self.report(format!("Variable '{target}' is not defined."));
+                }
+            },
+            Expression::Call { callee, args } => {
+                for arg in args {
+                    self.visit(arg);
+                }
+                let defined = BuiltinFunction::from_name(callee).is_some() || self.is_variable(callee) || self.is_function(callee);
+                if !defined {
+                    self.report(format!("Function '{callee}' is not defined."));
+                }
+            },
+            Expression::Unsafe(content) => self.visit_scope(vec![], content),
+            Expression::If { condition, then, otherwise } => {
+                self.visit(condition);
+                self.visit_scope(vec![], then);
+                self.visit_scope(vec![], otherwise);
+            },
+            Expression::IfLet { name, value, then, otherwise } => {
+                self.visit(value);
+                self.visit_scope(vec![name.as_str()], then);
+                self.visit_scope(vec![], otherwise);
+            },
+            Expression::While { condition, content } => {
+                self.visit(condition);
+                self.visit_scope(vec![], content);
+            },
+            Expression::Match { scrutinee, arms } => {
+                self.visit(scrutinee);
+                for arm in arms {
+                    let bindings = arm.pattern.bindings().unwrap_or_default();
+                    self.visit_scope(bindings, arm.guard.iter().chain(arm.body.iter()));
+                }
+            },
+            _ => {
+                for child in expression.children() {
+                    self.visit(child);
+                }
+            }
+        }
+    }
+}
+
+
+/// 意味解析で使う型(KSCTypeと違ってLLVMに依存しない)
+#[derive(Clone, PartialEq)]
+enum Type{
+    Number,
+    Int{
+        bits: u32,
+        signed: bool
+    },
+    Bool,
+    Void,
+    String,
+    Function{
+        return_type: Box<Type>,
+        parameter: Vec<Type>
+    },
+    Closure{
+        return_type: Box<Type>,
+        parameter: Vec<Type>
+    },
+    RawPtr(Box<Type>),
+    Array{
+        element: Box<Type>,
+        length: u32
+    },
+    List(Box<Type>),
+    Tuple(Vec<Type>),
+    Optional(Box<Type>),
+    /// 構造体とnewtype(名前で区別する)
+    Named(String),
+    /// 解析器がまだ型を決められない式の型。これが関わる検査はコード生成に任せる
+    Unknown
+}
+
+impl Type{
+    /// 診断に表示するための型の名前(KSCType::type_nameと同じ書き方)
+    fn name(&self) -> String {
+        let list = |types: &Vec<Type>| types.iter().map(|t| t.name()).collect::<Vec<String>>().join(", ");
+        return match self {
+            Type::Number => "Number".to_string(),
+            Type::Int { bits, signed } => format!("{}{bits}", if *signed { "i" } else { "u" }),
+            Type::Bool => "Bool".to_string(),
+            Type::Void => "Void".to_string(),
+            Type::String => "String".to_string(),
+            Type::Function { return_type, parameter } => format!("Function({}) -> {}", list(parameter), return_type.name()),
+            Type::Closure { return_type, parameter } => format!("Closure({}) -> {}", list(parameter), return_type.name()),
+            Type::RawPtr(pointee) => format!("RawPtr<{}>", pointee.name()),
+            Type::Array { element, length } if matches!(element.as_ref(), Type::Function { .. } | Type::Closure { .. }) => format!("({})[{length}]", element.name()),
+            Type::Array { element, length } => format!("{}[{length}]", element.name()),
+            Type::List(element) => format!("List<{}>", element.name()),
+            Type::Tuple(elements) => format!("({})", list(elements)),
+            Type::Optional(inner) => format!("Optional<{}>", inner.name()),
+            Type::Named(name) => name.to_string(),
+            Type::Unknown => "?".to_string(),
+        };
+    }
+
+    /// 同じ型として扱えるか(Unknownはどの型とも合う)
+    fn compatible(&self, other: &Type) -> bool {
+        let all = |a: &Vec<Type>, b: &Vec<Type>| a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.compatible(b));
+        return match (self, other) {
+            (Type::Unknown, _) | (_, Type::Unknown) => true,
+            (Type::Function { return_type: a, parameter: p }, Type::Function { return_type: b, parameter: q })
+            | (Type::Closure { return_type: a, parameter: p }, Type::Closure { return_type: b, parameter: q }) => a.compatible(b) && all(p, q),
+            (Type::RawPtr(a), Type::RawPtr(b)) | (Type::List(a), Type::List(b)) | (Type::Optional(a), Type::Optional(b)) => a.compatible(b),
+            (Type::Array { element: a, length: m }, Type::Array { element: b, length: n }) => m == n && a.compatible(b),
+            (Type::Tuple(a), Type::Tuple(b)) => all(a, b),
+            _ => self == other
+        };
+    }
+
+    fn is_known(&self) -> bool {
+        return *self != Type::Unknown;
+    }
+}
+
+/// 型のついた式(Analyzerが作る)。Spannedの包みは取り除いてある
+/// いまは型の誤りの報告と--dump-ast=typed、--stop-after typecheckにだけ使う。コード生成はこの木を読まず、ASTから型を求め直す
+struct TypedExpression<'p>{
+    #[cfg_attr(not(feature = "llvm"), allow(dead_code))]
+    expression: &'p Expression,
+    valuetype: Type,
+    #[cfg_attr(not(feature = "llvm"), allow(dead_code))]
+    children: Vec<TypedExpression<'p>>
+}
+
+#[cfg(feature = "llvm")]
+impl<'p> TypedExpression<'p>{
+    /// 木の中の式の型を、Spannedを外した式のアドレスごとに集める(--dump-ast=typedで文に型を書くのに使う)
+    fn collect_types(&self, types: &mut std::collections::HashMap<*const Expression, String>) {
+        types.insert(self.expression as *const Expression, self.valuetype.name());
+        for child in &self.children {
+            child.collect_types(types);
+        }
+    }
+
+    /// 式をKSCのソースコードの形にし、ブロックの中の文とトップレベルの式にその型を書く
+    fn pretty(&self) -> String {
+        if self.expression.is_nested_deeper_than(DEFAULT_MAX_EXPRESSION_DEPTH) {
+            return format!("{} // {}", self.expression, self.valuetype.name());
+        }
+        let mut types = std::collections::HashMap::new();
+        self.collect_types(&mut types);
+        return format!("{} // {}", self.expression.pretty(0, &types), self.valuetype.name());
+    }
+
+    /// 式の木を、種類と型を字下げして並べた文字列にする(--stop-after typecheckで出力する)
+    fn outline(&self, depth: usize) -> String {
+        let mut outline = format!("{}{}: {}\n", "  ".repeat(depth), self.expression.kind_name(), self.valuetype.name());
+        for child in &self.children {
+            outline.push_str(&child.outline(depth + 1));
+        }
+        return outline;
+    }
+}
+
+/// 意味解析(LLVMを使わずに式の型を決め、型の誤りをすべて集める)
+/// 見つけた誤りの式はUnknownになり、そこから先の誤りは報告しない。まだ扱えない式もUnknownにしてコード生成の検査に任せる
+/// スコープの決まりはNameResolverと同じ。名前の誤りはNameResolverが報告するので、ここでは見つからない名前をUnknownにする
+struct Analyzer<'p>{
+    variables: Vec<std::collections::HashMap<&'p str, (Type, bool)>>,
+    types: Vec<std::collections::HashMap<&'p str, Type>>,
+    functions: std::collections::HashMap<&'p str, Type>,
+    return_types: Vec<Type>,
+    current_span: Option<Span>,
+    /// いま見ている式の入れ子の深さと、その上限(--max-depth)
+    depth: usize,
+    max_depth: usize,
+    diagnostics: DiagnosticBag
+}
+
+impl<'p> Analyzer<'p>{
+    /// プログラム全体を解析して、型のついた木と見つかったエラーをすべて返す
+    /// importedはインターフェースから読み込んだ名前(型はコード生成で確かめるのでUnknownにする)
+    /// 入れ子がmax_depthより深い式はUnknownにして、その中は見ない
+    fn analyze(program: &'p Vec<Expression>, imported: &'p [String], max_depth: usize) -> (Vec<TypedExpression<'p>>, DiagnosticBag) {
+        let mut analyzer = Analyzer{
+            variables: vec![std::collections::HashMap::new()],
+            types: vec![std::collections::HashMap::new()],
+            functions: std::collections::HashMap::new(),
+            return_types: vec![],
+            current_span: None,
+            depth: 0,
+            max_depth,
+            diagnostics: DiagnosticBag::new()
+        };
+        for name in imported {
+            analyzer.functions.insert(name, Type::Unknown);
+        }
+        // トップレベルの関数は先に宣言されるので、定義より前からでも呼べる
+        for expression in program {
+            let function = match expression.unspanned() {
+                Expression::VariableDeclaration { value, .. } => value.unspanned(),
+                expression => expression
+            };
+            if let Expression::Function { name, return_type, param_types, .. } = function {
+                let functype = analyzer.function_type(return_type, param_types);
+                analyzer.functions.insert(name, functype);
+            }
+        }
+        let typed = program.iter().map(|expression| analyzer.visit(expression)).collect();
+        return (typed, analyzer.diagnostics);
+    }
+
+    fn report(&mut self, message: String) {
+        self.diagnostics.error(CompileError{ message, span: self.current_span });
+    }
+
+    fn search_variable(&self, name: &str) -> Option<&(Type, bool)> {
+        return self.variables.iter().rev().find_map(|scope| scope.get(name));
+    }
+
+    fn declare(&mut self, name: &'p str, valuetype: Type, mutable: bool) {
+        self.variables.last_mut().unwrap_or_else(||panic!("There is no scope yet!")).insert(name, (valuetype, mutable));
+    }
+
+    fn declare_type(&mut self, name: &'p str, valuetype: Type) {
+        self.types.last_mut().unwrap_or_else(||panic!("There is no scope yet!")).insert(name, valuetype);
+    }
+
+    /// 型名から型を探す(search_ksc_typeと同じ順に探す)
+    /// 書き方の誤りや見つからない型はUnknownにして、コード生成に報告させる
+    /// ただしシグネチャのないFunctionとClosure、Structはコード生成まで進めずにここで報告する
+    fn search_type(&mut self, typename: &str) -> Type {
+        return match typename {
+            "Number" => Type::Number,
+            "Bool" => Type::Bool,
+            "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64" => Type::Int{ bits: typename[1..].parse().unwrap(), signed: typename.starts_with('i') },
+            "Void" => Type::Void,
+            "String" => Type::String,
+            "Function" | "Closure" => {
+                self.report(format!("Type '{typename}' needs a signature, such as '{typename}(Number) -> Number'."));
+                Type::Unknown
+            },
+            "Struct" => {
+                self.report("Type 'Struct' is not allowed. Use the name of the struct instead.".to_string());
+                Type::Unknown
+            },
+            _ if typename.starts_with("Function(") => match self.search_signature(&typename["Function".len()..]) {
+                Some((parameter, return_type)) => Type::Function{ return_type: Box::from(return_type), parameter },
+                None => Type::Unknown
+            },
+            _ if typename.starts_with("Closure(") => match self.search_signature(&typename["Closure".len()..]) {
+                Some((parameter, return_type)) => Type::Closure{ return_type: Box::from(return_type), parameter },
+                None => Type::Unknown
+            },
+            _ if typename.ends_with(']') && typename.contains('[') => {
+                let (element, length) = typename[..typename.len() - 1].rsplit_once('[').unwrap();
+                match length.trim().parse() {
+                    Ok(length) => Type::Array{ element: Box::from(self.search_type(element)), length },
+                    Err(_) => Type::Unknown
+                }
+            },
+            _ if typename.starts_with('(') && typename.ends_with(')') && split_type_list(&typename[1..typename.len() - 1]).len() == 1 => {
+                self.search_type(&typename[1..typename.len() - 1])
+            },
+            _ if typename.starts_with('(') && typename.ends_with(')') => {
+                Type::Tuple(split_type_list(&typename[1..typename.len() - 1]).iter().map(|element| self.search_type(element)).collect())
+            },
+            _ if typename.starts_with("List<") && typename.ends_with('>') => Type::List(Box::from(self.search_type(&typename["List<".len()..typename.len() - 1]))),
+            _ if typename.starts_with("RawPtr<") && typename.ends_with('>') => Type::RawPtr(Box::from(self.search_type(&typename["RawPtr<".len()..typename.len() - 1]))),
+            _ if typename.starts_with("Optional<") && typename.ends_with('>') => Type::Optional(Box::from(self.search_type(&typename["Optional<".len()..typename.len() - 1]))),
+            // 総称型の構造体は、型引数を与えた名前(KSCType::type_nameと同じ書き方)の型になる
+            _ if typename.ends_with('>') && typename.contains('<') => {
+                let (base, args) = typename[..typename.len() - 1].split_once('<').unwrap();
+                let args = split_type_list(args).iter().map(|arg| self.search_type(arg)).collect::<Vec<Type>>();
+                match self.search_type(base) {
+                    Type::Named(base) if args.iter().all(|arg| arg.is_known()) => {
+                        Type::Named(format!("{base}<{}>", args.iter().map(|arg| arg.name()).collect::<Vec<String>>().join(", ")))
+                    },
+                    _ => Type::Unknown
+                }
+            },
+            _ => self.types.iter().rev().find_map(|scope| scope.get(typename)).cloned().unwrap_or(Type::Unknown)
+        };
+    }
+
+    /// "(Number, Number) -> Number"の形のシグネチャから引数と戻り値の型を探す
+    fn search_signature(&mut self, signature: &str) -> Option<(Vec<Type>, Type)> {
+        let (parameter, return_type) = split_signature(signature)?;
+        let parameter = split_type_list(parameter).iter().map(|p| self.search_type(p)).collect();
+        return Some((parameter, self.search_type(return_type)));
+    }
+
+    fn function_type(&mut self, return_type: &str, param_types: &[String]) -> Type {
+        return Type::Function{
+            return_type: Box::from(self.search_type(return_type)),
+            parameter: param_types.iter().map(|p| self.search_type(p)).collect()
+        };
+    }
+
+    /// 新しいスコープで、引数を宣言してから本体を順に見る(戻り値の型がSomeなら関数の本体)
+    fn visit_scope(&mut self, parameters: Vec<(&'p str, Type)>, return_type: Option<Type>, content: impl IntoIterator<Item = &'p Expression>) -> Vec<TypedExpression<'p>> {
+        self.variables.push(parameters.into_iter().map(|(name, valuetype)| (name, (valuetype, false))).collect());
+        self.types.push(std::collections::HashMap::new());
+        let is_function = return_type.is_some();
+        self.return_types.extend(return_type);
+        let typed = content.into_iter().map(|expression| self.visit(expression)).collect();
+        if is_function {
+            self.return_types.pop();
+        }
+        self.types.pop();
+        self.variables.pop();
+        return typed;
+    }
+
+    /// 関数やクロージャの本体を見る
+    fn visit_function(&mut self, functype: &Type, param_names: &'p [String], content: &'p [Expression]) -> Vec<TypedExpression<'p>> {
+        let (return_type, parameter) = match functype {
+            Type::Function { return_type, parameter } | Type::Closure { return_type, parameter } => (return_type.as_ref().clone(), parameter.clone()),
+            _ => unreachable!()
+        };
+        let parameters = param_names.iter().map(|name| name.as_str()).zip(parameter).collect();
+        return self.visit_scope(parameters, Some(return_type), content);
+    }
+
+    /// 式の型を決める
+    /// 入れ子がmax_depthより深ければ、再帰でスタックが溢れないようにUnknownにして止める(エラーはNameResolverが報告する)
+    fn visit(&mut self, expression: &'p Expression) -> TypedExpression<'p> {
+        if self.depth >= self.max_depth {
+            return TypedExpression{ expression, valuetype: Type::Unknown, children: vec![] };
+        }
+        self.depth += 1;
+        let typed = self.visit_unguarded(expression);
+        self.depth -= 1;
+        return typed;
+    }
+
+    /// 式の型を決める(深さの確認はvisitで行う)
+    fn visit_unguarded(&mut self, expression: &'p Expression) -> TypedExpression<'p> {
+        let (valuetype, children) = match expression {
+            Expression::Spanned { span, expression } => {
+                let previous_span = self.current_span.replace(*span);
+                let typed = self.visit(expression);
+                self.current_span = previous_span;
+                return typed;
+            },
+            Expression::Attributed { expression, .. } => return self.visit(expression),
+            Expression::NumberLiteral(_) => (Type::Number, vec![]),
+            Expression::IntLiteral(_) => (Type::Int{ bits: 32, signed: true }, vec![]),
+            Expression::BoolLiteral(_) => (Type::Bool, vec![]),
+            Expression::StringLiteral(_) => (Type::String, vec![]),
+            Expression::Identifier(name) => {
+                let valuetype = self.search_variable(name).map(|(valuetype, _)| valuetype)
+                                    .or_else(|| self.functions.get(name.as_str()))
+                                    .cloned()
+                                    .unwrap_or(Type::Unknown);
+                (valuetype, vec![])
+            },
+            Expression::VariableDeclaration { typename, name, mutable, value, .. } => {
+                let value = self.visit(value);
+                // 型名の省略はコード生成と同じ決まりで初期値の型から決める
+                let inferred = typename.is_empty()
+                                || typename == "auto"
+                                || (typename == "Function" && matches!(value.valuetype, Type::Function { .. } | Type::Unknown))
+                                || (typename == "Closure" && matches!(value.valuetype, Type::Closure { .. } | Type::Unknown));
+                let vartype = if inferred { value.valuetype.clone() } else { self.search_type(typename) };
+                if inferred && vartype == Type::Void {
+                    self.report(format!("The type of '{name}' cannot be inferred because the value is Void."));
+                } else if !vartype.compatible(&value.valuetype) {
+                    self.report(format!("Cannot be assigned because the type is different. '{name}' is declared as '{}', but the value is '{}'.", vartype.name(), value.valuetype.name()));
+                }
+                self.declare(name, vartype, *mutable);
+                (value.valuetype.clone(), vec![value])
+            },
+            Expression::ConstDeclaration { typename, name, value } => {
+                // 初期値がコンパイル時に評価できるかはコード生成で確かめる
+                let value = self.visit(value);
+                let consttype = if typename.is_empty() { value.valuetype.clone() } else { self.search_type(typename) };
+                if !consttype.compatible(&value.valuetype) {
+                    self.report(format!("Constant '{name}' is declared as '{}', but the value is '{}'.", consttype.name(), value.valuetype.name()));
+                }
+                self.declare(name, consttype, false);
+                (Type::Void, vec![value])
+            },
+            Expression::Assignment { target, value } => {
+                let value = self.visit(value);
+                if let Some((vartype, mutable)) = self.search_variable(target).cloned() {
+                    if !mutable {
+                        self.report(format!("Cannot assign twice to immutable variable '{target}'."));
+                    } else if !vartype.compatible(&value.valuetype) {
+                        self.report(format!("Cannot be assigned because the type is different. '{target}' is '{}', but the value is '{}'.", vartype.name(), value.valuetype.name()));
+                    }
+                }
+                (value.valuetype.clone(), vec![value])
+            },
+            Expression::Call { callee, args } => {
+                let args = args.iter().map(|arg| self.visit(arg)).collect::<Vec<TypedExpression>>();
+                (self.visit_call(callee, &args), args)
+            },
+            Expression::CallValue { callee, args } => {
+                let callee = self.visit(callee);
+                let args = args.iter().map(|arg| self.visit(arg)).collect::<Vec<TypedExpression>>();
+                let valuetype = self.check_call(&callee.valuetype.name(), callee.valuetype.clone(), &args);
+                let mut children = vec![callee];
+                children.extend(args);
+                (valuetype, children)
+            },
+            Expression::Unary { op, operand } => {
+                let operand = self.visit(operand);
+                let allowed = match op {
+                    UnaryOperator::NEG => matches!(operand.valuetype, Type::Number | Type::Int { signed: true, .. } | Type::Unknown),
+                    UnaryOperator::NOT => matches!(operand.valuetype, Type::Bool | Type::Unknown),
+                };
+                let valuetype = if allowed { operand.valuetype.clone() } else { Type::Unknown };
+                if !allowed {
+                    self.report(match op {
+                        UnaryOperator::NEG => "Negation is only possible with Number and signed integers.".to_string(),
+                        UnaryOperator::NOT => "Logical not is only possible with Bool.".to_string(),
+                    });
+                }
+                (valuetype, vec![operand])
+            },
+            Expression::Binary { op, lhs, rhs } => {
+                let left = self.visit(lhs);
+                let right = self.visit(rhs);
+                (self.visit_binary(*op, &left.valuetype, &right.valuetype), vec![left, right])
+            },
+            Expression::ChainedComparison { operands, .. } => {
+                let operands = operands.iter().map(|operand| self.visit(operand)).collect::<Vec<TypedExpression>>();
+                for pair in operands.windows(2) {
+                    self.visit_binary(BinaryOperation::Comparison(Predicate::EQUAL), &pair[0].valuetype, &pair[1].valuetype);
+                }
+                (Type::Bool, operands)
+            },
+            Expression::Cast { value, target_type } => {
+                let value = self.visit(value);
+                let target = self.search_type(target_type);
+                if target == Type::Bool && matches!(value.valuetype, Type::Int { .. } | Type::Number) {
+                    self.report(format!("Cannot cast '{}' to 'Bool'. Compare it with 0 instead.", value.valuetype.name()));
+                }
+                (target, vec![value])
+            },
+            Expression::Function { name, return_type, param_types, param_names, content, .. } => {
+                let functype = self.function_type(return_type, param_types);
+                self.functions.insert(name, functype.clone());
+                let content = self.visit_function(&functype, param_names, content);
+                (functype, content)
+            },
+            Expression::Closure { return_type, param_types, param_names, content } => {
+                let functype = match self.function_type(return_type, param_types) {
+                    Type::Function { return_type, parameter } => Type::Closure{ return_type, parameter },
+                    _ => unreachable!()
+                };
+                let content = self.visit_function(&functype, param_names, content);
+                (functype, content)
+            },
+            Expression::Impl { methods, .. } => {
+                // メソッドは型名::メソッド名で呼ばれるので、visitが素の名前で登録した関数は元に戻す(メソッドの呼び出しはコード生成で確かめる)
+                let functions = self.functions.clone();
+                let methods = methods.iter().map(|method| self.visit(method)).collect();
+                self.functions = functions;
+                (Type::Void, methods)
+            },
+            Expression::ExternFunction { name, return_type, param_types } => {
+                let functype = self.function_type(return_type, param_types);
+                self.functions.insert(name, functype.clone());
+                (functype, vec![])
+            },
+            Expression::Return(value) => {
+                let value = value.as_ref().map(|value| self.visit(value));
+                let returned = value.as_ref().map(|value| value.valuetype.clone()).unwrap_or(Type::Void);
+                match self.return_types.last().cloned() {
+                    None => self.report("'return' cannot be used outside of a function.".to_string()),
+                    Some(return_type) if !return_type.compatible(&returned) => {
+                        self.report(format!("The function returns '{}', but '{}' is returned.", return_type.name(), returned.name()));
+                    },
+                    Some(_) => {}
+                }
+                (Type::Void, value.into_iter().collect())
+            },
+            Expression::Unsafe(content) => {
+                let content = self.visit_scope(vec![], None, content);
+                (content.last().map(|last| last.valuetype.clone()).unwrap_or(Type::Void), content)
+            },
+            Expression::TupleLiteral(elements) => {
+                let elements = elements.iter().map(|element| self.visit(element)).collect::<Vec<TypedExpression>>();
+                if elements.len() < 2 {
+                    self.report("A tuple needs at least 2 elements.".to_string());
+                }
+                (Type::Tuple(elements.iter().map(|element| element.valuetype.clone()).collect()), elements)
+            },
+            Expression::TupleDestructure { names, mutable, value } => {
+                let value = self.visit(value);
+                let elements = match &value.valuetype {
+                    Type::Tuple(elements) if elements.len() == names.len() => elements.clone(),
+                    Type::Tuple(elements) => {
+                        self.report(format!("The tuple has {} elements, but {} names were given.", elements.len(), names.len()));
+                        vec![Type::Unknown; names.len()]
+                    },
+                    Type::Unknown => vec![Type::Unknown; names.len()],
+                    valuetype => {
+                        self.report(format!("Only tuples can be destructured, but the value is '{}'.", valuetype.name()));
+                        vec![Type::Unknown; names.len()]
+                    }
+                };
+                for (name, element) in names.iter().zip(elements).filter(|(name, _)| name.as_str() != "_") {
+                    self.declare(name, element, *mutable);
+                }
+                (Type::Void, vec![value])
+            },
+            Expression::Match { scrutinee, arms } => {
+                let scrutinee = self.visit(scrutinee);
+                let mut arm_types: Vec<Type> = vec![];
+                let mut children = vec![];
+                children.push(scrutinee);
+                for arm in arms {
+                    let scrutinee_type = children[0].valuetype.clone();
+                    // ヴァリアントの値の型はコード生成で確かめる
+                    let payload_names = arm.pattern.variant().map(|(_, names)| names.clone()).unwrap_or_default();
+                    let bindings = arm.pattern.bindings().unwrap_or_default().into_iter()
+                                    .map(|name| (name, if payload_names.iter().any(|payload| payload == name) { Type::Unknown } else { scrutinee_type.clone() }))
+                                    .collect();
+                    let mut typed = self.visit_scope(bindings, None, arm.guard.iter().chain(arm.body.iter()));
+                    if arm.guard.is_some() && !typed[0].valuetype.compatible(&Type::Bool) {
+                        self.report(format!("A match guard must be Bool, but it is '{}'.", typed[0].valuetype.name()));
+                    }
+                    let body_type = if typed.len() > arm.guard.iter().count() { typed.last().unwrap().valuetype.clone() } else { Type::Void };
+                    arm_types.push(body_type);
+                    children.append(&mut typed);
+                }
+                if arm_types.iter().any(|arm_type| !arm_type.compatible(&arm_types[0])) {
+                    self.report("All arms of a match must have the same type.".to_string());
+                }
+                (arm_types.into_iter().find(|arm_type| arm_type.is_known()).unwrap_or(Type::Unknown), children)
+            },
+            Expression::StructDeclaration { name, defaults, .. } => {
+                self.declare_type(name, Type::Named(name.to_string()));
+                (Type::Void, defaults.iter().flatten().map(|default| self.visit(default)).collect())
+            },
+            Expression::NewTypeDeclaration { name, .. } | Expression::EnumDeclaration { name, .. } => {
+                self.declare_type(name, Type::Named(name.to_string()));
+                (Type::Void, vec![])
+            },
+            Expression::EnumVariant { typename, values, .. } => {
+                let valuetype = self.search_type(typename);
+                (valuetype, values.iter().map(|value| self.visit(value)).collect())
+            },
+            Expression::TypeAlias { name, target } => {
+                let target = self.search_type(target);
+                self.declare_type(name, target);
+                (Type::Void, vec![])
+            },
+            Expression::StructLiteral { typename, field_values } => {
+                let valuetype = self.search_type(typename);
+                (valuetype, field_values.iter().map(|(_, value)| self.visit(value)).collect())
+            },
+            Expression::Wrap { typename, value } => {
+                let valuetype = self.search_type(typename);
+                (valuetype, vec![self.visit(value)])
+            },
+            Expression::FieldAccess { object, field } => {
+                let object = self.visit(object);
+                // 構造体のフィールドの型はコード生成で確かめる
+                let valuetype = match &object.valuetype {
+                    Type::Tuple(elements) => match field.parse::<usize>().ok().and_then(|index| elements.get(index)) {
+                        Some(element) => element.clone(),
+                        None => {
+                            self.report(format!("The tuple '{}' has no element '{field}'. Elements are accessed by their index, such as '.0'.", object.valuetype.name()));
+                            Type::Unknown
+                        }
+                    },
+                    _ => Type::Unknown
+                };
+                (valuetype, vec![object])
+            },
+            Expression::ListNew(element) => (Type::List(Box::from(self.search_type(element))), vec![]),
+            Expression::ListLen(list) => (Type::Int{ bits: 32, signed: true }, vec![self.visit(list)]),
+            Expression::New(value) => {
+                let value = self.visit(value);
+                (Type::RawPtr(Box::from(value.valuetype.clone())), vec![value])
+            },
+            Expression::Delete(pointer) => (Type::Void, vec![self.visit(pointer)]),
+            Expression::NoneLiteral(inner) => (Type::Optional(Box::from(self.search_type(inner))), vec![]),
+            Expression::SomeValue(value) => {
+                let value = self.visit(value);
+                if value.valuetype == Type::Void {
+                    self.report("'some' cannot wrap Void.".to_string());
+                }
+                (Type::Optional(Box::from(value.valuetype.clone())), vec![value])
+            },
+            Expression::If { condition, then, otherwise } => {
+                let condition = self.visit(condition);
+                if !condition.valuetype.compatible(&Type::Bool) {
+                    self.report(format!("The condition of 'if' must be Bool, but it is '{}'.", condition.valuetype.name()));
+                }
+                let mut then = self.visit_scope(vec![], None, then);
+                let mut otherwise = self.visit_scope(vec![], None, otherwise);
+                // 枝の型が違えば文として使われているとみなす(値を使おうとしたところでVoidとして報告される)
+                let branch_type = |typed: &Vec<TypedExpression>| typed.last().map(|last| last.valuetype.clone()).unwrap_or(Type::Void);
+                let (then_type, otherwise_type) = (branch_type(&then), branch_type(&otherwise));
+                let valuetype = if otherwise.is_empty() || !then_type.compatible(&otherwise_type) {
+                    Type::Void
+                } else if then_type.is_known() {
+                    then_type
+                } else {
+                    otherwise_type
+                };
+                let mut children = vec![condition];
+                children.append(&mut then);
+                children.append(&mut otherwise);
+                (valuetype, children)
+            },
+            Expression::IfLet { name, value, then, otherwise } => {
+                let value = self.visit(value);
+                let inner = match &value.valuetype {
+                    Type::Optional(inner) => inner.as_ref().clone(),
+                    Type::Unknown => Type::Unknown,
+                    valuetype => {
+                        self.report(format!("'if let' needs an Optional, but the value is '{}'.", valuetype.name()));
+                        Type::Unknown
+                    }
+                };
+                let mut then = self.visit_scope(vec![(name.as_str(), inner)], None, then);
+                let mut otherwise = self.visit_scope(vec![], None, otherwise);
+                let branch_type = |typed: &Vec<TypedExpression>| typed.last().map(|last| last.valuetype.clone()).unwrap_or(Type::Void);
+                let (then_type, otherwise_type) = (branch_type(&then), branch_type(&otherwise));
+                let valuetype = if otherwise.is_empty() {
+                    Type::Void
+                } else if !then_type.compatible(&otherwise_type) {
+                    self.report(format!("Both branches of 'if let' must have the same type, but they are '{}' and '{}'.", then_type.name(), otherwise_type.name()));
+                    Type::Unknown
+                } else if then_type.is_known() {
+                    then_type
+                } else {
+                    otherwise_type
+                };
+                let mut children = vec![value];
+                children.append(&mut then);
+                children.append(&mut otherwise);
+                (valuetype, children)
+            },
+            Expression::While { condition, content } => {
+                let condition = self.visit(condition);
+                if !condition.valuetype.compatible(&Type::Bool) {
+                    self.report(format!("The condition of 'while' must be Bool, but it is '{}'.", condition.valuetype.name()));
+                }
+                let mut children = vec![condition];
+                children.append(&mut self.visit_scope(vec![], None, content));
+                (Type::Void, children)
+            },
+            Expression::Import(_) | Expression::Break | Expression::Continue => (Type::Void, vec![]),
+            _ => (Type::Unknown, expression.children().into_iter().map(|child| self.visit(child)).collect())
+        };
+        return TypedExpression{ expression, valuetype, children };
+    }
+
+    /// 関数呼び出しの型を決める(引数の数と型を確かめる)
+    fn visit_call(&mut self, callee: &str, args: &[TypedExpression]) -> Type {
+        // partialは残りの引数を受け取るクロージャになる。ほかの組み込み関数の引数はコード生成で確かめる
+        if let (Some(BuiltinFunction::Partial), Some((function, bound))) = (BuiltinFunction::from_name(callee), args.split_first()) {
+            return match &function.valuetype {
+                Type::Function { return_type, parameter } | Type::Closure { return_type, parameter } if bound.len() <= parameter.len() => {
+                    let bound_type = Type::Function{ return_type: Box::from(Type::Void), parameter: parameter[..bound.len()].to_vec() };
+                    self.check_call(&function.valuetype.name(), bound_type, bound);
+                    Type::Closure{ return_type: return_type.clone(), parameter: parameter[bound.len()..].to_vec() }
+                },
+                _ => Type::Unknown
+            };
+        }
+        match BuiltinFunction::from_name(callee) {
+            Some(BuiltinFunction::Format) => return Type::String,
+            Some(BuiltinFunction::Assert) => {
+                if let Some(condition) = args.first() {
+                    if !condition.valuetype.compatible(&Type::Bool) {
+                        self.report(format!("The condition of 'assert' must be Bool, but it is '{}'.", condition.valuetype.name()));
+                    }
+                }
+                return Type::Void;
+            },
+            Some(BuiltinFunction::Overflowing(_, mode)) => {
+                let operand = args.first().map(|arg| arg.valuetype.clone()).unwrap_or(Type::Unknown);
+                return match (mode, operand) {
+                    (_, Type::Unknown) => Type::Unknown,
+                    (OverflowMode::Checked, operand) => Type::Optional(Box::from(operand)),
+                    (_, operand) => operand
+                };
+            },
+            Some(_) => return Type::Unknown,
+            None => {}
+        }
+        let calleetype = self.search_variable(callee).map(|(valuetype, _)| valuetype)
+                            .or_else(|| self.functions.get(callee))
+                            .cloned()
+                            .unwrap_or(Type::Unknown);
+        return self.check_call(callee, calleetype, args);
+    }
+
+    /// 呼ぶ値の型から、引数の数と型を確かめて戻り値の型を決める
+    fn check_call(&mut self, callee: &str, calleetype: Type, args: &[TypedExpression]) -> Type {
+        let (return_type, parameter) = match calleetype {
+            Type::Function { return_type, parameter } | Type::Closure { return_type, parameter } => (*return_type, parameter),
+            Type::Unknown => return Type::Unknown,
+            _ => {
+                self.report(format!("'{callee}' is not a function."));
+                return Type::Unknown;
+            }
+        };
+        if args.len() != parameter.len() {
+            self.report(format!("Function '{callee}' takes {} arguments but {} were given.", parameter.len(), args.len()));
+        } else {
+            for (i, (arg, param_type)) in args.iter().zip(parameter.iter()).enumerate() {
+                if !param_type.compatible(&arg.valuetype) {
+                    self.report(format!("Argument {} of function '{callee}' must be '{}', but it is '{}'.", i + 1, param_type.name(), arg.valuetype.name()));
+                }
+            }
+        }
+        return return_type;
+    }
+
+    /// 二項演算の型を決める(コード生成と同じ順に確かめる)
+    fn visit_binary(&mut self, op: BinaryOperation, left: &Type, right: &Type) -> Type {
+        if let BinaryOperation::Logical(op) = op {
+            let operator = match op {
+                LogicalOperator::AND => "&&",
+                LogicalOperator::OR => "||",
+            };
+            for (side, operand) in [("left", left), ("right", right)] {
+                if !operand.compatible(&Type::Bool) {
+                    self.report(format!("The {side} side of '{operator}' must be Bool, but it is '{}'.", operand.name()));
+                }
+            }
+            return Type::Bool;
+        }
+        if !left.compatible(right) {
+            self.report(format!("The left value and the right value have different types. ('{}' and '{}')", left.name(), right.name()));
+            return Type::Unknown;
+        }
+        let operand = if left.is_known() { left } else { right };
+        if *operand == Type::Void {
+            self.report("Void cannot be an operand.".to_string());
+            return Type::Unknown;
+        }
+        let bool_message = |usage: &str| format!("{usage} cannot be Bool. Cast it explicitly to an integer (false is 0, true is 1).");
+        let message = match op {
+            // 構造体などの演算はメソッドを呼ぶので、メソッドがあるかと戻り値の型はコード生成で確かめる
+            BinaryOperation::Comparison(_) => {
+                if matches!(operand, Type::Number | Type::Int { .. } | Type::Bool | Type::Named(_) | Type::Unknown) {
+                    return Type::Bool;
+                }
+                "Only Number, integers and Bool are comparable.".to_string()
+            },
+            BinaryOperation::Arithmetic(BinaryOperator::ADD) if *operand == Type::String => return Type::String,
+            BinaryOperation::Arithmetic(op) if op.is_bitwise() => match operand {
+                Type::Int { .. } | Type::Unknown => return operand.clone(),
+                Type::Bool => bool_message("An operand of a bitwise operation or a shift"),
+                _ => format!("Bitwise operations and shifts are only possible with integers, but the operands are '{}'.", operand.name())
+            },
+            BinaryOperation::Arithmetic(_) => match operand {
+                Type::Number | Type::Int { .. } | Type::Unknown => return operand.clone(),
+                Type::Named(_) => return Type::Unknown,
+                Type::Bool => bool_message("An operand of an arithmetic operation"),
+                _ => "Four arithmetic operations are only possible with Number and integers.".to_string()
+            },
+            BinaryOperation::Logical(_) => unreachable!(),
+        };
+        self.report(message);
+        return Type::Unknown;
+    }
+}
+
+/// "(Number, Number) -> Number"の形のシグネチャを、引数の並びと戻り値の型に分ける
+/// 戻り値が関数の型("(Number) -> Function(Number) -> Number")でも分けられるように、先頭の(に対応する)を括弧を数えて探す
+fn split_signature(signature: &str) -> Option<(&str, &str)> {
+    let rest = signature.strip_prefix('(')?;
+    let mut depth = 0;
+    for (index, c) in rest.char_indices() {
+        match c {
+            '<' | '(' | '[' => depth += 1,
+            // ->の>は括弧ではない
+            '>' if rest[..index].ends_with('-') => {},
+            ')' if depth == 0 => {
+                let return_type = rest[index + 1..].trim_start().strip_prefix("->")?.trim();
+                return Some((&rest[..index], return_type));
+            },
+            '>' | ')' | ']' => depth -= 1,
+            _ => {}
+        }
+    }
+    return None;
+}
+
+/// 型名のカンマ区切りの並びを分ける(<>や()の中のカンマでは分けない)
+fn split_type_list(list: &str) -> Vec<String> {
+    let mut types = vec![];
+    let mut depth = 0;
+    let mut current = String::new();
+    for c in list.chars() {
+        match c {
+            '<' | '(' | '[' => depth += 1,
+            // ->の>は括弧ではない
+            '>' if current.ends_with('-') => {},
+            '>' | ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                types.push(current.trim().to_string());
+                current.clear();
+                continue;
+            },
+            _ => {}
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() {
+        types.push(current.trim().to_string());
+    }
+    return types;
+}
+
+/// 型名の中の型引数の名前を実際の型の名前に置き換える(List<A>はList<Number>に。名前の一部だけが同じ識別子は置き換えない)
+#[cfg(feature = "llvm")]
+fn substitute_type_params(typename: &str, params: &[String], args: &[String]) -> String {
+    let replace = |word: &str| params.iter().position(|param| param == word).map(|index| args[index].clone()).unwrap_or_else(|| word.to_string());
+    let mut substituted = String::new();
+    let mut word = String::new();
+    for c in typename.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+            continue;
+        }
+        substituted.push_str(&replace(&word));
+        word.clear();
+        substituted.push(c);
+    }
+    substituted.push_str(&replace(&word));
+    return substituted;
+}
+
+
+/// 意味解析関連関数 (ASTを解析して対応する関連関数にIRを書かせる)
+#[cfg(feature = "llvm")]
+impl<'a, 'ctx> Compiler<'a, 'ctx> where 'a: 'ctx{
+
+    /// ファイルパスから実際のモジュール名を割り出してモジュールを作成する。
+    pub fn initialize_module_by_filepath(&mut self, filepath: &PathBuf) {
+        let filename = filepath.file_name().unwrap().to_string_lossy().to_string();
+        let filepath_as_str = filepath.to_str().unwrap();
+        let mut haser = Sha256::new();
+        haser.input_str(filepath_as_str);
+        let hex = haser.result_str();
+        self.create_module((filename + &hex).as_str());
+    }
+
+    /// -gのとき、DWARFのデバッグ情報を書き出す準備をする(initialize_module_by_filepathの後に呼ぶ)
+    fn enable_debug_info(&mut self, filepath: &PathBuf) {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let filename = filepath.file_name().unwrap().to_string_lossy().to_string();
+        let directory = filepath.parent().map(|parent| parent.to_string_lossy().to_string()).unwrap_or_default();
+        module.add_basic_value_flag("Debug Info Version", FlagBehavior::Warning, self.context.i32_type().const_int(3, false));
+        module.add_basic_value_flag("Dwarf Version", FlagBehavior::Warning, self.context.i32_type().const_int(4, false));
+        self.debug_info = Some(module.create_debug_info_builder(
+            true, DWARFSourceLanguage::C, &filename, &directory, "ksc1000", false, "", 0, "", DWARFEmissionKind::Full, 0, false, false
+        ));
+    }
+
+    /// ASTを意味解析してLLVMを書く
+    /// エラーはdiagnosticsに集める(エラーがあったかどうかはdiagnostics.has_errors()でわかる)
+    pub fn build(&mut self, program: &'a Vec<Expression>) where 'a: 'ctx{
+        self.emit_event(CompilerEvent::PhaseStarted(Phase::Codegen));
+        self.build_program(program);
+        self.emit_event(CompilerEvent::PhaseFinished(Phase::Codegen));
+    }
+
+    /// build()の本体
+    /// トップレベルの関数でエラーが起きても、スタックを元に戻して次の式から続ける
+    /// それ以外の式(変数宣言など)はあとの式がその結果を使うかもしれないので、そこで止める
+    fn build_program(&mut self, program: &'a Vec<Expression>) where 'a: 'ctx{
+        self.declare_functions(program);
+        for expression in program{
+            let saved = (self.stack.len(), self.stack_current_function.len(), self.stack_loop.len(), self.stack_return_type.len(), self.function_frames.len(), self.unsafe_depth);
+            match self.compile_expression(expression) {
+                Ok(value) => self.check_unused_result(expression, &value),
+                Err(error) => {
+                    self.report_error(error);
+                    let (stack, current_function, loops, return_types, frames, unsafe_depth) = saved;
+                    self.stack.truncate(stack);
+                    self.stack_current_function.truncate(current_function);
+                    self.stack_loop.truncate(loops);
+                    self.stack_return_type.truncate(return_types);
+                    self.function_frames.truncate(frames);
+                    self.temporaries.clear();
+                    self.unsafe_depth = unsafe_depth;
+                    self.expression_depth = 0;
+                    self.current_span = None;
+                    let is_function = match expression.unspanned() {
+                        Expression::VariableDeclaration { value, .. } => matches!(value.unspanned(), Expression::Function { .. }),
+                        expression => matches!(expression, Expression::Function { .. } | Expression::Impl { .. })
+                    };
+                    if !is_function {
+                        return;
+                    }
+                },
+            }
+        }
+        if self.diagnostics.has_errors() {
+            return;
+        }
+        // wasm32-unknown-unknownにはCのmainを呼ぶランタイムがないので、ホストがエクスポートされたmainを直接呼ぶ
+        if self.is_freestanding_wasm() {
+            if let Some((dibuilder, _)) = &self.debug_info {
+                dibuilder.finalize();
+            }
+            return;
+        }
+        if !self.library {
+            if let Err(error) = self.create_entry_point() {
+                self.report_error(error);
+                return;
+            }
+        }
+        if let Some((dibuilder, _)) = &self.debug_info {
+            dibuilder.finalize();
+        }
+    }
+
+    /// エラーをdiagnosticsに加えて、イベントでも知らせる
+    fn report_error(&mut self, error: CompileError) {
+        self.emit_event(CompilerEvent::Diagnostic(&error));
+        self.diagnostics.error(error);
+    }
+
+
+    /// 本体をコンパイルする前に、トップレベルの関数のシグネチャをすべて宣言しておく
+    /// これで関数は自分自身や後で定義される関数を呼べる(シグネチャに使う型は、それより前に宣言されている必要がある)
+    /// シグネチャの型がまだ見つからない関数(プログラムの中で定義する構造体を受け取るものなど)は、定義したところで宣言する
+    fn declare_functions(&mut self, program: &'a Vec<Expression>) where 'a: 'ctx{
+        for expression in program {
+            let function = match expression.unspanned() {
+                Expression::VariableDeclaration { value, .. } => value.unspanned(),
+                expression => expression
+            };
+            if let Expression::Function { name, return_type, param_types, type_params, .. } = function {
+                if !type_params.is_empty() {
+                    continue;
+                }
+                // @must_use は定義より前の呼び出しにも効くように、ここで登録しておく
+                if expression.attributes().contains(&&KSCAttribute::MustUse) {
+                    self.must_use_functions.push(name.to_string());
+                }
+                let return_type_ksc = match self.search_ksc_type(return_type) {
+                    Ok(return_type_ksc) => return_type_ksc,
+                    Err(_) => continue
+                };
+                let param_types_ksc = match param_types.iter().map(|p| self.search_ksc_type(p)).collect::<Result<Vec<KSCType>, CompileError>>() {
+                    Ok(param_types_ksc) => param_types_ksc,
+                    Err(_) => continue
+                };
+                let param_types = match param_types_ksc.iter().map(|p| self.get_basic_type(p).map(|p| p.as_any_type_enum())).collect::<Option<Vec<AnyTypeEnum>>>() {
+                    Some(param_types) => param_types,
+                    None => continue
+                };
+                let func = self.create_function_declare(name.as_str(), &self.get_return_type(&return_type_ksc), &param_types);
+                // _で始まる関数はインターフェースに書き出さないので、ほかのモジュールからリンクされないようにする
+                if name.starts_with('_') {
+                    func.set_linkage(Linkage::Internal);
+                }
+                let reference = func.get_type().ptr_type(AddressSpace::Generic);
+                self.register_function(name, func, KSCType::Function{ reference, return_type: Box::from(return_type_ksc), parameter: param_types_ksc });
+            }
+        }
+    }
+
+    /// インターフェースの構造体の行で、フィールドの前に書く@repr(c)の印(読み込む側も宣言の順に並べるため)
+    fn repr_marker(&self, name: &str) -> &'static str {
+        return if self.repr_c_structs.iter().any(|repr_c| repr_c == name) { "\t@repr(c)" } else { "" };
+    }
+
+    /// unsafeな箇所の一覧(--emit unsafe-report)を書き出す(build()の後に呼ぶ)。コンパイルした順に1行ずつ並べる
+    fn write_unsafe_report(&self) -> String {
+        return self.unsafe_usages.iter().map(|usage| format!("{usage}\n")).collect();
+    }
+
+    /// モジュールの公開インターフェース(.ksci)を書き出す(build()の後に呼ぶ)
+    /// トップレベルの型とKSCで定義した関数を、定義の順にタブ区切りで1行ずつ並べる。型は名前で書くので、使う型より後に並ぶ
+    /// 名前が_で始まる関数はモジュールの中だけで使うものとして書き出さない(型は公開する関数のシグネチャに現れうるので書き出す)
+    /// 構造体のフィールドの初期値は含まない
+    fn write_interface(&self) -> String {
+        let mut interface = "ksci\t1\n".to_string();
+        let globals = &self.stack.first().unwrap_or_else(||panic!("There is no stack yet!")).types;
+        for name in self.exported_types.iter().filter(|name| !self.imported_names.contains(name)) {
+            let line = match &globals[name] {
+                KSCType::Struct { name: struct_name, field_names, contents, .. } if struct_name == name => {
+                    let fields = field_names.iter().zip(contents.iter()).map(|(field_name, field_type)| format!("\t{field_name}: {}", field_type.type_name()));
+                    format!("struct\t{name}{}{}", self.repr_marker(name), fields.collect::<String>())
+                },
+                KSCType::NewType { name: newtype_name, underlying, .. } if newtype_name == name => format!("newtype\t{name}\t{}", underlying.type_name()),
+                KSCType::Enum { name: enum_name, variant_names, payloads, .. } if enum_name == name => {
+                    let variants = variant_names.iter().zip(payloads.iter()).map(|(variant, payload)| match payload.is_empty() {
+                        true => format!("\t{variant}"),
+                        false => format!("\t{variant}({})", payload.iter().map(|valuetype| valuetype.type_name()).collect::<Vec<String>>().join(", "))
+                    });
+                    format!("enum\t{name}{}", variants.collect::<String>())
+                },
+                target => format!("alias\t{name}\t{}", target.type_name()),
+            };
+            interface.push_str(&line);
+            interface.push('\n');
+        }
+        let mut generic_names = self.generic_structs.keys().filter(|name| !self.imported_names.contains(name)).collect::<Vec<&String>>();
+        generic_names.sort();
+        for name in generic_names {
+            let (params, field_names, field_types) = &self.generic_structs[name];
+            let fields = field_names.iter().zip(field_types.iter()).map(|(field_name, field_type)| format!("\t{field_name}: {field_type}"));
+            interface.push_str(&format!("generic\t{name}\t{}{}{}\n", params.join(", "), self.repr_marker(name), fields.collect::<String>()));
+        }
+        let mut function_names = self.functions.keys()
+                                    .filter(|name| name.as_str() != "main" && !name.starts_with('_') && !self.extern_functions.contains(name) && !self.imported_names.contains(name))
+                                    .collect::<Vec<&String>>();
+        function_names.sort();
+        for name in function_names {
+            let (_, functype) = &self.functions[name];
+            interface.push_str(&format!("function\t{name}\t{}\n", functype.type_name()));
+        }
+        return interface;
+    }
+
+    /// ほかのモジュールのインターフェース(.ksci)を読み込み、その型と関数をこのモジュールから使えるようにする(build()の前に呼ぶ)
+    /// 関数は宣言だけを作るので、本体はリンクするときに相手のモジュールから来る。読み込んだものはwrite_interfaceで書き出さない
+    fn load_interface(&mut self, name: &str, interface: &str) -> Result<(), CompileError> {
+        let invalid = |line: usize, reason: &str| CompileError{ message: format!("Invalid interface '{name}' at line {line}: {reason}"), span: None };
+        let mut lines = interface.lines().enumerate().map(|(index, line)| (index + 1, line.split('\t').collect::<Vec<&str>>()));
+        match lines.next() {
+            Some((_, header)) if header == ["ksci", "1"] => {},
+            _ => return Err(invalid(1, "the header must be 'ksci 1'."))
+        }
+        for (line, fields) in lines {
+            if let Some(imported_name) = fields.get(1) {
+                self.imported_names.push(imported_name.to_string());
+            }
+            match fields.as_slice() {
+                ["struct", typename, fields @ ..] => {
+                    self.define_type_name(typename)?;
+                    let fields = match fields {
+                        ["@repr(c)", fields @ ..] => {
+                            self.repr_c_structs.push(typename.to_string());
+                            fields
+                        },
+                        fields => fields
+                    };
+                    let mut field_names = vec![];
+                    let mut contents = vec![];
+                    let mut field_llvm_types = vec![];
+                    for field in fields {
+                        let (field_name, field_type) = field.split_once(": ").ok_or_else(|| invalid(line, "a field must be 'name: Type'."))?;
+                        let field_type = self.search_ksc_type(&field_type.to_string()).map_err(|error| invalid(line, &error.message))?;
+                        field_llvm_types.push(self.get_basic_type(&field_type).ok_or_else(|| invalid(line, "a field cannot be Void."))?);
+                        field_names.push(field_name.to_string());
+                        contents.push(field_type);
+                    }
+                    let (reference, field_indices) = self.create_ksc_struct_type(typename, &field_llvm_types, self.repr_c_structs.iter().any(|repr_c| repr_c == typename));
+                    let default_values = vec![None; field_names.len()];
+                    self.insert_new_type_to_stack(typename, KSCType::Struct{ name: typename.to_string(), reference, field_names, contents, default_values, field_indices });
+                },
+                ["newtype", typename, underlying] => {
+                    self.define_type_name(typename)?;
+                    let underlying = self.search_ksc_type(&underlying.to_string()).map_err(|error| invalid(line, &error.message))?;
+                    let underlying_llvm_type = self.get_basic_type(&underlying).ok_or_else(|| invalid(line, "a newtype cannot wrap Void."))?;
+                    let reference = self.create_struct_type(typename, &vec![underlying_llvm_type]);
+                    self.insert_new_type_to_stack(typename, KSCType::NewType{ name: typename.to_string(), reference, underlying: Box::from(underlying) });
+                },
+                ["generic", typename, params, fields @ ..] => {
+                    self.define_type_name(typename)?;
+                    let fields = match fields {
+                        ["@repr(c)", fields @ ..] => {
+                            self.repr_c_structs.push(typename.to_string());
+                            fields
+                        },
+                        fields => fields
+                    };
+                    let mut field_names = vec![];
+                    let mut field_types = vec![];
+                    for field in fields {
+                        let (field_name, field_type) = field.split_once(": ").ok_or_else(|| invalid(line, "a field must be 'name: Type'."))?;
+                        field_names.push(field_name.to_string());
+                        field_types.push(field_type.to_string());
+                    }
+                    self.generic_structs.insert(typename.to_string(), (split_type_list(params), field_names, field_types));
+                },
+                ["enum", typename, variants @ ..] => {
+                    self.define_type_name(typename)?;
+                    let mut variant_names = vec![];
+                    let mut payloads = vec![];
+                    for variant in variants {
+                        let (variant_name, payload) = match variant.split_once('(') {
+                            Some((variant_name, payload)) => {
+                                let payload = payload.strip_suffix(')').ok_or_else(|| invalid(line, "a variant must be 'Name' or 'Name(Type, ..)'."))?;
+                                let payload = split_type_list(payload).iter()
+                                                .map(|valuetype| self.search_ksc_type(valuetype))
+                                                .collect::<Result<Vec<KSCType>, CompileError>>().map_err(|error| invalid(line, &error.message))?;
+                                (variant_name, payload)
+                            },
+                            None => (*variant, vec![])
+                        };
+                        if payload.iter().any(|valuetype| self.get_basic_type(valuetype).is_none()) {
+                            return Err(invalid(line, "a variant cannot hold Void."));
+                        }
+                        variant_names.push(variant_name.to_string());
+                        payloads.push(payload);
+                    }
+                    let enumtype = self.create_enum_type(typename, variant_names, payloads);
+                    self.insert_new_type_to_stack(typename, enumtype);
+                },
+                ["alias", typename, target] => {
+                    self.define_type_name(typename)?;
+                    let target = self.search_ksc_type(&target.to_string()).map_err(|error| invalid(line, &error.message))?;
+                    self.insert_new_type_to_stack(typename, target);
+                },
+                ["function", function_name, signature] => {
+                    let (return_type, parameter) = match self.search_ksc_type(&signature.to_string()).map_err(|error| invalid(line, &error.message))? {
+                        KSCType::Function { return_type, parameter, .. } => (return_type, parameter),
+                        _ => return Err(invalid(line, "the type of a function must be 'Function(..) -> R'."))
+                    };
+                    let param_types = parameter.iter()
+                                        .map(|p| self.get_basic_type(p).map(|p| p.as_any_type_enum()).ok_or_else(|| invalid(line, "a parameter cannot be Void.")))
+                                        .collect::<Result<Vec<AnyTypeEnum>, CompileError>>()?;
+                    let func = self.create_function_declare(function_name, &self.get_return_type(&return_type), &param_types);
+                    let reference = func.get_type().ptr_type(AddressSpace::Generic);
+                    self.register_function(function_name, func, KSCType::Function{ reference, return_type, parameter });
+                },
+                _ => return Err(invalid(line, "unknown entry."))
+            }
+        }
+        return Ok(());
+    }
+
+    /// 書き出したモジュールを検証する(build()の後に呼ぶ)
+    /// LLVMの検証に失敗したら、壊れている関数の名前を添えてエラーにする
+    pub fn verify(&self) -> Result<(), CompileError> {
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        if let Err(message) = module.verify() {
+            let broken_functions = module.get_functions()
+                                    .filter(|func| !func.verify(false))
+                                    .map(|func| format!("'{}'", func.get_name().to_string_lossy()))
+                                    .collect::<Vec<String>>();
+            let location = if broken_functions.is_empty() { "the module".to_string() } else { broken_functions.join(", ") };
+            let error = CompileError{ message: format!("Generated IR is invalid in {location}.\n{}", message.to_string()), span: None };
+            self.emit_event(CompilerEvent::Diagnostic(&error));
+            return Err(error);
+        }
+        return Ok(());
+    }
+
+    /// コンパイルの統計(--stats)を書き出す(build()の後に呼ぶ)
+    fn write_stats(&self, program: &Vec<Expression>) -> String {
+        let mut node_counts: HashMap<&str, usize> = HashMap::new();
+        let mut pending: Vec<&Expression> = program.iter().collect();
+        while let Some(expression) = pending.pop() {
+            *node_counts.entry(expression.kind_name()).or_insert(0) += 1;
+            pending.extend(expression.children());
+        }
+        let mut node_counts = node_counts.into_iter().collect::<Vec<(&str, usize)>>();
+        node_counts.sort();
+
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let defined_functions = module.get_functions().filter(|func| func.count_basic_blocks() > 0).collect::<Vec<FunctionValue>>();
+        let basic_blocks = defined_functions.iter().flat_map(|func| func.get_basic_blocks()).collect::<Vec<BasicBlock>>();
+        let instructions: usize = defined_functions.iter().map(|func| self.count_instructions(*func)).sum();
+
+        let mut stats = "======== STATS ========\n".to_string();
+        for (kind, count) in node_counts {
+            stats.push_str(&format!("{kind}: {count}\n"));
+        }
+        stats.push_str(&format!("functions compiled: {}\n", defined_functions.len()));
+        stats.push_str(&format!("basic blocks: {}\n", basic_blocks.len()));
+        stats.push_str(&format!("instructions: {instructions}\n"));
+        stats.push_str(&format!("peak scope depth: {}\n", self.peak_scope_depth));
+        stats.push_str("========= END =========\n");
+        return stats;
+    }
+
+    /// 組み込み関数の呼び出しをコンパイルする
+    fn compile_builtin_call(&mut self, builtin: BuiltinFunction, args: &'ctx Vec<Expression>) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        return match builtin {
+            BuiltinFunction::Fma => self.compile_fma(args),
+            BuiltinFunction::Print => self.compile_print("print", args, false),
+            BuiltinFunction::Println => self.compile_print("println", args, true),
+            BuiltinFunction::Partial => self.compile_partial(args),
+            BuiltinFunction::Format => self.compile_format(args),
+            BuiltinFunction::Overflowing(op, mode) => self.compile_overflowing(op, mode, args),
+            BuiltinFunction::Assert => self.compile_assert(args),
+        };
+    }
+
+    /// 呼ぶ関数の引数の数と型を確かめながら、引数を順にコンパイルする
+    fn compile_arguments(&mut self, callee: &str, parameter: &[KSCType<'ctx>], args: &'ctx [Expression]) -> Result<Vec<BasicValueEnum<'ctx>>, CompileError> where 'a: 'ctx{
+        if args.len() != parameter.len() {
+            return Err(CompileError{ message: format!("Function '{callee}' takes {} arguments but {} were given.", parameter.len(), args.len()), span: None });
+        }
+        let mut arg_values = vec![];
+        for (i, (arg, param_type)) in args.iter().zip(parameter.iter()).enumerate() {
+            let executed = self.compile_expression(arg)?;
+            if self.types_compatible(param_type, &executed.valuetype) == Compatibility::Incompatible {
+                return Err(CompileError{ message: format!("Argument {} of function '{callee}' must be '{}', but it is '{}'.", i + 1, param_type.type_name(), executed.valuetype.type_name()), span: None });
+            }
+            arg_values.push(executed.value.ok_or_else(|| CompileError{ message: format!("Void cannot be passed as an argument of '{callee}'."), span: None })?);
+        }
+        return Ok(arg_values);
+    }
+
+    /// 組み込み関数partial(f, x, ..)をコンパイルする
+    /// fとx, ..の値をヒープの環境にコピーし、残りの引数を受け取るクロージャにする
+    fn compile_partial(&mut self, args: &'ctx Vec<Expression>) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        if args.len() < 2 {
+            return Err(CompileError{ message: format!("Function 'partial' takes a function and at least 1 argument to bind, but {} arguments were given.", args.len()), span: None });
+        }
+        let callee = self.compile_expression(&args[0])?;
+        let (return_type, parameter, calls_closure) = match &callee.valuetype {
+            KSCType::Function { return_type, parameter, .. } => (return_type.as_ref().clone(), parameter.clone(), false),
+            KSCType::Closure { return_type, parameter, .. } => (return_type.as_ref().clone(), parameter.clone(), true),
+            valuetype => return Err(CompileError{ message: format!("The first argument of 'partial' must be a function, but it is '{}'.", valuetype.type_name()), span: None })
+        };
+        let bound = &args[1..];
+        if bound.len() > parameter.len() {
+            return Err(CompileError{ message: format!("'partial' binds {} arguments, but the function takes only {}.", bound.len(), parameter.len()), span: None });
+        }
+        let bound_values = self.compile_arguments(&callee.valuetype.type_name(), &parameter[..bound.len()], bound)?;
+        let callee_value = callee.value.unwrap_or_else(||panic!("A function value has no value."));
+
+        // 環境は呼ぶ関数と束縛した値の参照を持つ
+        let env_ksctypes = std::iter::once(callee.valuetype.clone()).chain(parameter[..bound.len()].iter().cloned()).collect::<Vec<KSCType>>();
+        let env_values = std::iter::once(callee_value).chain(bound_values.iter().cloned()).collect::<Vec<BasicValueEnum>>();
+        for (valuetype, value) in env_ksctypes.iter().zip(env_values.iter()) {
+            self.take_ownership(&KSCValue{ valuetype: valuetype.clone(), value: Some(*value) });
+        }
+        let env_fields = env_values.iter().map(|value| value.get_type()).collect::<Vec<BasicTypeEnum>>();
+        let env_type = self.context.struct_type(&env_fields, false);
+        let drop = self.get_or_create_env_drop(&format!("partial.{}.drop", self.closure_count), env_type, &env_ksctypes);
+        let env = self.create_rc_allocation(BasicTypeEnum::StructType(env_type), drop, "partial_env");
+        let typed_env = self.builder.build_pointer_cast(env, env_type.ptr_type(AddressSpace::Generic), "partial_env");
+        for (index, value) in std::iter::once(&callee_value).chain(bound_values.iter()).enumerate() {
+            self.create_store_variable(self.create_field_pointer(typed_env, index as u32, "bound"), value);
+        }
+
+        let remaining = parameter[bound.len()..].to_vec();
+        let fn_type = self.get_signature_type(&return_type, &remaining, true)?;
+        let closure_type = self.get_closure_type(fn_type);
+        let name = format!("partial.{}", self.closure_count);
+        self.closure_count += 1;
+        let func = self.create_partial_wrapper(&name, fn_type, env_type, calls_closure);
+        self.emit_function_compiled(&name, func);
+        let value = self.create_closure(func, env, closure_type);
+        let result = KSCValue{
+            valuetype: KSCType::Closure{ reference: closure_type, return_type: Box::from(return_type), parameter: remaining },
+            value: Some(value)
+        };
+        self.add_temporary(&result);
+        return Ok(result);
+    }
+
+    /// 組み込み関数print(x)/println(x)をコンパイルする(printlnは引数なしなら改行のみ)
+    /// print(format("x = {}", x))は文字列を作らず、formatの書式と値をそのまま渡す1回のprintfにする
+    fn compile_print(&mut self, name: &str, args: &'ctx Vec<Expression>, newline: bool) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        if args.len() > 1 || (!newline && args.is_empty()) {
+            return Err(CompileError{ message: format!("Function '{name}' takes 1 argument but {} were given.", args.len()), span: None });
+        }
+        let mut format = String::new();
+        let mut values = vec![];
+        let mut printers = vec![];
+        let host_print = self.is_freestanding_wasm();
+        if let (Some(Expression::Call { callee, args: format_args }), false) = (args.first().map(|arg| arg.unspanned()), host_print) {
+            if let Some(BuiltinFunction::Format) = BuiltinFunction::from_name(callee) {
+                let (mut format, values) = self.compile_format_arguments(format_args)?;
+                if newline {
+                    format.push('\n');
+                }
+                self.create_printf(&format, &values);
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            }
+        }
+        if let Some(arg) = args.first() {
+            let executed = self.compile_expression(arg)?;
+            match (executed.valuetype, executed.value) {
+                (KSCType::Number(_), Some(value)) => { format.push_str("%f"); values.push(value); printers.push("ksc_print_f64"); },
+                (KSCType::Int { reference, signed }, Some(value)) => {
+                    // printfの可変長引数なので、32bitより狭い整数はi32に広げて渡す(ホストの関数にはいつもi64で渡す)
+                    printers.push(if signed { "ksc_print_i64" } else { "ksc_print_u64" });
+                    let (specifier, width) = match (signed, host_print || reference.get_bit_width() > 32) {
+                        (true, false) => ("%d", 32),
+                        (false, false) => ("%u", 32),
+                        (true, true) => ("%lld", 64),
+                        (false, true) => ("%llu", 64),
+                    };
+                    format.push_str(specifier);
+                    values.push(self.create_int_cast(value.into_int_value(), self.context.custom_width_int_type(width), signed).as_basic_value_enum());
+                },
+                (KSCType::Bool(_), Some(value)) => {
+                    format.push_str("%s");
+                    values.push(self.create_bool_to_string(value.into_int_value()).as_basic_value_enum());
+                    printers.push("ksc_print_str");
+                },
+                (KSCType::String(_), Some(value)) => { format.push_str("%s"); values.push(value); printers.push("ksc_print_str"); },
+                _ => return Err(CompileError{ message: format!("Function '{name}' can only print Number, integers, Bool and String."), span: None })
+            }
+        }
+        if host_print {
+            self.create_host_print(&printers, &values, newline);
+            return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+        }
+        if newline {
+            format.push('\n');
+        }
+        self.create_printf(&format, &values);
+        return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+    }
+
+    /// 組み込み関数format("x = {}", x, ..)をコンパイルする
+    /// 書式は文字列リテラルで、{}を順に値(Number、整数、Bool、String)の文字列に置き換える。{{と}}は{と}になる
+    fn compile_format(&mut self, args: &'ctx Vec<Expression>) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        if self.is_freestanding_wasm() {
+            return Err(CompileError{ message: "Function 'format' is not available for wasm32-unknown-unknown because there is no snprintf.".to_string(), span: None });
+        }
+        let (format, arg_values) = self.compile_format_arguments(args)?;
+        let value = self.create_formatted_string(&format, &arg_values);
+        let result = KSCValue{ valuetype: KSCType::String(self.context.i8_type().ptr_type(AddressSpace::Generic)), value: Some(value.as_basic_value_enum()) };
+        self.add_temporary(&result);
+        return Ok(result);
+    }
+
+    /// formatの引数から、printfの書式と可変長引数に渡す値を作る
+    fn compile_format_arguments(&mut self, args: &'ctx Vec<Expression>) -> Result<(String, Vec<BasicValueEnum<'ctx>>), CompileError> where 'a: 'ctx{
+        let template = match args.first().map(|arg| arg.unspanned()) {
+            Some(Expression::StringLiteral(template)) => template,
+            _ => return Err(CompileError{ message: "The first argument of 'format' must be a string literal.".to_string(), span: None })
+        };
+
+        // {}で区切った書式の断片(printfの書式として読まれるので、%は%%にしておく)
+        let mut pieces = vec![String::new()];
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            let piece = pieces.last_mut().unwrap();
+            match (c, chars.peek()) {
+                ('{', Some('{')) | ('}', Some('}')) => { chars.next(); piece.push(c); },
+                ('{', Some('}')) => { chars.next(); pieces.push(String::new()); },
+                ('{' | '}', _) => return Err(CompileError{ message: format!("Unmatched '{c}' in the format string. Write '{c}{c}' for a literal brace."), span: None }),
+                ('%', _) => piece.push_str("%%"),
+                _ => piece.push(c)
+            }
+        }
+        let values = &args[1..];
+        if values.len() != pieces.len() - 1 {
+            return Err(CompileError{ message: format!("The format string has {} placeholders, but {} values were given.", pieces.len() - 1, values.len()), span: None });
+        }
+
+        let mut format = pieces[0].clone();
+        let mut arg_values = vec![];
+        for (value, piece) in values.iter().zip(&pieces[1..]) {
+            let executed = self.compile_expression(value)?;
+            match (executed.valuetype, executed.value) {
+                (KSCType::Number(_), Some(value)) => { format.push_str("%f"); arg_values.push(value); },
+                (KSCType::Int { reference, signed }, Some(value)) => {
+                    // 可変長引数なので、32bitより狭い整数はi32に広げて渡す
+                    let (specifier, width) = match (signed, reference.get_bit_width() > 32) {
+                        (true, false) => ("%d", 32),
+                        (false, false) => ("%u", 32),
+                        (true, true) => ("%lld", 64),
+                        (false, true) => ("%llu", 64),
+                    };
+                    format.push_str(specifier);
+                    arg_values.push(self.create_int_cast(value.into_int_value(), self.context.custom_width_int_type(width), signed).as_basic_value_enum());
+                },
+                (KSCType::Bool(_), Some(value)) => {
+                    format.push_str("%s");
+                    arg_values.push(self.create_bool_to_string(value.into_int_value()).as_basic_value_enum());
+                },
+                (KSCType::String(_), Some(value)) => { format.push_str("%s"); arg_values.push(value); },
+                (valuetype, _) => return Err(CompileError{ message: format!("Function 'format' can only format Number, integers, Bool and String, but '{}' was given.", valuetype.type_name()), span: None })
+            }
+            format.push_str(piece);
+        }
+        return Ok((format, arg_values));
+    }
+
+    /// 組み込み関数fma(a, b, c)をコンパイルする
+    fn compile_fma(&mut self, args: &'ctx Vec<Expression>) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        if args.len() != 3 {
+            return Err(CompileError{ message: format!("Function 'fma' takes 3 arguments but {} were given.", args.len()), span: None });
+        }
+        let mut operands: Vec<FloatValue> = vec![];
+        for (i, arg) in args.iter().enumerate() {
+            let executed = self.compile_expression(arg)?;
+            operands.push(match (&executed.valuetype, executed.value) {
+                (KSCType::Number(_), Some(value)) => value.into_float_value(),
+                _ => return Err(CompileError{ message: format!("Argument {} of function 'fma' must be Number, but it is '{}'.", i + 1, executed.valuetype.type_name()), span: None })
+            });
+        }
+        let value = self.create_fma(operands[0], operands[1], operands[2]);
+        return Ok(KSCValue{ valuetype: KSCType::Number(self.context.f64_type()), value: Some(value.as_basic_value_enum()) });
+    }
+
+    /// assert(condition, "message")をコンパイルする(strip_assertsなら条件も評価しない)
+    fn compile_assert(&mut self, args: &'ctx Vec<Expression>) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        let message = match args.get(1).map(|arg| arg.unspanned()) {
+            None if args.len() == 1 => "assertion failed\n".to_string(),
+            Some(Expression::StringLiteral(message)) if args.len() == 2 => format!("assertion failed: {message}\n"),
+            _ => return Err(CompileError{ message: "'assert' takes a condition and an optional string literal message.".to_string(), span: None })
+        };
+        if self.strip_asserts {
+            return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+        }
+        let executed = self.compile_expression(&args[0])?;
+        let condition = match (&executed.valuetype, executed.value) {
+            (KSCType::Bool(_), Some(condition)) => condition.into_int_value(),
+            _ => return Err(CompileError{ message: format!("The condition of 'assert' must be Bool, but it is '{}'.", executed.valuetype.type_name()), span: None })
+        };
+        self.create_assert(condition, &message);
+        return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+    }
+
+    /// wrapping_add、trapping_add、checked_addなど(引数は同じ型の整数2つ)
+    /// checkedはOptionalを返し、あふれたときは値なしになる
+    fn compile_overflowing(&mut self, op: BinaryOperator, mode: OverflowMode, args: &'ctx Vec<Expression>) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        let name = format!("{}_{}", match mode {
+            OverflowMode::Wrap => "wrapping",
+            OverflowMode::Trap => "trapping",
+            OverflowMode::Checked => "checked"
+        }, match op {
+            BinaryOperator::ADD => "add",
+            BinaryOperator::SUB => "sub",
+            _ => "mul"
+        });
+        if args.len() != 2 {
+            return Err(CompileError{ message: format!("Function '{name}' takes 2 arguments but {} were given.", args.len()), span: None });
+        }
+        let left = self.compile_expression(&args[0])?;
+        let right = self.compile_expression(&args[1])?;
+        let (valuetype, signed, left_value, right_value) = match (&left.valuetype, &right.valuetype, left.value, right.value) {
+            (KSCType::Int { reference, signed }, KSCType::Int { reference: right_reference, signed: right_signed }, Some(left_value), Some(right_value))
+                if reference == right_reference && signed == right_signed => (left.valuetype.clone(), *signed, left_value.into_int_value(), right_value.into_int_value()),
+            _ => return Err(CompileError{ message: format!("Function '{name}' takes two integers of the same type, but '{}' and '{}' were given.", left.valuetype.type_name(), right.valuetype.type_name()), span: None })
+        };
+        let value = match mode {
+            OverflowMode::Wrap => match op {
+                BinaryOperator::ADD => self.builder.build_int_add(left_value, right_value, "add"),
+                BinaryOperator::SUB => self.builder.build_int_sub(left_value, right_value, "sub"),
+                _ => self.builder.build_int_mul(left_value, right_value, "mul")
+            },
+            OverflowMode::Trap => self.create_trapping_int_operator(op, left_value, right_value, signed),
+            OverflowMode::Checked => {
+                let (value, overflowed) = self.create_overflowing_int_operator(op, left_value, right_value, signed);
+                // 値なしの中身は0にそろえる(NoneLiteralと同じ)
+                let payload = self.builder.build_select(overflowed, value.get_type().const_zero(), value, "payload");
+                let present = self.builder.build_not(overflowed, "present");
+                let optional = self.get_optional_type(valuetype)?;
+                let reference = match &optional {
+                    KSCType::Optional { reference, .. } => *reference,
+                    _ => unreachable!()
+                };
+                let result = self.create_tuple(reference, &vec![present.as_basic_value_enum(), payload]);
+                return Ok(KSCValue{ valuetype: optional, value: Some(result) });
+            }
+        };
+        return Ok(KSCValue{ valuetype, value: Some(value.as_basic_value_enum()) });
+    }
+
+    /// いまコンパイルしている関数の名前(関数の外ならトップレベル)
+    fn current_function_name(&self) -> String {
+        return self.stack_current_function.last()
+                .map(|func| func.get_name().to_string_lossy().to_string())
+                .unwrap_or_else(||"<top level>".to_string());
+    }
+
+    /// 構造体や配列の式を、中身を指せるようにポインタとして得る(変数ならそのalloca、それ以外は一時領域に置く)
+    fn compile_aggregate_pointer(&mut self, expression: &'ctx Expression) -> Result<(PointerValue<'ctx>, KSCType<'ctx>), CompileError> where 'a: 'ctx{
+        if let Expression::Identifier(name) = expression.unspanned() {
+            if let Some(variable) = self.search_variable(name) {
+                return Ok((variable.pointer, variable.valuetype.clone()));
+            }
+        }
+        let executed = self.compile_expression(expression)?;
+        let value = executed.value.ok_or_else(|| CompileError{ message: "Void has no fields or elements.".to_string(), span: None })?;
+        let pointer = self.create_entry_block_alloca(value.get_type(), "aggregate_tmp");
+        self.create_store_variable(pointer, &value);
+        return Ok((pointer, executed.valuetype));
+    }
+
+    /// 文の位置にある`let b = a`と`b = a`で、aが構造体、タプルか配列の変数なら、値を読まずにcreate_aggregate_copyで写す(写したらtrue)
+    /// 参照を持つ値(参照を数えなおす)と、型が同じでないときや@alignがあるとき(エラーを変えない)は、ふつうにコンパイルする
+    fn compile_aggregate_copy(&mut self, expression: &'ctx Expression) -> Result<bool, CompileError> where 'a: 'ctx{
+        if let Expression::Spanned { span, expression } = expression {
+            let previous_span = self.current_span.replace(*span);
+            self.restore_debug_location();
+            let result = self.compile_aggregate_copy(expression);
+            self.current_span = previous_span;
+            self.restore_debug_location();
+            return result;
+        }
+        let (target, source) = match expression {
+            Expression::VariableDeclaration { align: None, value, .. } | Expression::Assignment { value, .. } => match value.unspanned() {
+                Expression::Identifier(source) => (expression, source),
+                _ => return Ok(false)
+            },
+            _ => return Ok(false)
+        };
+        let (source_pointer, sourcetype) = match self.search_variable(source) {
+            Some(variable) if matches!(variable.valuetype, KSCType::Struct { .. } | KSCType::Tuple { .. } | KSCType::Array { .. }) && !Self::is_reference_counted(&variable.valuetype) => (variable.pointer, variable.valuetype.clone()),
+            _ => return Ok(false)
+        };
+        if self.stack_current_function.is_empty() {
+            return Ok(false);
+        }
+        let basic_type = self.get_basic_type(&sourcetype).unwrap_or_else(||panic!("'{source}' has no type."));
+        match target {
+            Expression::VariableDeclaration { typename, name, mutable, .. } => {
+                if !(typename.is_empty() || typename == "auto") {
+                    let vartype = self.search_ksc_type(typename)?;
+                    if self.types_compatible(&vartype, &sourcetype) != Compatibility::Identical {
+                        return Ok(false);
+                    }
+                }
+                let pointer = self.create_entry_block_alloca(basic_type, name);
+                self.create_aggregate_copy(pointer, source_pointer, basic_type);
+                self.insert_new_variable_to_stack(name, KSCVariable{ valuetype: sourcetype, pointer, mutable: *mutable });
+            },
+            Expression::Assignment { target, .. } => {
+                // 同じ変数どうしではllvm.memcpyの範囲が重なる
+                let pointer = match self.search_variable(target) {
+                    Some(variable) if target != source && variable.mutable && self.types_compatible(&variable.valuetype, &sourcetype) == Compatibility::Identical => variable.pointer,
+                    _ => return Ok(false)
+                };
+                self.create_aggregate_copy(pointer, source_pointer, basic_type);
+            },
+            _ => unreachable!()
+        }
+        return Ok(true);
+    }
+
+    /// 無名関数をコンパイルする(関数の中で宣言された、外側の変数を使う関数もこれでクロージャにする)
+    fn compile_closure(&mut self, return_type: &String, param_types: &'ctx [String], param_names: &'ctx [String], content: &'ctx [Expression]) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        let started = std::time::Instant::now();
+        let return_type_ksc = self.search_ksc_type(return_type)?;
+        let param_types_ksc = param_types.iter().map(|p| self.search_ksc_type(p)).collect::<Result<Vec<KSCType>, CompileError>>()?;
+        if param_types.len() != param_names.len() {
+            panic!("The number of parameters does not match the type and name.");
+        }
+
+        // 本体で使われている外側の変数を捕まえる
+        let captures = self.outer_variables(param_names, content);
+
+        // 捕まえた変数の値を環境の構造体にコピーする(環境はヒープに置く)
+        let mut capture_types = vec![];
+        for (name, valuetype, _) in &captures {
+            capture_types.push(self.get_basic_type(valuetype).ok_or_else(|| CompileError{ message: format!("Closure cannot capture '{name}' because it is Void."), span: None })?);
+        }
+        let env_type = self.context.struct_type(&capture_types, false);
+        let env_ptr_type = env_type.ptr_type(AddressSpace::Generic);
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let env = if captures.is_empty() {
+            i8_ptr_type.const_null()
+        } else {
+            // 環境は参照の数を数え、最後の参照がなくなったら捕まえた値の参照を解放する
+            let capture_ksctypes = captures.iter().map(|(_, valuetype, _)| valuetype.clone()).collect::<Vec<KSCType>>();
+            let drop = self.get_or_create_env_drop(&format!("closure.{}.drop", self.closure_count), env_type, &capture_ksctypes);
+            let env = self.create_rc_allocation(BasicTypeEnum::StructType(env_type), drop, "closure_env");
+            let typed_env = self.builder.build_pointer_cast(env, env_ptr_type, "closure_env");
+            for (index, (name, valuetype, pointer)) in captures.iter().enumerate() {
+                let value = self.create_load_variable(*pointer, name);
+                self.create_retain(value, valuetype);
+                self.create_store_variable(self.create_field_pointer(typed_env, index as u32, name), &value);
+            }
+            env
+        };
+
+        // 本体は環境を先頭の引数に受け取る別の関数に書き出す
+        let fn_type = self.get_signature_type(&return_type_ksc, &param_types_ksc, true)?;
+        let closure_type = self.get_closure_type(fn_type);
+        let module = self.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let closure_name = format!("closure.{}", self.closure_count);
+        let func = module.add_function(&closure_name, fn_type, None);
+        self.closure_count += 1;
+        self.apply_float_mode(func);
+        self.apply_sanitizer(func);
+        let previous_block = self.builder.get_insert_block();
+        let previous_loops = std::mem::take(&mut self.stack_loop);
+        self.builder.position_at_end(self.context.append_basic_block(func, "entry"));
+        self.attach_debug_info(func, &closure_name);
+        self.create_shadow_stack_push(&closure_name);
+        self.stack_current_function.push(func);
+
+        // 捕まえた変数は環境の中を指す変更できない変数として、仮引数は普通の変数として登録する
+        // 捕まえた値の参照は環境が持っているので、スコープを抜けても解放しない
+        self.function_frames.push((self.stack.len(), self.temporaries.len()));
+        self.enter_scope();
+        let typed_env = self.builder.build_pointer_cast(func.get_nth_param(0).unwrap().into_pointer_value(), env_ptr_type, "env");
+        for (index, (name, valuetype, _)) in captures.iter().enumerate() {
+            let pointer = self.create_field_pointer(typed_env, index as u32, name);
+            self.insert_new_variable_to_stack(name, KSCVariable{ valuetype: valuetype.clone(), pointer, mutable: false });
+        }
+        for ((param_name, param_type), param) in param_names.iter().zip(param_types_ksc.iter()).zip(func.get_param_iter().skip(1)) {
+            let pointer = self.create_variable(param_name, &param, None);
+            self.retain_parameter(pointer, param_type);
+            self.insert_new_variable_to_stack(param_name, KSCVariable{ valuetype: param_type.clone(), pointer, mutable: false });
+        }
+        self.stack_return_type.push(return_type_ksc.clone());
+        let last = self.compile_statements(content)?;
+        self.stack_return_type.pop();
+        self.exit_scope();
+        self.finish_function_body(&closure_name, &return_type_ksc, last)?;
+        self.finish_function_frame();
+        self.check_function_budget(&closure_name, func, started);
+        self.emit_function_compiled(&closure_name, func);
+        self.stack_current_function.pop();
+        self.restore_debug_location();
+
+        self.stack_loop = previous_loops;
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+        let value = self.create_closure(func, env, closure_type);
+        let result = KSCValue{
+            valuetype: KSCType::Closure{ reference: closure_type, return_type: Box::from(return_type_ksc), parameter: param_types_ksc },
+            value: Some(value)
+        };
+        self.add_temporary(&result);
+        return Ok(result);
+    }
+
+    /// 関数の本体で使われている、外側の関数の変数(仮引数の名前で隠れているものを除く)
+    /// グローバル変数はどの関数からも直接読めるので含めない
+    fn outer_variables(&self, param_names: &[String], content: &'ctx [Expression]) -> Vec<(&'ctx str, KSCType<'ctx>, PointerValue<'ctx>)> {
+        let mut captures: Vec<(&str, KSCType, PointerValue)> = vec![];
+        for name in content.iter().flat_map(|expression| expression.referenced_variables()) {
+            if param_names.iter().any(|param_name| param_name == name) || captures.iter().any(|(captured, _, _)| *captured == name) {
+                continue;
+            }
+            if let Some(variable) = self.stack[1..].iter().rev().find_map(|stack| stack.values.get(name)) {
+                captures.push((name, variable.valuetype.clone(), variable.pointer));
+            }
+        }
+        return captures;
+    }
+
+    /// 関数の仮引数の型を探す
+    /// メソッドの最初の仮引数selfは型をSelfと書き、呼び出し側の値へのポインタ(RawPtr<型>)で受け取る
+    fn search_parameter_types(&mut self, receiver: Option<&KSCType<'ctx>>, param_names: &[String], param_types: &[String]) -> Result<Vec<KSCType<'ctx>>, CompileError> {
+        let mut types = vec![];
+        for (index, (param_name, param_type)) in param_names.iter().zip(param_types).enumerate() {
+            match receiver {
+                Some(impltype) if index == 0 && param_name == "self" => {
+                    if param_type != "Self" {
+                        return Err(CompileError{ message: format!("The type of 'self' must be 'Self', but '{param_type}' was given."), span: None });
+                    }
+                    let reference = self.get_basic_type(impltype).unwrap_or_else(||panic!("Methods cannot be defined for Void!")).ptr_type(AddressSpace::Generic);
+                    types.push(KSCType::RawPtr{ reference, pointee: Box::from(impltype.clone()) });
+                },
+                _ => types.push(self.search_ksc_type(param_type)?)
+            }
+        }
+        return Ok(types);
+    }
+
+    /// 最初の仮引数がobjecttypeへのポインタなら、メソッドとしてobject.method()の形で呼べる
+    fn takes_receiver(parameter: &[KSCType<'ctx>], objecttype: &KSCType<'ctx>) -> bool {
+        return matches!(parameter.first(), Some(KSCType::RawPtr { pointee, .. }) if pointee.type_name() == objecttype.type_name());
+    }
+
+    /// impl ブロックのメソッドをコンパイルする
+    /// 本体より先にすべてのシグネチャを宣言するので、メソッドどうしは定義の順によらず呼び合える
+    fn compile_impl(&mut self, impltype: &KSCType<'ctx>, methods: &'ctx [Expression]) -> Result<(), CompileError> where 'a: 'ctx{
+        for method in methods {
+            let (name, return_type, param_types, param_names) = match method.unspanned() {
+                Expression::Function { name, return_type, param_types, param_names, .. } => (name, return_type, param_types, param_names),
+                expression => return Err(CompileError{ message: format!("An impl block can only contain functions, but '{}' was given.", expression.kind_name()), span: None })
+            };
+            let name = format!("{}::{name}", impltype.type_name());
+            let return_type_ksc = self.search_ksc_type(return_type)?;
+            let param_types_ksc = self.search_parameter_types(Some(impltype), param_names, param_types)?;
+            let param_types = param_types_ksc.iter()
+                                .map(|p| self.get_basic_type(p).unwrap_or_else(||panic!("You cannot expect Void as argument.")).as_any_type_enum())
+                                .collect::<Vec<AnyTypeEnum>>();
+            let func = self.create_function_declare(&name, &self.get_return_type(&return_type_ksc), &param_types);
+            let reference = func.get_type().ptr_type(AddressSpace::Generic);
+            self.register_function(&name, func, KSCType::Function{ reference, return_type: Box::from(return_type_ksc), parameter: param_types_ksc });
+        }
+        for method in methods {
+            self.pending_receiver = Some(impltype.clone());
+            self.compile_expression(method)?;
+        }
+        return Ok(());
+    }
+
+    /// impl ブロックがトレイトのメソッドをすべて同じシグネチャで定義しているか確かめ、型がトレイトを実装したことを記録する
+    /// メソッドの仮引数の型は書かれたとおりに比べる(selfはSelfとして数える)
+    fn check_trait_impl(&mut self, trait_name: &str, impltype: &KSCType<'ctx>, methods: &'ctx [Expression]) -> Result<(), CompileError> where 'a: 'ctx{
+        let (method_names, method_types) = self.traits.get(trait_name)
+                                             .cloned()
+                                             .ok_or_else(|| CompileError{ message: format!("Trait '{trait_name}' is not defined."), span: None })?;
+        let typename = impltype.type_name();
+        let mut defined = vec![];
+        for method in methods {
+            let (name, return_type, param_types) = match method.unspanned() {
+                Expression::Function { name, return_type, param_types, .. } => (name, return_type, param_types),
+                _ => continue
+            };
+            let index = method_names.iter()
+                            .position(|method_name| method_name == name)
+                            .ok_or_else(|| CompileError{ message: format!("Method '{name}' is not a member of trait '{trait_name}'."), span: None })?;
+            let expected = self.search_ksc_type(&method_types[index])?.type_name();
+            let parameter = param_types.iter().map(|p| self.search_ksc_type(p).map(|p| p.type_name())).collect::<Result<Vec<String>, CompileError>>()?.join(", ");
+            let actual = format!("Function({parameter}) -> {}", self.search_ksc_type(return_type)?.type_name());
+            if expected != actual {
+                return Err(CompileError{ message: format!("Method '{name}' of '{typename}' does not match trait '{trait_name}'. Expected '{expected}', but '{actual}' was given."), span: None });
+            }
+            defined.push(name);
+        }
+        if let Some(missing) = method_names.iter().find(|method_name| !defined.contains(method_name)) {
+            return Err(CompileError{ message: format!("Implementation of trait '{trait_name}' for '{typename}' is missing method '{missing}'."), span: None });
+        }
+        self.trait_impls.push((trait_name.to_string(), typename));
+        return Ok(());
+    }
+
+    /// 総称型の関数に型引数を与えた関数(max<Point>)を作って、その名前を返す。同じ型引数なら前に作った関数を使う
+    /// 型引数が制約のトレイトを実装しているかはここで確かめる。本体は、型引数の名前を実際の型の名前にしたスコープでコンパイルする
+    fn instantiate_generic_function(&mut self, callee: &str) -> Result<String, CompileError> where 'a: 'ctx{
+        let (base, args) = callee[..callee.len() - 1].split_once('<').unwrap();
+        let function = self.generic_functions[base];
+        let type_params = match function.unspanned() {
+            Expression::Function { type_params, .. } => type_params,
+            _ => unreachable!()
+        };
+        let args = split_type_list(args).iter().map(|arg| self.search_ksc_type(arg)).collect::<Result<Vec<KSCType>, CompileError>>()?;
+        if args.len() != type_params.len() {
+            return Err(CompileError{ message: format!("Generic function '{base}' takes {} type arguments, but {} were given.", type_params.len(), args.len()), span: None });
+        }
+        let name = format!("{base}<{}>", args.iter().map(|arg| arg.type_name()).collect::<Vec<String>>().join(", "));
+        if self.search_function(&name).is_some() {
+            return Ok(name);
+        }
+        for ((param, bounds), arg) in type_params.iter().zip(args.iter()) {
+            if let Some(bound) = bounds.iter().find(|bound| !self.trait_impls.contains(&(bound.to_string(), arg.type_name()))) {
+                return Err(CompileError{ message: format!("Type '{}' does not implement trait '{bound}', which is required by '{param}' of '{base}'.", arg.type_name()), span: None });
+            }
+        }
+
+        // 本体から呼び出し側の変数が見えないように、グローバルのスコープの上でコンパイルする
+        let previous_block = self.builder.get_insert_block();
+        let previous_loops = std::mem::take(&mut self.stack_loop);
+        let outer_scopes = self.stack.split_off(1);
+        self.enter_scope();
+        for ((param, _), arg) in type_params.iter().zip(args) {
+            self.insert_new_type_to_stack(param, arg);
+        }
+        self.pending_instance = Some(name.clone());
+        let result = self.compile_expression(function);
+        self.pending_instance = None;
+        self.stack.truncate(1);
+        self.stack.extend(outer_scopes);
+        self.stack_loop = previous_loops;
+        if let Some(block) = previous_block {
+            self.builder.position_at_end(block);
+        }
+        result?;
+        return Ok(name);
+    }
+
+    /// スコープを抜ける(そのスコープで定義された変数や型は見えなくなり、変数が持っていた参照は解放される)
+    fn exit_scope(&mut self) {
+        if self.stack.len() <= 1 {
+            panic!("Cannot exit the global scope!");
+        }
+        let scope = self.stack.pop().unwrap();
+        self.release_variables(&scope.owned);
+    }
+
+    /// 変数が参照カウントで管理する値を持つなら、いまのスコープを抜けるときに解放するよう登録する(値の参照は持ち主が先に取っておく)
+    fn own_variable(&mut self, pointer: PointerValue<'ctx>, valuetype: &KSCType<'ctx>) {
+        if Self::is_reference_counted(valuetype) {
+            self.stack.last_mut().unwrap_or_else(||panic!("There is no stack yet!")).owned.push((pointer, valuetype.clone()));
+        }
+    }
+
+    /// 変数の値を読んで、その参照を解放する
+    fn release_variables(&self, owned: &[(PointerValue<'ctx>, KSCType<'ctx>)]) {
+        for (pointer, valuetype) in owned.iter().rev() {
+            if self.can_insert_instructions() {
+                let value = self.create_load_variable(*pointer, "owned");
+                self.create_release(value, valuetype);
+            }
+        }
+    }
+
+    /// 式が新しく作った参照を覚えておく(文の終わりまでに持ち主が決まらなければ解放される)
+    fn add_temporary(&mut self, value: &KSCValue<'ctx>) {
+        if let (true, Some(basic_value)) = (Self::is_reference_counted(&value.valuetype), value.value) {
+            self.temporaries.push((basic_value, value.valuetype.clone()));
+        }
+    }
+
+    /// 値が覚えておいた一時的な参照なら、そこから外す(外せたかを返す)。参照をそのまま別の持ち主に渡すときに使う
+    fn claim_temporary(&mut self, value: &KSCValue<'ctx>) -> bool {
+        let position = value.value.and_then(|basic_value| self.temporaries.iter().rposition(|(temporary, _)| *temporary == basic_value));
+        if let Some(position) = position {
+            self.temporaries.remove(position);
+            return true;
+        }
+        return false;
+    }
+
+    /// 値の参照を1つ持ち主のものにする(一時的な参照ならそれを引き取り、変数などから読んだだけの値なら参照の数を増やす)
+    /// 変数への代入や、リスト・クロージャの環境・構造体などに値をしまうときに使う
+    fn take_ownership(&mut self, value: &KSCValue<'ctx>) {
+        if !self.claim_temporary(value) {
+            if let Some(basic_value) = value.value {
+                self.create_retain(basic_value, &value.valuetype);
+            }
+        }
+    }
+
+    /// mark以降に覚えた一時的な参照をすべて解放する
+    fn release_temporaries(&mut self, mark: usize) {
+        let temporaries = self.temporaries.split_off(mark.min(self.temporaries.len()));
+        for (value, valuetype) in temporaries.iter().rev() {
+            self.create_release(*value, valuetype);
+        }
+    }
+
+    /// returnの前に、いまの関数のスコープの変数と一時的な参照をすべて解放する(スコープ自体はそのまま)
+    fn release_function_frame(&self) {
+        let (scope_depth, temporaries) = match self.function_frames.last() {
+            Some(frame) => *frame,
+            None => return
+        };
+        for scope in self.stack[scope_depth.min(self.stack.len())..].iter().rev() {
+            self.release_variables(&scope.owned);
+        }
+        for (value, valuetype) in self.temporaries[temporaries.min(self.temporaries.len())..].iter().rev() {
+            self.create_release(*value, valuetype);
+        }
+    }
+
+    /// 仮引数の値の参照を1つ取り、関数のスコープを抜けるときに解放するよう登録する
+    fn retain_parameter(&mut self, pointer: PointerValue<'ctx>, valuetype: &KSCType<'ctx>) {
+        if Self::is_reference_counted(valuetype) {
+            let value = self.create_load_variable(pointer, "param");
+            self.create_retain(value, valuetype);
+            self.own_variable(pointer, valuetype);
+        }
+    }
+
+    /// 関数の本体を閉じたあとに、その関数の一時的な参照の記録を捨てる(returnかfinish_function_bodyで始末済み)
+    fn finish_function_frame(&mut self) {
+        if let Some((_, temporaries)) = self.function_frames.pop() {
+            self.temporaries.truncate(temporaries);
+        }
+    }
+
+    /// 文の並び(関数やブロックの本体)をコンパイルして、最後の式の値を返す(空ならVoid)
+    /// 最後以外の式の値は捨てられ、文の中で作られた一時的な参照は文の終わりで解放される
+    /// 最後の式の値は参照を1つ持った一時的な値として返す(ブロックのスコープを抜けても残る)
+    fn compile_statements(&mut self, content: &'ctx [Expression]) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        let mut last = KSCValue{ valuetype: KSCType::Void, value: None };
+        for (index, expression) in content.iter().enumerate() {
+            let mark = self.temporaries.len();
+            if index + 1 < content.len() && self.compile_aggregate_copy(expression)? {
+                continue;
+            }
+            last = self.compile_expression(expression)?;
+            if index + 1 < content.len() {
+                self.check_unused_result(expression, &last);
+                self.release_temporaries(mark);
+            } else {
+                self.take_ownership(&last);
+                self.release_temporaries(mark);
+                self.add_temporary(&last);
+            }
+        }
+        return Ok(last);
+    }
+
+    /// 関数の命令数やコード生成にかかった時間が上限を超えていたら警告する(startedは関数のコード生成を始めた時刻)
+    /// 警告の位置は関数の定義(コード生成が膨らんだ元)
+    fn check_function_budget(&mut self, name: &str, func: FunctionValue<'ctx>, started: std::time::Instant) {
+        let elapsed = started.elapsed();
+        if let Some(budget) = self.instruction_budget {
+            let instructions = self.count_instructions(func);
+            if instructions > budget {
+                let message = format!("Function '{name}' has {instructions} instructions, which exceeds the budget of {budget}.");
+                self.diagnostics.warning(CompileError{ message, span: self.current_span });
+            }
+        }
+        if let Some(budget) = self.codegen_time_budget {
+            if elapsed > budget {
+                let message = format!("Compiling function '{name}' took {} ms, which exceeds the budget of {} ms.", elapsed.as_millis(), budget.as_millis());
+                self.diagnostics.warning(CompileError{ message, span: self.current_span });
+            }
+        }
+    }
+
+    /// 捨てられる値が、値を作るだけの式の結果か、@must_useの関数や型の値なら警告する(unused result)
+    /// @allow(unused_result)のついた式や、その中の式では警告しない
+    fn check_unused_result(&mut self, expression: &Expression, value: &KSCValue<'ctx>) {
+        let allowed = self.allowed_lints.iter().any(|lint| lint == "unused_result")
+                        || expression.attributes().contains(&&KSCAttribute::Allow("unused_result".to_string()));
+        if allowed || matches!(value.valuetype, KSCType::Void) {
+            return;
+        }
+        let must_use_type = match &value.valuetype {
+            KSCType::Struct { name, .. } | KSCType::NewType { name, .. } => self.must_use_types.contains(name),
+            _ => false
+        };
+        let message = match expression.unspanned() {
+            Expression::Call { callee, .. } if self.must_use_functions.contains(callee) => {
+                format!("The result of '{callee}' ('{}') must be used.", value.valuetype.type_name())
+            },
+            _ if must_use_type => format!("Values of type '{}' must be used.", value.valuetype.type_name()),
+            _ if expression.is_pure_value() => format!("The result of this {} expression ('{}') is unused.", expression.kind_name(), value.valuetype.type_name()),
+            _ => return
+        };
+        let span = match expression {
+            Expression::Spanned { span, .. } => Some(*span),
+            _ => self.current_span
+        };
+        self.diagnostics.warning(CompileError{ message, span });
+    }
+
+    /// 関数の本体の終わりを閉じる(終わりまで来たらVoidの関数はreturnする)
+    /// 最後の式の型が戻り値の型と同じなら、returnを書かなくてもその値を返す
+    fn finish_function_body(&self, name: &str, return_type: &KSCType<'ctx>, last: KSCValue<'ctx>) -> Result<(), CompileError> {
+        let block = self.builder.get_insert_block().unwrap_or_else(||panic!("No function."));
+        if block.get_terminator().is_some() {
+            return Ok(());
+        }
+        if let KSCType::Void = return_type {
+            // 捨てられる最後の式の値(compile_statementsが参照を1つ持たせている)を解放する
+            if let Some(value) = last.value {
+                self.create_release(value, &last.valuetype);
+            }
+            self.create_return(&None);
+            return Ok(());
+        }
+        if self.types_compatible(return_type, &last.valuetype) == Compatibility::Identical {
+            self.create_return(&last.value);
+            return Ok(());
+        }
+        // returnの後ろに作られた、どこからも分岐してこないブロック
+        let is_entry = block.get_parent().and_then(|func| func.get_first_basic_block()) == Some(block);
+        if !is_entry && block.get_first_use().is_none() {
+            self.builder.build_unreachable();
+            return Ok(());
+        }
+        if !matches!(last.valuetype, KSCType::Void) {
+            return Err(CompileError{ message: format!("Function '{name}' must return '{}', but its last expression is '{}'.", return_type.type_name(), last.valuetype.type_name()), span: None });
+        }
+        return Err(CompileError{ message: format!("Function '{name}' must return '{}', but it can reach the end without returning.", return_type.type_name()), span: None });
+    }
+
+    /// matchをコンパイルする
+    /// 腕ごとにパターンを調べるブロックを作り、当てはまれば腕の本体へ、外れれば次の腕へ分岐する。腕の値はphiでまとめる
+    /// パターンがリテラルだけ(最後の腕は_でもよい)でガードもなければ、パターンを順に調べずにswitchで腕へ飛ぶ
+    /// 列挙型はタグをパターンと照らし合わせ、ヴァリアントの値は腕の中でメモリから読む
+    fn compile_match(&mut self, scrutinee: &'ctx Expression, arms: &'ctx Vec<MatchArm>) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        let executed = self.compile_expression(scrutinee)?;
+        let (scrutinee_value, enum_pointer) = match (&executed.valuetype, executed.value) {
+            (KSCType::Bool(_) | KSCType::Int { .. }, Some(value)) => (value.into_int_value(), None),
+            (KSCType::Enum { .. }, Some(value)) => {
+                let pointer = self.create_entry_block_alloca(value.get_type(), "scrutinee");
+                self.create_store_variable(pointer, &value);
+                let tag = self.builder.build_extract_value(value.into_struct_value(), 0, "tag").unwrap().into_int_value();
+                (tag, Some(pointer))
+            },
+            _ => return Err(CompileError{ message: format!("Only Bool, integers and enums can be matched, but the value is '{}'.", executed.valuetype.type_name()), span: None })
+        };
+        self.check_match_patterns(&executed.valuetype, arms)?;
+
+        let end_block = self.create_block("match_end");
+        let arm_blocks = arms.iter().map(|_| self.create_block("match_arm")).collect::<Vec<BasicBlock>>();
+        let use_switch = Self::is_switchable(arms);
+        if use_switch {
+            self.create_match_switch(scrutinee_value, &executed.valuetype, arms, &arm_blocks);
+        }
+        let mut results: Vec<(KSCValue, BasicBlock)> = vec![];
+        for (arm, arm_block) in arms.iter().zip(arm_blocks) {
+            let next_block = match use_switch {
+                true => None,
+                false => {
+                    let condition = self.create_pattern_test(&arm.pattern, scrutinee_value, &executed.valuetype);
+                    let next_block = self.create_block("match_next");
+                    self.builder.build_conditional_branch(condition, arm_block, next_block);
+                    Some(next_block)
+                }
+            };
+
+            self.builder.position_at_end(arm_block);
+            self.enter_scope();
+            let bindings = arm.pattern.bindings().map_err(|message| CompileError{ message, span: None })?;
+            let payload = match (arm.pattern.variant(), enum_pointer) {
+                (Some((variant, names)), Some(pointer)) => {
+                    let payload_types = Self::variant_payload(&executed.valuetype, variant).unwrap();
+                    Some((names, self.create_variant_payload_pointer(pointer, &payload_types), payload_types))
+                },
+                _ => None
+            };
+            for name in bindings {
+                let payload_index = payload.as_ref().and_then(|(names, _, _)| names.iter().position(|payload_name| payload_name == name));
+                let (value, valuetype) = match (payload_index, &payload) {
+                    // ヴァリアントの値は調べる値から借りているので、参照を1つ取っておく
+                    (Some(index), Some((_, payload_pointer, payload_types))) => {
+                        let value = self.create_load_variable(self.create_field_pointer(*payload_pointer, index as u32, name), name);
+                        self.create_retain(value, &payload_types[index]);
+                        (value, payload_types[index].clone())
+                    },
+                    _ => (executed.value.unwrap(), executed.valuetype.clone())
+                };
+                let pointer = self.create_variable(name, &value, None);
+                if payload_index.is_some() {
+                    self.own_variable(pointer, &valuetype);
+                }
+                self.insert_new_variable_to_stack(name, KSCVariable{ valuetype, pointer, mutable: false });
+            }
+            // ガードはパターンに当てはまったあとで評価し、偽なら次の腕へ進む
+            if let Some(guard) = &arm.guard {
+                let mark = self.temporaries.len();
+                let guard = self.compile_expression(guard)?;
+                let guard_value = match (&guard.valuetype, guard.value) {
+                    (KSCType::Bool(_), Some(value)) => value.into_int_value(),
+                    _ => return Err(CompileError{ message: format!("A match guard must be Bool, but it is '{}'.", guard.valuetype.type_name()), span: None })
+                };
+                self.release_temporaries(mark);
+                let body_block = self.create_block("match_body");
+                self.builder.build_conditional_branch(guard_value, body_block, next_block.unwrap());
+                self.builder.position_at_end(body_block);
+            }
+            let last = self.compile_statements(&arm.body)?;
+            self.exit_scope();
+            // 腕の値の参照はphiの値に引き継ぐ
+            self.claim_temporary(&last);
+            self.builder.build_unconditional_branch(end_block);
+            results.push((last, self.builder.get_insert_block().unwrap()));
+
+            if let Some(next_block) = next_block {
+                self.builder.position_at_end(next_block);
+            }
+        }
+        // パターンは網羅されているので、どの腕にも当てはまらないことはない
+        if !use_switch {
+            self.builder.build_unreachable();
+        }
+        self.builder.position_at_end(end_block);
+
+        let resulttype = results.first().map(|(result, _)| result.valuetype.clone()).unwrap_or(KSCType::Void);
+        if results.iter().any(|(result, _)| self.types_compatible(&resulttype, &result.valuetype) == Compatibility::Incompatible) {
+            return Err(CompileError{ message: "All arms of a match must have the same type.".to_string(), span: None });
+        }
+        let basic_type = match self.get_basic_type(&resulttype) {
+            Some(basic_type) => basic_type,
+            None => return Ok(KSCValue{ valuetype: KSCType::Void, value: None })
+        };
+        let phi = self.builder.build_phi(basic_type, "match");
+        for (result, block) in &results {
+            phi.add_incoming(&[(&result.value.unwrap(), *block)]);
+        }
+        let result = KSCValue{ valuetype: resulttype, value: Some(phi.as_basic_value()) };
+        self.add_temporary(&result);
+        return Ok(result);
+    }
+
+    /// ifをコンパイルする
+    /// 両方の枝が同じ型の値で終わればphiでまとめて式の値にする。elseがないか、枝の値がVoidだったり型が違ったりするときは文として扱い、Voidを返す
+    fn compile_if(&mut self, condition: &'ctx Expression, then: &'ctx Vec<Expression>, otherwise: &'ctx Vec<Expression>) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        let executed = self.compile_expression(condition)?;
+        let condition = match (&executed.valuetype, executed.value) {
+            (KSCType::Bool(_), Some(condition)) => condition.into_int_value(),
+            _ => return Err(CompileError{ message: format!("The condition of 'if' must be Bool, but it is '{}'.", executed.valuetype.type_name()), span: None })
+        };
+        let (then_block, else_block, cont_block) = self.create_if_branch(condition);
+
+        // elseがなければ値は捨てるので、thenの値の参照はthenの中で解放する
+        let discard = otherwise.is_empty();
+        let mut results: Vec<(KSCValue, Vec<PointerValue>, BasicBlock)> = vec![];
+        for (block, content) in [(then_block, then), (else_block, otherwise)] {
+            self.start_if_branch(&block);
+            let mark = self.temporaries.len();
+            self.enter_scope();
+            let last = self.compile_statements(content)?;
+            self.exit_scope();
+            // 値の参照は枝を抜けても生きているように引き取り、式の値にならなかったときのために参照のポインタも覚えておく
+            let mut rc_pointers = vec![];
+            if !discard && last.value.is_some() && self.can_insert_instructions() {
+                self.take_ownership(&last);
+                rc_pointers = last.value.map(|value| self.get_rc_pointers(value, &last.valuetype)).unwrap_or_default();
+            }
+            self.release_temporaries(mark);
+            results.push((last, rc_pointers, self.end_if_branch(&cont_block)));
+        }
+        self.builder.position_at_end(cont_block);
+        if discard {
+            return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+        }
+
+        let (then_result, then_pointers, then_end) = &results[0];
+        let (else_result, else_pointers, else_end) = &results[1];
+        let merged = if self.types_compatible(&then_result.valuetype, &else_result.valuetype) == Compatibility::Incompatible {
+            None
+        } else {
+            self.merge_if_branch(then_result.value, else_result.value, *then_end, *else_end, cont_block)
+        };
+        match merged {
+            Some(value) => {
+                let result = KSCValue{ valuetype: then_result.valuetype.clone(), value: Some(value) };
+                self.add_temporary(&result);
+                return Ok(result);
+            },
+            None => {
+                self.create_release_unmerged(&[(*then_end, then_pointers.clone()), (*else_end, else_pointers.clone())]);
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            }
+        }
+    }
+
+    /// whileをコンパイルする
+    /// 条件を評価するブロック(continueで戻る先)と本体のブロックを並べ、条件が成り立たなくなったら(breakしたら)ループの後ろのブロックに進む
+    fn compile_while(&mut self, condition: &'ctx Expression, content: &'ctx [Expression]) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        let header_block = self.create_block("while_cond");
+        let body_block = self.create_block("while_body");
+        let exit_block = self.create_block("while_end");
+        self.builder.build_unconditional_branch(header_block);
+
+        self.builder.position_at_end(header_block);
+        let mark = self.temporaries.len();
+        let executed = self.compile_expression(condition)?;
+        let condition = match (&executed.valuetype, executed.value) {
+            (KSCType::Bool(_), Some(condition)) => condition.into_int_value(),
+            _ => return Err(CompileError{ message: format!("The condition of 'while' must be Bool, but it is '{}'.", executed.valuetype.type_name()), span: None })
+        };
+        // 条件の中で作った参照は、本体に入る前に解放する
+        self.release_temporaries(mark);
+        self.builder.build_conditional_branch(condition, body_block, exit_block);
+
+        self.builder.position_at_end(body_block);
+        self.stack_loop.push(LoopContext{ header_block, exit_block, scope_depth: self.stack.len(), temporaries: self.temporaries.len() });
+        self.enter_scope();
+        let result = self.compile_statements(content);
+        self.exit_scope();
+        self.stack_loop.pop();
+        result?;
+        self.release_temporaries(mark);
+        if self.can_insert_instructions() {
+            self.builder.build_unconditional_branch(header_block);
+        }
+
+        self.builder.position_at_end(exit_block);
+        return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+    }
+
+    /// breakとcontinue。ループの中で宣言した変数と、ループの本体で作った一時的な参照を解放してから飛ぶ
+    fn create_loop_jump(&mut self, loop_context: &LoopContext<'ctx>, target: BasicBlock<'ctx>, after: &str) {
+        for scope in self.stack[loop_context.scope_depth.min(self.stack.len())..].iter().rev() {
+            self.release_variables(&scope.owned);
+        }
+        for (value, valuetype) in self.temporaries[loop_context.temporaries.min(self.temporaries.len())..].iter().rev() {
+            self.create_release(*value, valuetype);
+        }
+        if self.can_insert_instructions() {
+            self.builder.build_unconditional_branch(target);
+        }
+        self.start_unreachable_block(after);
+    }
+
+    /// if letをコンパイルする
+    /// 値があるかどうかで分岐し、thenでは中身を変数として登録してから本体をコンパイルする。両方の値はphiでまとめる
+    fn compile_if_let(&mut self, name: &str, value: &'ctx Expression, then: &'ctx Vec<Expression>, otherwise: &'ctx Vec<Expression>) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        let executed = self.compile_expression(value)?;
+        let (inner, optional) = match (&executed.valuetype, executed.value) {
+            (KSCType::Optional { inner, .. }, Some(optional)) => (*inner.clone(), optional.into_struct_value()),
+            _ => return Err(CompileError{ message: format!("'if let' needs an Optional, but the value is '{}'.", executed.valuetype.type_name()), span: None })
+        };
+        let present = self.builder.build_extract_value(optional, 0, "present").unwrap().into_int_value();
+        let (then_block, else_block, cont_block) = self.create_if_branch(present);
+
+        // elseがなければ値は捨てるので、thenの値の参照はthenの中で解放する
+        let discard = otherwise.is_empty();
+        let mut results: Vec<(KSCValue, BasicBlock)> = vec![];
+        for (block, content, binds) in [(then_block, then, true), (else_block, otherwise, false)] {
+            self.start_if_branch(&block);
+            let mark = self.temporaries.len();
+            self.enter_scope();
+            if binds {
+                let payload = self.builder.build_extract_value(optional, 1, name).unwrap();
+                self.create_retain(payload, &inner);
+                let pointer = self.create_variable(name, &payload, None);
+                self.own_variable(pointer, &inner);
+                self.insert_new_variable_to_stack(name, KSCVariable{ valuetype: inner.clone(), pointer, mutable: false });
+            }
+            let last = self.compile_statements(content)?;
+            self.exit_scope();
+            if discard {
+                self.release_temporaries(mark);
+            } else {
+                self.claim_temporary(&last);
+            }
+            results.push((last, self.end_if_branch(&cont_block)));
+        }
+        self.builder.position_at_end(cont_block);
+
+        let (then_result, then_end) = &results[0];
+        let (else_result, else_end) = &results[1];
+        if discard {
+            return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+        }
+        if self.types_compatible(&then_result.valuetype, &else_result.valuetype) == Compatibility::Incompatible {
+            return Err(CompileError{ message: format!("Both branches of 'if let' must have the same type, but they are '{}' and '{}'.", then_result.valuetype.type_name(), else_result.valuetype.type_name()), span: None });
+        }
+        let (then_value, else_value) = match (then_result.value, else_result.value) {
+            (Some(then_value), Some(else_value)) => (then_value, else_value),
+            _ => return Ok(KSCValue{ valuetype: KSCType::Void, value: None })
+        };
+        let phi = self.builder.build_phi(then_value.get_type(), "if_let");
+        phi.add_incoming(&[(&then_value, *then_end), (&else_value, *else_end)]);
+        let result = KSCValue{ valuetype: then_result.valuetype.clone(), value: Some(phi.as_basic_value()) };
+        self.add_temporary(&result);
+        return Ok(result);
+    }
+
+    /// matchをswitchにできるか(ガードがなく、パターンがリテラルかその|で、_は最後の腕のパターン全体としてだけ使われている)
+    /// 束縛があってもよい(束縛される値は調べる値そのもの)
+    fn is_switchable(arms: &Vec<MatchArm>) -> bool {
+        return arms.iter().enumerate().all(|(index, arm)| {
+            let alternatives = arm.pattern.alternatives();
+            let literals = alternatives.iter().all(|alternative| matches!(alternative, Pattern::Bool(_) | Pattern::Int(_) | Pattern::Variant { .. }));
+            let wildcard = index + 1 == arms.len() && matches!(alternatives.as_slice(), [Pattern::Wildcard]);
+            arm.guard.is_none() && (literals || wildcard)
+        });
+    }
+
+    /// matchの腕へ飛ぶswitchを作る(_の腕がなければ、どのリテラルにも当てはまらないことはないのでunreachableへ飛ぶ)
+    fn create_match_switch(&self, value: IntValue<'ctx>, valuetype: &KSCType<'ctx>, arms: &Vec<MatchArm>, arm_blocks: &[BasicBlock<'ctx>]) {
+        let inttype = value.get_type();
+        let mut cases = vec![];
+        let mut default_block = None;
+        for (arm, arm_block) in arms.iter().zip(arm_blocks) {
+            for alternative in arm.pattern.alternatives() {
+                match alternative {
+                    Pattern::Bool(boolean) => cases.push((inttype.const_int(*boolean as u64, false), *arm_block)),
+                    Pattern::Int(number) => cases.push((inttype.const_int(*number as u64, true), *arm_block)),
+                    Pattern::Variant { variant, .. } => cases.push((inttype.const_int(Self::variant_tag(valuetype, variant).unwrap() as u64, false), *arm_block)),
+                    _ => default_block = Some(*arm_block)
+                }
+            }
+        }
+        let current_block = self.builder.get_insert_block().unwrap();
+        let default_block = default_block.unwrap_or_else(|| {
+            let unreachable_block = self.create_block("match_unreachable");
+            self.builder.position_at_end(unreachable_block);
+            self.builder.build_unreachable();
+            self.builder.position_at_end(current_block);
+            unreachable_block
+        });
+        self.builder.build_switch(value, default_block, &cases);
+    }
+
+    /// パターンに当てはまるかを調べる条件(i1)
+    /// 列挙型ではvalueはタグ
+    fn create_pattern_test(&self, pattern: &Pattern, value: IntValue<'ctx>, valuetype: &KSCType<'ctx>) -> IntValue<'ctx> {
+        let inttype = value.get_type();
+        let signed = valuetype.is_signed();
+        return match pattern {
+            Pattern::Wildcard => self.context.custom_width_int_type(1).const_all_ones(),
+            Pattern::Bool(boolean) => {
+                let expected = inttype.const_int(*boolean as u64, false);
+                self.create_comparison_operator(Predicate::EQUAL, value.as_basic_value_enum(), expected.as_basic_value_enum(), false)
+            },
+            Pattern::Int(number) => {
+                let expected = inttype.const_int(*number as u64, true);
+                self.create_comparison_operator(Predicate::EQUAL, value.as_basic_value_enum(), expected.as_basic_value_enum(), signed)
+            },
+            Pattern::Range { start, end } => {
+                let start = inttype.const_int(*start as u64, true);
+                let end = inttype.const_int(*end as u64, true);
+                let above = self.create_comparison_operator(Predicate::GREATER_THAN_OR_EQUAL, value.as_basic_value_enum(), start.as_basic_value_enum(), signed);
+                let below = self.create_comparison_operator(Predicate::LESS_THAN_OR_EQUAL, value.as_basic_value_enum(), end.as_basic_value_enum(), signed);
+                self.builder.build_and(above, below, "in_range")
+            },
+            Pattern::Or(patterns) => {
+                let mut condition = self.context.custom_width_int_type(1).const_zero();
+                for pattern in patterns {
+                    let test = self.create_pattern_test(pattern, value, valuetype);
+                    condition = self.builder.build_or(condition, test, "any_of");
+                }
+                condition
+            },
+            Pattern::Binding { pattern, .. } => self.create_pattern_test(pattern, value, valuetype),
+            Pattern::Variant { variant, .. } => {
+                let expected = inttype.const_int(Self::variant_tag(valuetype, variant).unwrap() as u64, false);
+                self.create_comparison_operator(Predicate::EQUAL, value.as_basic_value_enum(), expected.as_basic_value_enum(), false)
+            },
+        };
+    }
+
+    /// 列挙型のヴァリアントのタグ
+    fn variant_tag(valuetype: &KSCType<'ctx>, variant: &str) -> Option<usize> {
+        return match valuetype {
+            KSCType::Enum { variant_names, .. } => variant_names.iter().position(|variant_name| variant_name == variant),
+            _ => None
+        };
+    }
+
+    /// 列挙型のヴァリアントの値の型
+    fn variant_payload(valuetype: &KSCType<'ctx>, variant: &str) -> Option<Vec<KSCType<'ctx>>> {
+        return match valuetype {
+            KSCType::Enum { payloads, .. } => Self::variant_tag(valuetype, variant).map(|tag| payloads[tag].clone()),
+            _ => None
+        };
+    }
+
+    /// matchのパターンを調べる(型が合っているか、決して選ばれない腕がないか、すべての値を網羅しているか)
+    /// 値の集合は両端を含む区間の並びで表す。Boolはfalseを0、trueを1とする
+    fn check_match_patterns(&self, valuetype: &KSCType<'ctx>, arms: &Vec<MatchArm>) -> Result<(), CompileError> {
+        let (min, max): (i128, i128) = match valuetype {
+            KSCType::Bool(_) => (0, 1),
+            KSCType::Int { reference, signed: true } => (-(1 << (reference.get_bit_width() - 1)), (1 << (reference.get_bit_width() - 1)) - 1),
+            KSCType::Int { reference, signed: false } => (0, (1 << reference.get_bit_width()) - 1),
+            KSCType::Enum { variant_names, .. } => (0, variant_names.len() as i128 - 1),
+            _ => unreachable!(),
+        };
+        let mut covered: Vec<(i128, i128)> = vec![];
+        for (index, arm) in arms.iter().enumerate() {
+            arm.pattern.bindings().map_err(|message| CompileError{ message, span: None })?;
+            let alternatives = arm.pattern.alternatives();
+            // |でまとめたヴァリアントでは、束縛した名前の型がヴァリアントごとに違うかもしれない
+            if alternatives.len() > 1 && alternatives.iter().any(|alternative| matches!(alternative, Pattern::Variant { names, .. } if names.iter().any(|name| name != "_"))) {
+                return Err(CompileError{ message: format!("The pattern of arm {} binds values of a variant, so it cannot be combined with '|'.", index + 1), span: None });
+            }
+            let mut arm_covered: Vec<(i128, i128)> = vec![];
+            for alternative in &alternatives {
+                let (start, end) = match (*alternative, valuetype) {
+                    (Pattern::Wildcard, _) => (min, max),
+                    (Pattern::Bool(boolean), KSCType::Bool(_)) => (*boolean as i128, *boolean as i128),
+                    (Pattern::Int(number), KSCType::Int { .. }) => (*number as i128, *number as i128),
+                    (Pattern::Range { start, end }, KSCType::Int { .. }) => {
+                        if start > end {
+                            return Err(CompileError{ message: format!("The range {start}..={end} of arm {} is empty.", index + 1), span: None });
+                        }
+                        (*start as i128, *end as i128)
+                    },
+                    (Pattern::Variant { variant, names }, KSCType::Enum { name, .. }) => {
+                        let tag = Self::variant_tag(valuetype, variant)
+                                    .ok_or_else(|| CompileError{ message: format!("Enum '{name}' has no variant '{variant}' (arm {}).", index + 1), span: None })?;
+                        let payload = Self::variant_payload(valuetype, variant).unwrap();
+                        if names.len() != payload.len() {
+                            return Err(CompileError{ message: format!("Variant '{name}::{variant}' has {} values, but the pattern of arm {} has {} names.", payload.len(), index + 1, names.len()), span: None });
+                        }
+                        (tag as i128, tag as i128)
+                    },
+                    _ => return Err(CompileError{ message: format!("The pattern of arm {} does not match the type '{}'.", index + 1, valuetype.type_name()), span: None })
+                };
+                if start < min || end > max {
+                    return Err(CompileError{ message: format!("The pattern of arm {} is out of the range of '{}'.", index + 1, valuetype.type_name()), span: None });
+                }
+                // 前の腕や同じ腕の前の選択肢ですでに網羅されている値しかないパターンは決して選ばれない
+                let seen = covered.iter().chain(arm_covered.iter()).cloned().collect::<Vec<(i128, i128)>>();
+                if Self::interval_gaps(&seen, start, end).is_empty() {
+                    if alternatives.len() == 1 {
+                        return Err(CompileError{ message: format!("Arm {} is unreachable because the earlier arms already cover its pattern.", index + 1), span: None });
+                    }
+                    return Err(CompileError{ message: format!("'{alternative}' in arm {} is already covered by an earlier pattern.", index + 1), span: None });
+                }
+                arm_covered.push((start, end));
+            }
+            // ガードのある腕は当てはまらないことがあるので、網羅の計算には入れない
+            if arm.guard.is_none() {
+                covered.extend(arm_covered);
+            }
+        }
+        let mut gaps = Self::interval_gaps(&covered, min, max);
+        // ヴァリアントには順序がないので、足りないヴァリアントは1つずつ挙げる
+        if let KSCType::Enum { .. } = valuetype {
+            gaps = gaps.iter().flat_map(|(start, end)| (*start..=*end).map(|tag| (tag, tag))).collect();
+        }
+        if !gaps.is_empty() {
+            let describe = |value: i128| match valuetype {
+                KSCType::Bool(_) => (value == 1).to_string(),
+                KSCType::Enum { variant_names, .. } => variant_names[value as usize].to_string(),
+                _ => value.to_string(),
+            };
+            let missing = gaps.iter().take(3).map(|(start, end)| {
+                if start == end { describe(*start) } else { format!("{}..={}", describe(*start), describe(*end)) }
+            }).collect::<Vec<String>>().join(", ");
+            let more = if gaps.len() > 3 { ", ..." } else { "" };
+            return Err(CompileError{ message: format!("The match is not exhaustive. Missing: {missing}{more}"), span: None });
+        }
+        return Ok(());
+    }
+
+    /// start..=endのうちcoveredのどの区間にも含まれない部分
+    fn interval_gaps(covered: &Vec<(i128, i128)>, start: i128, end: i128) -> Vec<(i128, i128)> {
+        let mut sorted = covered.clone();
+        sorted.sort();
+        let mut gaps = vec![];
+        let mut next = start;
+        for (covered_start, covered_end) in sorted {
+            if covered_end < next {
+                continue;
+            }
+            if covered_start > end {
+                break;
+            }
+            if covered_start > next {
+                gaps.push((next, covered_start - 1));
+            }
+            next = next.max(covered_end + 1);
+        }
+        if next <= end {
+            gaps.push((next, end));
+        }
+        return gaps;
+    }
+
+    /// 論理演算(&&と||)をコンパイルする
+    /// 左辺で結果が決まるとき(&&で偽、||で真)は右辺を評価しない
+    fn compile_logical(&mut self, op: LogicalOperator, lhs: &'ctx Expression, rhs: &'ctx Expression) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        let operator = match op {
+            LogicalOperator::AND => "&&",
+            LogicalOperator::OR => "||",
+        };
+        let left = self.compile_expression(lhs)?;
+        let left_value = match (&left.valuetype, left.value) {
+            (KSCType::Bool(_), Some(value)) => value.into_int_value(),
+            _ => return Err(CompileError{ message: format!("The left side of '{operator}' must be Bool, but it is '{}'.", left.valuetype.type_name()), span: None })
+        };
+        let booltype = self.context.bool_type();
+        let (then_block, else_block, cont_block) = self.create_if_branch(left_value);
+
+        // &&は左辺が真のとき、||は左辺が偽のときだけ右辺を評価する
+        let (evaluate_block, shortcut_block, shortcut_value) = match op {
+            LogicalOperator::AND => (then_block, else_block, booltype.const_zero()),
+            LogicalOperator::OR => (else_block, then_block, booltype.const_all_ones()),
+        };
+        self.start_if_branch(&evaluate_block);
+        // 右辺で作られた一時的な参照は、評価されたときだけ通るブロックの中で解放する
+        let mark = self.temporaries.len();
+        let right = self.compile_expression(rhs)?;
+        let right_value = match (&right.valuetype, right.value) {
+            (KSCType::Bool(_), Some(value)) => value,
+            _ => return Err(CompileError{ message: format!("The right side of '{operator}' must be Bool, but it is '{}'.", right.valuetype.type_name()), span: None })
+        };
+        self.release_temporaries(mark);
+        let evaluate_end = self.end_if_branch(&cont_block);
+        self.start_if_branch(&shortcut_block);
+        let shortcut_end = self.end_if_branch(&cont_block);
+
+        let value = match op {
+            LogicalOperator::AND => self.merge_if_branch(Some(right_value), Some(shortcut_value.as_basic_value_enum()), evaluate_end, shortcut_end, cont_block),
+            LogicalOperator::OR => self.merge_if_branch(Some(shortcut_value.as_basic_value_enum()), Some(right_value), shortcut_end, evaluate_end, cont_block),
+        };
+        return Ok(self.create_bool_value(value.unwrap_or_else(||panic!("Both sides of a logical operation are Bool.")).into_int_value()));
+    }
+
+    /// 比較演算をコンパイルする(比較できる型か確かめてから比較する)
+    /// 構造体などは、==と!=ならequalsメソッド、大小の比較ならcompareメソッド(負、0、正の整数を返す)を呼んで比べる
+    fn compile_comparison(&mut self, predicate: Predicate, valuetype: &KSCType<'ctx>, left: BasicValueEnum<'ctx>, right: BasicValueEnum<'ctx>) -> Result<KSCValue<'ctx>, CompileError> {
+        if Self::has_operator_methods(valuetype) {
+            let method = match predicate {
+                Predicate::EQUAL | Predicate::NOT_EQUAL => "equals",
+                _ => "compare"
+            };
+            let result = self.create_operator_method_call(method, valuetype, left, right)?;
+            let value = result.value.ok_or_else(|| CompileError{ message: format!("Method '{method}' of '{}' must return a value.", valuetype.type_name()), span: None })?;
+            return match (predicate, &result.valuetype) {
+                (Predicate::EQUAL, KSCType::Bool(_)) => Ok(result),
+                (Predicate::NOT_EQUAL, KSCType::Bool(_)) => Ok(KSCValue{ valuetype: result.valuetype, value: Some(self.create_unary_operator(UnaryOperator::NOT, &value)) }),
+                (Predicate::EQUAL | Predicate::NOT_EQUAL, _) => Err(CompileError{ message: format!("Method 'equals' of '{}' must return Bool.", valuetype.type_name()), span: None }),
+                (_, KSCType::Int { reference, signed }) => {
+                    let zero = reference.const_zero().as_basic_value_enum();
+                    Ok(self.create_bool_value(self.create_comparison_operator(predicate, value, zero, *signed)))
+                },
+                _ => Err(CompileError{ message: format!("Method 'compare' of '{}' must return an integer.", valuetype.type_name()), span: None })
+            };
+        }
+        if !matches!(valuetype, KSCType::Number(_) | KSCType::Int { .. } | KSCType::Bool(_)) {
+            return Err(CompileError{ message: "Only Number, integers and Bool are comparable.".to_string(), span: None });
+        }
+        return Ok(self.create_bool_value(self.create_comparison_operator(predicate, left, right, valuetype.is_signed())));
+    }
+
+    /// 演算子をメソッドで定義できる型(構造体、列挙型、newtype)
+    fn has_operator_methods(valuetype: &KSCType<'ctx>) -> bool {
+        return matches!(valuetype, KSCType::Struct { .. } | KSCType::Enum { .. } | KSCType::NewType { .. });
+    }
+
+    /// 演算子を定義するメソッドの名前(+ならadd)。ビット演算とシフトはメソッドで定義できない
+    fn operator_method_name(op: BinaryOperator) -> Option<&'static str> {
+        return match op {
+            BinaryOperator::ADD => Some("add"),
+            BinaryOperator::SUB => Some("sub"),
+            BinaryOperator::MUL => Some("mul"),
+            BinaryOperator::DIV => Some("div"),
+            BinaryOperator::REM => Some("rem"),
+            _ => None
+        };
+    }
+
+    /// 演算子を定義するメソッド(型名::method(self, other: Self))を呼ぶ。左辺はselfとしてポインタで渡す
+    fn create_operator_method_call(&mut self, method: &str, valuetype: &KSCType<'ctx>, left: BasicValueEnum<'ctx>, right: BasicValueEnum<'ctx>) -> Result<KSCValue<'ctx>, CompileError> {
+        let callee = format!("{}::{method}", valuetype.type_name());
+        let return_type = match self.search_function(&callee).map(|(_, ksctype)| ksctype) {
+            Some(KSCType::Function { return_type, parameter, .. }) if parameter.len() == 2 && Self::takes_receiver(parameter, valuetype) && parameter[1].type_name() == valuetype.type_name() => return_type.as_ref().clone(),
+            Some(_) => return Err(CompileError{ message: format!("Method '{callee}' must take '(self, other: Self)' to be used as an operator."), span: None }),
+            None => return Err(CompileError{ message: format!("'{}' does not support this operator. Define the method '{method}' in an impl block to overload it.", valuetype.type_name()), span: None })
+        };
+        let pointer = self.create_entry_block_alloca(left.get_type(), "operand");
+        self.create_store_variable(pointer, &left);
+        let value = self.create_function_call(&callee, &vec![pointer.as_basic_value_enum(), right]);
+        let result = KSCValue{ valuetype: return_type, value };
+        self.add_temporary(&result);
+        return Ok(result);
+    }
+
+    /// コンパイル時に評価した定数をLLVMの定数にする
+    fn create_constant_value(&self, constant: ConstantValue) -> KSCValue<'ctx> {
+        return match constant {
+            ConstantValue::Number(number) => {
+                let numbertype = self.context.f64_type();
+                KSCValue{ valuetype: KSCType::Number(numbertype), value: Some(numbertype.const_float(number).as_basic_value_enum()) }
+            },
+            ConstantValue::Int(number) => {
+                let inttype = self.context.i32_type();
+                KSCValue{ valuetype: KSCType::Int{ reference: inttype, signed: true }, value: Some(inttype.const_int(number as u64, true).as_basic_value_enum()) }
+            },
+            ConstantValue::Bool(value) => self.create_bool_value(self.context.bool_type().const_int(value as u64, false))
+        };
+    }
+
+    /// トップレベルの変数宣言をグローバル変数にする。どの関数からも読め、mutならどの関数からも書き込める
+    /// 初期値はコンパイル時に評価できる式か関数で、初期化のためのコードは作らない
+    fn compile_global_variable(&mut self, typename: &String, name: &str, mutable: bool, align: Option<u32>, value: &'ctx Expression) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        let executed = match self.fold_constant(value)? {
+            Some(constant) => self.create_constant_value(constant),
+            None if matches!(value.unspanned(), Expression::Function { .. }) => self.compile_expression(value)?,
+            None => return Err(CompileError{ message: format!("The initial value of global variable '{name}' must be a constant or a function."), span: None })
+        };
+        let initial_value = executed.value.ok_or_else(|| CompileError{ message: format!("Variable '{name}' cannot be initialized with Void."), span: None })?;
+        let inferred = typename.is_empty() || typename == "auto" || (typename == "Function" && matches!(executed.valuetype, KSCType::Function { .. }));
+        let vartype = if inferred { executed.valuetype.clone() } else { self.search_ksc_type(typename)? };
+        if self.types_compatible(&vartype, &executed.valuetype) == Compatibility::Incompatible {
+            return Err(CompileError{ message: format!("Cannot be assigned because the type is different. '{name}' is declared as '{}', but the value is '{}'.", vartype.type_name(), executed.valuetype.type_name()), span: None });
+        }
+        let pointer = self.create_global_variable(name, &initial_value, mutable, align);
+        self.insert_new_variable_to_stack(name, KSCVariable{ valuetype: vartype, pointer, mutable });
+        return Ok(executed);
+    }
+
+    /// 条件(i1)をBoolの値にする。比較や論理演算の結果はすべてこれを通してBoolとして扱う
+    fn create_bool_value(&self, condition: IntValue<'ctx>) -> KSCValue<'ctx> {
+        return KSCValue{ valuetype: KSCType::Bool(self.context.bool_type()), value: Some(condition.as_basic_value_enum()) };
+    }
+
+    /// Boolを整数やNumberとして使おうとしたときのエラー(Boolは明示的にキャストしたときだけ数になる)
+    fn bool_is_not_a_number(&self, usage: &str) -> CompileError {
+        return CompileError{ message: format!("{usage} cannot be Bool. Cast it explicitly to an integer (false is 0, true is 1)."), span: None };
+    }
+
+    /// 同じスコープで同じ名前の型がすでに定義されていればエラーにする
+    fn define_type_name(&self, name: &str) -> Result<(), CompileError> {
+        let already_defined = self.stack.last()
+                                .unwrap_or_else(||panic!("There is no stack yet!"))
+                                .types
+                                .contains_key(name);
+        if already_defined {
+            return Err(CompileError{ message: format!("Type '{name}' is already defined in this scope."), span: None });
+        }
+        return Ok(());
+    }
+
+    /// unsafeブロックの中でなければエラーにする
+    fn require_unsafe(&self, operation: &str) -> Result<(), CompileError> {
+        if self.unsafe_depth == 0 {
+            return Err(CompileError{ message: format!("{operation} is only allowed inside an unsafe block."), span: None });
+        }
+        return Ok(());
+    }
+
+    /// 生ポインタの式をコンパイルして、ポインタと指す先の型を取り出す
+    fn compile_raw_pointer(&mut self, expression: &'ctx Expression) -> Result<(PointerValue<'ctx>, KSCType<'ctx>, KSCType<'ctx>), CompileError> where 'a: 'ctx{
+        let executed = self.compile_expression(expression)?;
+        return match (&executed.valuetype, executed.value) {
+            (KSCType::RawPtr { pointee, .. }, Some(value)) => Ok((value.into_pointer_value(), *pointee.clone(), executed.valuetype.clone())),
+            _ => Err(CompileError{ message: "Expected a RawPtr.".to_string(), span: None })
+        };
+    }
+
+    /// 式をコンパイルする
+    /// 深すぎる入れ子でコンパイラ自身のスタックが溢れないように、深さがmax_expression_depthを超えたらエラーにする
+    fn compile_expression(&mut self, expression: &'ctx Expression) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        if self.expression_depth >= self.max_expression_depth {
+            return Err(CompileError{ message: format!("Expressions are nested too deeply. The limit is {}.", self.max_expression_depth), span: None });
+        }
+        self.expression_depth += 1;
+        let result = self.compile_expression_unguarded(expression);
+        self.expression_depth -= 1;
+        return result;
+    }
+
+    /// 式をコンパイルする(深さの確認はcompile_expressionで行う)
+    fn compile_expression_unguarded(&mut self, expression: &'ctx Expression) -> Result<KSCValue<'ctx>, CompileError> where 'a: 'ctx{
+        match expression {
+            Expression::Function { name, return_type, param_types, param_names, type_params, content } => {
+                let started = std::time::Instant::now();
+
+                // impl ブロックのメソッドなら、名前を型名::メソッド名にする
+                // 総称型の関数は宣言を覚えておくだけにして、型引数を与えた関数(instantiate_generic_function)のときだけコンパイルする
+                let receiver = self.pending_receiver.take();
+                let name = match (&receiver, type_params.is_empty()) {
+                    (Some(impltype), true) => format!("{}::{name}", impltype.type_name()),
+                    (None, true) => name.to_string(),
+                    (Some(_), false) => return Err(CompileError{ message: format!("Method '{name}' cannot have type parameters."), span: None }),
+                    (None, false) => match self.pending_instance.take() {
+                        Some(instance) => instance,
+                        None => {
+                            self.generic_functions.insert(name.to_string(), expression);
+                            return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+                        }
+                    }
+                };
+                // 関数の中で宣言された関数が外側の変数を使うなら、それを捕まえたクロージャを関数の名前の変数に入れる
+                if receiver.is_none() && !self.stack_current_function.is_empty() && !self.outer_variables(param_names, content).is_empty() {
+                    let closure = self.compile_closure(return_type, param_types, param_names, content)?;
+                    let value = closure.value.unwrap_or_else(||panic!("A closure has no value."));
+                    self.take_ownership(&closure);
+                    let pointer = self.create_variable(&name, &value, None);
+                    self.own_variable(pointer, &closure.valuetype);
+                    self.insert_new_variable_to_stack(&name, KSCVariable{ valuetype: closure.valuetype.clone(), pointer, mutable: false });
+                    return Ok(closure);
+                }
+                let param_types_ksc = self.search_parameter_types(receiver.as_ref(), param_names, param_types)?;
+                let param_names: Vec<&str> = param_names.iter().map(|s| &**s).collect();
+
+                let return_type_ksc = self.search_ksc_type(return_type)?;
+                let return_type = self.get_return_type(&return_type_ksc);
+
+                let param_types:Vec<BasicMetadataTypeEnum> = param_types_ksc
+                    .iter()
+                    .map(|p|{
+                        return BasicMetadataTypeEnum::from(self.get_basic_type(p).unwrap_or_else(||panic!("You cannot expect Void as argument.")));
+                    }).collect::<Vec<BasicMetadataTypeEnum>>();
+
+                // 関数の中で宣言された関数を書き終えたら、外側の関数の続きに戻る
+                let previous_block = self.builder.get_insert_block();
+                let previous_loops = std::mem::take(&mut self.stack_loop);
+                let (func, param_pointers) = self.create_function(name.as_str(), &return_type, &param_types[..], &param_names);
+                let func_ptr = func.get_type().ptr_type(AddressSpace::Generic);
+                let functype = KSCType::Function { reference: func_ptr, return_type: Box::from(return_type_ksc.clone()), parameter: param_types_ksc.clone() };
+                self.register_function(&name, func, functype.clone());
+                self.stack_current_function.push(func);
+
+                // 関数本体は新しいスコープで、仮引数を変数として登録してからコンパイルする
+                // 仮引数の値は呼び出し側から借りているので、参照を1つ取っておく
+                self.function_frames.push((self.stack.len(), self.temporaries.len()));
+                self.enter_scope();
+                for (index, ((param_name, param_type), pointer)) in param_names.iter().zip(param_types_ksc.iter()).zip(param_pointers).enumerate() {
+                    // selfは呼び出し側の値へのポインタなので、そのポインタを変数の場所にする(値は呼び出し側が持っている)
+                    if let (Some(impltype), 0, "self") = (&receiver, index, *param_name) {
+                        let pointer = self.create_load_variable(pointer, "self").into_pointer_value();
+                        self.insert_new_variable_to_stack(param_name, KSCVariable{ valuetype: impltype.clone(), pointer, mutable: false });
+                        continue;
+                    }
+                    self.retain_parameter(pointer, param_type);
+                    self.insert_new_variable_to_stack(param_name, KSCVariable{ valuetype: param_type.clone(), pointer, mutable: false });
+                }
+                self.stack_return_type.push(return_type_ksc.clone());
+                let last = self.compile_statements(content)?;
+                self.stack_return_type.pop();
+                self.exit_scope();
+                self.finish_function_body(&name, &return_type_ksc, last)?;
+                self.finish_function_frame();
+                self.check_function_budget(&name, func, started);
+                self.emit_function_compiled(&name, func);
+                self.stack_current_function.pop();
+                self.restore_debug_location();
+                self.stack_loop = previous_loops;
+                if let Some(block) = previous_block {
+                    self.builder.position_at_end(block);
+                }
+
+                let func_kscvalue = KSCValue{
+                    valuetype: functype,
+                    value: Some(func.as_global_value().as_pointer_value().as_basic_value_enum())
+                };
+                return Ok(func_kscvalue);
+            },
+            Expression::Closure { return_type, param_types, param_names, content } => {
+                return self.compile_closure(return_type, param_types, param_names, content);
+            },
+            Expression::Impl { typename, trait_name, methods } => {
+                let impltype = self.search_ksc_type(typename)?;
+                if self.get_basic_type(&impltype).is_none() {
+                    return Err(CompileError{ message: "Methods cannot be defined for Void.".to_string(), span: None });
+                }
+                self.impl_types.push(impltype.clone());
+                let result = match trait_name {
+                    Some(trait_name) => self.check_trait_impl(trait_name, &impltype, methods),
+                    None => Ok(())
+                };
+                let result = result.and_then(|_| self.compile_impl(&impltype, methods));
+                self.impl_types.pop();
+                result?;
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+            Expression::TraitDeclaration { name, method_names, method_types } => {
+                if self.traits.contains_key(name) {
+                    return Err(CompileError{ message: format!("Trait '{name}' is already defined."), span: None });
+                }
+                if let Some(method_type) = method_types.iter().find(|method_type| !method_type.starts_with("Function(")) {
+                    return Err(CompileError{ message: format!("Methods of trait '{name}' must have a function type, but '{method_type}' was given."), span: None });
+                }
+                self.traits.insert(name.to_string(), (method_names.clone(), method_types.clone()));
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+            Expression::ExternFunction { name, return_type, param_types } => {
+                let return_type_ksc = self.search_ksc_type(return_type)?;
+                let param_types_ksc = param_types.iter().map(|p| self.search_ksc_type(p)).collect::<Result<Vec<KSCType>, CompileError>>()?;
+                let mut param_types = vec![];
+                for param_type in &param_types_ksc {
+                    let param_type = self.get_basic_type(param_type)
+                                        .ok_or_else(|| CompileError{ message: format!("Extern function '{name}' cannot take Void as argument."), span: None })?;
+                    param_types.push(param_type.as_any_type_enum());
+                }
+                let func = self.create_function_declare(name.as_str(), &self.get_return_type(&return_type_ksc), &param_types);
+                self.extern_functions.push(name.to_string());
+                let func_ptr = func.get_type().ptr_type(AddressSpace::Generic);
+                let functype = KSCType::Function { reference: func_ptr, return_type: Box::from(return_type_ksc), parameter: param_types_ksc };
+                self.register_function(name, func, functype.clone());
+                return Ok(KSCValue{
+                    valuetype: functype,
+                    value: Some(func.as_global_value().as_pointer_value().as_basic_value_enum())
+                });
+            },
+            Expression::StructDeclaration { name, type_params, field_names, field_types, defaults } => {
+                if field_names.len() != field_types.len() || field_names.len() != defaults.len() {
+                    panic!("The number of fields does not match the names, types and defaults of struct '{name}'.");
+                }
+                self.define_type_name(name)?;
+                if self.generic_structs.contains_key(name) {
+                    return Err(CompileError{ message: format!("Type '{name}' is already defined in this scope."), span: None });
+                }
+                // 総称型の構造体は、型引数を与えて使われるまでフィールドの型を決められない
+                if !type_params.is_empty() {
+                    if defaults.iter().any(|default| default.is_some()) {
+                        return Err(CompileError{ message: format!("Fields of generic struct '{name}' cannot have default values."), span: None });
+                    }
+                    self.generic_structs.insert(name.to_string(), (type_params.clone(), field_names.clone(), field_types.clone()));
+                    return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+                }
+                let contents = field_types.iter().map(|field_type| self.search_ksc_type(field_type)).collect::<Result<Vec<KSCType>, CompileError>>()?;
+                let mut field_llvm_types = vec![];
+                let mut default_values = vec![];
+                for ((field_name, field_type), default) in field_names.iter().zip(contents.iter()).zip(defaults.iter()) {
+                    let field_llvm_type = self.get_basic_type(field_type)
+                                            .ok_or_else(|| CompileError{ message: format!("Field '{field_name}' of struct '{name}' cannot be Void."), span: None })?;
+                    field_llvm_types.push(field_llvm_type);
+                    let default_value = match default {
+                        Some(default) => {
+                            let executed = self.compile_expression(default)?;
+                            if self.types_compatible(field_type, &executed.valuetype) == Compatibility::Incompatible {
+                                return Err(CompileError{ message: format!("The default value of field '{field_name}' of struct '{name}' must be '{}', but it is '{}'.", field_type.type_name(), executed.valuetype.type_name()), span: None });
+                            }
+                            if !executed.value.map_or(false, |value| self.is_constant(&value)) {
+                                return Err(CompileError{ message: format!("The default value of field '{field_name}' of struct '{name}' must be a constant."), span: None });
+                            }
+                            Some(executed)
+                        },
+                        None => None
+                    };
+                    default_values.push(default_value);
+                }
+                let (reference, field_indices) = self.create_ksc_struct_type(name, &field_llvm_types, self.repr_c_structs.contains(name));
+                self.insert_new_type_to_stack(name, KSCType::Struct{
+                    name: name.to_string(),
+                    reference,
+                    field_names: field_names.clone(),
+                    contents,
+                    default_values,
+                    field_indices
+                });
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+            Expression::EnumDeclaration { name, variant_names, variant_types } => {
+                if variant_names.len() != variant_types.len() {
+                    panic!("The number of variants does not match the names and types of enum '{name}'.");
+                }
+                self.define_type_name(name)?;
+                if variant_names.is_empty() {
+                    return Err(CompileError{ message: format!("Enum '{name}' needs at least 1 variant."), span: None });
+                }
+                let mut payloads = vec![];
+                for (index, (variant, types)) in variant_names.iter().zip(variant_types.iter()).enumerate() {
+                    if variant_names[..index].contains(variant) {
+                        return Err(CompileError{ message: format!("Enum '{name}' has variant '{variant}' more than once."), span: None });
+                    }
+                    let payload = types.iter().map(|valuetype| self.search_ksc_type(valuetype)).collect::<Result<Vec<KSCType>, CompileError>>()?;
+                    if payload.iter().any(|valuetype| self.get_basic_type(valuetype).is_none()) {
+                        return Err(CompileError{ message: format!("Variant '{name}::{variant}' cannot hold Void."), span: None });
+                    }
+                    payloads.push(payload);
+                }
+                let enumtype = self.create_enum_type(name, variant_names.clone(), payloads);
+                self.insert_new_type_to_stack(name, enumtype);
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+            Expression::EnumVariant { typename, variant, values } => {
+                let enumtype = self.search_ksc_type(typename)?;
+                let (reference, tag, payload) = match &enumtype {
+                    KSCType::Enum { reference, variant_names, payloads, .. } => {
+                        let tag = variant_names.iter()
+                                    .position(|variant_name| variant_name == variant)
+                                    .ok_or_else(|| CompileError{ message: format!("Enum '{typename}' has no variant '{variant}'."), span: None })?;
+                        (*reference, tag, payloads[tag].clone())
+                    },
+                    _ => return Err(CompileError{ message: format!("'{typename}' is not an enum."), span: None })
+                };
+                if values.len() != payload.len() {
+                    return Err(CompileError{ message: format!("Variant '{typename}::{variant}' takes {} values, but {} were given.", payload.len(), values.len()), span: None });
+                }
+                let pointer = self.create_entry_block_alloca(BasicTypeEnum::StructType(reference), typename);
+                let tag_value = self.context.i32_type().const_int(tag as u64, false).as_basic_value_enum();
+                self.create_store_variable(self.create_field_pointer(pointer, 0, "tag"), &tag_value);
+                let payload_pointer = self.create_variant_payload_pointer(pointer, &payload);
+                for (index, (value, valuetype)) in values.iter().zip(payload.iter()).enumerate() {
+                    let executed = self.compile_expression(value)?;
+                    if self.types_compatible(valuetype, &executed.valuetype) == Compatibility::Incompatible {
+                        return Err(CompileError{ message: format!("Value {} of variant '{typename}::{variant}' must be '{}', but it is '{}'.", index + 1, valuetype.type_name(), executed.valuetype.type_name()), span: None });
+                    }
+                    // ヴァリアントにしまった参照は、列挙型の値を解放するときに一緒に解放する
+                    self.take_ownership(&executed);
+                    self.create_store_variable(self.create_field_pointer(payload_pointer, index as u32, variant), &executed.value.unwrap());
+                }
+                let value = self.create_load_variable(pointer, typename);
+                let result = KSCValue{ valuetype: enumtype, value: Some(value) };
+                self.add_temporary(&result);
+                return Ok(result);
+            },
+            Expression::TypeAlias { name, target } => {
+                self.define_type_name(name)?;
+                let target = self.search_ksc_type(target)?;
+                self.insert_new_type_to_stack(name, target);
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+            Expression::NewTypeDeclaration { name, underlying } => {
+                self.define_type_name(name)?;
+                let underlying = self.search_ksc_type(underlying)?;
+                let underlying_llvm_type = self.get_basic_type(&underlying)
+                                            .ok_or_else(|| CompileError{ message: format!("Newtype '{name}' cannot wrap Void."), span: None })?;
+                let reference = self.create_struct_type(name, &vec![underlying_llvm_type]);
+                self.insert_new_type_to_stack(name, KSCType::NewType{ name: name.to_string(), reference, underlying: Box::from(underlying) });
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+            Expression::Wrap { typename, value } => {
+                let newtype = self.search_ksc_type(typename)?;
+                let (reference, underlying) = match &newtype {
+                    KSCType::NewType { reference, underlying, .. } => (*reference, *underlying.clone()),
+                    _ => return Err(CompileError{ message: format!("'{typename}' is not a newtype."), span: None })
+                };
+                let executed = self.compile_expression(value)?;
+                if self.types_compatible(&underlying, &executed.valuetype) == Compatibility::Incompatible {
+                    return Err(CompileError{ message: format!("Newtype '{typename}' wraps '{}', but the value is '{}'.", underlying.type_name(), executed.valuetype.type_name()), span: None });
+                }
+                let wrapped = executed.value.ok_or_else(|| CompileError{ message: format!("Newtype '{typename}' cannot wrap Void."), span: None })?;
+                let pointer = self.create_entry_block_alloca(BasicTypeEnum::StructType(reference), typename);
+                self.create_store_variable(self.create_field_pointer(pointer, 0, typename), &wrapped);
+                let value = self.create_load_variable(pointer, typename);
+                return Ok(KSCValue{ valuetype: newtype, value: Some(value) });
+            },
+            Expression::Unwrap(value) => {
+                let (pointer, valuetype) = self.compile_aggregate_pointer(value)?;
+                let (name, underlying) = match &valuetype {
+                    KSCType::NewType { name, underlying, .. } => (name.to_string(), *underlying.clone()),
+                    _ => return Err(CompileError{ message: format!("Only newtypes can be unwrapped, but the value is '{}'.", valuetype.type_name()), span: None })
+                };
+                let value = self.create_load_variable(self.create_field_pointer(pointer, 0, &name), &name);
+                return Ok(KSCValue{ valuetype: underlying, value: Some(value) });
+            },
+            Expression::ConstDeclaration { typename, name, value } => {
+                let constant = self.fold_constant(value)?
+                                    .ok_or_else(|| CompileError{ message: format!("The value of constant '{name}' cannot be evaluated at compile time."), span: None })?;
+                let valuetype = self.create_constant_value(constant).valuetype;
+                if !typename.is_empty() {
+                    let consttype = self.search_ksc_type(typename)?;
+                    if consttype.type_name() != valuetype.type_name() {
+                        return Err(CompileError{ message: format!("Constant '{name}' is declared as '{}', but the value is '{}'.", consttype.type_name(), valuetype.type_name()), span: None });
+                    }
+                }
+                self.stack.last_mut().unwrap_or_else(||panic!("There is no stack yet!")).constants.insert(name.to_string(), constant);
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+            Expression::VariableDeclaration { typename, name, mutable, align, value } => {
+                if let Some(align) = align {
+                    if !align.is_power_of_two() {
+                        return Err(CompileError{ message: format!("Alignment of '{name}' must be a power of two, but {align} was given."), span: None });
+                    }
+                }
+                // 関数の外で宣言された変数はグローバル変数にする
+                if self.stack_current_function.is_empty() {
+                    return self.compile_global_variable(typename, name, *mutable, *align, value);
+                }
+                let executed = self.compile_expression(value)?;
+                // 型名が省略されている(空か"auto")ときは初期値の型にする
+                // 型名が単に"Function"や"Closure"のときは、シグネチャを初期値の型から決める
+                let inferred = typename.is_empty()
+                                || typename == "auto"
+                                || (typename == "Function" && matches!(executed.valuetype, KSCType::Function { .. }))
+                                || (typename == "Closure" && matches!(executed.valuetype, KSCType::Closure { .. }));
+                let vartype = if inferred {
+                    if let KSCType::Void = executed.valuetype {
+                        return Err(CompileError{ message: format!("The type of '{name}' cannot be inferred because the value is Void."), span: None });
+                    }
+                    executed.valuetype.clone()
+                } else {
+                    self.search_ksc_type(typename)?
+                };
+                if self.types_compatible(&vartype, &executed.valuetype) == Compatibility::Incompatible {
+                    return Err(CompileError{ message: format!("Cannot be assigned because the type is different. '{name}' is declared as '{}', but the value is '{}'.", vartype.type_name(), executed.valuetype.type_name()), span: None });
+                }
+                let initial_value = executed.value.ok_or_else(|| CompileError{ message: format!("Variable '{name}' cannot be initialized with Void."), span: None })?;
+                self.take_ownership(&executed);
+                let pointer = self.create_variable(name, &initial_value, *align);
+                self.own_variable(pointer, &vartype);
+                self.insert_new_variable_to_stack(name, KSCVariable{ valuetype: vartype, pointer, mutable: *mutable });
+                return Ok(executed);
+            },
+            Expression::NumberLiteral(number) => {
+                let numbertype = self.context.f64_type();
+                let value = self.create_constant_number(&BasicTypeEnum::FloatType(numbertype), *number);
+                return Ok(KSCValue{ valuetype: KSCType::Number(numbertype), value: Some(value) });
+            },
+            Expression::IntLiteral(number) => {
+                if *number < i32::MIN as i64 || *number > i32::MAX as i64 {
+                    return Err(CompileError{ message: format!("Integer literal {number} does not fit in i32."), span: None });
+                }
+                let inttype = self.context.i32_type();
+                let value = self.create_constant_number(&BasicTypeEnum::IntType(inttype), *number as f64);
+                return Ok(KSCValue{ valuetype: KSCType::Int{ reference: inttype, signed: true }, value: Some(value) });
+            },
+            Expression::BoolLiteral(boolean) => {
+                return Ok(self.create_bool_value(self.context.bool_type().const_int(*boolean as u64, false)));
+            },
+            Expression::StringLiteral(text) => {
+                let pointer = self.create_string_literal(text);
+                return Ok(KSCValue{ valuetype: KSCType::String(pointer.get_type()), value: Some(pointer.as_basic_value_enum()) });
+            },
+            Expression::StructLiteral { typename, field_values } => {
+                let structtype = self.search_ksc_type(typename)?;
+                let (reference, field_names, contents, default_values, field_indices) = match &structtype {
+                    KSCType::Struct { reference, field_names, contents, default_values, field_indices, .. } => (*reference, field_names.clone(), contents.clone(), default_values.clone(), field_indices.clone()),
+                    _ => return Err(CompileError{ message: format!("'{typename}' is not a struct."), span: None })
+                };
+                for (field_name, _) in field_values {
+                    if !field_names.contains(field_name) {
+                        return Err(CompileError{ message: format!("Struct '{typename}' has no field named '{field_name}'."), span: None });
+                    }
+                    if field_values.iter().filter(|(name, _)| name == field_name).count() > 1 {
+                        return Err(CompileError{ message: format!("Field '{field_name}' of struct '{typename}' is specified more than once."), span: None });
+                    }
+                }
+                let pointer = self.create_entry_block_alloca(BasicTypeEnum::StructType(reference), typename);
+                for (index, ((field_name, field_type), default)) in field_names.iter().zip(contents.iter()).zip(default_values.iter()).enumerate() {
+                    let executed = match field_values.iter().find(|(name, _)| name == field_name) {
+                        Some((_, value)) => self.compile_expression(value)?,
+                        None => default.clone().ok_or_else(|| CompileError{ message: format!("Field '{field_name}' of struct '{typename}' is missing and has no default value."), span: None })?
+                    };
+                    if self.types_compatible(field_type, &executed.valuetype) == Compatibility::Incompatible {
+                        return Err(CompileError{ message: format!("Field '{field_name}' of struct '{typename}' must be '{}', but it is '{}'.", field_type.type_name(), executed.valuetype.type_name()), span: None });
+                    }
+                    let field_value = executed.value.ok_or_else(|| CompileError{ message: format!("Field '{field_name}' has no value."), span: None })?;
+                    // フィールドにしまった参照は、構造体の値を解放するときに一緒に解放する
+                    self.take_ownership(&executed);
+                    let field_pointer = self.create_field_pointer(pointer, field_indices[index], field_name);
+                    self.create_store_variable(field_pointer, &field_value);
+                }
+                let value = self.create_load_variable(pointer, typename);
+                let result = KSCValue{ valuetype: structtype, value: Some(value) };
+                self.add_temporary(&result);
+                return Ok(result);
+            },
+            Expression::StructConversion { value, target_type } => {
+                let (source, sourcetype) = self.compile_aggregate_pointer(value)?;
+                let targettype = self.search_ksc_type(target_type)?;
+                let (reference, field_names, source_indices, target_indices) = match (&sourcetype, &targettype) {
+                    (KSCType::Struct { field_indices: source_indices, .. }, KSCType::Struct { reference, field_names, field_indices: target_indices, .. }) if self.structurally_equal(&sourcetype, &targettype) => {
+                        (*reference, field_names.clone(), source_indices.clone(), target_indices.clone())
+                    },
+                    _ => return Err(CompileError{ message: format!("'{}' cannot be converted to '{target_type}'. Only structs with the same fields can be converted.", sourcetype.type_name()), span: None })
+                };
+                let destination = self.create_entry_block_alloca(BasicTypeEnum::StructType(reference), target_type);
+                if source_indices == target_indices {
+                    // フィールドの並びが同じなら、構造体ごとまとめて写す
+                    self.create_aggregate_copy(destination, source, BasicTypeEnum::StructType(reference));
+                } else {
+                    // フィールドの並べ方は構造体ごとに違いうるので(@repr(c)など)、フィールドを一つずつ写す
+                    for (index, field_name) in field_names.iter().enumerate() {
+                        let field_value = self.create_load_variable(self.create_field_pointer(source, source_indices[index], field_name), field_name);
+                        self.create_store_variable(self.create_field_pointer(destination, target_indices[index], field_name), &field_value);
+                    }
+                }
+                let converted = self.create_load_variable(destination, target_type);
+                return Ok(KSCValue{ valuetype: targettype, value: Some(converted) });
+            },
+            Expression::FieldAccess { object, field } => {
+                let (pointer, objecttype) = self.compile_aggregate_pointer(object)?;
+                let (index, fieldtype) = match &objecttype {
+                    KSCType::Struct { name, field_names, contents, field_indices, .. } => {
+                        let index = field_names.iter()
+                                        .position(|field_name| field_name == field)
+                                        .ok_or_else(|| CompileError{ message: format!("Struct '{name}' has no field named '{field}'."), span: None })?;
+                        (field_indices[index] as usize, contents[index].clone())
+                    },
+                    KSCType::Tuple { elements, .. } => {
+                        let index = field.parse::<usize>()
+                                        .ok()
+                                        .filter(|index| *index < elements.len())
+                                        .ok_or_else(|| CompileError{ message: format!("The tuple '{}' has no element '{field}'. Elements are accessed by their index, such as '.0'.", objecttype.type_name()), span: None })?;
+                        (index, elements[index].clone())
+                    },
+                    _ => return Err(CompileError{ message: format!("Field '{field}' can only be accessed on a struct or a tuple."), span: None })
+                };
+                let field_pointer = self.create_field_pointer(pointer, index as u32, field);
+                let value = self.create_load_variable(field_pointer, field);
+                return Ok(KSCValue{ valuetype: fieldtype, value: Some(value) });
+            },
+            Expression::ArrayLiteral(elements) => {
+                let mut element_values = vec![];
+                for element in elements {
+                    element_values.push(self.compile_expression(element)?);
+                }
+                let elementtype = element_values.first()
+                                    .map(|element| element.valuetype.clone())
+                                    .ok_or_else(|| CompileError{ message: "The element type of an empty array literal cannot be determined.".to_string(), span: None })?;
+                if element_values.iter().any(|element| self.types_compatible(&elementtype, &element.valuetype) == Compatibility::Incompatible) {
+                    return Err(CompileError{ message: "All elements of an array literal must have the same type.".to_string(), span: None });
+                }
+                let element_basic_type = self.get_basic_type(&elementtype)
+                                            .ok_or_else(|| CompileError{ message: "Arrays cannot contain Void.".to_string(), span: None })?;
+                let reference = element_basic_type.array_type(elements.len() as u32);
+                let arraytype = KSCType::Array{ reference, element: Box::from(elementtype.clone()), length: elements.len() as u32 };
+                // 関数の名前だけを並べた配列は、定数の関数ポインタの表にする
+                let functions = element_values.iter()
+                                    .map(|element| match element.value {
+                                        Some(BasicValueEnum::PointerValue(pointer)) if pointer.is_const() => Some(pointer),
+                                        _ => None
+                                    })
+                                    .collect::<Option<Vec<PointerValue>>>();
+                if let (KSCType::Function { .. }, Some(functions)) = (&elementtype, functions) {
+                    let table = self.create_function_table(element_basic_type.into_pointer_type(), &functions);
+                    let value = self.create_load_variable(table, "fn_table");
+                    return Ok(KSCValue{ valuetype: arraytype, value: Some(value) });
+                }
+                let pointer = self.create_entry_block_alloca(BasicTypeEnum::ArrayType(reference), "array");
+                for element in &element_values {
+                    self.take_ownership(element);
+                }
+                for (i, element) in element_values.iter().enumerate() {
+                    let index = self.context.i32_type().const_int(i as u64, false);
+                    let element_pointer = self.create_element_pointer(pointer, index);
+                    self.create_store_variable(element_pointer, &element.value.unwrap_or_else(||panic!("Array element has no value.")));
+                }
+                let value = self.create_load_variable(pointer, "array");
+                return Ok(KSCValue{ valuetype: arraytype, value: Some(value) });
+            },
+            Expression::Index { array, index } => {
+                let (pointer, arraytype) = self.compile_aggregate_pointer(array)?;
+                let index = self.compile_expression(index)?;
+                let index = match (index.valuetype, index.value) {
+                    (valuetype, Some(index)) if valuetype.is_i32() => index.into_int_value(),
+                    (KSCType::Bool(_), _) => return Err(self.bool_is_not_a_number("An index")),
+                    _ => return Err(CompileError{ message: "An index must be i32.".to_string(), span: None })
+                };
+                return match arraytype {
+                    KSCType::Array { element, length, .. } => {
+                        if let Some(constant) = index.get_sign_extended_constant() {
+                            if constant < 0 || constant >= length as i64 {
+                                return Err(CompileError{ message: format!("Index {constant} is out of bounds for an array of length {length}."), span: None });
+                            }
+                        } else if self.bounds_check {
+                            self.create_bounds_check(index, index.get_type().const_int(length as u64, false));
+                        }
+                        let element_pointer = self.create_element_pointer(pointer, index);
+                        let value = self.create_load_variable(element_pointer, "element");
+                        Ok(KSCValue{ valuetype: *element, value: Some(value) })
+                    },
+                    KSCType::List { element, .. } => {
+                        let list = self.create_load_variable(pointer, "list").into_pointer_value();
+                        let element_type = self.get_basic_type(&element).unwrap_or_else(||panic!("Lists cannot contain Void."));
+                        let value = self.create_list_get(list, index, element_type);
+                        Ok(KSCValue{ valuetype: *element, value: Some(value) })
+                    },
+                    _ => Err(CompileError{ message: format!("'{}' cannot be indexed. Only arrays and lists can be indexed.", arraytype.type_name()), span: None })
+                };
+            },
+            Expression::ListNew(element_type) => {
+                let listtype = self.search_ksc_type(&format!("List<{element_type}>"))?;
+                let list = self.create_list_new(&listtype);
+                let result = KSCValue{ valuetype: listtype, value: Some(list.as_basic_value_enum()) };
+                self.add_temporary(&result);
+                return Ok(result);
+            },
+            Expression::ListPush { list, value } => {
+                let list = self.compile_expression(list)?;
+                let element = match &list.valuetype {
+                    KSCType::List { element, .. } => *element.clone(),
+                    _ => return Err(CompileError{ message: format!("'{}' is not a list.", list.valuetype.type_name()), span: None })
+                };
+                let executed = self.compile_expression(value)?;
+                if self.types_compatible(&element, &executed.valuetype) == Compatibility::Incompatible {
+                    return Err(CompileError{ message: format!("Cannot push '{}' to '{}'.", executed.valuetype.type_name(), list.valuetype.type_name()), span: None });
+                }
+                let list_pointer = list.value.unwrap_or_else(||panic!("A list has no value.")).into_pointer_value();
+                self.take_ownership(&executed);
+                self.create_list_push(list_pointer, &executed.value.ok_or_else(|| CompileError{ message: "A list element has no value.".to_string(), span: None })?);
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+            Expression::ListLen(list) => {
+                let list = self.compile_expression(list)?;
+                let list_pointer = match (&list.valuetype, list.value) {
+                    (KSCType::List { .. }, Some(value)) => value.into_pointer_value(),
+                    _ => return Err(CompileError{ message: format!("'{}' is not a list.", list.valuetype.type_name()), span: None })
+                };
+                let len = self.create_list_len(list_pointer);
+                return Ok(KSCValue{ valuetype: KSCType::Int{ reference: self.context.i32_type(), signed: true }, value: Some(len.as_basic_value_enum()) });
+            },
+            Expression::Identifier(name) => {
+                if let Some(constant) = self.search_constant(name) {
+                    return Ok(self.create_constant_value(constant));
+                }
+                // 変数として見えなければ、関数の名前としてその関数へのポインタにする
+                if self.search_variable(name).is_none() {
+                    if let Some((func, functype)) = self.search_function(name) {
+                        return Ok(KSCValue{ valuetype: functype.clone(), value: Some(func.as_global_value().as_pointer_value().as_basic_value_enum()) });
+                    }
+                }
+                let variable = self.search_variable(name).unwrap_or_else(||panic!("Variable '{name}' is not defined!"));
+                let value = self.create_load_variable(variable.pointer, name);
+                return Ok(KSCValue{ valuetype: variable.valuetype.clone(), value: Some(value) });
+            },
+            Expression::Assignment { target, value } => {
+                let executed = self.compile_expression(value)?;
+                let variable = self.search_variable(target).unwrap_or_else(||panic!("Variable '{target}' is not defined!"));
+                if !variable.mutable {
+                    panic!("Cannot assign twice to immutable variable '{target}'.");
+                }
+                if self.types_compatible(&variable.valuetype, &executed.valuetype) == Compatibility::Incompatible {
+                    return Err(CompileError{ message: format!("Cannot be assigned because the type is different. '{target}' is '{}', but the value is '{}'.", variable.valuetype.type_name(), executed.valuetype.type_name()), span: None });
+                }
+                let new_value = executed.value.ok_or_else(|| CompileError{ message: format!("Void cannot be assigned to variable '{target}'."), span: None })?;
+                let (pointer, valuetype) = (variable.pointer, variable.valuetype.clone());
+                // 新しい値の参照を先に取ってから古い値を解放する(s = sでも解放されない)
+                self.take_ownership(&executed);
+                let old_value = match Self::is_reference_counted(&valuetype) {
+                    true => Some(self.create_load_variable(pointer, target)),
+                    false => None
+                };
+                self.create_store_variable(pointer, &new_value);
+                if let Some(old_value) = old_value {
+                    self.create_release(old_value, &valuetype);
+                }
+                return Ok(executed);
+            },
+            Expression::Call { callee, args } => {
+                if let Some(builtin) = BuiltinFunction::from_name(callee) {
+                    return self.compile_builtin_call(builtin, args);
+                }
+                // 総称型の関数に型引数を与えた呼び出し(max<Point>(a, b))は、その型の関数を作ってから呼ぶ
+                let instance;
+                let callee = match callee.split_once('<') {
+                    Some((base, _)) if callee.ends_with('>') && self.generic_functions.contains_key(base) => {
+                        instance = self.instantiate_generic_function(callee)?;
+                        &instance
+                    },
+                    _ => callee
+                };
+                // 変数として見えなければ、関数の記号表から探す
+                let (calleetype, pointer) = match self.search_variable(callee) {
+                    Some(variable) => (variable.valuetype.clone(), Some(variable.pointer)),
+                    None => (self.search_function(callee).map(|(_, ksctype)| ksctype.clone()).unwrap_or_else(||panic!("Function '{callee}' is not defined!")), None)
+                };
+                let (return_type, parameter, closure) = match calleetype {
+                    KSCType::Function { return_type, parameter, .. } => (*return_type, parameter, None),
+                    KSCType::Closure { return_type, parameter, .. } => (*return_type, parameter, pointer),
+                    _ => panic!("'{callee}' is not a function!")
+                };
+                let arg_values = self.compile_arguments(callee, &parameter, args)?;
+                if self.extern_functions.contains(callee) {
+                    self.unsafe_usages.push(UnsafeUsage::ExternCall{ function: self.current_function_name(), callee: callee.to_string() });
+                }
+                // 記号表にない名前は、関数ポインタを持つ変数として間接的に呼ぶ
+                let value = match (closure, pointer) {
+                    (Some(pointer), _) => self.create_closure_call(self.create_load_variable(pointer, callee).into_struct_value(), &arg_values),
+                    (None, _) if self.search_function(callee).is_some() => self.create_function_call(callee, &arg_values),
+                    (None, Some(pointer)) => self.create_indirect_call(self.create_load_variable(pointer, callee).into_pointer_value(), &arg_values),
+                    (None, None) => panic!("Function '{callee}' is not defined!")
+                };
+                // 外部関数が返した文字列にはヘッダーがないので、参照カウントで管理する文字列にコピーする
+                let value = match (&return_type, value) {
+                    (KSCType::String(_), Some(text)) if self.extern_functions.contains(callee) => {
+                        let string_from_c = self.get_or_create_string_from_c();
+                        self.builder.build_call(string_from_c, &[text.into()], "string").try_as_basic_value().left()
+                    },
+                    (_, value) => value
+                };
+                // 関数の戻り値は参照を1つ持っている
+                let result = KSCValue{ valuetype: return_type, value };
+                self.add_temporary(&result);
+                return Ok(result);
+            },
+            Expression::CallValue { callee, args } => {
+                let executed = self.compile_expression(callee)?;
+                let callee_name = executed.valuetype.type_name();
+                let (return_type, parameter) = match &executed.valuetype {
+                    KSCType::Function { return_type, parameter, .. } | KSCType::Closure { return_type, parameter, .. } => (return_type.as_ref().clone(), parameter.clone()),
+                    _ => return Err(CompileError{ message: format!("'{callee_name}' is not a function."), span: None })
+                };
+                let arg_values = self.compile_arguments(&callee_name, &parameter, args)?;
+                let value = match (&executed.valuetype, executed.value) {
+                    (KSCType::Closure { .. }, Some(closure)) => self.create_closure_call(closure.into_struct_value(), &arg_values),
+                    (_, Some(func)) => self.create_indirect_call(func.into_pointer_value(), &arg_values),
+                    (_, None) => unreachable!()
+                };
+                let result = KSCValue{ valuetype: return_type, value };
+                self.add_temporary(&result);
+                return Ok(result);
+            },
+            Expression::MethodCall { object, method, args } => {
+                let (pointer, objecttype) = self.compile_aggregate_pointer(object)?;
+                let callee = format!("{}::{method}", objecttype.type_name());
+                let (return_type, parameter) = match self.search_function(&callee).map(|(_, ksctype)| ksctype) {
+                    Some(KSCType::Function { return_type, parameter, .. }) if Self::takes_receiver(parameter, &objecttype) => (return_type.as_ref().clone(), parameter.clone()),
+                    Some(_) => return Err(CompileError{ message: format!("'{callee}' does not take 'self'. Call it as '{callee}(...)' instead."), span: None }),
+                    None => return Err(CompileError{ message: format!("Type '{}' has no method '{method}'.", objecttype.type_name()), span: None })
+                };
+                let mut arg_values = vec![pointer.as_basic_value_enum()];
+                arg_values.extend(self.compile_arguments(&callee, &parameter[1..], args)?);
+                let value = self.create_function_call(&callee, &arg_values);
+                let result = KSCValue{ valuetype: return_type, value };
+                self.add_temporary(&result);
+                return Ok(result);
+            },
+            Expression::Unary { op, operand } => {
+                if let Some(constant) = self.fold_constant(expression)? {
+                    return Ok(self.create_constant_value(constant));
+                }
+                let executed = self.compile_expression(operand)?;
+                let allowed = match op {
+                    UnaryOperator::NEG => matches!(executed.valuetype, KSCType::Number(_) | KSCType::Int { signed: true, .. }),
+                    UnaryOperator::NOT => matches!(executed.valuetype, KSCType::Bool(_)),
+                };
+                if !allowed {
+                    let message = match op {
+                        UnaryOperator::NEG => "Negation is only possible with Number and signed integers.",
+                        UnaryOperator::NOT => "Logical not is only possible with Bool.",
+                    };
+                    return Err(CompileError{ message: message.to_string(), span: None });
+                }
+                let operand_value = executed.value.ok_or_else(|| CompileError{ message: "Void cannot be an operand.".to_string(), span: None })?;
+                let value = self.create_unary_operator(*op, &operand_value);
+                return Ok(KSCValue{ valuetype: executed.valuetype, value: Some(value) });
+            },
+            Expression::Binary { op, lhs, rhs } => {
+                // オペランドがリテラルと定数だけなら、コンパイル時に計算した値にする
+                if let Some(constant) = self.fold_constant(expression)? {
+                    return Ok(self.create_constant_value(constant));
+                }
+                if let BinaryOperation::Logical(op) = op {
+                    return self.compile_logical(*op, lhs, rhs);
+                }
+                let left = self.compile_expression(lhs)?;
+                let right = self.compile_expression(rhs)?;
+                if self.types_compatible(&left.valuetype, &right.valuetype) == Compatibility::Incompatible {
+                    return Err(CompileError{ message: format!("The left value and the right value have different types. ('{}' and '{}')", left.valuetype.type_name(), right.valuetype.type_name()), span: None });
+                }
+                let (left_value, right_value) = match (left.value, right.value) {
+                    (Some(left_value), Some(right_value)) => (left_value, right_value),
+                    _ => return Err(CompileError{ message: "Void cannot be an operand.".to_string(), span: None })
+                };
+                match op {
+                    BinaryOperation::Arithmetic(BinaryOperator::ADD) if matches!(left.valuetype, KSCType::String(_)) => {
+                        let value = self.create_string_concat(left_value.into_pointer_value(), right_value.into_pointer_value());
+                        let result = KSCValue{ valuetype: left.valuetype, value: Some(value.as_basic_value_enum()) };
+                        self.add_temporary(&result);
+                        return Ok(result);
+                    },
+                    BinaryOperation::Arithmetic(op) if op.is_bitwise() => {
+                        if let KSCType::Bool(_) = left.valuetype {
+                            return Err(self.bool_is_not_a_number("An operand of a bitwise operation or a shift"));
+                        }
+                        if !matches!(left.valuetype, KSCType::Int { .. }) {
+                            return Err(CompileError{ message: format!("Bitwise operations and shifts are only possible with integers, but the operands are '{}'.", left.valuetype.type_name()), span: None });
+                        }
+                        let value = self.create_binnary_operator(*op, &left_value, &right_value, left.valuetype.is_signed());
+                        return Ok(KSCValue{ valuetype: left.valuetype, value: Some(value) });
+                    },
+                    BinaryOperation::Arithmetic(op) => {
+                        if let (true, Some(method)) = (Self::has_operator_methods(&left.valuetype), Self::operator_method_name(*op)) {
+                            return self.create_operator_method_call(method, &left.valuetype, left_value, right_value);
+                        }
+                        if let KSCType::Bool(_) = left.valuetype {
+                            return Err(self.bool_is_not_a_number("An operand of an arithmetic operation"));
+                        }
+                        if !matches!(left.valuetype, KSCType::Number(_) | KSCType::Int { .. }) {
+                            return Err(CompileError{ message: "Four arithmetic operations are only possible with Number and integers.".to_string(), span: None });
+                        }
+                        if let (BinaryOperator::ADD, KSCType::Number(_)) = (op, &left.valuetype) {
+                            if let Some(value) = self.create_fused_add(left_value.into_float_value(), right_value.into_float_value()) {
+                                return Ok(KSCValue{ valuetype: left.valuetype, value: Some(value.as_basic_value_enum()) });
+                            }
+                        }
+                        let value = self.create_binnary_operator(*op, &left_value, &right_value, left.valuetype.is_signed());
+                        return Ok(KSCValue{ valuetype: left.valuetype, value: Some(value) });
+                    },
+                    BinaryOperation::Comparison(predicate) => {
+                        return self.compile_comparison(*predicate, &left.valuetype, left_value, right_value);
+                    },
+                    BinaryOperation::Logical(_) => unreachable!(),
+                }
+            },
+            Expression::ChainedComparison { operands, predicates } => {
+                if predicates.is_empty() || operands.len() != predicates.len() + 1 {
+                    panic!("A chained comparison needs one more operand than comparisons.");
+                }
+                let booltype = self.context.bool_type();
+                let end_block = self.create_block("chain_end");
+                let mut incoming: Vec<(IntValue, BasicBlock)> = vec![];
+                let mut left = self.compile_expression(&operands[0])?;
+                for (i, predicate) in predicates.iter().enumerate() {
+                    let mark = self.temporaries.len();
+                    let right = self.compile_expression(&operands[i + 1])?;
+                    if self.types_compatible(&left.valuetype, &right.valuetype) == Compatibility::Incompatible {
+                        return Err(CompileError{ message: format!("The left value and the right value have different types. ('{}' and '{}')", left.valuetype.type_name(), right.valuetype.type_name()), span: None });
+                    }
+                    let (left_value, right_value) = match (left.value, right.value) {
+                        (Some(left_value), Some(right_value)) => (left_value, right_value),
+                        _ => return Err(CompileError{ message: "Void cannot be an operand.".to_string(), span: None })
+                    };
+                    let condition = self.compile_comparison(*predicate, &left.valuetype, left_value, right_value)?.value.unwrap().into_int_value();
+                    self.release_temporaries(mark);
+                    let current_block = self.builder.get_insert_block().unwrap();
+                    if i + 1 == predicates.len() {
+                        incoming.push((condition, current_block));
+                        self.builder.build_unconditional_branch(end_block);
+                    } else {
+                        incoming.push((booltype.const_zero(), current_block));
+                        let next_block = self.create_block("chain_next");
+                        self.builder.build_conditional_branch(condition, next_block, end_block);
+                        self.builder.position_at_end(next_block);
+                    }
+                    left = right;
+                }
+                self.builder.position_at_end(end_block);
+                let phi = self.builder.build_phi(booltype, "chained");
+                for (value, block) in &incoming {
+                    phi.add_incoming(&[(value, *block)]);
+                }
+                return Ok(self.create_bool_value(phi.as_basic_value().into_int_value()));
+            },
+            Expression::Cast { value, target_type } => {
+                let executed = self.compile_expression(value)?;
+                let target_ksctype = self.search_ksc_type(target_type)?;
+                let value = match (&executed.valuetype, &target_ksctype, executed.value) {
+                    // 広げるときは元の型の符号で拡張する
+                    (KSCType::Int { signed, .. }, KSCType::Int { reference, .. }, Some(value)) => {
+                        self.create_int_cast(value.into_int_value(), *reference, *signed).as_basic_value_enum()
+                    },
+                    // 小数点以下は切り捨て
+                    (KSCType::Number(_), KSCType::Int { reference, signed }, Some(value)) => {
+                        self.create_float_to_int(value.into_float_value(), *reference, *signed).as_basic_value_enum()
+                    },
+                    (KSCType::Int { signed, .. }, KSCType::Number(reference), Some(value)) => {
+                        self.create_int_to_float(value.into_int_value(), *reference, *signed).as_basic_value_enum()
+                    },
+                    // Boolはfalseが0、trueが1
+                    (KSCType::Bool(_), KSCType::Int { reference, .. }, Some(value)) => {
+                        self.create_int_cast(value.into_int_value(), *reference, false).as_basic_value_enum()
+                    },
+                    (KSCType::Bool(_), KSCType::Number(reference), Some(value)) => {
+                        self.create_int_to_float(value.into_int_value(), *reference, false).as_basic_value_enum()
+                    },
+                    (KSCType::Int { .. } | KSCType::Number(_), KSCType::Bool(_), Some(_)) => {
+                        return Err(CompileError{ message: format!("Cannot cast '{}' to 'Bool'. Compare it with 0 instead.", executed.valuetype.type_name()), span: None });
+                    },
+                    (KSCType::Number(_), KSCType::Number(_), Some(value)) | (KSCType::Bool(_), KSCType::Bool(_), Some(value)) => value,
+                    (_, _, None) => return Err(CompileError{ message: format!("Cannot cast Void to '{}'.", target_ksctype.type_name()), span: None }),
+                    _ => return Err(CompileError{ message: format!("Cannot cast '{}' to '{}'. Only Number, integers and Bool can be cast.", executed.valuetype.type_name(), target_ksctype.type_name()), span: None })
+                };
+                return Ok(KSCValue{ valuetype: target_ksctype, value: Some(value) });
+            },
+            Expression::BitCast { value, target_type } => {
+                let executed = self.compile_expression(value)?;
+                let target_ksctype = self.search_ksc_type(target_type)?;
+                let (source_value, destination_type) = match (executed.value, self.get_basic_type(&target_ksctype)) {
+                    (Some(source_value), Some(destination_type)) => (source_value, destination_type),
+                    _ => return Err(CompileError{ message: format!("bitcast<{target_type}> cannot be used with Void."), span: None })
+                };
+                let source_size = self.get_bit_size(&source_value.get_type());
+                let destination_size = self.get_bit_size(&destination_type);
+                if source_size != destination_size {
+                    return Err(CompileError{ message: format!("bitcast<{target_type}> requires the same size, but the value is {source_size} bits and '{target_type}' is {destination_size} bits."), span: None });
+                }
+                let value = self.create_bitcast(source_value, destination_type);
+                return Ok(KSCValue{ valuetype: target_ksctype, value: Some(value) });
+            },
+            Expression::Unsafe(content) => {
+                self.unsafe_usages.push(UnsafeUsage::Block{ function: self.current_function_name() });
+                self.enter_scope();
+                self.unsafe_depth += 1;
+                let last = self.compile_statements(content)?;
+                self.unsafe_depth -= 1;
+                self.exit_scope();
+                return Ok(last);
+            },
+            Expression::AddressOf(name) => {
+                let variable = self.search_variable(name).unwrap_or_else(||panic!("Variable '{name}' is not defined!"));
+                let pointee = variable.valuetype.clone();
+                let pointer = variable.pointer;
+                return Ok(KSCValue{ valuetype: KSCType::RawPtr{ reference: pointer.get_type(), pointee: Box::from(pointee) }, value: Some(pointer.as_basic_value_enum()) });
+            },
+            Expression::PointerOffset { pointer, offset } => {
+                self.require_unsafe("Pointer arithmetic")?;
+                let (pointer, _, pointer_type) = self.compile_raw_pointer(pointer)?;
+                let offset = self.compile_expression(offset)?;
+                let offset = match (offset.valuetype, offset.value) {
+                    (valuetype, Some(offset)) if valuetype.is_i32() => offset.into_int_value(),
+                    (KSCType::Bool(_), _) => return Err(self.bool_is_not_a_number("A pointer offset")),
+                    _ => return Err(CompileError{ message: "A pointer offset must be i32.".to_string(), span: None })
+                };
+                let value = self.create_pointer_offset(pointer, offset);
+                return Ok(KSCValue{ valuetype: pointer_type, value: Some(value.as_basic_value_enum()) });
+            },
+            Expression::PointerLoad(pointer) => {
+                self.require_unsafe("Dereferencing a RawPtr")?;
+                let (pointer, pointee, _) = self.compile_raw_pointer(pointer)?;
+                let value = self.create_load_variable(pointer, "deref");
+                return Ok(KSCValue{ valuetype: pointee, value: Some(value) });
+            },
+            Expression::PointerStore { pointer, value } => {
+                self.require_unsafe("Writing through a RawPtr")?;
+                let (pointer, pointee, _) = self.compile_raw_pointer(pointer)?;
+                let executed = self.compile_expression(value)?;
+                if self.types_compatible(&pointee, &executed.valuetype) == Compatibility::Incompatible {
+                    return Err(CompileError{ message: format!("The RawPtr points to '{}', but the value is '{}'.", pointee.type_name(), executed.valuetype.type_name()), span: None });
+                }
+                let new_value = executed.value.ok_or_else(|| CompileError{ message: "Void cannot be stored through a RawPtr.".to_string(), span: None })?;
+                self.take_ownership(&executed);
+                self.create_store_variable(pointer, &new_value);
+                return Ok(executed);
+            },
+            Expression::New(value) => {
+                let executed = self.compile_expression(value)?;
+                let new_value = executed.value.ok_or_else(|| CompileError{ message: "Void cannot be allocated with 'new'.".to_string(), span: None })?;
+                // RawPtrの指す先は参照の数を数えないので、しまった参照はdeleteしても解放されない
+                self.take_ownership(&executed);
+                let memory = self.create_heap_allocation(new_value.get_type(), "new");
+                let pointer = self.builder.build_pointer_cast(memory, new_value.get_type().ptr_type(AddressSpace::Generic), "new_pointer");
+                self.create_store_variable(pointer, &new_value);
+                return Ok(KSCValue{ valuetype: KSCType::RawPtr{ reference: pointer.get_type(), pointee: Box::from(executed.valuetype) }, value: Some(pointer.as_basic_value_enum()) });
+            },
+            Expression::Delete(pointer) => {
+                // newで確保したものでないポインタや、解放済みのポインタを渡すと壊れるので、unsafeの中でだけ許す
+                self.require_unsafe("Freeing a RawPtr")?;
+                let (pointer, _, _) = self.compile_raw_pointer(pointer)?;
+                self.create_heap_free(pointer);
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+            Expression::Return(value) => {
+                let return_type = self.stack_return_type.last().cloned()
+                                    .ok_or_else(|| CompileError{ message: "'return' cannot be used outside of a function.".to_string(), span: None })?;
+                let executed = match value {
+                    Some(value) => self.compile_expression(value)?,
+                    None => KSCValue{ valuetype: KSCType::Void, value: None }
+                };
+                if self.types_compatible(&return_type, &executed.valuetype) == Compatibility::Incompatible {
+                    return Err(CompileError{ message: format!("The function returns '{}', but '{}' is returned.", return_type.type_name(), executed.valuetype.type_name()), span: None });
+                }
+                // 戻り値の参照は呼び出し側に渡し、それ以外の変数と一時的な参照は解放する
+                self.take_ownership(&executed);
+                self.release_function_frame();
+                self.create_return(&executed.value);
+                self.start_unreachable_block("after_return");
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+            Expression::TupleLiteral(elements) => {
+                if elements.len() < 2 {
+                    return Err(CompileError{ message: "A tuple needs at least 2 elements.".to_string(), span: None });
+                }
+                let mut element_types = vec![];
+                let mut element_values = vec![];
+                for element in elements {
+                    let executed = self.compile_expression(element)?;
+                    element_values.push(executed.value.ok_or_else(|| CompileError{ message: "Tuples cannot contain Void.".to_string(), span: None })?);
+                    self.take_ownership(&executed);
+                    element_types.push(executed.valuetype);
+                }
+                let reference = self.context.struct_type(&element_values.iter().map(|value| value.get_type()).collect::<Vec<BasicTypeEnum>>(), false);
+                let value = self.create_tuple(reference, &element_values);
+                let result = KSCValue{ valuetype: KSCType::Tuple{ reference, elements: element_types }, value: Some(value) };
+                self.add_temporary(&result);
+                return Ok(result);
+            },
+            Expression::TupleDestructure { names, mutable, value } => {
+                let executed = self.compile_expression(value)?;
+                let (elements, tuple) = match (&executed.valuetype, executed.value) {
+                    (KSCType::Tuple { elements, .. }, Some(tuple)) => (elements.clone(), tuple.into_struct_value()),
+                    _ => return Err(CompileError{ message: format!("Only tuples can be destructured, but the value is '{}'.", executed.valuetype.type_name()), span: None })
+                };
+                if elements.len() != names.len() {
+                    return Err(CompileError{ message: format!("The tuple has {} elements, but {} names were given.", elements.len(), names.len()), span: None });
+                }
+                for (index, (name, element)) in names.iter().zip(elements.iter()).enumerate() {
+                    if name == "_" {
+                        continue;
+                    }
+                    let element_value = self.builder.build_extract_value(tuple, index as u32, name).unwrap();
+                    self.create_retain(element_value, element);
+                    let pointer = self.create_variable(name, &element_value, None);
+                    self.own_variable(pointer, element);
+                    self.insert_new_variable_to_stack(name, KSCVariable{ valuetype: element.clone(), pointer, mutable: *mutable });
+                }
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+            Expression::NoneLiteral(inner) => {
+                let inner = self.search_ksc_type(inner)?;
+                let optional = self.get_optional_type(inner)?;
+                let value = match &optional {
+                    KSCType::Optional { reference, .. } => reference.const_zero().as_basic_value_enum(),
+                    _ => unreachable!()
+                };
+                return Ok(KSCValue{ valuetype: optional, value: Some(value) });
+            },
+            Expression::SomeValue(value) => {
+                let executed = self.compile_expression(value)?;
+                let payload = executed.value.ok_or_else(|| CompileError{ message: "'some' cannot wrap Void.".to_string(), span: None })?;
+                self.take_ownership(&executed);
+                let optional = self.get_optional_type(executed.valuetype)?;
+                let reference = match &optional {
+                    KSCType::Optional { reference, .. } => *reference,
+                    _ => unreachable!()
+                };
+                let present = self.context.bool_type().const_all_ones().as_basic_value_enum();
+                let result = KSCValue{ valuetype: optional, value: Some(self.create_tuple(reference, &vec![present, payload])) };
+                self.add_temporary(&result);
+                return Ok(result);
+            },
+            Expression::If { condition, then, otherwise } => {
+                return self.compile_if(condition, then, otherwise);
+            },
+            Expression::IfLet { name, value, then, otherwise } => {
+                return self.compile_if_let(name, value, then, otherwise);
+            },
+            Expression::Match { scrutinee, arms } => {
+                return self.compile_match(scrutinee, arms);
+            },
+            // importはbuildの前にインターフェースとして読み込んであるので、ここでは何もしない
+            Expression::Import(_) => {
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+            Expression::While { condition, content } => {
+                return self.compile_while(condition, content);
+            },
+            Expression::Break => {
+                let loop_context = *self.stack_loop.last().ok_or_else(|| CompileError{ message: "'break' cannot be used outside of a loop.".to_string(), span: None })?;
+                self.create_loop_jump(&loop_context, loop_context.exit_block, "after_break");
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+            Expression::Attributed { attributes, expression } => {
+                for attribute in attributes {
+                    match (attribute, expression.unspanned()) {
+                        (KSCAttribute::MustUse, Expression::Function { name, .. }) => {
+                            if !self.must_use_functions.contains(name) {
+                                self.must_use_functions.push(name.to_string());
+                            }
+                        },
+                        (KSCAttribute::MustUse, Expression::StructDeclaration { name, .. } | Expression::NewTypeDeclaration { name, .. }) => {
+                            self.must_use_types.push(name.to_string());
+                        },
+                        (KSCAttribute::MustUse, _) => {
+                            return Err(CompileError{ message: "'@must_use' can only be put on functions, structs and newtypes.".to_string(), span: None });
+                        },
+                        (KSCAttribute::Repr(repr), Expression::StructDeclaration { name, .. }) if repr == "c" => {
+                            self.repr_c_structs.push(name.to_string());
+                        },
+                        (KSCAttribute::Repr(repr), Expression::StructDeclaration { .. }) if repr == "ksc" => {},
+                        (KSCAttribute::Repr(repr), Expression::StructDeclaration { .. }) => {
+                            return Err(CompileError{ message: format!("Unknown representation '{repr}'. Use '@repr(ksc)' or '@repr(c)'."), span: None });
+                        },
+                        (KSCAttribute::Repr(_), _) => {
+                            return Err(CompileError{ message: "'@repr' can only be put on structs.".to_string(), span: None });
+                        },
+                        (KSCAttribute::Allow(lint), _) if lint == "unused_result" => {},
+                        (KSCAttribute::Allow(lint), _) => {
+                            return Err(CompileError{ message: format!("Unknown lint '{lint}'. Only 'unused_result' can be allowed."), span: None });
+                        },
+                    }
+                }
+                let previous_lints = self.allowed_lints.len();
+                for attribute in attributes {
+                    if let KSCAttribute::Allow(lint) = attribute {
+                        self.allowed_lints.push(lint.to_string());
+                    }
+                }
+                let result = self.compile_expression(expression);
+                self.allowed_lints.truncate(previous_lints);
+                return result;
+            },
+            Expression::Spanned { span, expression } => {
+                // 内側の位置ほど詳しいので、エラーには最も内側の位置をつける
+                let previous_span = self.current_span.replace(*span);
+                self.restore_debug_location();
+                let result = self.compile_expression(expression);
+                self.current_span = previous_span;
+                self.restore_debug_location();
+                return result.map_err(|mut error| {
+                    error.span.get_or_insert(*span);
+                    error
+                });
+            },
+            Expression::Continue => {
+                let loop_context = *self.stack_loop.last().ok_or_else(|| CompileError{ message: "'continue' cannot be used outside of a loop.".to_string(), span: None })?;
+                self.create_loop_jump(&loop_context, loop_context.header_block, "after_continue");
+                return Ok(KSCValue{ valuetype: KSCType::Void, value: None });
+            },
+        }
+    }
+}
+
+
+/// check_programが解析に使うスレッドのスタックの大きさ
+/// デバッグビルドのAnalyzer::visitは一段で15KBほど使うので、テストのスレッドの2MBではDEFAULT_MAX_EXPRESSION_DEPTHまで潜れない
+const CHECK_STACK_SIZE: usize = 16 * 1024 * 1024;
+
+/// 名前と型の誤りを調べる(LLVMを使わない。fuzz/のターゲットやテストから使う)
+/// 誤りがあれば、表示する形の文字列にしてErrで返す。どんなASTを渡してもpanicしてはいけない
+/// 呼び出し元のスレッドのスタックの大きさによらないように、CHECK_STACK_SIZEのスタックを持つスレッドで解析する
+pub fn check_program(program: &Vec<Expression>) -> Result<(), String> {
+    return check_program_importing(program, &[], &SourceMap::new());
+}
+
+/// ほかのモジュールから読み込んだ名前(importedはCompiler::imported_names)を使えるものとしてcheck_programする
+/// 誤りの位置はsourcesに登録したファイルの行として表示する
+fn check_program_importing(program: &Vec<Expression>, imported: &[String], sources: &SourceMap) -> Result<(), String> {
+    return std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(CHECK_STACK_SIZE)
+            .spawn_scoped(scope, || {
+                let (_, type_diagnostics) = Analyzer::analyze(program, imported, DEFAULT_MAX_EXPRESSION_DEPTH);
+                let mut diagnostics = NameResolver::resolve(program, imported, DEFAULT_MAX_EXPRESSION_DEPTH);
+                diagnostics.append(type_diagnostics);
+                if diagnostics.has_errors() {
+                    return Err(diagnostics.render(sources));
+                }
+                return Ok(());
+            })
+            .unwrap_or_else(|error| panic!("Failed to start the checking thread: {error}"))
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    });
+}
+
+/// インタプリタの値(整数はi32だけを扱う)
+#[derive(Debug, Clone, PartialEq)]
+enum Value{
+    Number(f64),
+    Int(i32),
+    Bool(bool),
+    String(String),
+    Void
+}
+
+impl Value{
+    /// 値の型の名前
+    fn type_name(&self) -> &'static str {
+        return match self {
+            Value::Number(_) => "Number",
+            Value::Int(_) => "i32",
+            Value::Bool(_) => "Bool",
+            Value::String(_) => "String",
+            Value::Void => "Void"
+        };
+    }
+}
+
+/// インタプリタが式の評価を途中でやめる理由
+enum Interrupt{
+    Break,
+    Continue,
+    Return(Value),
+    Error(String)
+}
+
+/// インタプリタの関数呼び出しの深さの上限(これより深い再帰はエラーにする)
+const INTERPRETER_MAX_CALL_DEPTH: usize = 1000;
+
+/// LLVMを使わずにASTをたどって実行するインタプリタ(--no-default-featuresのバックエンド)
+/// 扱えるのはNumber、i32、Bool、Stringの値と、変数、演算、if、while、関数呼び出し、printとprintlnだけで、ほかの式はエラーにする
+/// check_programを通ったプログラムを実行する前提で、型の誤りは調べなおさない
+struct Interpreter<'a>{
+    functions: std::collections::HashMap<&'a str, &'a Expression>,
+    /// 実行中の関数の変数のスコープ(内側が後ろ)
+    scopes: Vec<std::collections::HashMap<String, Value>>,
+    call_depth: usize
+}
+
+impl<'a> Interpreter<'a>{
+    fn new(program: &'a Vec<Expression>) -> Interpreter<'a> {
+        let mut functions = std::collections::HashMap::new();
+        for expression in program {
+            if let Expression::Function { name, .. } = expression.unspanned() {
+                functions.insert(name.as_str(), expression.unspanned());
+            }
+        }
+        return Interpreter{ functions, scopes: vec![], call_depth: 0 };
+    }
+
+    fn unsupported(expression: &Expression) -> Interrupt {
+        return Interrupt::Error(format!("'{}' is not supported by the interpreter yet.", expression.kind_name()));
+    }
+
+    /// 型の名前がインタプリタで扱える型か調べる
+    fn check_type(typename: &str) -> Result<(), Interrupt> {
+        return match typename {
+            "" | "auto" | "Number" | "i32" | "Bool" | "String" | "Void" => Ok(()),
+            _ => Err(Interrupt::Error(format!("Type '{typename}' is not supported by the interpreter yet.")))
+        };
+    }
+
+    /// 関数nameを呼ぶ
+    fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        let (return_type, param_types, param_names, type_params, content) = match self.functions.get(name) {
+            Some(Expression::Function { return_type, param_types, param_names, type_params, content, .. }) => (return_type, param_types, param_names, type_params, content),
+            _ => return Err(format!("Function '{name}' is not defined."))
+        };
+        if !type_params.is_empty() {
+            return Err(format!("Generic function '{name}' is not supported by the interpreter yet."));
+        }
+        if param_names.len() != args.len() {
+            return Err(format!("Function '{name}' takes {} arguments but {} were given.", param_names.len(), args.len()));
+        }
+        for typename in param_types.iter().chain(std::iter::once(return_type)) {
+            if let Err(Interrupt::Error(message)) = Interpreter::check_type(typename) {
+                return Err(message);
+            }
+        }
+        for (index, (arg, param_type)) in args.iter().zip(param_types).enumerate() {
+            if arg.type_name() != param_type {
+                return Err(format!("Argument {} of function '{name}' must be {param_type} but it is {}.", index + 1, arg.type_name()));
+            }
+        }
+        if self.call_depth >= INTERPRETER_MAX_CALL_DEPTH {
+            return Err(format!("Calls are nested deeper than {INTERPRETER_MAX_CALL_DEPTH}."));
+        }
+
+        let parameters = param_names.iter().cloned().zip(args).collect();
+        let saved_scopes = std::mem::replace(&mut self.scopes, vec![parameters]);
+        self.call_depth += 1;
+        let result = self.evaluate_block(content);
+        self.call_depth -= 1;
+        self.scopes = saved_scopes;
+        let value = match result {
+            Ok(value) | Err(Interrupt::Return(value)) => value,
+            Err(Interrupt::Break) => return Err("'break' cannot be used outside of a loop.".to_string()),
+            Err(Interrupt::Continue) => return Err("'continue' cannot be used outside of a loop.".to_string()),
+            Err(Interrupt::Error(message)) => return Err(message)
+        };
+        // 戻り値がVoidの関数は、最後の式の値を捨てる
+        if return_type.is_empty() || return_type == "Void" {
+            return Ok(Value::Void);
+        }
+        return Ok(value);
+    }
+
+    /// 新しいスコープで式を順に評価し、最後の式の値を返す(空ならVoid)
+    fn evaluate_block(&mut self, content: &Vec<Expression>) -> Result<Value, Interrupt> {
+        self.scopes.push(std::collections::HashMap::new());
+        let mut result = Ok(Value::Void);
+        for expression in content {
+            result = self.evaluate(expression);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.scopes.pop();
+        return result;
+    }
+
+    fn variable(&mut self, name: &str) -> Result<&mut Value, Interrupt> {
+        return self.scopes.iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+            .ok_or_else(|| Interrupt::Error(format!("Variable '{name}' is not supported by the interpreter yet.")));
+    }
+
+    fn evaluate(&mut self, expression: &Expression) -> Result<Value, Interrupt> {
+        return match expression {
+            Expression::Spanned { expression, .. } | Expression::Attributed { expression, .. } => self.evaluate(expression),
+            Expression::NumberLiteral(number) => Ok(Value::Number(*number)),
+            Expression::IntLiteral(number) => i32::try_from(*number)
+                .map(Value::Int)
+                .map_err(|_| Interrupt::Error(format!("Integer literal {number} does not fit in i32."))),
+            Expression::BoolLiteral(boolean) => Ok(Value::Bool(*boolean)),
+            Expression::StringLiteral(string) => Ok(Value::String(string.clone())),
+            Expression::Identifier(name) => Ok(self.variable(name)?.clone()),
+            Expression::VariableDeclaration { typename, name, value, .. } | Expression::ConstDeclaration { typename, name, value } => {
+                Interpreter::check_type(typename)?;
+                let value = self.evaluate(value)?;
+                self.scopes.last_mut().unwrap_or_else(||panic!("There is no scope to declare '{name}' in.")).insert(name.clone(), value);
+                Ok(Value::Void)
+            },
+            Expression::Assignment { target, value } => {
+                let value = self.evaluate(value)?;
+                *self.variable(target)? = value;
+                Ok(Value::Void)
+            },
+            Expression::Unary { op, operand } => match (op, self.evaluate(operand)?) {
+                (UnaryOperator::NEG, Value::Number(number)) => Ok(Value::Number(-number)),
+                (UnaryOperator::NEG, Value::Int(number)) => Ok(Value::Int(number.wrapping_neg())),
+                (UnaryOperator::NOT, Value::Bool(boolean)) => Ok(Value::Bool(!boolean)),
+                (op, value) => Err(Interrupt::Error(format!("'{op}' cannot be applied to {} in the interpreter.", value.type_name())))
+            },
+            Expression::Binary { op: BinaryOperation::Logical(op), lhs, rhs } => {
+                let lhs = self.evaluate(lhs)?;
+                match (op, lhs) {
+                    (LogicalOperator::AND, Value::Bool(false)) => Ok(Value::Bool(false)),
+                    (LogicalOperator::OR, Value::Bool(true)) => Ok(Value::Bool(true)),
+                    (_, Value::Bool(_)) => self.evaluate(rhs),
+                    (_, value) => Err(Interrupt::Error(format!("The operands of a logical operator must be Bool, but it is {}.", value.type_name())))
+                }
+            },
+            Expression::Binary { op, lhs, rhs } => {
+                let lhs = self.evaluate(lhs)?;
+                let rhs = self.evaluate(rhs)?;
+                Interpreter::binary(*op, lhs, rhs)
+            },
+            Expression::If { condition, then, otherwise } => {
+                let branch = match self.evaluate(condition)? {
+                    Value::Bool(true) => then,
+                    Value::Bool(false) => otherwise,
+                    value => return Err(Interrupt::Error(format!("The condition of 'if' must be Bool, but it is {}.", value.type_name())))
+                };
+                let value = self.evaluate_block(branch)?;
+                Ok(if otherwise.is_empty() { Value::Void } else { value })
+            },
+            Expression::While { condition, content } => {
+                loop {
+                    match self.evaluate(condition)? {
+                        Value::Bool(true) => {},
+                        Value::Bool(false) => break,
+                        value => return Err(Interrupt::Error(format!("The condition of 'while' must be Bool, but it is {}.", value.type_name())))
+                    }
+                    match self.evaluate_block(content) {
+                        Ok(_) | Err(Interrupt::Continue) => {},
+                        Err(Interrupt::Break) => break,
+                        Err(interrupt) => return Err(interrupt)
+                    }
+                }
+                Ok(Value::Void)
+            },
+            Expression::Break => Err(Interrupt::Break),
+            Expression::Continue => Err(Interrupt::Continue),
+            Expression::Return(value) => {
+                let value = match value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Void
+                };
+                Err(Interrupt::Return(value))
+            },
+            Expression::Unsafe(content) => self.evaluate_block(content),
+            Expression::Call { callee, args } => {
+                let mut values = vec![];
+                for arg in args {
+                    values.push(self.evaluate(arg)?);
+                }
+                match (BuiltinFunction::from_name(callee), values.as_slice()) {
+                    (Some(BuiltinFunction::Print), [value]) => { print!("{}", Interpreter::display(value)?); Ok(Value::Void) },
+                    (Some(BuiltinFunction::Println), [value]) => { println!("{}", Interpreter::display(value)?); Ok(Value::Void) },
+                    (Some(BuiltinFunction::Println), []) => { println!(); Ok(Value::Void) },
+                    (Some(_), _) => Err(Interrupt::Error(format!("Function '{callee}' is not supported by the interpreter yet."))),
+                    (None, _) => self.call(callee, values).map_err(Interrupt::Error)
+                }
+            },
+            expression => Err(Interpreter::unsupported(expression))
+        };
+    }
+
+    /// printとprintlnで表示する文字列(コード生成したprintfと同じく、Numberは小数点以下6桁)
+    fn display(value: &Value) -> Result<String, Interrupt> {
+        return match value {
+            Value::Number(number) => Ok(format!("{number:.6}")),
+            Value::Int(number) => Ok(number.to_string()),
+            Value::Bool(boolean) => Ok(boolean.to_string()),
+            Value::String(string) => Ok(string.clone()),
+            Value::Void => Err(Interrupt::Error("Function 'print' can only print Number, integers, Bool and String.".to_string()))
+        };
+    }
+
+    /// 二項演算(論理演算以外)。整数の演算があふれたときは--overflow wrapと同じく折り返す
+    fn binary(op: BinaryOperation, lhs: Value, rhs: Value) -> Result<Value, Interrupt> {
+        let compare = |ordering: Option<std::cmp::Ordering>, predicate: Predicate| {
+            use std::cmp::Ordering;
+            return Value::Bool(match (predicate, ordering) {
+                (_, None) => matches!(predicate, Predicate::NOT_EQUAL),
+                (Predicate::EQUAL, Some(ordering)) => ordering == Ordering::Equal,
+                (Predicate::NOT_EQUAL, Some(ordering)) => ordering != Ordering::Equal,
+                (Predicate::GREATER_THAN, Some(ordering)) => ordering == Ordering::Greater,
+                (Predicate::GREATER_THAN_OR_EQUAL, Some(ordering)) => ordering != Ordering::Less,
+                (Predicate::LESS_THAN, Some(ordering)) => ordering == Ordering::Less,
+                (Predicate::LESS_THAN_OR_EQUAL, Some(ordering)) => ordering != Ordering::Greater,
+            });
+        };
+        let division_by_zero = || Interrupt::Error("Division by zero.".to_string());
+        return match (op, lhs, rhs) {
+            (BinaryOperation::Arithmetic(op), Value::Number(lhs), Value::Number(rhs)) if !op.is_bitwise() => Ok(Value::Number(match op {
+                BinaryOperator::ADD => lhs + rhs,
+                BinaryOperator::SUB => lhs - rhs,
+                BinaryOperator::MUL => lhs * rhs,
+                BinaryOperator::DIV => lhs / rhs,
+                _ => lhs % rhs
+            })),
+            (BinaryOperation::Arithmetic(op), Value::Int(lhs), Value::Int(rhs)) => Ok(Value::Int(match op {
+                BinaryOperator::ADD => lhs.wrapping_add(rhs),
+                BinaryOperator::SUB => lhs.wrapping_sub(rhs),
+                BinaryOperator::MUL => lhs.wrapping_mul(rhs),
+                BinaryOperator::DIV => lhs.checked_div(rhs).ok_or_else(division_by_zero)?,
+                BinaryOperator::REM => lhs.checked_rem(rhs).ok_or_else(division_by_zero)?,
+                BinaryOperator::AND => lhs & rhs,
+                BinaryOperator::OR => lhs | rhs,
+                BinaryOperator::XOR => lhs ^ rhs,
+                BinaryOperator::SHL => lhs.wrapping_shl(rhs as u32),
+                BinaryOperator::ASHR => lhs.wrapping_shr(rhs as u32),
+                BinaryOperator::LSHR => (lhs as u32).wrapping_shr(rhs as u32) as i32
+            })),
+            (BinaryOperation::Comparison(predicate), Value::Number(lhs), Value::Number(rhs)) => Ok(compare(lhs.partial_cmp(&rhs), predicate)),
+            (BinaryOperation::Comparison(predicate), Value::Int(lhs), Value::Int(rhs)) => Ok(compare(Some(lhs.cmp(&rhs)), predicate)),
+            (BinaryOperation::Comparison(predicate @ (Predicate::EQUAL | Predicate::NOT_EQUAL)), Value::Bool(lhs), Value::Bool(rhs)) => Ok(compare(Some(lhs.cmp(&rhs)), predicate)),
+            (op, lhs, rhs) => Err(Interrupt::Error(format!("'{op}' cannot be applied to {} and {} in the interpreter.", lhs.type_name(), rhs.type_name())))
+        };
+    }
+}
+
+/// プログラムを調べてから、関数nameをインタプリタで呼んで戻り値を返す(LLVMを使わない)
+/// 引数と戻り値はrun_functionと同じくNumber、整数(i32)、Boolだけを扱う
+/// check_programと同じく、CHECK_STACK_SIZEのスタックを持つスレッドで実行する
+pub fn interpret(program: &Vec<Expression>, name: &str, args: &[ConstantValue]) -> Result<ConstantValue, String> {
+    return match interpret_value(program, name, args)? {
+        Value::Number(number) => Ok(ConstantValue::Number(number)),
+        Value::Int(number) => Ok(ConstantValue::Int(number)),
+        Value::Bool(boolean) => Ok(ConstantValue::Bool(boolean)),
+        _ => Err(format!("Function '{name}' does not return Number, an integer or Bool."))
+    };
+}
+
+fn interpret_value(program: &Vec<Expression>, name: &str, args: &[ConstantValue]) -> Result<Value, String> {
+    check_program(program)?;
+    let args = args.iter().map(|arg| match arg {
+        ConstantValue::Number(number) => Value::Number(*number),
+        ConstantValue::Int(number) => Value::Int(*number),
+        ConstantValue::Bool(boolean) => Value::Bool(*boolean)
+    }).collect();
+    return std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(CHECK_STACK_SIZE)
+            .spawn_scoped(scope, || Interpreter::new(program).call(name, args))
+            .unwrap_or_else(|error| panic!("Failed to start the interpreter thread: {error}"))
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    });
+}
+
+/// テストでコンパイルするときの設定(compile_to_ir_withとrun_function_withに渡す)
+/// 既定値はコマンドラインで何も指定しないときと同じ
+#[cfg(feature = "llvm")]
+#[derive(Debug, Clone)]
+pub struct TestOptions{
+    pub float_mode: FloatMode,
+    /// --overflowと同じ(WrapかTrap。Checkedはchecked_addなどの組み込み関数だけのもの)
+    pub overflow_mode: OverflowMode,
+    /// 検証の後に最適化する-Oの数字(Noneなら最適化しない)
+    pub optimization_level: Option<u8>,
+    /// ビルドの前に読み込む、ほかのモジュールのインターフェース(.ksciの中身。emit_interfaceで作る)
+    pub interfaces: Vec<String>,
+    /// Cのmainを作らない(テストのプログラムはmainを持たないことが多いので既定値はtrue。run_executableでは使わない)
+    pub library: bool,
+    /// --sanitize addressと同じく、関数をAddressSanitizerの計装対象にする
+    pub sanitize_address: bool,
+    /// プログラムのSpanが指すソースファイルの名前と中身(file_idはこの並びの番号)
+    pub sources: Vec<(String, String)>,
+    /// -gと同じく、DWARFのデバッグ情報をつける(ファイル名はsourcesの最初のファイル)
+    pub debug_info: bool,
+    /// --shadow-stackと同じく、関数の入口と出口でシャドウスタックに関数名を積み降ろしする(run_executableではcompiled/shadow_stack.cもリンクする)
+    pub shadow_stack: bool,
+    /// --targetと同じく、モジュールのターゲットを設定する(Noneならこのマシン。run_functionとrun_executableでは使わない)
+    pub target: Option<String>
+}
+
+#[cfg(feature = "llvm")]
+impl Default for TestOptions {
+    fn default() -> Self {
+        return TestOptions{ float_mode: FloatMode::Strict, overflow_mode: OverflowMode::Wrap, optimization_level: None, interfaces: vec![], library: true, sanitize_address: false, sources: vec![], debug_info: false, shadow_stack: false, target: None };
+    }
+}
+
+/// プログラムを解析してコード生成し、LLVMの検証を通ったモジュールをbodyに渡す(テスト用)
+/// 名前や型の誤り、コード生成のエラー、検証の失敗は、表示する形の文字列にしてErrで返す
+#[cfg(feature = "llvm")]
+fn with_verified_module<T>(program: &Vec<Expression>, options: &TestOptions, body: impl FnOnce(&Compiler) -> Result<T, String>) -> Result<T, String> {
+    let context = Context::create();
+    let builder = context.create_builder();
+    let mut compiler = Compiler::new(&context, &builder);
+    if options.overflow_mode == OverflowMode::Checked {
+        return Err("OverflowMode::Checked can only be used by checked_add, checked_sub and checked_mul.".to_string());
+    }
+    compiler.float_mode = options.float_mode;
+    compiler.overflow_mode = options.overflow_mode;
+    compiler.sanitize_address = options.sanitize_address;
+    compiler.shadow_stack = options.shadow_stack;
+    compiler.initialize_module_by_filepath(&PathBuf::from("test.ksc"));
+    if let Some(target) = &options.target {
+        compiler.set_target_triple(target).map_err(|error| error.message)?;
+    }
+    for (name, text) in &options.sources {
+        compiler.add_source(name, text);
+    }
+    if options.debug_info {
+        let filename = options.sources.first().map(|(name, _)| name.as_str()).unwrap_or("test.ksc");
+        compiler.enable_debug_info(&PathBuf::from(filename));
+    }
+    for (index, interface) in options.interfaces.iter().enumerate() {
+        compiler.load_interface(&format!("interface{index}.ksci"), interface).map_err(|error| error.message)?;
+    }
+    check_program_importing(program, &compiler.imported_names, &compiler.sources)?;
+    compiler.library = options.library;
+    compiler.build(program);
+    if compiler.diagnostics.has_errors() {
+        return Err(compiler.diagnostics.render(&compiler.sources));
+    }
+    compiler.verify().map_err(|error| compiler.sources.render(&error))?;
+    if let Some(level) = options.optimization_level {
+        compiler.optimize(level).map_err(|error| compiler.sources.render(&error))?;
+    }
+    return body(&compiler);
+}
+
+/// プログラムをコンパイルし、検証を通ったLLVM IRを返す(テスト用)
+#[cfg(feature = "llvm")]
+pub fn compile_to_ir(program: &Vec<Expression>) -> Result<String, String> {
+    return compile_to_ir_with(program, &TestOptions::default());
+}
+
+/// 設定を指定してcompile_to_irする
+#[cfg(feature = "llvm")]
+pub fn compile_to_ir_with(program: &Vec<Expression>, options: &TestOptions) -> Result<String, String> {
+    return with_verified_module(program, options, |compiler| Ok(compiler.emit_as_text().unwrap()));
+}
+
+/// プログラムをコンパイルし、そのモジュールのインターフェース(.ksci)を返す(テスト用。TestOptionsのinterfacesに渡せる)
+#[cfg(feature = "llvm")]
+pub fn emit_interface(program: &Vec<Expression>, options: &TestOptions) -> Result<String, String> {
+    return with_verified_module(program, options, |compiler| Ok(compiler.write_interface()));
+}
+
+/// プログラムをコンパイルし、unsafeな箇所の一覧(--emit unsafe-reportの出力)を返す(テスト用)
+#[cfg(feature = "llvm")]
+pub fn unsafe_report(program: &Vec<Expression>, options: &TestOptions) -> Result<String, String> {
+    return with_verified_module(program, options, |compiler| Ok(compiler.write_unsafe_report()));
+}
+
+/// プログラムをコンパイルし、その統計(--statsの出力)を返す(テスト用)
+#[cfg(feature = "llvm")]
+pub fn compile_stats(program: &Vec<Expression>, options: &TestOptions) -> Result<String, String> {
+    return with_verified_module(program, options, |compiler| Ok(compiler.write_stats(program)));
+}
+
+/// プログラムをコンパイルし、--emit asmと同じくモジュールのターゲットのアセンブリを返す(テスト用。optimization_levelがNoneなら-O0で書き出す)
+#[cfg(feature = "llvm")]
+pub fn compile_to_assembly(program: &Vec<Expression>, options: &TestOptions) -> Result<String, String> {
+    return with_verified_module(program, options, |compiler| compiler.emit_assembly(options.optimization_level.unwrap_or(0)).map_err(|error| error.message));
+}
+
+/// テストが書き出す一時ファイルの名前につける番号(並列に走るテストどうしで重ならないように)
+#[cfg(feature = "llvm")]
+static EXECUTABLE_COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+/// プログラムをmainのある実行ファイルにして、ランタイム(compiled/*.c)とclangでリンクして実行する(テスト用)
+/// 一時ディレクトリに書き出すので、パッケージのディレクトリ(./compiled/preload.cのある場所)で呼ぶこと
+/// 実行できれば、終了コードや出力はそのまま返す(ASanの報告は標準エラー出力に出る)
+#[cfg(feature = "llvm")]
+pub fn run_executable(program: &Vec<Expression>, options: &TestOptions) -> Result<std::process::Output, String> {
+    let options = TestOptions{ library: false, ..options.clone() };
+    let ir = with_verified_module(program, &options, |compiler| Ok(compiler.emit_as_text().unwrap()))?;
+    let count = EXECUTABLE_COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let ir_path = env::temp_dir().join(format!("ksc1000-test-{}-{count}.ll", std::process::id()));
+    std::fs::write(&ir_path, ir).map_err(|error| format!("Failed to write '{}': {error}", ir_path.display()))?;
+    let executable = ir_path.with_extension("out");
+    let status = link_command(&ir_path, &[], options.shadow_stack, options.sanitize_address, false, &executable)
+                    .status()
+                    .map_err(|error| format!("Failed to run clang: {error}"))?;
+    if !status.success() {
+        return Err(format!("Failed to link '{}'.", ir_path.display()));
+    }
+    let output = std::process::Command::new(&executable).output().map_err(|error| format!("Failed to run '{}': {error}", executable.display()));
+    let _ = std::fs::remove_file(&ir_path);
+    let _ = std::fs::remove_file(&executable);
+    return output;
+}
+
+/// プログラムをコンパイルし、--emit bcと同じく一時ファイルに書き出したビットコードを読み戻して返す(テスト用)
+#[cfg(feature = "llvm")]
+pub fn compile_to_bitcode(program: &Vec<Expression>, options: &TestOptions) -> Result<Vec<u8>, String> {
+    let count = EXECUTABLE_COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let path = env::temp_dir().join(format!("ksc1000-test-{}-{count}.bc", std::process::id()));
+    if !with_verified_module(program, options, |compiler| Ok(compiler.emit_bitcode(&path)))? {
+        return Err(format!("Failed to write '{}'.", path.display()));
+    }
+    let bitcode = std::fs::read(&path).map_err(|error| format!("Failed to read '{}': {error}", path.display()));
+    let _ = std::fs::remove_file(&path);
+    return bitcode;
+}
+
+/// プログラムをコンパイルし、KSCの関数nameをJITで呼んで戻り値を返す(テスト用)
+/// 引数と戻り値はNumber、整数(i32に収まるもの)、Boolだけを扱う
+/// MCJITのrun_functionは引数のない関数とCのmainの形の関数しか呼べないので、引数を定数にしてnameを呼ぶ引数のない関数を作り、それを呼ぶ
+#[cfg(feature = "llvm")]
+pub fn run_function(program: &Vec<Expression>, name: &str, args: &[ConstantValue]) -> Result<ConstantValue, String> {
+    return run_function_with(program, name, args, &TestOptions::default());
+}
+
+/// 設定を指定してrun_functionする
+#[cfg(feature = "llvm")]
+pub fn run_function_with(program: &Vec<Expression>, name: &str, args: &[ConstantValue], options: &TestOptions) -> Result<ConstantValue, String> {
+    return with_verified_module(program, options, |compiler| {
+        let module = compiler.module.as_ref().unwrap_or_else(||panic!("There is no Module yet. Create module first."));
+        let func = module.get_function(Compiler::symbol_name(name))
+                    .ok_or_else(|| format!("Function '{name}' is not defined."))?;
+        let params = func.get_params();
+        if params.len() != args.len() {
+            return Err(format!("Function '{name}' takes {} arguments but {} were given.", params.len(), args.len()));
+        }
+        let mut values: Vec<BasicMetadataValueEnum> = vec![];
+        for (index, (arg, param)) in args.iter().zip(params).enumerate() {
+            values.push(match (arg, param.get_type()) {
+                (ConstantValue::Number(number), BasicTypeEnum::FloatType(floattype)) => floattype.const_float(*number).into(),
+                (ConstantValue::Bool(boolean), BasicTypeEnum::IntType(inttype)) if inttype.get_bit_width() == 1 => inttype.const_int(*boolean as u64, false).into(),
+                (ConstantValue::Int(number), BasicTypeEnum::IntType(inttype)) if inttype.get_bit_width() > 1 => inttype.const_int(*number as i64 as u64, true).into(),
+                (arg, _) => return Err(format!("Argument {} of function '{name}' cannot be {arg:?}.", index + 1))
+            });
+        }
+        let return_type = match func.get_type().get_return_type() {
+            Some(return_type @ (BasicTypeEnum::FloatType(_) | BasicTypeEnum::IntType(_))) => return_type,
+            _ => return Err(format!("Function '{name}' does not return Number, an integer or Bool."))
+        };
+        let wrapper = module.add_function("ksc.test_call", return_type.fn_type(&[], false), None);
+        compiler.builder.position_at_end(compiler.context.append_basic_block(wrapper, "entry"));
+        let result = compiler.builder.build_call(func, &values, "result")
+                        .try_as_basic_value()
+                        .left()
+                        .unwrap_or_else(||panic!("Function '{name}' returned no value."));
+        compiler.builder.build_return(Some(&result));
+
+        Target::initialize_native(&InitializationConfig::default())?;
+        let engine = module.create_jit_execution_engine(OptimizationLevel::None).map_err(|message| message.to_string())?;
+        let result = unsafe { engine.run_function(wrapper, &[]) };
+        return match return_type {
+            BasicTypeEnum::FloatType(floattype) => Ok(ConstantValue::Number(result.as_float(&floattype))),
+            BasicTypeEnum::IntType(inttype) if inttype.get_bit_width() == 1 => Ok(ConstantValue::Bool(result.as_int(false) != 0)),
+            _ => Ok(ConstantValue::Int(result.as_int(true) as i32))
+        };
+    });
+}
+
+/// パーサーがまだないので、ksc1000が実行するプログラムはASTとしてここに書く
+/// fn gcd(a: Number, b: Number) -> Number { if b == 0 { a } else { gcd(b, a % b) } }
+/// fn main() -> Number { gcd(48, 18) }(終了コードは6)
+fn builtin_program() -> Vec<Expression> {
+    return vec![
+        Expression::Function {
+            name: "gcd".to_string(),
+            return_type: "Number".to_string(),
+            param_types: vec![
+                "Number".to_string(),
+                "Number".to_string()
+            ],
+            param_names: vec![
+                "a".to_string(),
+                "b".to_string()
+            ],
+            type_params: vec![],
+            content: vec![
+                Expression::If {
+                    condition: Box::from(Expression::Binary {
+                        op: BinaryOperation::Comparison(Predicate::EQUAL),
+                        lhs: Box::from(Expression::Identifier("b".to_string())),
+                        rhs: Box::from(Expression::NumberLiteral(0.0))
+                    }),
+                    then: vec![Expression::Identifier("a".to_string())],
+                    otherwise: vec![Expression::Call {
+                        callee: "gcd".to_string(),
+                        args: vec![
+                            Expression::Identifier("b".to_string()),
+                            Expression::Binary {
+                                op: BinaryOperation::Arithmetic(BinaryOperator::REM),
+                                lhs: Box::from(Expression::Identifier("a".to_string())),
+                                rhs: Box::from(Expression::Identifier("b".to_string()))
+                            }
+                        ]
+                    }]
+                }
+            ]
+        },
+        Expression::Function {
+            name: "main".to_string(),
+            return_type: "Number".to_string(),
+            param_types: vec![],
+            param_names: vec![],
+            type_params: vec![],
+            content: vec![Expression::Call {
+                callee: "gcd".to_string(),
+                args: vec![Expression::NumberLiteral(48.0), Expression::NumberLiteral(18.0)]
+            }]
+        }
+    ];
+}
+
+/// LLVMなしでビルドしたとき(--no-default-features)のrun
+/// コード生成はできないので、プログラムを調べてからインタプリタでmainを実行し、その値を終了コードにする(Voidなら0)
+/// コード生成の設定のオプションは使えないのでエラーにする
+#[cfg(not(feature = "llvm"))]
+pub fn run() {
+    if let Some(option) = std::env::args().skip(1).find(|arg| arg.starts_with('-')) {
+        eprintln!("error: ksc1000 was built without the 'llvm' feature, so '{option}' is not available. It can only run programs with the interpreter.");
+        std::process::exit(2);
+    }
+    let program = builtin_program();
+    let code = match interpret_value(&program, "main", &[]) {
+        Ok(Value::Number(number)) => number as i32,
+        Ok(Value::Int(number)) => number,
+        Ok(Value::Bool(boolean)) => boolean as i32,
+        Ok(_) => 0,
+        Err(message) => {
+            eprintln!("{message}");
+            1
+        }
+    };
+    std::process::exit(code);
+}
+
+/// サブコマンド
+#[cfg(feature = "llvm")]
+#[derive(Clone, Copy, PartialEq)]
+enum CliCommand{
+    /// コンパイルして出力ファイルに書き出す
+    Build,
+    /// コンパイルし、ランタイムとリンクして実行する(終了コードは実行したプログラムのもの)
+    Run,
+    /// エラーがないかだけを調べる(何も書き出さない)
+    Check,
+    /// コンパイルした結果を標準出力に書き出す
+    Emit
+}
+
+/// --emitで選ぶ出力の種類
+#[cfg(feature = "llvm")]
+#[derive(Clone, Copy, PartialEq)]
+enum EmitKind{
+    LlvmIr,
+    UnsafeReport,
+    /// ほかのモジュールから使うためのインターフェース(.ksci)
+    Interface,
+    /// LLVMのビットコード(.bc)。バイナリなので標準出力には書かない
+    Bitcode,
+    /// ターゲットのアセンブリ(.s)
+    Assembly
+}
+
+/// --dump-astで標準エラー出力に書くAST
+#[cfg(feature = "llvm")]
+#[derive(Clone, Copy, PartialEq)]
+enum AstDump{
+    /// KSCのソースコードの形
+    Source,
+    /// 文ごとにAnalyzerが決めた型を書いたもの(--dump-ast=typed)
+    Typed
+}
+
+/// コマンドラインの設定
+#[cfg(feature = "llvm")]
+struct CliOptions{
+    command: CliCommand,
+    input: PathBuf,
+    output: Option<PathBuf>,
+    emit: EmitKind,
+    interfaces: Vec<PathBuf>,
+    optimization_level: u8,
+    target: Option<String>,
+    verbose: bool,
+    debug_info: bool,
+    float_mode: FloatMode,
+    overflow_mode: OverflowMode,
+    /// -O1以上でもassertを残す(--release-asserts offで外す)
+    release_asserts: bool,
+    max_expression_depth: Option<usize>,
+    instruction_budget: Option<usize>,
+    codegen_time_budget_ms: Option<u64>,
+    bounds_check: bool,
+    shadow_stack: bool,
+    sanitize_address: bool,
+    dump_ir_per_function: bool,
+    print_stats: bool,
+    stop_after: Option<Phase>,
+    dump_ast: Option<AstDump>,
+    /// 最適化でしたこと(フィールドを並べ替えて小さくした構造体)を標準エラー出力に書く
+    opt_report: bool,
+    /// ライブラリとしてビルドする(--libか--emit interface。Cのmainを作らない)
+    library: bool,
+    /// ビルドした結果を./compiled/cacheに残し、入力も設定も同じなら次のビルドで使う
+    cache: bool
+}
+
+#[cfg(feature = "llvm")]
+const USAGE: &str = "usage: ksc1000 [build|run|check|emit] [input] [options]
+
+options:
+    -o <path>                 output file (build: ./compiled/ksc.ll, emit: stdout)
+    --emit <llvm-ir|bc|asm|unsafe-report|interface>
+    --interface <path>        use the types and functions of another module's .ksci (repeatable)
+    -O0, -O1, -O2, -O3        optimization level
+    --target <triple>         target triple of the module (e.g. wasm32-unknown-unknown, wasm32-wasi)
+    --verbose, -v             print the progress of each phase to stderr
+    -g                        emit DWARF debug info
+    --fp <strict|fast>        floating-point mode (fast fuses a * b + c into fma and sets function-level fast-math attributes)
+    --overflow <wrap|trap>    what +, - and * on integers do when they overflow
+    --release-asserts <on|off> keep assert with -O1 or higher (default: on)
+    --max-depth <n>           limit of expression nesting
+    --budget-instructions <n> warn about functions with more than n instructions
+    --budget-time <ms>        warn about functions that take longer than ms to compile
+    --bounds-check            check array indices at run time
+    --shadow-stack            print the KSC call stack on crashes
+    --sanitize address        instrument functions for AddressSanitizer
+    --stats                   print compilation statistics
+    --dump-ast[=typed]        print the AST as KSC source to stderr (typed: with the type of each statement)
+    --dump-ir-per-function    print the IR of each function and after each optimization stage to stderr, with the time of each stage
+    --opt-report              print the structs whose fields were reordered and the bytes saved to stderr
+    --lib                     build a library module without a C main (implied by --emit interface)
+    --no-cache                do not read or write ./compiled/cache
+    --stop-after <phase>      stop after lex, parse, typecheck, codegen or opt";
+
+/// 値を省略できるオプション(値は--name=valueでだけ渡せるので、--name valueに分けない)
+#[cfg(feature = "llvm")]
+const OPTIONAL_VALUE_OPTIONS: [&str; 1] = ["--dump-ast"];
+
+#[cfg(feature = "llvm")]
+impl CliOptions{
+    /// コマンドライン引数(プログラム名を除く)を読む
+    /// サブコマンドを省略するとbuild、入力ファイルを省略すると./example.kscになる。--name=valueは--name valueと同じ(値を省略できる--dump-astを除く)
+    fn parse(args: Vec<String>) -> Result<CliOptions, String> {
+        let mut options = CliOptions{
+            command: CliCommand::Build,
+            input: PathBuf::from("./example.ksc"),
+            output: None,
+            emit: EmitKind::LlvmIr,
+            interfaces: vec![],
+            optimization_level: 0,
+            target: None,
+            verbose: false,
+            debug_info: false,
+            float_mode: FloatMode::Strict,
+            overflow_mode: OverflowMode::Wrap,
+            release_asserts: true,
+            max_expression_depth: None,
+            instruction_budget: None,
+            codegen_time_budget_ms: None,
+            bounds_check: false,
+            shadow_stack: false,
+            sanitize_address: false,
+            dump_ir_per_function: false,
+            print_stats: false,
+            stop_after: None,
+            dump_ast: None,
+            opt_report: false,
+            library: false,
+            cache: true
+        };
+        // 値を省略できるオプションは、次の引数を値として読まないように=を含めたまま読む
+        let mut args = args.into_iter().flat_map(|arg| match arg.split_once('=') {
+            Some((name, value)) if arg.starts_with("--") && !OPTIONAL_VALUE_OPTIONS.contains(&name) => vec![name.to_string(), value.to_string()],
+            _ => vec![arg]
+        });
+        let mut positional = 0;
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("'{arg}' expects a value."));
+            match arg.as_str() {
+                "-o" => options.output = Some(PathBuf::from(value()?)),
+                "--emit" => options.emit = match value()?.as_str() {
+                    "llvm-ir" => EmitKind::LlvmIr,
+                    "unsafe-report" => EmitKind::UnsafeReport,
+                    "interface" => EmitKind::Interface,
+                    "bc" => EmitKind::Bitcode,
+                    "asm" => EmitKind::Assembly,
+                    kind => return Err(format!("Unknown emit kind '{kind}'. Use llvm-ir, bc, asm, unsafe-report or interface.")),
+                },
+                "--interface" => options.interfaces.push(PathBuf::from(value()?)),
+                "-O0" => options.optimization_level = 0,
+                "-O1" => options.optimization_level = 1,
+                "-O2" => options.optimization_level = 2,
+                "-O3" => options.optimization_level = 3,
+                "--target" => options.target = Some(value()?),
+                "--verbose" | "-v" => options.verbose = true,
+                "-g" => options.debug_info = true,
+                "--fp" => options.float_mode = match value()?.as_str() {
+                    "fast" => FloatMode::Fast,
+                    "strict" => FloatMode::Strict,
+                    mode => return Err(format!("Unknown floating-point mode '{mode}'. Use 'strict' or 'fast'.")),
+                },
+                "--overflow" => options.overflow_mode = match value()?.as_str() {
+                    "wrap" => OverflowMode::Wrap,
+                    "trap" => OverflowMode::Trap,
+                    "checked" => return Err("'--overflow checked' would change the type of every integer operation. Use checked_add, checked_sub or checked_mul instead.".to_string()),
+                    mode => return Err(format!("Unknown overflow mode '{mode}'. Use 'wrap' or 'trap'.")),
+                },
+                "--release-asserts" => options.release_asserts = match value()?.as_str() {
+                    "on" => true,
+                    "off" => false,
+                    mode => return Err(format!("Unknown value '{mode}' for --release-asserts. Use 'on' or 'off'.")),
+                },
+                "--max-depth" => {
+                    let depth = value()?;
+                    options.max_expression_depth = Some(depth.parse().map_err(|_| format!("--max-depth expects a number, but '{depth}' was given."))?);
+                },
+                "--budget-instructions" => {
+                    let budget = value()?;
+                    options.instruction_budget = Some(budget.parse().map_err(|_| format!("--budget-instructions expects a number, but '{budget}' was given."))?);
+                },
+                "--budget-time" => {
+                    let budget = value()?;
+                    options.codegen_time_budget_ms = Some(budget.parse().map_err(|_| format!("--budget-time expects a number of milliseconds, but '{budget}' was given."))?);
+                },
+                "--bounds-check" => options.bounds_check = true,
+                "--shadow-stack" => options.shadow_stack = true,
+                "--sanitize" => options.sanitize_address = match value()?.as_str() {
+                    "address" => true,
+                    sanitizer => return Err(format!("Unknown sanitizer '{sanitizer}'. Use 'address'.")),
+                },
+                "--stats" => options.print_stats = true,
+                "--dump-ast" => options.dump_ast = Some(AstDump::Source),
+                dump if dump.starts_with("--dump-ast=") => options.dump_ast = match &dump["--dump-ast=".len()..] {
+                    "typed" => Some(AstDump::Typed),
+                    kind => return Err(format!("Unknown AST dump '{kind}'. Use --dump-ast or --dump-ast=typed.")),
+                },
+                "--dump-ir-per-function" => options.dump_ir_per_function = true,
+                "--opt-report" => options.opt_report = true,
+                "--lib" => options.library = true,
+                "--no-cache" => options.cache = false,
+                "--stop-after" => options.stop_after = match value()?.as_str() {
+                    "lex" => return Err("ksc1000 has no lexer yet (the program is given as an AST), so there are no tokens to print.".to_string()),
+                    "parse" => Some(Phase::Parse),
+                    "typecheck" => Some(Phase::Typecheck),
+                    "codegen" => Some(Phase::Codegen),
+                    "opt" => Some(Phase::Opt),
+                    phase => return Err(format!("Unknown phase '{phase}'. Use lex, parse, typecheck, codegen or opt.")),
+                },
+                flag if flag.starts_with('-') => return Err(format!("Unknown option '{flag}'.")),
+                command if positional == 0 && ["build", "run", "check", "emit"].contains(&command) => {
+                    options.command = match command {
+                        "build" => CliCommand::Build,
+                        "run" => CliCommand::Run,
+                        "check" => CliCommand::Check,
+                        _ => CliCommand::Emit,
+                    };
+                    positional += 1;
+                },
+                input if positional <= 1 => {
+                    options.input = PathBuf::from(input);
+                    positional = 2;
+                },
+                extra => return Err(format!("Unexpected argument '{extra}'.")),
+            }
+        }
+        if options.command == CliCommand::Run && options.emit != EmitKind::LlvmIr {
+            return Err("'run' can only be used with --emit llvm-ir.".to_string());
+        }
+        if options.emit == EmitKind::Interface {
+            options.library = true;
+        }
+        if options.command == CliCommand::Run && options.library {
+            return Err("'run' needs the C main of a program, so it cannot be used with --lib.".to_string());
+        }
+        let wasm = options.target.as_ref().map(|target| target.starts_with("wasm32")).unwrap_or(false);
+        if wasm && options.command == CliCommand::Run {
+            return Err("'run' links with clang for this machine, so it cannot run WebAssembly. Build the module and link it with wasm-ld (see compiled/Makefile).".to_string());
+        }
+        if wasm && (options.sanitize_address || options.shadow_stack) {
+            return Err("--sanitize and --shadow-stack need runtimes that are not available for WebAssembly.".to_string());
+        }
+        if options.command == CliCommand::Emit && options.emit == EmitKind::Bitcode && options.output.is_none() {
+            return Err("Bitcode is binary, so it is not written to stdout. Give the output file with -o.".to_string());
+        }
+        return Ok(options);
+    }
+}
+
+/// ビルドのキャッシュの鍵(SHA-256)
+/// 入力ファイルの中身、AST、コード生成に効く設定、読み込むインターフェースの中身のどれかが変われば鍵も変わる
+#[cfg(feature = "llvm")]
+fn cache_key(options: &CliOptions, program: &Vec<Expression>, interfaces: &[PathBuf]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input_str(env!("CARGO_PKG_VERSION"));
+    hasher.input(&std::fs::read(&options.input).unwrap_or_default());
+    hasher.input_str(&format!("{program:?}"));
+    let settings = (
+        options.optimization_level, &options.target, options.debug_info, options.float_mode == FloatMode::Fast, options.overflow_mode,
+        options.optimization_level > 0 && !options.release_asserts,
+        options.max_expression_depth, options.bounds_check, options.shadow_stack, options.sanitize_address, options.library
+    );
+    hasher.input_str(&format!("{settings:?}"));
+    for interface in interfaces {
+        hasher.input(&std::fs::read(interface).unwrap_or_default());
+    }
+    return hasher.result_str();
+}
+
+/// プログラムのimportを入力ファイルのディレクトリからの相対パスとして解決し、重複を除いて返す
+#[cfg(feature = "llvm")]
+fn resolve_imports(input: &PathBuf, program: &Vec<Expression>) -> Vec<PathBuf> {
+    let directory = input.parent().map(|parent| parent.to_path_buf()).unwrap_or_default();
+    let mut imports: Vec<PathBuf> = vec![];
+    for expression in program {
+        if let Expression::Import(path) = expression.unspanned() {
+            let path = directory.join(path);
+            if !imports.contains(&path) {
+                imports.push(path);
+            }
+        }
+    }
+    return imports;
+}
+
+/// IRを、importしたモジュールのIRとランタイム(compiled/*.c)とリンクしてexecutableを作るclangのコマンド
+#[cfg(feature = "llvm")]
+fn link_command(ir_path: &PathBuf, modules: &[PathBuf], shadow_stack: bool, sanitize_address: bool, debug_info: bool, executable: &PathBuf) -> std::process::Command {
+    let mut clang = std::process::Command::new("clang");
+    clang.arg(ir_path).args(modules).arg("./compiled/preload.c");
+    if shadow_stack {
+        clang.arg("./compiled/shadow_stack.c");
+    }
+    if sanitize_address {
+        clang.arg("-fsanitize=address");
+    }
+    if debug_info {
+        clang.arg("-g");
+    }
+    clang.arg("-lm").arg("-o").arg(executable);
+    return clang;
+}
+
+/// 書き出したIRを、importしたモジュールのIRとランタイム(compiled/*.c)とリンクして実行し、終了コードを返す
+#[cfg(feature = "llvm")]
+fn link_and_run(options: &CliOptions, ir_path: &PathBuf, modules: &[PathBuf]) -> i32 {
+    let executable = ir_path.with_extension("out");
+    let mut clang = link_command(ir_path, modules, options.shadow_stack, options.sanitize_address, options.debug_info, &executable);
+    if options.verbose {
+        eprintln!("[ksc1000] {:?}", clang);
+    }
+    match clang.status() {
+        Ok(status) if status.success() => {},
+        Ok(_) => {
+            eprintln!("error: Failed to link '{}'.", ir_path.display());
+            return 1;
+        },
+        Err(error) => {
+            eprintln!("error: Failed to run clang: {error}");
+            return 1;
+        },
+    }
+    return match std::process::Command::new(&executable).status() {
+        Ok(status) => status.code().unwrap_or(1),
+        Err(error) => {
+            eprintln!("error: Failed to run '{}': {error}", executable.display());
+            1
+        },
+    };
+}
+
+/// コマンドラインのksc1000(src/main.rsから呼ばれる)
+#[cfg(feature = "llvm")]
+pub fn run() {
+    env::set_var("RUST_LOG", "debug");
+    env_logger::init();
+
+    if env::args().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return;
+    }
+
+    let options = match CliOptions::parse(env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    // --verboseと--dump-ir-per-functionでは、フェーズごとにかかった時間を書く
+    let tracing = options.verbose || options.dump_ir_per_function;
+    let parse_started = std::time::Instant::now();
+
+    // パーサーがまだないので、プログラムはbuiltin_programのASTを使う(入力ファイルはモジュール名とデバッグ情報に使う)
+    let program = builtin_program();
+    // コンパイラのイベントはまだ受け取れないので、Parseの時間はここで書く
+    if tracing {
+        eprintln!("[ksc1000] Parse finished in {:.2?}", parse_started.elapsed());
+    }
+
+    if options.stop_after == Some(Phase::Parse) {
+        for expression in &program {
+            print!("{}", expression.outline(0));
+        }
+        return;
+    }
+
+    let context = Context::create();// 'ctx
+    let builder = context.create_builder();
+    let mut compiler = Compiler::new(&context,&builder);
+
+    compiler.float_mode = options.float_mode;
+    compiler.overflow_mode = options.overflow_mode;
+    compiler.strip_asserts = options.optimization_level > 0 && !options.release_asserts;
+    compiler.bounds_check = options.bounds_check;
+    compiler.shadow_stack = options.shadow_stack;
+    compiler.sanitize_address = options.sanitize_address;
+    compiler.library = options.library;
+    compiler.dump_ir_per_function = options.dump_ir_per_function;
+    if let Some(depth) = options.max_expression_depth {
+        compiler.max_expression_depth = depth;
+    }
+    compiler.instruction_budget = options.instruction_budget;
+    compiler.codegen_time_budget = options.codegen_time_budget_ms.map(std::time::Duration::from_millis);
+
+    compiler.initialize_module_by_filepath(&options.input);
+    // パーサーができたら、入力ファイルの式にはこのファイルのSpanがつく
+    if let Ok(text) = std::fs::read_to_string(&options.input) {
+        compiler.add_source(&options.input.to_string_lossy(), &text);
+    }
+
+    if let Some(target) = &options.target {
+        if let Err(error) = compiler.set_target_triple(target) {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+
+    if options.debug_info {
+        compiler.enable_debug_info(&options.input);
+    }
+
+    // importしたファイルは、そのファイルと同じ場所に書き出したインターフェースを読み込む
+    let imports = resolve_imports(&options.input, &program);
+    for import in &imports {
+        if !import.with_extension("ksci").exists() {
+            eprintln!("error: Module '{}' has not been compiled. Run 'ksc1000 build {0} --emit interface -o {}' and 'ksc1000 build {0} -o {}' first.",
+                      import.display(), import.with_extension("ksci").display(), import.with_extension("ll").display());
+            std::process::exit(1);
+        }
+    }
+    let interfaces = options.interfaces.iter().cloned().chain(imports.iter().map(|import| import.with_extension("ksci"))).collect::<Vec<PathBuf>>();
+    for path in &interfaces {
+        let loaded = std::fs::read_to_string(path)
+                        .map_err(|error| CompileError{ message: format!("Failed to read interface '{}': {error}", path.display()), span: None })
+                        .and_then(|interface| compiler.load_interface(&path.to_string_lossy(), &interface));
+        if let Err(error) = loaded {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+
+    if tracing {
+        let mut started: Vec<(Phase, std::time::Instant)> = vec![];
+        compiler.on_event(move |event| match event {
+            CompilerEvent::PhaseStarted(phase) => {
+                eprintln!("[ksc1000] {phase:?} started");
+                started.push((*phase, std::time::Instant::now()));
+            },
+            CompilerEvent::PhaseFinished(phase) => {
+                let index = started.iter().rposition(|(started_phase, _)| started_phase == phase);
+                match index {
+                    Some(index) => eprintln!("[ksc1000] {phase:?} finished in {:.2?}", started.remove(index).1.elapsed()),
+                    None => eprintln!("[ksc1000] {phase:?} finished"),
+                }
+            },
+            CompilerEvent::FunctionCompiled { name } => eprintln!("[ksc1000] compiled '{name}'"),
+            CompilerEvent::OptimizationStage { name, elapsed } => eprintln!("[ksc1000] optimization stage '{name}' took {elapsed:.2?}"),
+            CompilerEvent::IrDump { after, ir } => eprintln!("[ksc1000] IR after {after}:\n{ir}"),
+            CompilerEvent::Diagnostic(_) | CompilerEvent::StructReordered { .. } => {},
+        });
+    }
+    if options.opt_report {
+        compiler.on_event(|event| if let CompilerEvent::StructReordered { name, declared_size, size } = event {
+            eprintln!("[ksc1000] reordered the fields of struct '{name}': {declared_size} -> {size} bytes ({} bytes saved)", declared_size - size);
+        });
+    }
+
+    // 入力も設定も前のビルドと同じなら、キャッシュしたモジュールを使って解析からコード生成と最適化までを飛ばす
+    // キャッシュはエラーのなかったビルドだけが書くので、警告は再表示されない(関数の上限を調べるときはキャッシュを使わない)
+    let use_cache = options.cache && matches!(options.emit, EmitKind::LlvmIr | EmitKind::Bitcode | EmitKind::Assembly) && options.command != CliCommand::Check && options.stop_after.is_none() && !options.print_stats && options.dump_ast.is_none() && !options.dump_ir_per_function && !options.opt_report
+                    && options.instruction_budget.is_none() && options.codegen_time_budget_ms.is_none();
+    let cache_path = if use_cache {
+        Some(PathBuf::from("./compiled/cache").join(format!("{}.bc", cache_key(&options, &program, &interfaces))))
+    } else {
+        None
+    };
+    let cached = cache_path.as_ref()
+                    .filter(|path| path.exists())
+                    .and_then(|path| Module::parse_bitcode_from_path(path, &context).ok());
+    let from_cache = cached.is_some();
+    if let Some(module) = cached {
+        if options.verbose {
+            eprintln!("[ksc1000] using cached module '{}'", cache_path.as_ref().unwrap().display());
+        }
+        compiler.module = Some(module);
+    }
+
+    if !from_cache {
+        // 名前の誤りはLLVMを使わずにまとめて見つけられるので、コード生成より先にすべて報告する
+        // 型の誤りも同じようにまとめて報告する(定義されていない名前の型はUnknownになるので、誤りが重ねて報告されることはない)
+        let imported_names = compiler.imported_names.clone();
+        compiler.emit_event(CompilerEvent::PhaseStarted(Phase::Typecheck));
+        let (typed_program, type_diagnostics) = Analyzer::analyze(&program, &imported_names, compiler.max_expression_depth);
+        // ASTは名前や型の誤りがあっても書く(パーサーや脱糖の誤りを調べるのに使う)
+        match options.dump_ast {
+            Some(AstDump::Source) => program.iter().for_each(|expression| eprintln!("{expression}")),
+            Some(AstDump::Typed) => typed_program.iter().for_each(|typed| eprintln!("{}", typed.pretty())),
+            None => {}
+        }
+        let mut diagnostics = NameResolver::resolve(&program, &imported_names, compiler.max_expression_depth);
+        diagnostics.append(type_diagnostics);
+        compiler.emit_event(CompilerEvent::PhaseFinished(Phase::Typecheck));
+        if diagnostics.has_errors() {
+            eprintln!("{}", diagnostics.render(&compiler.sources));
+            std::process::exit(1);
+        }
+
+        // checkは型の誤りがわかれば十分なので、IRの検証と最適化と書き出しを飛ばす
+        // Analyzerがまだ扱えない式はコード生成で確かめるので、IRは作られる
+        compiler.build(&program);
+        if !compiler.diagnostics.is_empty() {
+            eprintln!("{}", compiler.diagnostics.render(&compiler.sources));
+        }
+        if compiler.diagnostics.has_errors() {
+            std::process::exit(1);
+        }
+        if options.command == CliCommand::Check {
+            return;
+        }
+        if let Err(error) = compiler.verify() {
+            eprintln!("{}", compiler.sources.render(&error));
+            std::process::exit(1);
+        }
+
+        if options.stop_after == Some(Phase::Typecheck) {
+            for typed in &typed_program {
+                print!("{}", typed.outline(0));
+            }
+            return;
+        }
+
+        if options.stop_after == Some(Phase::Codegen) {
+            println!("{}", compiler.emit_as_text().unwrap());
+            return;
+        }
+
+        if let Err(error) = compiler.optimize(options.optimization_level) {
+            eprintln!("{}", compiler.sources.render(&error));
+            std::process::exit(1);
+        }
+
+        if options.stop_after == Some(Phase::Opt) {
+            println!("{}", compiler.emit_as_text().unwrap());
+            return;
+        }
+
+        if let Some(path) = &cache_path {
+            if std::fs::create_dir_all("./compiled/cache").is_err() || !compiler.emit_bitcode(path) {
+                eprintln!("warning: Failed to write the build cache '{}'.", path.display());
+            }
+        }
+
+        if options.print_stats {
+            print!("{}", compiler.write_stats(&program));
+        }
+    }
+
+    compiler.emit_event(CompilerEvent::PhaseStarted(Phase::Emit));
+    if options.emit == EmitKind::Bitcode {
+        let output = options.output.clone().unwrap_or_else(|| PathBuf::from("./compiled/ksc.bc"));
+        if !compiler.emit_bitcode(&output) {
+            eprintln!("error: Failed to write '{}'.", output.display());
+            std::process::exit(1);
+        }
+        compiler.emit_event(CompilerEvent::PhaseFinished(Phase::Emit));
+        return;
+    }
+
+    let artifact = match options.emit {
+        EmitKind::LlvmIr => compiler.emit_as_text().unwrap(),
+        EmitKind::UnsafeReport => compiler.write_unsafe_report(),
+        EmitKind::Interface => compiler.write_interface(),
+        EmitKind::Assembly => match compiler.emit_assembly(options.optimization_level) {
+            Ok(assembly) => assembly,
+            Err(error) => {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+        },
+        EmitKind::Bitcode => unreachable!(),
+    };
+
+    if options.command == CliCommand::Emit && options.output.is_none() {
+        print!("{artifact}");
+        compiler.emit_event(CompilerEvent::PhaseFinished(Phase::Emit));
+        return;
+    }
+
+    let default_output = match options.emit {
+        EmitKind::LlvmIr => "./compiled/ksc.ll",
+        EmitKind::UnsafeReport => "./compiled/ksc.unsafe.txt",
+        EmitKind::Interface => "./compiled/ksc.ksci",
+        EmitKind::Assembly => "./compiled/ksc.s",
+        EmitKind::Bitcode => unreachable!(),
+    };
+    let output = options.output.clone().unwrap_or_else(|| PathBuf::from(default_output));
+    if let Err(error) = File::create(&output).and_then(|mut file| file.write_all(artifact.as_bytes())) {
+        eprintln!("error: Failed to write '{}': {error}", output.display());
+        std::process::exit(1);
+    }
+    compiler.emit_event(CompilerEvent::PhaseFinished(Phase::Emit));
+
+    if options.command == CliCommand::Run {
+        // importしたモジュールは、それぞれをビルドしたときの.llとリンクする
+        // キャッシュの.bcの鍵はモジュールのASTから求めるので、パーサーがなくASTを持たないここでは探せない
+        let modules = imports.iter().map(|import| import.with_extension("ll")).collect::<Vec<PathBuf>>();
+        std::process::exit(link_and_run(&options, &output, &modules));
+    }
+}
+
+// コマンドラインの読み方はCliOptionsがクレートの外に出ないので、ここで確かめる
+#[cfg(all(test, feature = "llvm"))]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliOptions, String> {
+        return CliOptions::parse(args.iter().map(|arg| arg.to_string()).collect());
+    }
+
+    #[test]
+    fn release_asserts_takes_on_or_off() {
+        assert!(parse(&["build", "a.ksc", "-O2"]).unwrap().release_asserts);
+        assert!(!parse(&["build", "a.ksc", "-O2", "--release-asserts", "off"]).unwrap().release_asserts);
+        assert!(!parse(&["build", "a.ksc", "--release-asserts=off", "-O2"]).unwrap().release_asserts);
+        assert!(parse(&["build", "a.ksc", "--release-asserts=off", "--release-asserts", "on"]).unwrap().release_asserts);
+        assert_eq!(parse(&["build", "a.ksc", "--release-asserts", "maybe"]).err(), Some("Unknown value 'maybe' for --release-asserts. Use 'on' or 'off'.".to_string()));
+        assert_eq!(parse(&["build", "a.ksc", "--release-asserts"]).err(), Some("'--release-asserts' expects a value.".to_string()));
+    }
+}
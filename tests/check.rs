@@ -0,0 +1,78 @@
+//! LLVMを使わない名前と型の検査(check_program)。どんなASTを渡してもpanicせず、誤りは文字列で返る
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::*;
+use ksc1000::{check_program, BinaryOperator, Expression, Predicate, UnaryOperator};
+
+/// !!!...!trueをdepth重に入れ子にした式
+fn nested_not(depth: usize) -> Expression {
+    let mut expression = Expression::BoolLiteral(true);
+    for _ in 0..depth {
+        expression = Expression::Unary { op: UnaryOperator::NOT, operand: Box::from(expression) };
+    }
+    return expression;
+}
+
+#[test]
+fn deeply_nested_expression_is_an_error() {
+    let program = vec![function("f", "Bool", &[], vec![nested_not(100_000)])];
+    let error = check_program(&program).unwrap_err();
+    assert!(error.contains("Expressions are nested too deeply."), "{error}");
+    assert_eq!(program[0].to_string(), "/* Function nested more than 256 levels deep */");
+    // 木を捨てるときのdropも再帰するので、このテストのスタックでは捨てない
+    std::mem::forget(program);
+}
+
+#[test]
+fn nesting_within_the_limit_is_accepted() {
+    let program = vec![function("f", "Bool", &[], vec![nested_not(200)])];
+    assert_eq!(check_program(&program), Ok(()));
+}
+
+#[test]
+fn function_type_without_a_signature_is_an_error() {
+    let program = vec![function("f", "Number", &[("g", "Function")], vec![number(1.0)])];
+    let error = check_program(&program).unwrap_err();
+    assert!(error.contains("Type 'Function' needs a signature"), "{error}");
+}
+
+#[test]
+fn bare_struct_and_closure_types_are_errors() {
+    let program = vec![function("f", "Struct", &[("g", "Closure")], vec![])];
+    let error = check_program(&program).unwrap_err();
+    assert!(error.contains("Type 'Struct' is not allowed."), "{error}");
+    assert!(error.contains("Type 'Closure' needs a signature"), "{error}");
+}
+
+#[test]
+fn while_condition_must_be_bool() {
+    let program = vec![function("f", "Void", &[], vec![while_loop(int(1), vec![Expression::Break])])];
+    let error = check_program(&program).unwrap_err();
+    assert!(error.contains("The condition of 'while' must be Bool, but it is 'i32'."), "{error}");
+}
+
+#[test]
+fn integer_cannot_be_cast_to_bool() {
+    let program = vec![function("f", "Bool", &[("x", "i32")], vec![Expression::Cast { value: Box::from(ident("x")), target_type: "Bool".to_string() }])];
+    let error = check_program(&program).unwrap_err();
+    assert!(error.contains("Cannot cast 'i32' to 'Bool'. Compare it with 0 instead."), "{error}");
+}
+
+#[test]
+fn bitwise_operators_need_integers() {
+    let program = vec![function("f", "Number", &[("x", "Number")], vec![arithmetic(BinaryOperator::AND, ident("x"), number(1.0))])];
+    let error = check_program(&program).unwrap_err();
+    assert!(error.contains("Bitwise operations and shifts are only possible with integers, but the operands are 'Number'."), "{error}");
+}
+
+#[test]
+fn bool_is_not_an_arithmetic_operand() {
+    // fn f(a: i32, b: i32) -> i32 { (a < b) + (a > b) }
+    let program = vec![function("f", "i32", &[("a", "i32"), ("b", "i32")], vec![
+        arithmetic(BinaryOperator::ADD, compare(Predicate::LESS_THAN, ident("a"), ident("b")), compare(Predicate::GREATER_THAN, ident("a"), ident("b")))
+    ])];
+    let error = check_program(&program).unwrap_err();
+    assert!(error.contains("An operand of an arithmetic operation cannot be Bool. Cast it explicitly to an integer (false is 0, true is 1)."), "{error}");
+}
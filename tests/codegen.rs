@@ -0,0 +1,1240 @@
+//! ASTをコンパイルし、LLVMの検証を通してからJITで実行して結果を確かめる
+#![cfg(feature = "llvm")]
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::*;
+use ksc1000::{compile_stats, compile_to_assembly, compile_to_bitcode, compile_to_ir, compile_to_ir_with, emit_interface, run_function, run_function_with, unsafe_report, BinaryOperator, ConstantValue, Expression, FloatMode, KSCAttribute, OverflowMode, Pattern, Predicate, TestOptions};
+
+#[test]
+fn gcd_of_48_and_18_is_6() {
+    let result = run_function(&vec![gcd()], "gcd", &[ConstantValue::Int(48), ConstantValue::Int(18)]);
+    assert_eq!(result, Ok(ConstantValue::Int(6)));
+}
+
+#[test]
+fn number_arithmetic() {
+    // fn average(a: Number, b: Number) -> Number { (a + b) / 2.0 }
+    let program = vec![function("average", "Number", &[("a", "Number"), ("b", "Number")], vec![
+        arithmetic(BinaryOperator::DIV, arithmetic(BinaryOperator::ADD, ident("a"), ident("b")), number(2.0))
+    ])];
+    let result = run_function(&program, "average", &[ConstantValue::Number(1.0), ConstantValue::Number(2.0)]);
+    assert_eq!(result, Ok(ConstantValue::Number(1.5)));
+}
+
+#[test]
+fn comparison_returns_bool() {
+    // fn less(a: i32, b: i32) -> Bool { a < b }
+    let program = vec![function("less", "Bool", &[("a", "i32"), ("b", "i32")], vec![
+        compare(Predicate::LESS_THAN, ident("a"), ident("b"))
+    ])];
+    assert_eq!(run_function(&program, "less", &[ConstantValue::Int(-1), ConstantValue::Int(3)]), Ok(ConstantValue::Bool(true)));
+    assert_eq!(run_function(&program, "less", &[ConstantValue::Int(3), ConstantValue::Int(3)]), Ok(ConstantValue::Bool(false)));
+}
+
+#[test]
+fn if_with_branches_of_different_types_is_a_statement() {
+    // fn f(a: i32) -> Number { if a == 0 { 1 } else { true }; 0.5 }
+    let program = vec![function("f", "Number", &[("a", "i32")], vec![
+        if_else(compare(Predicate::EQUAL, ident("a"), int(0)), vec![int(1)], vec![Expression::BoolLiteral(true)]),
+        number(0.5)
+    ])];
+    let ir = compile_to_ir(&program).unwrap();
+    assert!(!ir.contains("iftmp"), "{ir}");
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(0)]), Ok(ConstantValue::Number(0.5)));
+}
+
+#[test]
+fn undefined_name_is_an_error() {
+    let program = vec![function("f", "i32", &[], vec![ident("missing")])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("missing"), "{error}");
+}
+
+#[test]
+fn unknown_type_name_is_an_error() {
+    let program = vec![function("f", "i32", &[("x", "Nope")], vec![int(0)])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("Type 'Nope' is not defined!"), "{error}");
+}
+
+#[test]
+fn tuple_holding_void_is_an_error() {
+    let program = vec![function("f", "i32", &[("pair", "(i32, Void)")], vec![int(0)])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("Tuples cannot contain Void."), "{error}");
+}
+
+#[test]
+fn while_loop_repeats_until_the_condition_is_false() {
+    // fn sum(n: i32) -> i32 { let mut total = 0; let mut i = 0; while i < n { i = i + 1; total = total + i }; total }
+    let program = vec![function("sum", "i32", &[("n", "i32")], vec![
+        let_mut("total", "i32", int(0)),
+        let_mut("i", "i32", int(0)),
+        while_loop(compare(Predicate::LESS_THAN, ident("i"), ident("n")), vec![
+            assign("i", arithmetic(BinaryOperator::ADD, ident("i"), int(1))),
+            assign("total", arithmetic(BinaryOperator::ADD, ident("total"), ident("i")))
+        ]),
+        ident("total")
+    ])];
+    assert_eq!(run_function(&program, "sum", &[ConstantValue::Int(10)]), Ok(ConstantValue::Int(55)));
+    assert_eq!(run_function(&program, "sum", &[ConstantValue::Int(0)]), Ok(ConstantValue::Int(0)));
+}
+
+#[test]
+fn break_and_continue_jump_to_the_innermost_loop() {
+    // fn odd_sum(n: i32) -> i32 {
+    //     let mut total = 0; let mut i = 0
+    //     while true { i = i + 1; if i > n { break }; if i % 2 == 0 { continue }; total = total + i }
+    //     total
+    // }
+    let program = vec![function("odd_sum", "i32", &[("n", "i32")], vec![
+        let_mut("total", "i32", int(0)),
+        let_mut("i", "i32", int(0)),
+        while_loop(Expression::BoolLiteral(true), vec![
+            assign("i", arithmetic(BinaryOperator::ADD, ident("i"), int(1))),
+            if_else(compare(Predicate::GREATER_THAN, ident("i"), ident("n")), vec![Expression::Break], vec![]),
+            if_else(compare(Predicate::EQUAL, arithmetic(BinaryOperator::REM, ident("i"), int(2)), int(0)), vec![Expression::Continue], vec![]),
+            assign("total", arithmetic(BinaryOperator::ADD, ident("total"), ident("i")))
+        ]),
+        ident("total")
+    ])];
+    assert_eq!(run_function(&program, "odd_sum", &[ConstantValue::Int(10)]), Ok(ConstantValue::Int(25)));
+}
+
+#[test]
+fn break_outside_a_loop_is_an_error() {
+    let program = vec![function("f", "Void", &[], vec![Expression::Break])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("'break' cannot be used outside of a loop."), "{error}");
+
+    // クロージャの本体からは、外側の関数のループを抜けられない
+    let program = vec![function("f", "Void", &[], vec![
+        while_loop(Expression::BoolLiteral(true), vec![
+            let_variable("g", "", closure("Void", &[], vec![Expression::Continue])),
+            Expression::Break
+        ])
+    ])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("'continue' cannot be used outside of a loop."), "{error}");
+}
+
+#[test]
+fn private_functions_have_internal_linkage() {
+    // fn _square(x: Number) -> Number { x * x } fn area(r: Number) -> Number { _square(r) * 3.0 }
+    let program = vec![
+        function("_square", "Number", &[("x", "Number")], vec![arithmetic(BinaryOperator::MUL, ident("x"), ident("x"))]),
+        function("area", "Number", &[("r", "Number")], vec![arithmetic(BinaryOperator::MUL, call("_square", vec![ident("r")]), number(3.0))])
+    ];
+    let ir = compile_to_ir(&program).unwrap();
+    assert!(ir.contains("define internal double @_square("), "{ir}");
+    assert!(ir.contains("define double @area("), "{ir}");
+    assert_eq!(run_function(&program, "area", &[ConstantValue::Number(2.0)]), Ok(ConstantValue::Number(12.0)));
+}
+
+#[test]
+fn function_type_can_return_a_function() {
+    // fn apply(make: Function(Number) -> Function(Number) -> Number, twice: Function(Function(Number) -> Number, Number) -> Number) -> Number { 1.0 }
+    let program = vec![function("apply", "Number", &[
+        ("make", "Function(Number) -> Function(Number) -> Number"),
+        ("twice", "Function(Function(Number) -> Number, Number) -> Number")
+    ], vec![number(1.0)])];
+    let ir = compile_to_ir(&program).unwrap();
+    assert!(ir.contains("double (double)* (double)*"), "{ir}");
+    assert!(ir.contains("double (double (double)*, double)*"), "{ir}");
+}
+
+/// a * a + cを一回の丸めで計算すると2^-54が残り、乗算と加算で二回丸めると0になる値
+const FMA_A: f64 = 1.0 + 1.0 / 134217728.0;
+const FMA_C: f64 = -(1.0 + 1.0 / 67108864.0);
+const FMA_EXACT: f64 = 1.0 / 18014398509481984.0;
+
+/// fn muladd(a: Number, b: Number, c: Number) -> Number { a * b + c }
+fn muladd() -> Vec<Expression> {
+    return vec![function("muladd", "Number", &[("a", "Number"), ("b", "Number"), ("c", "Number")], vec![
+        arithmetic(BinaryOperator::ADD, arithmetic(BinaryOperator::MUL, ident("a"), ident("b")), ident("c"))
+    ])];
+}
+
+#[test]
+fn fma_rounds_once() {
+    let program = vec![function("f", "Number", &[("a", "Number"), ("b", "Number"), ("c", "Number")], vec![
+        call("fma", vec![ident("a"), ident("b"), ident("c")])
+    ])];
+    let args = [ConstantValue::Number(FMA_A), ConstantValue::Number(FMA_A), ConstantValue::Number(FMA_C)];
+    assert_eq!(run_function(&program, "f", &args), Ok(ConstantValue::Number(FMA_EXACT)));
+}
+
+#[test]
+fn multiply_add_is_not_fused_in_strict_mode() {
+    let args = [ConstantValue::Number(FMA_A), ConstantValue::Number(FMA_A), ConstantValue::Number(FMA_C)];
+    assert_eq!(run_function(&muladd(), "muladd", &args), Ok(ConstantValue::Number(0.0)));
+    assert!(!compile_to_ir(&muladd()).unwrap().contains("llvm.fma.f64"));
+}
+
+#[test]
+fn multiply_add_is_fused_in_fast_mode() {
+    let options = TestOptions{ float_mode: FloatMode::Fast, ..TestOptions::default() };
+    let args = [ConstantValue::Number(FMA_A), ConstantValue::Number(FMA_A), ConstantValue::Number(FMA_C)];
+    assert_eq!(run_function_with(&muladd(), "muladd", &args, &options), Ok(ConstantValue::Number(FMA_EXACT)));
+    let ir = compile_to_ir_with(&muladd(), &options).unwrap();
+    assert!(ir.contains("llvm.fma.f64") && !ir.contains("fmul"), "{ir}");
+}
+
+#[test]
+fn fast_mode_adds_fast_math_attributes_to_functions() {
+    let strict = compile_to_ir(&muladd()).unwrap();
+    assert!(!strict.contains("unsafe-fp-math"), "{strict}");
+    let options = TestOptions{ float_mode: FloatMode::Fast, ..TestOptions::default() };
+    let fast = compile_to_ir_with(&muladd(), &options).unwrap();
+    for key in ["unsafe-fp-math", "no-infs-fp-math", "no-nans-fp-math", "no-signed-zeros-fp-math"] {
+        assert!(fast.contains(&format!("\"{key}\"=\"true\"")), "{fast}");
+    }
+}
+
+#[test]
+fn fma_with_wrong_arguments_is_an_error() {
+    let program = vec![function("f", "Number", &[("a", "Number")], vec![call("fma", vec![ident("a"), ident("a")])])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("'fma' takes 3 arguments"), "{error}");
+}
+
+/// struct Padded { flag: Bool, value: Number, other: Bool }
+fn padded() -> Expression {
+    return struct_declaration("Padded", &[("flag", "Bool"), ("value", "Number"), ("other", "Bool")]);
+}
+
+fn padded_literal(typename: &str) -> Expression {
+    return struct_literal(typename, vec![("flag", Expression::BoolLiteral(true)), ("value", number(2.5)), ("other", Expression::BoolLiteral(false))]);
+}
+
+#[test]
+fn struct_fields_are_reordered_by_default() {
+    let program = vec![
+        padded(),
+        function("value", "Number", &[], vec![field(padded_literal("Padded"), "value")]),
+        function("other", "Bool", &[], vec![field(padded_literal("Padded"), "other")])
+    ];
+    let ir = compile_to_ir(&program).unwrap();
+    assert!(ir.contains("%Padded = type { double, i1, i1 }"), "{ir}");
+    assert_eq!(run_function(&program, "value", &[]), Ok(ConstantValue::Number(2.5)));
+    assert_eq!(run_function(&program, "other", &[]), Ok(ConstantValue::Bool(false)));
+}
+
+#[test]
+fn repr_c_keeps_the_declaration_order() {
+    let program = vec![
+        attributed(vec![KSCAttribute::Repr("c".to_string())], padded()),
+        function("value", "Number", &[], vec![field(padded_literal("Padded"), "value")])
+    ];
+    let ir = compile_to_ir(&program).unwrap();
+    assert!(ir.contains("%Padded = type { i1, double, i1 }"), "{ir}");
+    assert_eq!(run_function(&program, "value", &[]), Ok(ConstantValue::Number(2.5)));
+}
+
+#[test]
+fn conversion_between_layouts_copies_each_field() {
+    // Cのための@repr(c)の構造体への変換では、並べ方が違うのでフィールドを名前で対応させる
+    let program = vec![
+        padded(),
+        attributed(vec![KSCAttribute::Repr("c".to_string())], struct_declaration("CPadded", &[("flag", "Bool"), ("value", "Number"), ("other", "Bool")])),
+        function("f", "Number", &[], vec![
+            field(Expression::StructConversion { value: Box::from(padded_literal("Padded")), target_type: "CPadded".to_string() }, "value")
+        ])
+    ];
+    assert_eq!(run_function(&program, "f", &[]), Ok(ConstantValue::Number(2.5)));
+}
+
+#[test]
+fn struct_copies_are_lowered_to_memcpy() {
+    // fn f() -> Number { let mut a = Padded { .. }; let b = a; let mut c = b; a = c; c = Padded { value: 4.0, .. }; a.value + c.value }
+    let program = vec![
+        padded(),
+        struct_declaration("Twin", &[("flag", "Bool"), ("value", "Number"), ("other", "Bool")]),
+        function("f", "Number", &[], vec![
+            let_mut("a", "", padded_literal("Padded")),
+            let_variable("b", "", ident("a")),
+            let_mut("c", "Padded", ident("b")),
+            assign("a", ident("c")),
+            assign("c", struct_literal("Padded", vec![("flag", Expression::BoolLiteral(true)), ("value", number(4.0)), ("other", Expression::BoolLiteral(false))])),
+            arithmetic(BinaryOperator::ADD, field(ident("a"), "value"), field(ident("c"), "value"))
+        ]),
+        // 並べ方が同じ構造体への変換も、構造体ごと写す
+        function("g", "Number", &[], vec![
+            field(Expression::StructConversion { value: Box::from(padded_literal("Padded")), target_type: "Twin".to_string() }, "value")
+        ])
+    ];
+    let ir = compile_to_ir(&program).unwrap();
+    assert_eq!(ir.matches("call void @llvm.memcpy").count(), 4, "{ir}");
+    assert!(!ir.contains("load %Padded, %Padded* %a"), "{ir}");
+    assert_eq!(run_function(&program, "f", &[]), Ok(ConstantValue::Number(6.5)));
+    assert_eq!(run_function(&program, "g", &[]), Ok(ConstantValue::Number(2.5)));
+}
+
+#[test]
+fn print_of_a_format_call_is_a_single_printf() {
+    // fn f(x: i32) { println(format("x = {}, {}%", x, true)) }
+    let program = vec![function("f", "Void", &[("x", "i32")], vec![
+        call("println", vec![call("format", vec![string("x = {}, {}%"), ident("x"), Expression::BoolLiteral(true)])])
+    ])];
+    let ir = compile_to_ir(&program).unwrap();
+    assert!(ir.contains("c\"x = %d, %s%%\\0A\\00\""), "{ir}");
+    assert_eq!(ir.matches("call i32 (i8*, ...) @printf").count(), 1, "{ir}");
+    assert!(!ir.contains("@snprintf"), "{ir}");
+}
+
+#[test]
+fn unknown_repr_is_an_error() {
+    let program = vec![attributed(vec![KSCAttribute::Repr("packed".to_string())], padded())];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("Unknown representation 'packed'"), "{error}");
+}
+
+#[test]
+fn chained_redundant_loads_are_forwarded() {
+    // fn f(x: i32) -> i32 { let a: i32 = x; let b: i32 = a; b }
+    // aのloadを消すと、それをstoreしたbのloadもxに置き換わる
+    let program = vec![function("f", "i32", &[("x", "i32")], vec![
+        let_variable("a", "i32", ident("x")),
+        let_variable("b", "i32", ident("a")),
+        ident("b")
+    ])];
+    for level in 0..=3 {
+        let options = TestOptions{ optimization_level: Some(level), ..TestOptions::default() };
+        assert_eq!(run_function_with(&program, "f", &[ConstantValue::Int(7)], &options), Ok(ConstantValue::Int(7)));
+    }
+}
+
+fn overflowing_constant() -> Vec<Expression> {
+    // fn f() -> i32 { 2147483647 + 1 }
+    return vec![function("f", "i32", &[], vec![arithmetic(BinaryOperator::ADD, int(2147483647), int(1))])];
+}
+
+#[test]
+fn overflowing_constant_wraps_by_default() {
+    assert_eq!(run_function(&overflowing_constant(), "f", &[]), Ok(ConstantValue::Int(i32::MIN)));
+}
+
+#[test]
+fn overflowing_constant_is_an_error_with_overflow_trap() {
+    let options = TestOptions{ overflow_mode: OverflowMode::Trap, ..TestOptions::default() };
+    let error = compile_to_ir_with(&overflowing_constant(), &options).unwrap_err();
+    assert!(error.contains("overflows i32"), "{error}");
+
+    // あふれない定数はtrapでも折りたたむ
+    let program = vec![function("f", "i32", &[], vec![arithmetic(BinaryOperator::MUL, int(-46341), int(46340))])];
+    assert_eq!(run_function_with(&program, "f", &[], &options), Ok(ConstantValue::Int(-2147441940)));
+}
+
+fn importing(interfaces: Vec<String>) -> TestOptions {
+    return TestOptions{ interfaces, ..TestOptions::default() };
+}
+
+/// struct Point { x: Number, y: Number }だけのモジュールと、それを読み込んでPointを作る関数とxを読む関数を定義するモジュールのインターフェース
+/// 関数のシグネチャの型は関数を宣言する前に決まっている必要があるので、Pointは別のモジュールで宣言する
+fn geometry_interfaces() -> Vec<String> {
+    let types = emit_interface(&vec![struct_declaration("Point", &[("x", "Number"), ("y", "Number")])], &TestOptions::default()).unwrap();
+    let geometry = vec![
+        function("make", "Point", &[("x", "Number")], vec![struct_literal("Point", vec![("x", ident("x")), ("y", number(0.0))])]),
+        function("get_x", "Number", &[("p", "Point")], vec![field(ident("p"), "x")])
+    ];
+    let geometry = emit_interface(&geometry, &importing(vec![types.clone()])).unwrap();
+    return vec![types, geometry];
+}
+
+#[test]
+fn imported_struct_is_the_same_type_in_both_modules() {
+    // 読み込んだmakeが返すPointを、読み込んだget_xにそのまま渡せる
+    let program = vec![function("f", "Number", &[], vec![call("get_x", vec![call("make", vec![number(2.0)])])])];
+    let ir = compile_to_ir_with(&program, &importing(geometry_interfaces())).unwrap();
+    assert!(ir.contains("declare double @get_x(%Point"), "{ir}");
+}
+
+#[test]
+fn struct_literal_of_an_imported_struct_is_accepted() {
+    let program = vec![function("f", "Number", &[], vec![
+        call("get_x", vec![struct_literal("Point", vec![("x", number(1.0)), ("y", number(2.0))])])
+    ])];
+    assert!(compile_to_ir_with(&program, &importing(geometry_interfaces())).is_ok());
+}
+
+#[test]
+fn local_struct_with_the_same_fields_is_a_different_type() {
+    // フィールドが同じでも、宣言が違えば別の型(名前による型付け)
+    let program = vec![
+        struct_declaration("Vector", &[("x", "Number"), ("y", "Number")]),
+        function("f", "Number", &[], vec![
+            call("get_x", vec![struct_literal("Vector", vec![("x", number(1.0)), ("y", number(2.0))])])
+        ])
+    ];
+    let error = compile_to_ir_with(&program, &importing(geometry_interfaces())).unwrap_err();
+    assert!(error.contains("Vector") && error.contains("Point"), "{error}");
+}
+
+#[test]
+fn local_struct_can_be_converted_to_an_imported_struct() {
+    let program = vec![
+        struct_declaration("Vector", &[("x", "Number"), ("y", "Number")]),
+        function("f", "Number", &[], vec![
+            call("get_x", vec![Expression::StructConversion {
+                value: Box::from(struct_literal("Vector", vec![("x", number(1.0)), ("y", number(2.0))])),
+                target_type: "Point".to_string()
+            }])
+        ])
+    ];
+    assert!(compile_to_ir_with(&program, &importing(geometry_interfaces())).is_ok());
+}
+
+#[test]
+fn imported_struct_cannot_be_declared_again() {
+    let program = vec![struct_declaration("Point", &[("x", "Number"), ("y", "Number")])];
+    let error = compile_to_ir_with(&program, &importing(geometry_interfaces())).unwrap_err();
+    assert!(error.contains("Point"), "{error}");
+}
+
+#[test]
+fn interface_with_an_unknown_type_is_invalid() {
+    let program = vec![function("f", "i32", &[], vec![int(0)])];
+    let interfaces = vec![
+        "ksci\t1\nalias\tX\tNope\n".to_string(),
+        "ksci\t1\nstruct\tPoint\tx: Number\ty: Numbr\n".to_string(),
+        "ksci\t1\nfunction\tf\tFunction(Void) -> i32\n".to_string()
+    ];
+    let expected = ["at line 2: Type 'Nope' is not defined!", "at line 2: Type 'Numbr' is not defined!", "at line 2: You cannot expect Void as argument."];
+    for (interface, expected) in interfaces.into_iter().zip(expected) {
+        let error = compile_to_ir_with(&program, &importing(vec![interface])).unwrap_err();
+        assert!(error.contains("Invalid interface") && error.contains(expected), "{error}");
+    }
+}
+
+/// fn classify(x: i32) -> i32 { match x { 0 => 10, 1 | 2 => 20, _ => 30 } }
+fn classify() -> Vec<Expression> {
+    return vec![function("classify", "i32", &[("x", "i32")], vec![match_expression(ident("x"), vec![
+        arm(Pattern::Int(0), vec![int(10)]),
+        arm(Pattern::Or(vec![Pattern::Int(1), Pattern::Int(2)]), vec![int(20)]),
+        arm(Pattern::Wildcard, vec![int(30)])
+    ])])];
+}
+
+#[test]
+fn match_on_literals_is_a_switch() {
+    let ir = compile_to_ir(&classify()).unwrap();
+    assert!(ir.contains("switch i32"), "{ir}");
+    for (x, expected) in [(0, 10), (2, 20), (-5, 30)] {
+        assert_eq!(run_function(&classify(), "classify", &[ConstantValue::Int(x)]), Ok(ConstantValue::Int(expected)));
+    }
+}
+
+#[test]
+fn match_binds_the_value_of_a_range() {
+    // fn f(x: i32) -> i32 { match x { n @ 1..=9 => n * 2, _ => 0 } }
+    let program = vec![function("f", "i32", &[("x", "i32")], vec![match_expression(ident("x"), vec![
+        arm(binding("n", Pattern::Range { start: 1, end: 9 }), vec![arithmetic(BinaryOperator::MUL, ident("n"), int(2))]),
+        arm(Pattern::Wildcard, vec![int(0)])
+    ])])];
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(4)]), Ok(ConstantValue::Int(8)));
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(10)]), Ok(ConstantValue::Int(0)));
+}
+
+#[test]
+fn false_guard_falls_through_to_the_next_arm() {
+    // fn f(x: i32) -> i32 { match x { n @ _ if n > 10 => 1, 0..=10 => 2, _ => 3 } }
+    let program = vec![function("f", "i32", &[("x", "i32")], vec![match_expression(ident("x"), vec![
+        guarded_arm(binding("n", Pattern::Wildcard), compare(Predicate::GREATER_THAN, ident("n"), int(10)), vec![int(1)]),
+        arm(Pattern::Range { start: 0, end: 10 }, vec![int(2)]),
+        arm(Pattern::Wildcard, vec![int(3)])
+    ])])];
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(11)]), Ok(ConstantValue::Int(1)));
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(10)]), Ok(ConstantValue::Int(2)));
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(-1)]), Ok(ConstantValue::Int(3)));
+}
+
+#[test]
+fn non_exhaustive_match_lists_the_missing_values() {
+    // ガードのある腕は網羅に数えない
+    let program = vec![function("f", "i32", &[("b", "Bool"), ("c", "Bool")], vec![match_expression(ident("b"), vec![
+        guarded_arm(Pattern::Bool(true), ident("c"), vec![int(1)]),
+        arm(Pattern::Bool(false), vec![int(0)])
+    ])])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("The match is not exhaustive. Missing: true"), "{error}");
+
+    let program = vec![function("f", "i32", &[("x", "u8")], vec![match_expression(ident("x"), vec![
+        arm(Pattern::Range { start: 0, end: 9 }, vec![int(1)]),
+        arm(Pattern::Int(20), vec![int(2)])
+    ])])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("Missing: 10..=19, 21..=255"), "{error}");
+}
+
+#[test]
+fn arm_covered_by_earlier_arms_is_an_error() {
+    let program = vec![function("f", "i32", &[("x", "i32")], vec![match_expression(ident("x"), vec![
+        arm(Pattern::Range { start: 0, end: 9 }, vec![int(1)]),
+        arm(Pattern::Int(5), vec![int(2)]),
+        arm(Pattern::Wildcard, vec![int(3)])
+    ])])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("Arm 2 is unreachable"), "{error}");
+}
+
+/// enum Shape = Circle(Number) | Rect(Number, Number) | Empty
+fn shape() -> Expression {
+    return Expression::EnumDeclaration {
+        name: "Shape".to_string(),
+        variant_names: vec!["Circle".to_string(), "Rect".to_string(), "Empty".to_string()],
+        variant_types: vec![vec!["Number".to_string()], vec!["Number".to_string(), "Number".to_string()], vec![]]
+    };
+}
+
+fn variant(name: &str, values: Vec<Expression>) -> Expression {
+    return Expression::EnumVariant { typename: "Shape".to_string(), variant: name.to_string(), values };
+}
+
+fn variant_pattern(name: &str, names: &[&str]) -> Pattern {
+    return Pattern::Variant { variant: name.to_string(), names: names.iter().map(|name| name.to_string()).collect() };
+}
+
+/// match shape { Circle(r) => r * r * 3.0, Rect(w, h) => w * h, Empty => 0.0 }
+fn area(shape: Expression) -> Expression {
+    return match_expression(shape, vec![
+        arm(variant_pattern("Circle", &["r"]), vec![arithmetic(BinaryOperator::MUL, arithmetic(BinaryOperator::MUL, ident("r"), ident("r")), number(3.0))]),
+        arm(variant_pattern("Rect", &["w", "h"]), vec![arithmetic(BinaryOperator::MUL, ident("w"), ident("h"))]),
+        arm(variant_pattern("Empty", &[]), vec![number(0.0)])
+    ]);
+}
+
+#[test]
+fn match_on_enum_variants_binds_their_values() {
+    let program = vec![
+        shape(),
+        function("rect", "Number", &[("w", "Number")], vec![area(variant("Rect", vec![ident("w"), number(3.0)]))]),
+        function("circle", "Number", &[("r", "Number")], vec![area(variant("Circle", vec![ident("r")]))]),
+        function("empty", "Number", &[], vec![area(variant("Empty", vec![]))])
+    ];
+    assert_eq!(run_function(&program, "rect", &[ConstantValue::Number(2.0)]), Ok(ConstantValue::Number(6.0)));
+    assert_eq!(run_function(&program, "circle", &[ConstantValue::Number(2.0)]), Ok(ConstantValue::Number(12.0)));
+    assert_eq!(run_function(&program, "empty", &[]), Ok(ConstantValue::Number(0.0)));
+}
+
+#[test]
+fn function_can_take_an_enum_declared_in_the_program() {
+    // fn area_of(s: Shape) -> Number { match s { .. } } fn rect(w: Number) -> Number { area_of(Rect(w, 3.0)) }
+    let program = vec![
+        shape(),
+        function("area_of", "Number", &[("s", "Shape")], vec![area(ident("s"))]),
+        function("rect", "Number", &[("w", "Number")], vec![call("area_of", vec![variant("Rect", vec![ident("w"), number(3.0)])])])
+    ];
+    assert_eq!(run_function(&program, "rect", &[ConstantValue::Number(2.0)]), Ok(ConstantValue::Number(6.0)));
+}
+
+#[test]
+fn missing_enum_variant_is_reported_by_name() {
+    let program = vec![
+        shape(),
+        function("f", "Number", &[], vec![match_expression(variant("Empty", vec![]), vec![
+            arm(variant_pattern("Circle", &["_"]), vec![number(1.0)])
+        ])])
+    ];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("Missing: Rect, Empty"), "{error}");
+}
+
+fn with_source(name: &str, text: &str) -> TestOptions {
+    return TestOptions{ sources: vec![(name.to_string(), text.to_string())], ..TestOptions::default() };
+}
+
+#[test]
+fn name_error_shows_the_source_line_with_a_caret() {
+    let source = "fn f() -> i32 {\n    missing + 1\n}\n";
+    let program = vec![spanned_at(source, "fn f", function("f", "i32", &[], vec![
+        spanned_at(source, "missing + 1", arithmetic(BinaryOperator::ADD, spanned_at(source, "missing", ident("missing")), int(1)))
+    ]))];
+    let error = compile_to_ir_with(&program, &with_source("names.ksc", source)).unwrap_err();
+    assert!(error.contains("--> names.ksc:2:5\n  |\n2 |     missing + 1\n  |     ^^^^^^^\n"), "{error}");
+}
+
+#[test]
+fn codegen_error_shows_the_innermost_span() {
+    // 網羅性はコード生成で調べるので、位置はコンパイラのSourceMapで表示する
+    let source = "fn f(b: Bool) -> i32 {\n    match b { true => 1 }\n}\n";
+    let program = vec![spanned_at(source, "fn f", function("f", "i32", &[("b", "Bool")], vec![
+        spanned_at(source, "match b { true => 1 }", match_expression(ident("b"), vec![arm(Pattern::Bool(true), vec![int(1)])]))
+    ]))];
+    let error = compile_to_ir_with(&program, &with_source("match.ksc", source)).unwrap_err();
+    assert!(error.contains("Missing: false"), "{error}");
+    assert!(error.contains("--> match.ksc:2:5\n  |\n2 |     match b { true => 1 }\n  |     ^^^^^^^^^^^^^^^^^^^^^\n"), "{error}");
+}
+
+#[test]
+fn debug_info_has_the_lines_of_the_spans() {
+    let source = "fn twice(x: Number) -> Number {\n    x * 2.0\n}\n";
+    let program = vec![spanned_at(source, "fn twice", function("twice", "Number", &[("x", "Number")], vec![
+        spanned_at(source, "x * 2.0", arithmetic(BinaryOperator::MUL, ident("x"), number(2.0)))
+    ]))];
+    let options = TestOptions{ debug_info: true, ..with_source("twice.ksc", source) };
+    let ir = compile_to_ir_with(&program, &options).unwrap();
+    assert!(ir.contains("!DIFile(filename: \"twice.ksc\""), "{ir}");
+    assert!(ir.contains("!DISubprogram(name: \"twice\"") && ir.contains("line: 1"), "{ir}");
+    assert!(ir.contains("!DILocation(line: 2, column: 5"), "{ir}");
+    assert_eq!(run_function_with(&program, "twice", &[ConstantValue::Number(1.5)], &options), Ok(ConstantValue::Number(3.0)));
+}
+
+#[test]
+fn closure_keeps_the_value_captured_when_it_was_made() {
+    // fn f(x: i32) -> i32 { let mut k = 3; let add = |y: i32| -> i32 { y + k }; k = 100; add(x) }
+    let program = vec![function("f", "i32", &[("x", "i32")], vec![
+        let_mut("k", "i32", int(3)),
+        let_variable("add", "", closure("i32", &[("y", "i32")], vec![arithmetic(BinaryOperator::ADD, ident("y"), ident("k"))])),
+        assign("k", int(100)),
+        call("add", vec![ident("x")])
+    ])];
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(4)]), Ok(ConstantValue::Int(7)));
+}
+
+
+#[test]
+fn if_let_unwraps_some_and_skips_none() {
+    // fn first_positive(a: i32, b: i32) -> i32 {
+    //     let o = if a > 0 { some(a) } else if b > 0 { some(b) } else { none<i32> }
+    //     if let v = o { v } else { -1 }
+    // }
+    let program = vec![function("first_positive", "i32", &[("a", "i32"), ("b", "i32")], vec![
+        let_variable("o", "", if_else(
+            compare(Predicate::GREATER_THAN, ident("a"), int(0)),
+            vec![Expression::SomeValue(Box::from(ident("a")))],
+            vec![if_else(
+                compare(Predicate::GREATER_THAN, ident("b"), int(0)),
+                vec![Expression::SomeValue(Box::from(ident("b")))],
+                vec![Expression::NoneLiteral("i32".to_string())]
+            )]
+        )),
+        Expression::IfLet { name: "v".to_string(), value: Box::from(ident("o")), then: vec![ident("v")], otherwise: vec![int(-1)] }
+    ])];
+    assert_eq!(run_function(&program, "first_positive", &[ConstantValue::Int(2), ConstantValue::Int(3)]), Ok(ConstantValue::Int(2)));
+    assert_eq!(run_function(&program, "first_positive", &[ConstantValue::Int(0), ConstantValue::Int(3)]), Ok(ConstantValue::Int(3)));
+    assert_eq!(run_function(&program, "first_positive", &[ConstantValue::Int(0), ConstantValue::Int(0)]), Ok(ConstantValue::Int(-1)));
+}
+
+/// struct Point { x: Number, y: Number }
+fn point() -> Expression {
+    return struct_declaration("Point", &[("x", "Number"), ("y", "Number")]);
+}
+
+fn point_literal(x: Expression, y: Expression) -> Expression {
+    return struct_literal("Point", vec![("x", x), ("y", y)]);
+}
+
+/// impl typename { methods }、trait_nameがあればimpl trait_name for typename { methods }
+fn impl_block(typename: &str, trait_name: Option<&str>, methods: Vec<Expression>) -> Expression {
+    return Expression::Impl { typename: typename.to_string(), trait_name: trait_name.map(|name| name.to_string()), methods };
+}
+
+fn method_call(object: Expression, method: &str, args: Vec<Expression>) -> Expression {
+    return Expression::MethodCall { object: Box::from(object), method: method.to_string(), args };
+}
+
+#[test]
+fn methods_read_self_and_call_each_other() {
+    // impl Point {
+    //     fn dot(self: Self, other: Point) -> Number { self.x * other.x + self.y * other.y }
+    //     fn scaled_dot(self: Self, k: Number) -> Number { k * self.dot(Point { x: 1.0, y: 1.0 }) }
+    // }
+    // fn f(x: Number) -> Number { Point { x: x, y: 4.0 }.scaled_dot(2.0) }
+    let program = vec![
+        point(),
+        impl_block("Point", None, vec![
+            function("dot", "Number", &[("self", "Self"), ("other", "Point")], vec![arithmetic(BinaryOperator::ADD,
+                arithmetic(BinaryOperator::MUL, field(ident("self"), "x"), field(ident("other"), "x")),
+                arithmetic(BinaryOperator::MUL, field(ident("self"), "y"), field(ident("other"), "y"))
+            )]),
+            function("scaled_dot", "Number", &[("self", "Self"), ("k", "Number")], vec![
+                arithmetic(BinaryOperator::MUL, ident("k"), method_call(ident("self"), "dot", vec![point_literal(number(1.0), number(1.0))]))
+            ])
+        ]),
+        function("f", "Number", &[("x", "Number")], vec![method_call(point_literal(ident("x"), number(4.0)), "scaled_dot", vec![number(2.0)])])
+    ];
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Number(3.0)]), Ok(ConstantValue::Number(14.0)));
+}
+
+#[test]
+fn generic_function_calls_the_trait_method_of_its_type_argument() {
+    // trait Comparable { less: Function(Self, Self) -> Bool }
+    // impl Comparable for Point { fn less(self: Self, other: Self) -> Bool { self.x < other.x } }
+    // fn max<T: Comparable>(a: T, b: T) -> T { if a.less(b) { b } else { a } }
+    // fn f(a: Number, b: Number) -> Number { max<Point>(Point { x: a, y: 0.0 }, Point { x: b, y: 1.0 }).y }
+    let program = vec![
+        point(),
+        Expression::TraitDeclaration { name: "Comparable".to_string(), method_names: vec!["less".to_string()], method_types: vec!["Function(Self, Self) -> Bool".to_string()] },
+        impl_block("Point", Some("Comparable"), vec![
+            function("less", "Bool", &[("self", "Self"), ("other", "Self")], vec![compare(Predicate::LESS_THAN, field(ident("self"), "x"), field(ident("other"), "x"))])
+        ]),
+        Expression::Function {
+            name: "max".to_string(),
+            return_type: "T".to_string(),
+            param_types: vec!["T".to_string(), "T".to_string()],
+            param_names: vec!["a".to_string(), "b".to_string()],
+            type_params: vec![("T".to_string(), vec!["Comparable".to_string()])],
+            content: vec![if_else(method_call(ident("a"), "less", vec![ident("b")]), vec![ident("b")], vec![ident("a")])]
+        },
+        function("f", "Number", &[("a", "Number"), ("b", "Number")], vec![
+            field(call("max<Point>", vec![point_literal(ident("a"), number(0.0)), point_literal(ident("b"), number(1.0))]), "y")
+        ])
+    ];
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Number(1.0), ConstantValue::Number(2.0)]), Ok(ConstantValue::Number(1.0)));
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Number(3.0), ConstantValue::Number(2.0)]), Ok(ConstantValue::Number(0.0)));
+}
+
+#[test]
+fn operators_on_structs_call_their_methods() {
+    // impl Point {
+    //     fn add(self: Self, other: Self) -> Point { Point { x: self.x + other.x, y: self.y + other.y } }
+    //     fn equals(self: Self, other: Self) -> Bool { self.x == other.x }
+    // }
+    // fn sum_y(a: Number) -> Number { (Point { x: a, y: 1.0 } + Point { x: 2.0, y: a }).y }
+    // fn differ(a: Number) -> Bool { Point { x: a, y: 0.0 } != Point { x: 2.0, y: 5.0 } }
+    let program = vec![
+        point(),
+        impl_block("Point", None, vec![
+            function("add", "Point", &[("self", "Self"), ("other", "Self")], vec![point_literal(
+                arithmetic(BinaryOperator::ADD, field(ident("self"), "x"), field(ident("other"), "x")),
+                arithmetic(BinaryOperator::ADD, field(ident("self"), "y"), field(ident("other"), "y"))
+            )]),
+            function("equals", "Bool", &[("self", "Self"), ("other", "Self")], vec![compare(Predicate::EQUAL, field(ident("self"), "x"), field(ident("other"), "x"))])
+        ]),
+        function("sum_y", "Number", &[("a", "Number")], vec![
+            field(arithmetic(BinaryOperator::ADD, point_literal(ident("a"), number(1.0)), point_literal(number(2.0), ident("a"))), "y")
+        ]),
+        function("differ", "Bool", &[("a", "Number")], vec![
+            compare(Predicate::NOT_EQUAL, point_literal(ident("a"), number(0.0)), point_literal(number(2.0), number(5.0)))
+        ])
+    ];
+    assert_eq!(run_function(&program, "sum_y", &[ConstantValue::Number(4.0)]), Ok(ConstantValue::Number(5.0)));
+    assert_eq!(run_function(&program, "differ", &[ConstantValue::Number(2.0)]), Ok(ConstantValue::Bool(false)));
+    assert_eq!(run_function(&program, "differ", &[ConstantValue::Number(3.0)]), Ok(ConstantValue::Bool(true)));
+}
+
+#[test]
+fn format_writes_each_value_as_text() {
+    // extern fn strcmp(a: String, b: String) -> i32
+    // fn f(x: i32) -> i32 { strcmp(format("{} + {} = {}{{}}%", x, 2.5, x > 0), "3 + 2.500000 = true{}%") }
+    let program = vec![
+        Expression::ExternFunction { name: "strcmp".to_string(), return_type: "i32".to_string(), param_types: vec!["String".to_string(), "String".to_string()] },
+        function("f", "i32", &[("x", "i32")], vec![call("strcmp", vec![
+            call("format", vec![string("{} + {} = {}{{}}%"), ident("x"), number(2.5), compare(Predicate::GREATER_THAN, ident("x"), int(0))]),
+            string("3 + 2.500000 = true{}%")
+        ])])
+    ];
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(3)]), Ok(ConstantValue::Int(0)));
+    assert_ne!(run_function(&program, "f", &[ConstantValue::Int(4)]), Ok(ConstantValue::Int(0)));
+}
+
+#[test]
+fn functions_share_global_variables() {
+    // let mut counter: i32 = 0; let step = 5
+    // fn bump() -> i32 { counter = counter + step; counter } fn twice() -> i32 { bump(); bump() }
+    let program = vec![
+        let_mut("counter", "i32", int(0)),
+        let_variable("step", "", int(5)),
+        function("bump", "i32", &[], vec![
+            assign("counter", arithmetic(BinaryOperator::ADD, ident("counter"), ident("step"))),
+            ident("counter")
+        ]),
+        function("twice", "i32", &[], vec![call("bump", vec![]), call("bump", vec![])])
+    ];
+    assert_eq!(run_function(&program, "twice", &[]), Ok(ConstantValue::Int(10)));
+}
+
+#[test]
+fn nested_function_uses_the_outer_locals() {
+    // fn f(x: i32) -> i32 { let k = 3; fn add_k(y: i32) -> i32 { y + k }; fn square(y: i32) -> i32 { y * y }; let after = 1; square(add_k(x)) + after }
+    let program = vec![function("f", "i32", &[("x", "i32")], vec![
+        let_variable("k", "", int(3)),
+        function("add_k", "i32", &[("y", "i32")], vec![arithmetic(BinaryOperator::ADD, ident("y"), ident("k"))]),
+        function("square", "i32", &[("y", "i32")], vec![arithmetic(BinaryOperator::MUL, ident("y"), ident("y"))]),
+        let_variable("after", "", int(1)),
+        arithmetic(BinaryOperator::ADD, call("square", vec![call("add_k", vec![ident("x")])]), ident("after"))
+    ])];
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(4)]), Ok(ConstantValue::Int(50)));
+}
+
+#[test]
+fn if_with_void_branches_runs_the_taken_branch() {
+    // fn f(a: i32) -> i32 { let mut r = 0; if a > 0 { r = 10 }; if a == 1 { r = r + 1 } else { while false {} }; r }
+    let program = vec![function("f", "i32", &[("a", "i32")], vec![
+        let_mut("r", "i32", int(0)),
+        if_else(compare(Predicate::GREATER_THAN, ident("a"), int(0)), vec![assign("r", int(10))], vec![]),
+        if_else(
+            compare(Predicate::EQUAL, ident("a"), int(1)),
+            vec![assign("r", arithmetic(BinaryOperator::ADD, ident("r"), int(1)))],
+            vec![while_loop(Expression::BoolLiteral(false), vec![])]
+        ),
+        ident("r")
+    ])];
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(1)]), Ok(ConstantValue::Int(11)));
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(2)]), Ok(ConstantValue::Int(10)));
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(-1)]), Ok(ConstantValue::Int(0)));
+}
+#[test]
+fn strings_are_concatenated_with_plus() {
+    // extern fn strcmp(a: String, b: String) -> i32
+    // fn f() -> i32 { let greeting = "Hello, " + "KSC"; strcmp(greeting + "!", "Hello, KSC!") }
+    let program = vec![
+        Expression::ExternFunction { name: "strcmp".to_string(), return_type: "i32".to_string(), param_types: vec!["String".to_string(), "String".to_string()] },
+        function("f", "i32", &[], vec![
+            let_variable("greeting", "", arithmetic(BinaryOperator::ADD, string("Hello, "), string("KSC"))),
+            call("strcmp", vec![arithmetic(BinaryOperator::ADD, ident("greeting"), string("!")), string("Hello, KSC!")])
+        ])
+    ];
+    let ir = compile_to_ir(&program).unwrap();
+    assert_eq!(ir.matches("call i8* @ksc_string_concat").count(), 2, "{ir}");
+    assert_eq!(run_function(&program, "f", &[]), Ok(ConstantValue::Int(0)));
+}
+
+fn cast(value: Expression, typename: &str) -> Expression {
+    return Expression::Cast { value: Box::from(value), target_type: typename.to_string() };
+}
+
+fn bitcast(value: Expression, typename: &str) -> Expression {
+    return Expression::BitCast { value: Box::from(value), target_type: typename.to_string() };
+}
+
+#[test]
+fn bitcast_reads_the_bits_of_a_number() {
+    // fn exponent(x: Number) -> i32 { ((bitcast<i64>(x) >> 52 as i64) & 2047 as i64) as i32 - 1023 }
+    let program = vec![function("exponent", "i32", &[("x", "Number")], vec![
+        arithmetic(BinaryOperator::SUB, cast(arithmetic(BinaryOperator::AND,
+            arithmetic(BinaryOperator::ASHR, bitcast(ident("x"), "i64"), cast(int(52), "i64")),
+            cast(int(2047), "i64")
+        ), "i32"), int(1023))
+    ])];
+    assert_eq!(run_function(&program, "exponent", &[ConstantValue::Number(8.0)]), Ok(ConstantValue::Int(3)));
+    assert_eq!(run_function(&program, "exponent", &[ConstantValue::Number(0.25)]), Ok(ConstantValue::Int(-2)));
+}
+
+#[test]
+fn bitcast_between_different_sizes_is_an_error() {
+    let program = vec![function("f", "i32", &[("x", "Number")], vec![bitcast(ident("x"), "i32")])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("bitcast<i32> requires the same size, but the value is 64 bits and 'i32' is 32 bits."), "{error}");
+}
+
+fn pointer_load(pointer: Expression) -> Expression {
+    return Expression::PointerLoad(Box::from(pointer));
+}
+
+fn pointer_store(pointer: Expression, value: Expression) -> Expression {
+    return Expression::PointerStore { pointer: Box::from(pointer), value: Box::from(value) };
+}
+
+#[test]
+fn raw_pointer_writes_to_the_variable_it_points_to() {
+    // fn f(x: i32) -> i32 { let mut a = x; unsafe { let p = &a; *p = *p * 2 }; a }
+    let program = vec![function("f", "i32", &[("x", "i32")], vec![
+        let_mut("a", "", ident("x")),
+        Expression::Unsafe(vec![
+            let_variable("p", "", Expression::AddressOf("a".to_string())),
+            pointer_store(ident("p"), arithmetic(BinaryOperator::MUL, pointer_load(ident("p")), int(2)))
+        ]),
+        ident("a")
+    ])];
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(21)]), Ok(ConstantValue::Int(42)));
+}
+
+#[test]
+fn dereferencing_outside_unsafe_is_an_error() {
+    // fn f(x: i32) -> i32 { let p = &x; *p }
+    let program = vec![function("f", "i32", &[("x", "i32")], vec![
+        let_variable("p", "", Expression::AddressOf("x".to_string())),
+        pointer_load(ident("p"))
+    ])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("Dereferencing a RawPtr is only allowed inside an unsafe block."), "{error}");
+}
+
+#[test]
+fn unsafe_report_lists_unsafe_blocks_and_extern_calls() {
+    // extern fn abs(x: i32) -> i32
+    // fn f(x: i32) -> i32 { let mut a = x; unsafe { let p = &a; *p = 1 }; abs(a) }
+    // fn g() -> i32 { 0 }
+    let program = vec![
+        Expression::ExternFunction { name: "abs".to_string(), return_type: "i32".to_string(), param_types: vec!["i32".to_string()] },
+        function("f", "i32", &[("x", "i32")], vec![
+            let_mut("a", "", ident("x")),
+            Expression::Unsafe(vec![
+                let_variable("p", "", Expression::AddressOf("a".to_string())),
+                pointer_store(ident("p"), int(1))
+            ]),
+            call("abs", vec![ident("a")])
+        ]),
+        function("g", "i32", &[], vec![int(0)])
+    ];
+    assert_eq!(unsafe_report(&program, &TestOptions::default()), Ok("unsafe block in 'f'\nextern call to 'abs' in 'f'\n".to_string()));
+}
+
+#[test]
+fn stats_count_the_nodes_and_the_compiled_functions() {
+    let stats = compile_stats(&vec![gcd()], &TestOptions::default()).unwrap();
+    assert!(stats.starts_with("======== STATS ========\n"), "{stats}");
+    assert!(stats.ends_with("========= END =========\n"), "{stats}");
+    for line in ["Function: 1\n", "Call: 1\n", "Identifier: 5\n", "If: 1\n", "functions compiled: 1\n"] {
+        assert!(stats.contains(line), "{stats}");
+    }
+}
+
+fn index(array: Expression, index: Expression) -> Expression {
+    return Expression::Index { array: Box::from(array), index: Box::from(index) };
+}
+
+#[test]
+fn array_elements_are_read_by_index() {
+    // fn f(n: i32) -> i32 { let a: i32[4] = [3, 1, 4, 1]; let mut total = 0; let mut i = 0; while i < n { total = total + a[i]; i = i + 1 }; total + a[2] }
+    let program = vec![function("f", "i32", &[("n", "i32")], vec![
+        let_variable("a", "i32[4]", Expression::ArrayLiteral(vec![int(3), int(1), int(4), int(1)])),
+        let_mut("total", "i32", int(0)),
+        let_mut("i", "i32", int(0)),
+        while_loop(compare(Predicate::LESS_THAN, ident("i"), ident("n")), vec![
+            assign("total", arithmetic(BinaryOperator::ADD, ident("total"), index(ident("a"), ident("i")))),
+            assign("i", arithmetic(BinaryOperator::ADD, ident("i"), int(1)))
+        ]),
+        arithmetic(BinaryOperator::ADD, ident("total"), index(ident("a"), int(2)))
+    ])];
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(4)]), Ok(ConstantValue::Int(13)));
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(2)]), Ok(ConstantValue::Int(8)));
+}
+
+#[test]
+fn constant_index_out_of_bounds_is_an_error() {
+    let program = vec![function("f", "i32", &[], vec![index(Expression::ArrayLiteral(vec![int(1), int(2)]), int(2))])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("Index 2 is out of bounds for an array of length 2."), "{error}");
+}
+
+#[test]
+fn list_grows_as_values_are_pushed() {
+    // fn f(n: i32) -> i32 { let l = List<i32>(); let mut i = 0; while i < n { l.push(i * i); i = i + 1 }; len(l) * 1000 + l[n - 1] }
+    let program = vec![function("f", "i32", &[("n", "i32")], vec![
+        let_variable("l", "", Expression::ListNew("i32".to_string())),
+        let_mut("i", "i32", int(0)),
+        while_loop(compare(Predicate::LESS_THAN, ident("i"), ident("n")), vec![
+            list_push("l", arithmetic(BinaryOperator::MUL, ident("i"), ident("i"))),
+            assign("i", arithmetic(BinaryOperator::ADD, ident("i"), int(1)))
+        ]),
+        arithmetic(BinaryOperator::ADD,
+            arithmetic(BinaryOperator::MUL, Expression::ListLen(Box::from(ident("l"))), int(1000)),
+            index(ident("l"), arithmetic(BinaryOperator::SUB, ident("n"), int(1)))
+        )
+    ])];
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(10)]), Ok(ConstantValue::Int(10081)));
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(1)]), Ok(ConstantValue::Int(1000)));
+}
+
+#[test]
+fn shadow_stack_pushes_the_function_name_on_entry_and_pops_it_on_return() {
+    let options = TestOptions{ shadow_stack: true, ..TestOptions::default() };
+    let ir = compile_to_ir_with(&vec![gcd()], &options).unwrap();
+    assert!(ir.contains("c\"gcd\\00\""), "{ir}");
+    assert_eq!(ir.matches("call void @ksc_shadow_push(").count(), 1, "{ir}");
+    assert_eq!(ir.matches("call void @ksc_shadow_pop()").count(), ir.matches("  ret ").count(), "{ir}");
+    let ir = compile_to_ir(&vec![gcd()]).unwrap();
+    assert!(!ir.contains("ksc_shadow"), "{ir}");
+}
+
+#[test]
+fn unsigned_integers_divide_and_compare_without_the_sign() {
+    // fn half(x: i32) -> i32 { let u = x as u32; if u > 10 as u32 { (u / 2 as u32) as i32 } else { -1 } }
+    // fn as_i8(x: i32) -> i32 { (x as i8) as i32 }
+    // fn as_u8(x: i32) -> i32 { (x as u8) as i32 }
+    let program = vec![
+        function("half", "i32", &[("x", "i32")], vec![
+            let_variable("u", "", cast(ident("x"), "u32")),
+            if_else(
+                compare(Predicate::GREATER_THAN, ident("u"), cast(int(10), "u32")),
+                vec![cast(arithmetic(BinaryOperator::DIV, ident("u"), cast(int(2), "u32")), "i32")],
+                vec![int(-1)]
+            )
+        ]),
+        function("as_i8", "i32", &[("x", "i32")], vec![cast(cast(ident("x"), "i8"), "i32")]),
+        function("as_u8", "i32", &[("x", "i32")], vec![cast(cast(ident("x"), "u8"), "i32")])
+    ];
+    assert_eq!(run_function(&program, "half", &[ConstantValue::Int(-2)]), Ok(ConstantValue::Int(2147483647)));
+    assert_eq!(run_function(&program, "half", &[ConstantValue::Int(8)]), Ok(ConstantValue::Int(-1)));
+    assert_eq!(run_function(&program, "as_i8", &[ConstantValue::Int(200)]), Ok(ConstantValue::Int(-56)));
+    assert_eq!(run_function(&program, "as_u8", &[ConstantValue::Int(200)]), Ok(ConstantValue::Int(200)));
+    let ir = compile_to_ir(&program).unwrap();
+    assert!(ir.contains("udiv i32") && ir.contains("icmp ugt i32"), "{ir}");
+}
+
+#[test]
+fn casts_between_numbers_integers_and_bool() {
+    // fn truncate(x: Number) -> i32 { x as i32 }
+    // fn wrap_around(x: i32) -> Number { x as u32 as Number }
+    // fn count(a: Bool, b: Bool) -> i32 { a as i32 + b as i32 }
+    let program = vec![
+        function("truncate", "i32", &[("x", "Number")], vec![cast(ident("x"), "i32")]),
+        function("wrap_around", "Number", &[("x", "i32")], vec![cast(cast(ident("x"), "u32"), "Number")]),
+        function("count", "i32", &[("a", "Bool"), ("b", "Bool")], vec![arithmetic(BinaryOperator::ADD, cast(ident("a"), "i32"), cast(ident("b"), "i32"))])
+    ];
+    assert_eq!(run_function(&program, "truncate", &[ConstantValue::Number(2.9)]), Ok(ConstantValue::Int(2)));
+    assert_eq!(run_function(&program, "truncate", &[ConstantValue::Number(-2.9)]), Ok(ConstantValue::Int(-2)));
+    assert_eq!(run_function(&program, "wrap_around", &[ConstantValue::Int(-1)]), Ok(ConstantValue::Number(4294967295.0)));
+    assert_eq!(run_function(&program, "count", &[ConstantValue::Bool(true), ConstantValue::Bool(true)]), Ok(ConstantValue::Int(2)));
+    assert_eq!(run_function(&program, "count", &[ConstantValue::Bool(false), ConstantValue::Bool(true)]), Ok(ConstantValue::Int(1)));
+}
+
+#[test]
+fn chained_comparison_evaluates_each_operand_at_most_once() {
+    // let mut calls: i32 = 0
+    // fn tick(x: i32) -> i32 { calls = calls + 1; x }
+    // fn f(a: i32) -> i32 { let inside = 0 < tick(a) < tick(10); calls * 10 + inside as i32 }
+    let program = vec![
+        let_mut("calls", "i32", int(0)),
+        function("tick", "i32", &[("x", "i32")], vec![
+            assign("calls", arithmetic(BinaryOperator::ADD, ident("calls"), int(1))),
+            ident("x")
+        ]),
+        function("f", "i32", &[("a", "i32")], vec![
+            let_variable("inside", "", Expression::ChainedComparison {
+                operands: vec![int(0), call("tick", vec![ident("a")]), call("tick", vec![int(10)])],
+                predicates: vec![Predicate::LESS_THAN, Predicate::LESS_THAN]
+            }),
+            arithmetic(BinaryOperator::ADD, arithmetic(BinaryOperator::MUL, ident("calls"), int(10)), cast(ident("inside"), "i32"))
+        ])
+    ];
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(5)]), Ok(ConstantValue::Int(21)));
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(10)]), Ok(ConstantValue::Int(20)));
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(-1)]), Ok(ConstantValue::Int(10)));
+}
+
+#[test]
+fn bitwise_operators_and_shifts_on_integers() {
+    // fn mask(x: i32) -> i32 { ((x & 12) | 1) ^ 3 }
+    // fn shifts(x: i32) -> i32 { (x << 4) + (x >> 2) * 100 + (x >>> 28) * 10000 }
+    let program = vec![
+        function("mask", "i32", &[("x", "i32")], vec![
+            arithmetic(BinaryOperator::XOR, arithmetic(BinaryOperator::OR, arithmetic(BinaryOperator::AND, ident("x"), int(12)), int(1)), int(3))
+        ]),
+        function("shifts", "i32", &[("x", "i32")], vec![arithmetic(BinaryOperator::ADD,
+            arithmetic(BinaryOperator::ADD,
+                arithmetic(BinaryOperator::SHL, ident("x"), int(4)),
+                arithmetic(BinaryOperator::MUL, arithmetic(BinaryOperator::ASHR, ident("x"), int(2)), int(100))
+            ),
+            arithmetic(BinaryOperator::MUL, arithmetic(BinaryOperator::LSHR, ident("x"), int(28)), int(10000))
+        )])
+    ];
+    assert_eq!(run_function(&program, "mask", &[ConstantValue::Int(13)]), Ok(ConstantValue::Int(14)));
+    assert_eq!(run_function(&program, "shifts", &[ConstantValue::Int(-16)]), Ok(ConstantValue::Int(-256 - 400 + 150000)));
+}
+
+#[test]
+fn last_expression_of_a_function_is_returned() {
+    // fn clamp(x: i32) -> i32 { if x > 10 { return 10 }; let y = x * 2; y - x }
+    let program = vec![function("clamp", "i32", &[("x", "i32")], vec![
+        if_else(compare(Predicate::GREATER_THAN, ident("x"), int(10)), vec![Expression::Return(Some(Box::from(int(10))))], vec![]),
+        let_variable("y", "", arithmetic(BinaryOperator::MUL, ident("x"), int(2))),
+        arithmetic(BinaryOperator::SUB, ident("y"), ident("x"))
+    ])];
+    assert_eq!(run_function(&program, "clamp", &[ConstantValue::Int(7)]), Ok(ConstantValue::Int(7)));
+    assert_eq!(run_function(&program, "clamp", &[ConstantValue::Int(70)]), Ok(ConstantValue::Int(10)));
+}
+
+#[test]
+fn last_expression_of_the_wrong_type_is_an_error() {
+    let program = vec![function("f", "i32", &[], vec![number(1.0)])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("Function 'f' must return 'i32', but its last expression is 'Number'."), "{error}");
+    let program = vec![function("g", "i32", &[], vec![while_loop(Expression::BoolLiteral(false), vec![])])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("Function 'g' must return 'i32', but it can reach the end without returning."), "{error}");
+}
+
+#[test]
+fn comparison_results_are_bool_values() {
+    // fn order(a: Number, b: Number) -> Number { let less = a < b; let same = a == b; less as Number + same as Number * 10.0 }
+    let program = vec![function("order", "Number", &[("a", "Number"), ("b", "Number")], vec![
+        let_variable("less", "Bool", compare(Predicate::LESS_THAN, ident("a"), ident("b"))),
+        let_variable("same", "Bool", compare(Predicate::EQUAL, ident("a"), ident("b"))),
+        arithmetic(BinaryOperator::ADD, cast(ident("less"), "Number"), arithmetic(BinaryOperator::MUL, cast(ident("same"), "Number"), number(10.0)))
+    ])];
+    assert_eq!(run_function(&program, "order", &[ConstantValue::Number(1.0), ConstantValue::Number(2.0)]), Ok(ConstantValue::Number(1.0)));
+    assert_eq!(run_function(&program, "order", &[ConstantValue::Number(2.0), ConstantValue::Number(2.0)]), Ok(ConstantValue::Number(10.0)));
+    assert_eq!(run_function(&program, "order", &[ConstantValue::Number(3.0), ConstantValue::Number(2.0)]), Ok(ConstantValue::Number(0.0)));
+    let ir = compile_to_ir(&program).unwrap();
+    assert!(ir.contains("uitofp i1"), "{ir}");
+}
+
+#[test]
+fn every_failing_function_is_reported() {
+    // fn f() -> i32 { 1.0 }  fn g() -> Number { 2.0 }  fn h() -> Bool { 3 }
+    let program = vec![
+        function("f", "i32", &[], vec![number(1.0)]),
+        function("g", "Number", &[], vec![number(2.0)]),
+        function("h", "Bool", &[], vec![int(3)])
+    ];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("Function 'f' must return 'i32', but its last expression is 'Number'."), "{error}");
+    assert!(error.contains("Function 'h' must return 'Bool', but its last expression is 'i32'."), "{error}");
+    assert!(error.ends_with("error: aborting due to 2 previous errors"), "{error}");
+}
+
+#[test]
+fn function_table_is_called_through_an_index() {
+    // fn add(a: Number, b: Number) -> Number { a + b }
+    // fn sub(a: Number, b: Number) -> Number { a - b }
+    // fn apply(i: i32, a: Number, b: Number) -> Number { let ops: (Function(Number, Number) -> Number)[2] = [add, sub]; ops[i](a, b) }
+    let program = vec![
+        function("add", "Number", &[("a", "Number"), ("b", "Number")], vec![arithmetic(BinaryOperator::ADD, ident("a"), ident("b"))]),
+        function("sub", "Number", &[("a", "Number"), ("b", "Number")], vec![arithmetic(BinaryOperator::SUB, ident("a"), ident("b"))]),
+        function("apply", "Number", &[("i", "i32"), ("a", "Number"), ("b", "Number")], vec![
+            let_variable("ops", "(Function(Number, Number) -> Number)[2]", Expression::ArrayLiteral(vec![ident("add"), ident("sub")])),
+            call_value(index(ident("ops"), ident("i")), vec![ident("a"), ident("b")])
+        ])
+    ];
+    let ir = compile_to_ir(&program).unwrap();
+    assert!(ir.contains("private unnamed_addr constant [2 x double (double, double)*] [double (double, double)* @add, double (double, double)* @sub]"), "{ir}");
+    assert_eq!(run_function(&program, "apply", &[ConstantValue::Int(0), ConstantValue::Number(5.0), ConstantValue::Number(3.0)]), Ok(ConstantValue::Number(8.0)));
+    assert_eq!(run_function(&program, "apply", &[ConstantValue::Int(1), ConstantValue::Number(5.0), ConstantValue::Number(3.0)]), Ok(ConstantValue::Number(2.0)));
+}
+
+#[test]
+fn bitcode_has_the_magic_number_and_the_function_names() {
+    let bitcode = compile_to_bitcode(&vec![gcd()], &TestOptions::default()).unwrap();
+    assert!(bitcode.starts_with(b"BC\xC0\xDE"), "{:?}", &bitcode[..4]);
+    assert!(bitcode.windows(3).any(|name| name == b"gcd"));
+}
+
+#[test]
+fn assembly_defines_each_function() {
+    let assembly = compile_to_assembly(&vec![gcd()], &TestOptions::default()).unwrap();
+    assert!(assembly.contains(".globl\tgcd\n") && assembly.contains("\ngcd:"), "{assembly}");
+    let optimized = compile_to_assembly(&vec![gcd()], &TestOptions{ optimization_level: Some(2), ..TestOptions::default() }).unwrap();
+    assert!(optimized.contains("\ngcd:"), "{optimized}");
+    assert_ne!(assembly, optimized);
+}
+
+#[test]
+fn wasm_module_exports_its_functions_and_prints_through_the_host() {
+    // fn show(x: Number) { println(x) }
+    let program = vec![gcd(), function("show", "Void", &[("x", "Number")], vec![call("println", vec![ident("x")])])];
+    let options = TestOptions{ target: Some("wasm32-unknown-unknown".to_string()), ..TestOptions::default() };
+    let ir = compile_to_ir_with(&program, &options).unwrap();
+    assert!(ir.contains("target triple = \"wasm32-unknown-unknown\""), "{ir}");
+    assert!(ir.contains("\"wasm-export-name\"=\"gcd\"") && ir.contains("\"wasm-export-name\"=\"show\""), "{ir}");
+    assert!(ir.contains("call void @ksc_print_f64(double %") && !ir.contains("@printf"), "{ir}");
+    assert!(compile_to_assembly(&program, &options).unwrap().contains(".functype\tgcd (i32, i32) -> (i32)"));
+
+    let program = vec![function("f", "String", &[("x", "i32")], vec![call("format", vec![string("{}"), ident("x")])])];
+    let error = compile_to_ir_with(&program, &options).unwrap_err();
+    assert!(error.contains("Function 'format' is not available for wasm32-unknown-unknown because there is no snprintf."), "{error}");
+}
+
+#[test]
+fn value_made_with_new_outlives_its_function_until_deleted() {
+    // fn make(x: i32) -> RawPtr<i32> { new x }
+    // fn f(x: i32) -> i32 { let p = make(x * 2); unsafe { let v = *p; delete p; v } }
+    let program = vec![
+        function("make", "RawPtr<i32>", &[("x", "i32")], vec![Expression::New(Box::from(ident("x")))]),
+        function("f", "i32", &[("x", "i32")], vec![
+            let_variable("p", "", call("make", vec![arithmetic(BinaryOperator::MUL, ident("x"), int(2))])),
+            Expression::Unsafe(vec![
+                let_variable("v", "", pointer_load(ident("p"))),
+                Expression::Delete(Box::from(ident("p"))),
+                ident("v")
+            ])
+        ])
+    ];
+    let ir = compile_to_ir(&program).unwrap();
+    assert!(ir.contains("call i8* @ksc_alloc(") && ir.contains("call void @ksc_free("), "{ir}");
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(21)]), Ok(ConstantValue::Int(42)));
+}
+
+#[test]
+fn delete_outside_unsafe_is_an_error() {
+    let program = vec![function("f", "Void", &[("x", "i32")], vec![
+        let_variable("p", "", Expression::New(Box::from(ident("x")))),
+        Expression::Delete(Box::from(ident("p")))
+    ])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("Freeing a RawPtr is only allowed inside an unsafe block."), "{error}");
+}
+
+#[test]
+fn tuple_elements_are_read_by_their_index() {
+    // fn divmod(a: i32, b: i32) -> (i32, i32) { (a / b, a % b) }
+    // fn f(a: i32, b: i32) -> i32 { let t = divmod(a, b); t.0 * 100 + t.1 }
+    let program = vec![
+        function("divmod", "(i32, i32)", &[("a", "i32"), ("b", "i32")], vec![Expression::TupleLiteral(vec![
+            arithmetic(BinaryOperator::DIV, ident("a"), ident("b")),
+            arithmetic(BinaryOperator::REM, ident("a"), ident("b"))
+        ])]),
+        function("f", "i32", &[("a", "i32"), ("b", "i32")], vec![
+            let_variable("t", "", call("divmod", vec![ident("a"), ident("b")])),
+            arithmetic(BinaryOperator::ADD, arithmetic(BinaryOperator::MUL, field(ident("t"), "0"), int(100)), field(ident("t"), "1"))
+        ])
+    ];
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Int(17), ConstantValue::Int(5)]), Ok(ConstantValue::Int(302)));
+
+    let program = vec![function("g", "i32", &[], vec![field(Expression::TupleLiteral(vec![int(1), int(2)]), "2")])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("The tuple '(i32, i32)' has no element '2'. Elements are accessed by their index, such as '.0'."), "{error}");
+}
+
+#[test]
+fn generic_struct_is_made_once_for_each_type_argument() {
+    // struct Pair<A, B> { first: A, second: B }
+    // fn f(x: Number, n: i32) -> Number {
+    //     let p = Pair<Number, i32> { first: x, second: n }; let q: Pair<Number, i32> = p
+    //     let l = List<Number>(); l.push(q.first); l[0] + q.second as Number
+    // }
+    let program = vec![
+        Expression::StructDeclaration {
+            name: "Pair".to_string(),
+            type_params: vec!["A".to_string(), "B".to_string()],
+            field_names: vec!["first".to_string(), "second".to_string()],
+            field_types: vec!["A".to_string(), "B".to_string()],
+            defaults: vec![None, None]
+        },
+        function("f", "Number", &[("x", "Number"), ("n", "i32")], vec![
+            let_variable("p", "", struct_literal("Pair<Number, i32>", vec![("first", ident("x")), ("second", ident("n"))])),
+            let_variable("q", "Pair<Number, i32>", ident("p")),
+            let_variable("l", "", Expression::ListNew("Number".to_string())),
+            list_push("l", field(ident("q"), "first")),
+            arithmetic(BinaryOperator::ADD, index(ident("l"), int(0)), cast(field(ident("q"), "second"), "Number"))
+        ])
+    ];
+    let ir = compile_to_ir(&program).unwrap();
+    assert_eq!(ir.matches("%\"Pair<Number, i32>\" = type").count(), 1, "{ir}");
+    assert!(ir.contains("%\"List<Number>\" = type"), "{ir}");
+    assert_eq!(run_function(&program, "f", &[ConstantValue::Number(1.5), ConstantValue::Int(2)]), Ok(ConstantValue::Number(3.5)));
+}
+
+fn constant(name: &str, value: Expression) -> Expression {
+    return Expression::ConstDeclaration { typename: String::new(), name: name.to_string(), value: Box::from(value) };
+}
+
+#[test]
+fn constants_are_folded_and_can_size_arrays() {
+    // const N = 2 + 2
+    // fn area() -> i32 { (N + 1) * N }
+    // fn sum(x: Number) -> Number { let a: Number[N] = [x, x, x, x]; a[N - 1] * N as Number }
+    let program = vec![
+        constant("N", arithmetic(BinaryOperator::ADD, int(2), int(2))),
+        function("area", "i32", &[], vec![arithmetic(BinaryOperator::MUL, arithmetic(BinaryOperator::ADD, ident("N"), int(1)), ident("N"))]),
+        function("sum", "Number", &[("x", "Number")], vec![
+            let_variable("a", "Number[N]", Expression::ArrayLiteral(vec![ident("x"), ident("x"), ident("x"), ident("x")])),
+            arithmetic(BinaryOperator::MUL, index(ident("a"), arithmetic(BinaryOperator::SUB, ident("N"), int(1))), cast(ident("N"), "Number"))
+        ])
+    ];
+    let ir = compile_to_ir(&program).unwrap();
+    assert!(ir.contains("ret i32 20"), "{ir}");
+    assert!(ir.contains("alloca [4 x double]"), "{ir}");
+    assert_eq!(run_function(&program, "sum", &[ConstantValue::Number(1.5)]), Ok(ConstantValue::Number(6.0)));
+}
+
+#[test]
+fn constant_that_cannot_be_evaluated_is_an_error() {
+    let program = vec![function("f", "i32", &[("x", "i32")], vec![constant("M", ident("x")), ident("M")])];
+    let error = compile_to_ir(&program).unwrap_err();
+    assert!(error.contains("The value of constant 'M' cannot be evaluated at compile time."), "{error}");
+}
@@ -0,0 +1,152 @@
+//! テストでASTを組み立てるための関数(パーサーができるまではASTを直接書く)
+#![allow(dead_code)]
+
+use ksc1000::{BinaryOperation, BinaryOperator, Expression, KSCAttribute, MatchArm, Pattern, Predicate, Span};
+
+/// fn name(params) -> return_type { content }
+pub fn function(name: &str, return_type: &str, params: &[(&str, &str)], content: Vec<Expression>) -> Expression {
+    return Expression::Function {
+        name: name.to_string(),
+        return_type: return_type.to_string(),
+        param_types: params.iter().map(|(_, typename)| typename.to_string()).collect(),
+        param_names: params.iter().map(|(name, _)| name.to_string()).collect(),
+        type_params: vec![],
+        content
+    };
+}
+
+pub fn ident(name: &str) -> Expression {
+    return Expression::Identifier(name.to_string());
+}
+
+pub fn int(value: i64) -> Expression {
+    return Expression::IntLiteral(value);
+}
+
+pub fn number(value: f64) -> Expression {
+    return Expression::NumberLiteral(value);
+}
+
+pub fn arithmetic(op: BinaryOperator, lhs: Expression, rhs: Expression) -> Expression {
+    return Expression::Binary { op: BinaryOperation::Arithmetic(op), lhs: Box::from(lhs), rhs: Box::from(rhs) };
+}
+
+pub fn compare(predicate: Predicate, lhs: Expression, rhs: Expression) -> Expression {
+    return Expression::Binary { op: BinaryOperation::Comparison(predicate), lhs: Box::from(lhs), rhs: Box::from(rhs) };
+}
+
+pub fn call(callee: &str, args: Vec<Expression>) -> Expression {
+    return Expression::Call { callee: callee.to_string(), args };
+}
+
+/// let name: typename = value
+pub fn let_variable(name: &str, typename: &str, value: Expression) -> Expression {
+    return Expression::VariableDeclaration { typename: typename.to_string(), name: name.to_string(), mutable: false, align: None, value: Box::from(value) };
+}
+
+/// let mut name: typename = value
+pub fn let_mut(name: &str, typename: &str, value: Expression) -> Expression {
+    return Expression::VariableDeclaration { typename: typename.to_string(), name: name.to_string(), mutable: true, align: None, value: Box::from(value) };
+}
+
+/// target = value
+pub fn assign(target: &str, value: Expression) -> Expression {
+    return Expression::Assignment { target: target.to_string(), value: Box::from(value) };
+}
+
+/// while condition { content }
+pub fn while_loop(condition: Expression, content: Vec<Expression>) -> Expression {
+    return Expression::While { condition: Box::from(condition), content };
+}
+
+/// struct name { fields }(初期値なし)
+pub fn struct_declaration(name: &str, fields: &[(&str, &str)]) -> Expression {
+    return Expression::StructDeclaration {
+        name: name.to_string(),
+        type_params: vec![],
+        field_names: fields.iter().map(|(name, _)| name.to_string()).collect(),
+        field_types: fields.iter().map(|(_, typename)| typename.to_string()).collect(),
+        defaults: fields.iter().map(|_| None).collect()
+    };
+}
+
+pub fn struct_literal(typename: &str, fields: Vec<(&str, Expression)>) -> Expression {
+    return Expression::StructLiteral { typename: typename.to_string(), field_values: fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect() };
+}
+
+pub fn field(object: Expression, name: &str) -> Expression {
+    return Expression::FieldAccess { object: Box::from(object), field: name.to_string() };
+}
+
+pub fn attributed(attributes: Vec<KSCAttribute>, expression: Expression) -> Expression {
+    return Expression::Attributed { attributes, expression: Box::from(expression) };
+}
+
+pub fn if_else(condition: Expression, then: Vec<Expression>, otherwise: Vec<Expression>) -> Expression {
+    return Expression::If { condition: Box::from(condition), then, otherwise };
+}
+
+/// fn gcd(a: i32, b: i32) -> i32 { if b == 0 { a } else { gcd(b, a % b) } }
+pub fn gcd() -> Expression {
+    return function("gcd", "i32", &[("a", "i32"), ("b", "i32")], vec![
+        if_else(
+            compare(Predicate::EQUAL, ident("b"), int(0)),
+            vec![ident("a")],
+            vec![call("gcd", vec![ident("b"), arithmetic(BinaryOperator::REM, ident("a"), ident("b"))])]
+        )
+    ]);
+}
+
+pub fn string(value: &str) -> Expression {
+    return Expression::StringLiteral(value.to_string());
+}
+
+/// fn main() -> i32 { content }
+pub fn main_function(content: Vec<Expression>) -> Expression {
+    return function("main", "i32", &[], content);
+}
+
+/// |params| -> return_type { content }
+pub fn closure(return_type: &str, params: &[(&str, &str)], content: Vec<Expression>) -> Expression {
+    return Expression::Closure {
+        return_type: return_type.to_string(),
+        param_types: params.iter().map(|(_, typename)| typename.to_string()).collect(),
+        param_names: params.iter().map(|(name, _)| name.to_string()).collect(),
+        content
+    };
+}
+
+pub fn call_value(callee: Expression, args: Vec<Expression>) -> Expression {
+    return Expression::CallValue { callee: Box::from(callee), args };
+}
+
+/// list.push(value)
+pub fn list_push(list: &str, value: Expression) -> Expression {
+    return Expression::ListPush { list: Box::from(ident(list)), value: Box::from(value) };
+}
+
+/// match scrutinee { arms }
+pub fn match_expression(scrutinee: Expression, arms: Vec<MatchArm>) -> Expression {
+    return Expression::Match { scrutinee: Box::from(scrutinee), arms };
+}
+
+/// pattern => body
+pub fn arm(pattern: Pattern, body: Vec<Expression>) -> MatchArm {
+    return MatchArm{ pattern, guard: None, body };
+}
+
+/// pattern if guard => body
+pub fn guarded_arm(pattern: Pattern, guard: Expression, body: Vec<Expression>) -> MatchArm {
+    return MatchArm{ pattern, guard: Some(guard), body };
+}
+
+/// name @ pattern
+pub fn binding(name: &str, pattern: Pattern) -> Pattern {
+    return Pattern::Binding { name: name.to_string(), pattern: Box::from(pattern) };
+}
+
+/// sourceの中でtextが最初に現れる位置のSpanを式につける(file_idは0)
+pub fn spanned_at(source: &str, text: &str, expression: Expression) -> Expression {
+    let start = source.find(text).unwrap_or_else(|| panic!("'{text}' is not in the source."));
+    return Expression::Spanned { span: Span{ file_id: 0, start, end: start + text.len() }, expression: Box::from(expression) };
+}
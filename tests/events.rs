@@ -0,0 +1,43 @@
+//! Compilerを埋め込んで使い、on_eventで登録した関数に進み具合のイベントが届くことを確かめる
+#![cfg(feature = "llvm")]
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::*;
+use ksc1000::inkwell::context::Context;
+use ksc1000::{Compiler, CompilerEvent, Expression};
+use std::cell::RefCell;
+use std::path::PathBuf;
+
+/// programをビルドし、届いたイベントを文字列にして順に返す
+fn events_of(program: &Vec<Expression>) -> Vec<String> {
+    let events = RefCell::new(vec![]);
+    let context = Context::create();
+    let builder = context.create_builder();
+    let mut compiler = Compiler::new(&context, &builder);
+    compiler.initialize_module_by_filepath(&PathBuf::from("events.ksc"));
+    compiler.on_event(|event| events.borrow_mut().push(match event {
+        CompilerEvent::PhaseStarted(phase) => format!("started {phase:?}"),
+        CompilerEvent::PhaseFinished(phase) => format!("finished {phase:?}"),
+        CompilerEvent::FunctionCompiled { name } => format!("compiled {name}"),
+        CompilerEvent::Diagnostic(error) => format!("error {}", error.message),
+        event => format!("{event:?}")
+    }));
+    compiler.build(program);
+    drop(compiler);
+    return events.into_inner();
+}
+
+#[test]
+fn build_reports_phases_and_functions() {
+    let main = function("main", "i32", &[], vec![call("gcd", vec![int(48), int(18)])]);
+    let events = events_of(&vec![gcd(), main]);
+    assert_eq!(events, vec!["started Codegen", "compiled gcd", "compiled main", "finished Codegen"]);
+}
+
+#[test]
+fn errors_are_reported_as_diagnostics() {
+    let events = events_of(&vec![gcd()]);
+    assert_eq!(events, vec!["started Codegen", "compiled gcd", "error The program has no 'main' function.", "finished Codegen"]);
+}
@@ -0,0 +1,30 @@
+; ModuleID = 'test.kscd64a169cd51e989e3f086cc0b7b0c5b47e14d95d5f007c8ef0ad8bf83cdac4ad'
+source_filename = "test.kscd64a169cd51e989e3f086cc0b7b0c5b47e14d95d5f007c8ef0ad8bf83cdac4ad"
+
+define i32 @gcd(i32 %0, i32 %1) {
+gcd:
+  %a = alloca i32, align 4
+  store i32 %0, i32* %a, align 4
+  %b = alloca i32, align 4
+  store i32 %1, i32* %b, align 4
+  %b1 = load i32, i32* %b, align 4
+  %compared = icmp eq i32 %b1, 0
+  %ifcond = icmp ne i1 %compared, false
+  br i1 %ifcond, label %then, label %else
+
+then:                                             ; preds = %gcd
+  %a2 = load i32, i32* %a, align 4
+  br label %ifcont
+
+else:                                             ; preds = %gcd
+  %b3 = load i32, i32* %b, align 4
+  %a4 = load i32, i32* %a, align 4
+  %b5 = load i32, i32* %b, align 4
+  %rem = srem i32 %a4, %b5
+  %gcd6 = call i32 @gcd(i32 %b3, i32 %rem)
+  br label %ifcont
+
+ifcont:                                           ; preds = %else, %then
+  %iftmp = phi i32 [ %a2, %then ], [ %gcd6, %else ]
+  ret i32 %iftmp
+}
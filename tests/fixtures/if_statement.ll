@@ -0,0 +1,24 @@
+; ModuleID = 'test.kscd64a169cd51e989e3f086cc0b7b0c5b47e14d95d5f007c8ef0ad8bf83cdac4ad'
+source_filename = "test.kscd64a169cd51e989e3f086cc0b7b0c5b47e14d95d5f007c8ef0ad8bf83cdac4ad"
+
+define i32 @sign(i32 %0) {
+sign:
+  %a = alloca i32, align 4
+  store i32 %0, i32* %a, align 4
+  %a1 = load i32, i32* %a, align 4
+  %compared = icmp slt i32 %a1, 0
+  %ifcond = icmp ne i1 %compared, false
+  br i1 %ifcond, label %then, label %else
+
+then:                                             ; preds = %sign
+  ret i32 -1
+
+else:                                             ; preds = %sign
+  br label %ifcont
+
+ifcont:                                           ; preds = %else, %after_return
+  ret i32 1
+
+after_return:                                     ; No predecessors!
+  br label %ifcont
+}
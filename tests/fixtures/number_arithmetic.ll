@@ -0,0 +1,15 @@
+; ModuleID = 'test.kscd64a169cd51e989e3f086cc0b7b0c5b47e14d95d5f007c8ef0ad8bf83cdac4ad'
+source_filename = "test.kscd64a169cd51e989e3f086cc0b7b0c5b47e14d95d5f007c8ef0ad8bf83cdac4ad"
+
+define double @average(double %0, double %1) {
+average:
+  %a = alloca double, align 8
+  store double %0, double* %a, align 8
+  %b = alloca double, align 8
+  store double %1, double* %b, align 8
+  %a1 = load double, double* %a, align 8
+  %b2 = load double, double* %b, align 8
+  %add = fadd double %a1, %b2
+  %div = fdiv double %add, 2.000000e+00
+  ret double %div
+}
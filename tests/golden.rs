@@ -0,0 +1,79 @@
+//! 出力したLLVM IRを、tests/fixtures/<名前>.llに置いた期待する結果と比べる(スナップショットテスト)
+//! パーサーがまだないので、プログラムは.kscではなくASTとしてここに書く
+//! コード生成を変えてIRが変わるときは、KSC_BLESS=1 cargo test --test goldenで期待する結果を書き直し、差分をレビューする
+#![cfg(feature = "llvm")]
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::*;
+use ksc1000::{compile_to_ir, BinaryOperator, Expression, Predicate};
+use std::path::PathBuf;
+
+/// IRをtests/fixtures/<name>.llと比べる
+/// KSC_BLESSが設定されていれば、比べずに期待する結果を書き出す(期待する結果がないときもKSC_BLESSが必要)
+fn check_golden(name: &str, program: Vec<Expression>) {
+    let actual = compile_to_ir(&program).unwrap_or_else(|error| panic!("'{name}' failed to compile:\n{error}"));
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(format!("{name}.ll"));
+    if std::env::var_os("KSC_BLESS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, &actual).unwrap_or_else(|error| panic!("Failed to write '{}': {error}", path.display()));
+        eprintln!("wrote '{}'", path.display());
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|error| panic!("Failed to read '{}' ({error}). Run with KSC_BLESS=1 to create it.", path.display()));
+    if actual != expected {
+        panic!("The IR of '{name}' differs from '{}' (run with KSC_BLESS=1 to update it):\n{}", path.display(), line_diff(&expected, &actual));
+    }
+}
+
+/// 行ごとに比べた差分(-は期待する結果にだけある行、+は出力にだけある行)
+fn line_diff(expected: &str, actual: &str) -> String {
+    let expected = expected.lines().collect::<Vec<&str>>();
+    let actual = actual.lines().collect::<Vec<&str>>();
+    // 最長共通部分列で、変わっていない行を残す
+    let mut common = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+        }
+    }
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            i += 1;
+            j += 1;
+        } else if j < actual.len() && (i == expected.len() || common[i][j + 1] >= common[i + 1][j]) {
+            diff += &format!("+{}\n", actual[j]);
+            j += 1;
+        } else {
+            diff += &format!("-{}\n", expected[i]);
+            i += 1;
+        }
+    }
+    return diff;
+}
+
+#[test]
+fn golden_gcd() {
+    check_golden("gcd", vec![gcd()]);
+}
+
+#[test]
+fn golden_number_arithmetic() {
+    // fn average(a: Number, b: Number) -> Number { (a + b) / 2.0 }
+    check_golden("number_arithmetic", vec![function("average", "Number", &[("a", "Number"), ("b", "Number")], vec![
+        arithmetic(BinaryOperator::DIV, arithmetic(BinaryOperator::ADD, ident("a"), ident("b")), number(2.0))
+    ])]);
+}
+
+#[test]
+fn golden_if_statement() {
+    // fn sign(a: i32) -> i32 { if a < 0 { return -1 }; 1 }
+    check_golden("if_statement", vec![function("sign", "i32", &[("a", "i32")], vec![
+        if_else(compare(Predicate::LESS_THAN, ident("a"), int(0)), vec![Expression::Return(Some(Box::from(int(-1))))], vec![]),
+        int(1)
+    ])]);
+}
@@ -0,0 +1,82 @@
+//! LLVMを使わないインタプリタ(interpret)。--no-default-featuresでも実行される
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::*;
+use ksc1000::{interpret, BinaryOperator, ConstantValue, Expression, Predicate};
+
+#[test]
+fn recursive_function_returns_its_value() {
+    let program = vec![gcd()];
+    assert_eq!(interpret(&program, "gcd", &[ConstantValue::Int(48), ConstantValue::Int(18)]), Ok(ConstantValue::Int(6)));
+}
+
+#[test]
+fn break_and_continue_leave_the_innermost_loop() {
+    // fn odd_sum(n: i32) -> i32 {
+    //     let mut total = 0; let mut i = 0
+    //     while true { i = i + 1; if i > n { break }; if i % 2 == 0 { continue }; total = total + i }
+    //     total
+    // }
+    let program = vec![function("odd_sum", "i32", &[("n", "i32")], vec![
+        let_mut("total", "i32", int(0)),
+        let_mut("i", "i32", int(0)),
+        while_loop(Expression::BoolLiteral(true), vec![
+            assign("i", arithmetic(BinaryOperator::ADD, ident("i"), int(1))),
+            if_else(compare(Predicate::GREATER_THAN, ident("i"), ident("n")), vec![Expression::Break], vec![]),
+            if_else(compare(Predicate::EQUAL, arithmetic(BinaryOperator::REM, ident("i"), int(2)), int(0)), vec![Expression::Continue], vec![]),
+            assign("total", arithmetic(BinaryOperator::ADD, ident("total"), ident("i")))
+        ]),
+        ident("total")
+    ])];
+    assert_eq!(interpret(&program, "odd_sum", &[ConstantValue::Int(10)]), Ok(ConstantValue::Int(25)));
+}
+
+#[test]
+fn return_leaves_the_function_early() {
+    // fn sign(x: Number) -> Number { if x < 0.0 { return -1.0 }; 1.0 }
+    let program = vec![function("sign", "Number", &[("x", "Number")], vec![
+        if_else(compare(Predicate::LESS_THAN, ident("x"), number(0.0)), vec![Expression::Return(Some(Box::from(number(-1.0))))], vec![]),
+        number(1.0)
+    ])];
+    assert_eq!(interpret(&program, "sign", &[ConstantValue::Number(-3.0)]), Ok(ConstantValue::Number(-1.0)));
+    assert_eq!(interpret(&program, "sign", &[ConstantValue::Number(2.0)]), Ok(ConstantValue::Number(1.0)));
+}
+
+#[test]
+fn integer_division_by_zero_is_an_error() {
+    let program = vec![function("f", "i32", &[("x", "i32")], vec![arithmetic(BinaryOperator::DIV, int(1), ident("x"))])];
+    assert_eq!(interpret(&program, "f", &[ConstantValue::Int(0)]), Err("Division by zero.".to_string()));
+}
+
+#[test]
+fn program_that_does_not_check_is_not_run() {
+    let program = vec![function("f", "i32", &[], vec![ident("missing")])];
+    let error = interpret(&program, "f", &[]).unwrap_err();
+    assert!(error.contains("missing"), "{error}");
+}
+
+#[test]
+fn unsupported_expression_is_an_error() {
+    let program = vec![
+        struct_declaration("Point", &[("x", "Number")]),
+        function("f", "Number", &[], vec![field(struct_literal("Point", vec![("x", number(1.0))]), "x")])
+    ];
+    assert_eq!(interpret(&program, "f", &[]), Err("'FieldAccess' is not supported by the interpreter yet.".to_string()));
+}
+
+#[test]
+fn deep_recursion_is_an_error() {
+    // fn down(n: i32) -> i32 { if n == 0 { 0 } else { down(n - 1) } }
+    let program = vec![function("down", "i32", &[("n", "i32")], vec![
+        if_else(
+            compare(Predicate::EQUAL, ident("n"), int(0)),
+            vec![int(0)],
+            vec![call("down", vec![arithmetic(BinaryOperator::SUB, ident("n"), int(1))])]
+        )
+    ])];
+    assert_eq!(interpret(&program, "down", &[ConstantValue::Int(999)]), Ok(ConstantValue::Int(0)));
+    let error = interpret(&program, "down", &[ConstantValue::Int(100_000)]).unwrap_err();
+    assert!(error.contains("Calls are nested deeper than 1000."), "{error}");
+}
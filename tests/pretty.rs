@@ -0,0 +1,25 @@
+//! ASTをKSCのソースコードの形にする(Display for Expression)
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::*;
+use ksc1000::{BinaryOperator, Expression};
+
+#[test]
+fn gcd_is_printed_as_source() {
+    let expected = "fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}";
+    assert_eq!(gcd().to_string(), expected);
+}
+
+#[test]
+fn nested_operations_are_parenthesized() {
+    let expression = arithmetic(BinaryOperator::MUL, arithmetic(BinaryOperator::ADD, number(1.0), ident("x")), Expression::StringLiteral("a\"b".to_string()));
+    assert_eq!(expression.to_string(), "(1.0 + x) * \"a\\\"b\"");
+}
@@ -0,0 +1,163 @@
+//! ランタイム関数(文字列、List、クロージャの環境)を使う代表的なプログラムを--sanitize addressでビルドして実行する
+//! ASanがメモリの誤りやリークを見つけると、報告を標準エラー出力に書いて0以外で終わる
+//! clangとASanのランタイムが要るので、cargo test --features sanitizer-testsのときだけ動く
+#![cfg(feature = "sanitizer-tests")]
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::*;
+use ksc1000::{run_executable, BinaryOperator, Expression, Pattern, Predicate, TestOptions};
+
+/// --sanitize addressでビルドして実行し、正常に終わったことと、ASanの報告がないことを確かめる(標準出力を返す)
+fn assert_clean(program: Vec<Expression>) -> String {
+    let options = TestOptions{ sanitize_address: true, ..TestOptions::default() };
+    let output = run_executable(&program, &options).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(!stderr.contains("AddressSanitizer"), "{stderr}");
+    return String::from_utf8_lossy(&output.stdout).to_string();
+}
+
+/// 条件が成り立てば0、そうでなければ1(mainの終了コード)
+fn exit_code(condition: Expression) -> Expression {
+    return if_else(condition, vec![int(0)], vec![int(1)]);
+}
+
+#[test]
+fn gcd_is_clean() {
+    assert_clean(vec![gcd(), main_function(vec![
+        exit_code(compare(Predicate::EQUAL, call("gcd", vec![int(48), int(18)]), int(6)))
+    ])]);
+}
+
+#[test]
+fn concatenated_and_formatted_strings_are_freed() {
+    // let a = format("{} + {} = {}", 1, 2, 3); let b = a + "!"; println(b); let c = b; println(c + a); 0
+    let stdout = assert_clean(vec![main_function(vec![
+        let_variable("a", "", call("format", vec![string("{} + {} = {}"), int(1), int(2), int(3)])),
+        let_variable("b", "", arithmetic(BinaryOperator::ADD, ident("a"), string("!"))),
+        call("println", vec![ident("b")]),
+        let_variable("c", "", ident("b")),
+        call("println", vec![arithmetic(BinaryOperator::ADD, ident("c"), ident("a"))]),
+        int(0)
+    ])]);
+    assert_eq!(stdout, "1 + 2 = 3!\n1 + 2 = 3!1 + 2 = 3\n");
+}
+
+#[test]
+fn printed_format_call_matches_the_formatted_string() {
+    // let n = 7; print(format("{} + {}", n, "a")); println(format("={}", 1.5)); println(format("{}", n) + "!"); 0
+    let stdout = assert_clean(vec![main_function(vec![
+        let_variable("n", "", int(7)),
+        call("print", vec![call("format", vec![string("{} + {}"), ident("n"), string("a")])]),
+        call("println", vec![call("format", vec![string("={}"), number(1.5)])]),
+        call("println", vec![arithmetic(BinaryOperator::ADD, call("format", vec![string("{}"), ident("n")]), string("!"))]),
+        int(0)
+    ])]);
+    assert_eq!(stdout, "7 + a=1.500000\n7!\n");
+}
+
+#[test]
+fn list_of_strings_releases_its_elements() {
+    // let names = List<String>(); names.push(format("{}", 1)); names.push("two" + "!"); names.len() == 2
+    assert_clean(vec![main_function(vec![
+        let_variable("names", "", Expression::ListNew("String".to_string())),
+        list_push("names", call("format", vec![string("{}"), int(1)])),
+        list_push("names", arithmetic(BinaryOperator::ADD, string("two"), string("!"))),
+        exit_code(compare(Predicate::EQUAL, Expression::ListLen(Box::from(ident("names"))), int(2)))
+    ])]);
+}
+
+#[test]
+fn closure_environment_is_released() {
+    // let suffix = format("{}", 1); let f = |x: Number| -> String { format("{}", x) + suffix }; println(f(2.0)); println(f(3.0)); 0
+    assert_clean(vec![main_function(vec![
+        let_variable("suffix", "", call("format", vec![string("{}"), int(1)])),
+        let_variable("f", "", closure("String", &[("x", "Number")], vec![
+            arithmetic(BinaryOperator::ADD, call("format", vec![string("{}"), ident("x")]), ident("suffix"))
+        ])),
+        call("println", vec![call_value(ident("f"), vec![number(2.0)])]),
+        call("println", vec![call_value(ident("f"), vec![number(3.0)])]),
+        int(0)
+    ])]);
+}
+
+#[test]
+fn strings_returned_from_functions_are_freed() {
+    // fn greet(name: String) -> String { "hello, " + name } fn main() -> i32 { println(greet(greet("ksc"))); 0 }
+    assert_clean(vec![
+        function("greet", "String", &[("name", "String")], vec![arithmetic(BinaryOperator::ADD, string("hello, "), ident("name"))]),
+        main_function(vec![
+            call("println", vec![call("greet", vec![call("greet", vec![string("ksc")])])]),
+            int(0)
+        ])
+    ]);
+}
+
+/// struct Named { name: String, id: i32 } fn make(id: i32) -> Named { Named { name: format("{}", id), id: id } }
+fn named() -> Vec<Expression> {
+    return vec![
+        struct_declaration("Named", &[("name", "String"), ("id", "i32")]),
+        function("make", "Named", &[("id", "i32")], vec![
+            struct_literal("Named", vec![("name", call("format", vec![string("{}"), ident("id")])), ("id", ident("id"))])
+        ])
+    ];
+}
+
+#[test]
+fn struct_holding_a_string_releases_its_field() {
+    // let a = make(1); let b = a; println(b.name); println(make(2).name); let c = if b.id == 1 { make(3) } else { make(4) }; println(c.name); 0
+    let mut program = named();
+    program.push(main_function(vec![
+        let_variable("a", "", call("make", vec![int(1)])),
+        let_variable("b", "", ident("a")),
+        call("println", vec![field(ident("b"), "name")]),
+        call("println", vec![field(call("make", vec![int(2)]), "name")]),
+        let_variable("c", "", if_else(compare(Predicate::EQUAL, field(ident("b"), "id"), int(1)), vec![call("make", vec![int(3)])], vec![call("make", vec![int(4)])])),
+        call("println", vec![field(ident("c"), "name")]),
+        int(0)
+    ]));
+    assert_eq!(assert_clean(program), "1\n2\n3\n");
+}
+
+#[test]
+fn tuples_and_enums_holding_strings_release_them() {
+    // enum Message = Text(String) | Empty
+    // let t = (format("{}", 1), 2); let (s, n) = t; println(s);
+    // let m = Message::Text(format("{}", n)); println(match m { Text(text) => text, Empty => "empty" }); 0
+    let message = Expression::EnumDeclaration {
+        name: "Message".to_string(),
+        variant_names: vec!["Text".to_string(), "Empty".to_string()],
+        variant_types: vec![vec!["String".to_string()], vec![]]
+    };
+    let stdout = assert_clean(vec![message, main_function(vec![
+        let_variable("t", "", Expression::TupleLiteral(vec![call("format", vec![string("{}"), int(1)]), int(2)])),
+        Expression::TupleDestructure { names: vec!["s".to_string(), "n".to_string()], mutable: false, value: Box::from(ident("t")) },
+        call("println", vec![ident("s")]),
+        let_variable("m", "", Expression::EnumVariant { typename: "Message".to_string(), variant: "Text".to_string(), values: vec![call("format", vec![string("{}"), ident("n")])] }),
+        call("println", vec![match_expression(ident("m"), vec![
+            arm(Pattern::Variant { variant: "Text".to_string(), names: vec!["text".to_string()] }, vec![ident("text")]),
+            arm(Pattern::Variant { variant: "Empty".to_string(), names: vec![] }, vec![string("empty")])
+        ])]),
+        int(0)
+    ])]);
+    assert_eq!(stdout, "1\n2\n");
+}
+
+#[test]
+fn variables_in_a_loop_body_are_released_on_continue_and_break() {
+    // let mut i = 0; while true { let s = format("{}", i); i = i + 1; if i == 1 { continue }; println(s); if i == 3 { break } }; 0
+    let stdout = assert_clean(vec![main_function(vec![
+        let_mut("i", "i32", int(0)),
+        while_loop(Expression::BoolLiteral(true), vec![
+            let_variable("s", "", call("format", vec![string("{}"), ident("i")])),
+            assign("i", arithmetic(BinaryOperator::ADD, ident("i"), int(1))),
+            if_else(compare(Predicate::EQUAL, ident("i"), int(1)), vec![Expression::Continue], vec![]),
+            call("println", vec![ident("s")]),
+            if_else(compare(Predicate::EQUAL, ident("i"), int(3)), vec![Expression::Break], vec![])
+        ]),
+        int(0)
+    ])]);
+    assert_eq!(stdout, "1\n2\n");
+}